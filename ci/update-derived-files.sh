@@ -44,6 +44,7 @@ for cmd in \
     list-clients \
     move-pane-to-new-tab \
     rename-workspace \
+    screenshot \
     send-text \
     set-tab-title \
     set-window-title \
@@ -441,7 +441,7 @@ macro_rules! pdu {
 /// The overall version of the codec.
 /// This must be bumped when backwards incompatible changes
 /// are made to the types and protocol.
-pub const CODEC_VERSION: usize = 45;
+pub const CODEC_VERSION: usize = 50;
 
 // Defines the Pdu enum.
 // Each struct has an explicit identifying number.
@@ -502,6 +502,8 @@ pdu! {
     GetPaneDirection: 60,
     GetPaneDirectionResponse: 61,
     AdjustPaneSize: 62,
+    GetPanePalette: 63,
+    GetPanePaletteResponse: 64,
 }
 
 impl Pdu {
@@ -790,6 +792,17 @@ pub struct SetPalette {
     pub palette: ColorPalette,
 }
 
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetPanePalette {
+    pub pane_id: PaneId,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Debug)]
+pub struct GetPanePaletteResponse {
+    pub pane_id: PaneId,
+    pub palette: ColorPalette,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
 pub struct NotifyAlert {
     pub pane_id: PaneId,
@@ -925,6 +938,10 @@ pub struct GetPaneRenderChangesResponse {
 
     pub input_serial: Option<InputSerial>,
     pub seqno: SequenceNo,
+    /// The changes complete a synchronized update (DECSET 2026)
+    /// and should be presented together, rather than as the
+    /// dirty lines are fetched
+    pub synchronized: bool,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug)]
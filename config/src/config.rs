@@ -6,8 +6,9 @@ use crate::color::{
 use crate::daemon::DaemonOptions;
 use crate::exec_domain::ExecDomain;
 use crate::font::{
-    AllowSquareGlyphOverflow, DisplayPixelGeometry, FontLocatorSelection, FontRasterizerSelection,
-    FontShaperSelection, FreeTypeLoadFlags, FreeTypeLoadTarget, StyleRule, TextStyle,
+    AllowSquareGlyphOverflow, DisplayPixelGeometry, FontLocatorSelection, FontRangeRule,
+    FontRasterizerSelection, FontShaperSelection, FreeTypeLoadFlags, FreeTypeLoadTarget, StyleRule,
+    TextStyle,
 };
 use crate::frontend::FrontEndSelection;
 use crate::keyassignment::{
@@ -121,6 +122,11 @@ pub struct Config {
     #[dynamic(default)]
     pub font_rules: Vec<StyleRule>,
 
+    /// Rules that force ranges of codepoints to be rendered using
+    /// a specific font, ahead of the general font fallback
+    #[dynamic(default)]
+    pub font_range_rules: Vec<FontRangeRule>,
+
     /// When true (the default), PaletteIndex 0-7 are shifted to
     /// bright when the font intensity is bold.  The brightening
     /// doesn't apply to text that is the default color.
@@ -203,6 +209,20 @@ pub struct Config {
     )]
     pub scrollback_lines: usize,
 
+    /// When set to a non-zero value, lines that are scrolled out of
+    /// the in-memory scrollback (sized by `scrollback_lines`) are
+    /// compressed and paged out to a per-pane file on disk rather
+    /// than being discarded.  Up to this many lines are retained
+    /// on disk.
+    #[dynamic(default)]
+    pub scrollback_spill_lines: usize,
+
+    /// The directory in which the on-disk scrollback files are
+    /// created.  Defaults to a `scrollback` directory inside the
+    /// wezterm runtime directory.
+    #[dynamic(default = "default_scrollback_spill_dir")]
+    pub scrollback_spill_dir: PathBuf,
+
     /// If no `prog` is specified on the command line, use this
     /// instead of running the user's shell.
     /// For example, to have `wezterm` always run `top` by default,
@@ -405,6 +425,13 @@ pub struct Config {
     #[dynamic(default = "default_mux_output_parser_coalesce_delay_ms")]
     pub mux_output_parser_coalesce_delay_ms: u64,
 
+    /// The maximum number of ms that the mux will hold back the
+    /// output of a synchronized update (DECSET 2026) while waiting
+    /// for the application to end it.  This prevents a program
+    /// that forgets to end the update from freezing the pane.
+    #[dynamic(default = "default_synchronized_output_timeout_ms")]
+    pub synchronized_output_timeout_ms: u64,
+
     #[dynamic(default = "default_mux_env_remove")]
     pub mux_env_remove: Vec<String>,
 
@@ -774,6 +801,22 @@ pub struct Config {
     #[dynamic(default = "default_status_update_interval")]
     pub status_update_interval: u64,
 
+    /// When shell integration reports the status of commands,
+    /// show a colored gutter alongside each command and its
+    /// elapsed time next to its prompt.
+    #[dynamic(default = "default_true")]
+    pub show_command_status: bool,
+
+    /// Commands that run for at least this many milliseconds
+    /// trigger the `command-finished` event when they complete.
+    #[dynamic(default = "default_command_finished_event_threshold_ms")]
+    pub command_finished_event_threshold_ms: u64,
+
+    /// When scrolling through the output of a command, pin the
+    /// prompt of that command to the top of the pane.
+    #[dynamic(default)]
+    pub sticky_prompt: bool,
+
     #[dynamic(default)]
     pub experimental_pixel_positioning: bool,
 
@@ -851,6 +894,12 @@ pub struct Config {
     #[dynamic(default = "default_true")]
     pub allow_download_protocols: bool,
 
+    #[dynamic(default)]
+    pub clipboard_read_policy: ClipboardReadPolicy,
+
+    #[dynamic(default)]
+    pub clipboard_read_policy_by_domain: HashMap<String, ClipboardReadPolicy>,
+
     #[dynamic(default = "default_true")]
     pub allow_win32_input_mode: bool,
 
@@ -1481,6 +1530,15 @@ impl Config {
         Ok(())
     }
 
+    /// Returns the clipboard read policy that applies to panes
+    /// that belong to the named domain
+    pub fn clipboard_read_policy_for_domain(&self, domain_name: &str) -> ClipboardReadPolicy {
+        self.clipboard_read_policy_by_domain
+            .get(domain_name)
+            .copied()
+            .unwrap_or(self.clipboard_read_policy)
+    }
+
     pub fn resolve_color_scheme(&self) -> Option<&Palette> {
         let scheme_name = self.color_scheme.as_ref()?;
 
@@ -1657,6 +1715,10 @@ fn default_mux_output_parser_coalesce_delay_ms() -> u64 {
     3
 }
 
+fn default_synchronized_output_timeout_ms() -> u64 {
+    1000
+}
+
 fn default_mux_output_parser_buffer_size() -> usize {
     128 * 1024
 }
@@ -1687,6 +1749,10 @@ fn default_scrollback_lines() -> usize {
     3500
 }
 
+fn default_scrollback_spill_dir() -> PathBuf {
+    crate::RUNTIME_DIR.join("scrollback")
+}
+
 const MAX_SCROLLBACK_LINES: usize = 999_999_999;
 fn validate_scrollback_lines(value: &usize) -> Result<(), String> {
     if *value > MAX_SCROLLBACK_LINES {
@@ -1843,6 +1909,10 @@ fn default_status_update_interval() -> u64 {
     1_000
 }
 
+fn default_command_finished_event_threshold_ms() -> u64 {
+    10_000
+}
+
 fn default_alternate_buffer_wheel_scroll_speed() -> u8 {
     3
 }
@@ -2153,6 +2223,18 @@ pub enum NotificationHandling {
     SuppressFromFocusedWindow,
 }
 
+/// Controls whether an application may read the clipboard using OSC 52
+#[derive(Debug, FromDynamic, ToDynamic, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClipboardReadPolicy {
+    /// Queries are answered with empty data
+    #[default]
+    Never,
+    /// Prompt the user to allow or deny each query
+    Ask,
+    /// Queries are answered with the clipboard contents
+    Always,
+}
+
 fn validate_row_or_col(value: &u16) -> Result<(), String> {
     if *value < 1 {
         Err("initial_cols and initial_rows must be non-zero".to_string())
@@ -369,6 +369,10 @@ pub struct FontAttributes {
 
     #[dynamic(default)]
     pub harfbuzz_features: Option<Vec<String>>,
+    /// OpenType variation axis settings such as `wght=450`,
+    /// applied to variable fonts
+    #[dynamic(default)]
+    pub variations: Option<Vec<String>>,
     #[dynamic(default)]
     pub freetype_load_target: Option<FreeTypeLoadTarget>,
     #[dynamic(default)]
@@ -402,6 +406,7 @@ impl FontAttributes {
             is_fallback: false,
             is_synthetic: false,
             harfbuzz_features: None,
+            variations: None,
             freetype_load_target: None,
             freetype_render_target: None,
             freetype_load_flags: None,
@@ -419,6 +424,7 @@ impl FontAttributes {
             is_fallback: true,
             is_synthetic: false,
             harfbuzz_features: None,
+            variations: None,
             freetype_load_target: None,
             freetype_render_target: None,
             freetype_load_flags: None,
@@ -438,6 +444,7 @@ impl Default for FontAttributes {
             is_fallback: false,
             is_synthetic: false,
             harfbuzz_features: None,
+            variations: None,
             freetype_load_target: None,
             freetype_render_target: None,
             freetype_load_flags: None,
@@ -649,6 +656,98 @@ pub struct StyleRule {
     pub font: TextStyle,
 }
 
+/// An inclusive range of unicode codepoints, written as eg: `U+E000-U+F8FF`,
+/// or as `U+2500` for a single codepoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromDynamic, ToDynamic)]
+#[dynamic(try_from = "String", into = "String")]
+pub struct CodepointRange {
+    pub first: u32,
+    pub last: u32,
+}
+
+impl CodepointRange {
+    pub fn contains(&self, c: char) -> bool {
+        (self.first..=self.last).contains(&(c as u32))
+    }
+}
+
+impl std::fmt::Display for CodepointRange {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.first == self.last {
+            write!(fmt, "U+{:04X}", self.first)
+        } else {
+            write!(fmt, "U+{:04X}-U+{:04X}", self.first, self.last)
+        }
+    }
+}
+
+impl From<&CodepointRange> for String {
+    fn from(val: &CodepointRange) -> Self {
+        val.to_string()
+    }
+}
+
+impl From<CodepointRange> for String {
+    fn from(val: CodepointRange) -> Self {
+        val.to_string()
+    }
+}
+
+impl TryFrom<String> for CodepointRange {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, String> {
+        fn parse_codepoint(cp: &str, s: &str) -> Result<u32, String> {
+            let hex = cp
+                .trim()
+                .strip_prefix("U+")
+                .or_else(|| cp.trim().strip_prefix("u+"))
+                .ok_or_else(|| format!("expected a codepoint like U+E000 in `{}`", s))?;
+            let value = u32::from_str_radix(hex, 16)
+                .map_err(|err| format!("invalid codepoint `{}` in `{}`: {}", cp, s, err))?;
+            if value > 0x10FFFF {
+                return Err(format!("codepoint `{}` in `{}` is out of range", cp, s));
+            }
+            Ok(value)
+        }
+
+        let (first, last) = match s.split_once('-') {
+            Some((first, last)) => (parse_codepoint(first, &s)?, parse_codepoint(last, &s)?),
+            None => {
+                let cp = parse_codepoint(&s, &s)?;
+                (cp, cp)
+            }
+        };
+        if first > last {
+            return Err(format!("the range `{}` ends before it starts", s));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+/// Defines a rule that forces a set of codepoints to be rendered
+/// using a specific font, rather than the first font in the fallback
+/// list that happens to have glyphs for them:
+///
+/// ```lua
+/// font_range_rules = {
+///   { ranges = { 'U+E000-U+F8FF' }, font = wezterm.font 'Symbols Nerd Font' },
+/// }
+/// ```
+///
+/// The rules are applied when resolving every `TextStyle`, ahead of the
+/// general fallback; the first rule that lists a codepoint wins.
+#[derive(Debug, Clone, FromDynamic, ToDynamic)]
+pub struct FontRangeRule {
+    pub ranges: Vec<CodepointRange>,
+    pub font: TextStyle,
+}
+
+impl FontRangeRule {
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|range| range.contains(c))
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
 pub enum AllowSquareGlyphOverflow {
     Never,
@@ -720,4 +819,37 @@ mod test {
             assert_eq!(style.font[0].family, "Inconsolata");
         }
     }
+
+    #[test]
+    fn codepoint_ranges() {
+        let parse = |s: &str| CodepointRange::try_from(s.to_string());
+
+        let range = parse("U+E000-U+F8FF").unwrap();
+        assert_eq!(
+            range,
+            CodepointRange {
+                first: 0xe000,
+                last: 0xf8ff
+            }
+        );
+        assert!(range.contains('\u{f0e7}'));
+        assert!(!range.contains('a'));
+        assert_eq!(range.to_string(), "U+E000-U+F8FF");
+
+        let single = parse("u+2500").unwrap();
+        assert_eq!(
+            single,
+            CodepointRange {
+                first: 0x2500,
+                last: 0x2500
+            }
+        );
+        assert_eq!(single.to_string(), "U+2500");
+        assert_eq!(parse(" U+1F300 - U+1F5FF ").unwrap().last, 0x1f5ff);
+
+        assert!(parse("E000-F8FF").is_err());
+        assert!(parse("U+F8FF-U+E000").is_err());
+        assert!(parse("U+110000").is_err());
+        assert!(parse("U+XYZ").is_err());
+    }
 }
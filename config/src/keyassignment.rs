@@ -1,3 +1,4 @@
+use crate::color::ColorSpec;
 use crate::default_true;
 use crate::keys::KeyNoAction;
 use crate::window::WindowLevel;
@@ -116,15 +117,17 @@ pub enum Pattern {
     CaseSensitiveString(String),
     CaseInSensitiveString(String),
     Regex(String),
+    Fuzzy(String),
     CurrentSelectionOrEmptyString,
 }
 
 impl Pattern {
     pub fn is_empty(&self) -> bool {
         match self {
-            Self::CaseSensitiveString(s) | Self::CaseInSensitiveString(s) | Self::Regex(s) => {
-                s.is_empty()
-            }
+            Self::CaseSensitiveString(s)
+            | Self::CaseInSensitiveString(s)
+            | Self::Regex(s)
+            | Self::Fuzzy(s) => s.is_empty(),
             Self::CurrentSelectionOrEmptyString => true,
         }
     }
@@ -307,11 +310,51 @@ impl Default for ScrollbackEraseMode {
     }
 }
 
+/// The document format produced when exporting the content of a pane
+#[derive(Debug, Copy, Clone, PartialEq, Eq, FromDynamic, ToDynamic, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// A self-contained, styled, HTML document
+    Html,
+    /// Text with embedded escape sequences for color and styling
+    Ansi,
+    /// Unattributed text
+    Text,
+}
+
+impl Default for ExportFormat {
+    fn default() -> Self {
+        Self::Html
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Ansi => "ansi",
+            Self::Text => "txt",
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub struct ExportScrollbackArguments {
+    #[dynamic(default)]
+    pub format: ExportFormat,
+    /// The name of the file to create in the downloads directory.
+    /// If omitted, a name is derived from the pane id.
+    #[dynamic(default)]
+    pub file_name: Option<String>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
 pub enum ClipboardCopyDestination {
     Clipboard,
     PrimarySelection,
     ClipboardAndPrimarySelection,
+    /// A named register that is local to wezterm, which can be
+    /// pasted later using `PasteFrom`
+    Register(char),
 }
 impl_lua_conversion_dynamic!(ClipboardCopyDestination);
 
@@ -325,6 +368,8 @@ impl Default for ClipboardCopyDestination {
 pub enum ClipboardPasteSource {
     Clipboard,
     PrimarySelection,
+    /// A named register that was populated by `CopyTo`
+    Register(char),
 }
 
 impl Default for ClipboardPasteSource {
@@ -333,6 +378,21 @@ impl Default for ClipboardPasteSource {
     }
 }
 
+/// What `JumpToDirectory` does with the chosen directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub enum DirectoryJumpTarget {
+    /// Send a `cd` command to the current pane
+    ChangeDirectory,
+    /// Spawn a new tab in that directory
+    SpawnTab,
+}
+
+impl Default for DirectoryJumpTarget {
+    fn default() -> Self {
+        Self::ChangeDirectory
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
 pub enum PaneSelectMode {
     Activate,
@@ -458,6 +518,26 @@ pub struct QuickSelectArguments {
     /// How many lines before and how many lines after the viewport to
     /// search to produce the quickselect results
     pub scope_lines: Option<usize>,
+    /// Patterns that each have their own action and label color.
+    /// These take precedence over `patterns`
+    #[dynamic(default)]
+    pub rules: Vec<QuickSelectRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
+pub struct QuickSelectRule {
+    /// The regex to match
+    pub regex: String,
+    /// The action to perform when a match for this rule is selected.
+    /// If not set, the `action` from the QuickSelectArguments is used
+    #[dynamic(default)]
+    pub action: Option<Box<KeyAssignment>>,
+    /// Overrides the quick_select_label_fg color for matches of this rule
+    #[dynamic(default)]
+    pub label_color: Option<ColorSpec>,
+    /// A name for the rule, passed to action callbacks
+    #[dynamic(default)]
+    pub name: String,
 }
 
 #[derive(Debug, Clone, PartialEq, FromDynamic, ToDynamic)]
@@ -505,15 +585,15 @@ pub struct InputSelector {
     pub fuzzy_description: String,
 }
 
-fn default_num_alphabet() -> String {
+pub fn default_num_alphabet() -> String {
     "1234567890abcdefghilmnopqrstuvwxyz".to_string()
 }
 
-fn default_description() -> String {
+pub fn default_description() -> String {
     "Select an item and press Enter = accept,  Esc = cancel,  / = filter".to_string()
 }
 
-fn default_fuzzy_description() -> String {
+pub fn default_fuzzy_description() -> String {
     "Fuzzy matching: ".to_string()
 }
 
@@ -569,6 +649,9 @@ pub enum KeyAssignment {
     ScrollByLine(isize),
     ScrollByCurrentEventWheelDelta,
     ScrollToPrompt(isize),
+    CopyCommandOutput(ClipboardCopyDestination),
+    ToggleCommandOutputCollapse,
+    ShowCommandHistory,
     ScrollToTop,
     ScrollToBottom,
     ShowTabNavigator,
@@ -582,7 +665,10 @@ pub enum KeyAssignment {
     ShowLauncher,
     ShowLauncherArgs(LauncherActionArgs),
     ClearScrollback(ScrollbackEraseMode),
+    ExportScrollback(ExportScrollbackArguments),
     Search(Pattern),
+    SearchAllPanes(Pattern),
+    JumpToDirectory(DirectoryJumpTarget),
     ActivateCopyMode,
 
     SelectTextAtMouseCursor(SelectionMode),
@@ -720,6 +806,27 @@ pub enum CopyModeAssignment {
     JumpBackward { prev_char: bool },
     JumpAgain,
     JumpReverse,
+    MoveToMatchingBracket,
+    SetMark(char),
+    JumpToMark { mark: char, exact: bool },
+    SelectTextObject { object: TextObject, around: bool },
+    SelectRegister(char),
+}
+
+/// The vim-style text objects that can be selected in copy mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromDynamic, ToDynamic)]
+pub enum TextObject {
+    /// A run of word characters, or of other non-blank characters
+    Word,
+    /// A run of non-blank characters
+    BigWord,
+    /// A run of non-blank lines, or of blank lines
+    Paragraph,
+    /// Text between a pair of the specified quote character
+    Quoted(char),
+    /// Text between the specified opening bracket and its
+    /// matching closing bracket
+    Bracketed(char),
 }
 
 pub type KeyTable = HashMap<(KeyCode, Modifiers), KeyTableEntry>;
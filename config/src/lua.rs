@@ -489,6 +489,8 @@ struct LuaFontAttributes {
     #[dynamic(default)]
     pub harfbuzz_features: Option<Vec<String>>,
     #[dynamic(default)]
+    pub variations: Option<Vec<String>>,
+    #[dynamic(default)]
     pub freetype_load_target: Option<FreeTypeLoadTarget>,
     #[dynamic(default)]
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
@@ -577,6 +579,7 @@ fn font<'lua>(
             is_fallback: false,
             is_synthetic: false,
             harfbuzz_features: attrs.harfbuzz_features,
+            variations: attrs.variations,
             freetype_load_target: attrs.freetype_load_target,
             freetype_render_target: attrs.freetype_render_target,
             freetype_load_flags: match attrs.freetype_load_flags {
@@ -626,6 +629,7 @@ fn font_with_fallback<'lua>(
                 is_fallback: idx != 0,
                 is_synthetic: false,
                 harfbuzz_features: attrs.harfbuzz_features,
+                variations: attrs.variations,
                 freetype_load_target: attrs.freetype_load_target,
                 freetype_render_target: attrs.freetype_render_target,
                 freetype_load_flags: match attrs.freetype_load_flags {
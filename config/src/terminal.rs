@@ -1,6 +1,7 @@
 //! Bridge our gui config into the terminal crate configuration
 
 use crate::{configuration, ConfigHandle, NewlineCanon};
+use std::path::PathBuf;
 use std::sync::Mutex;
 use termwiz::cell::UnicodeVersion;
 use wezterm_term::color::ColorPalette;
@@ -52,6 +53,14 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         self.configuration().scrollback_lines
     }
 
+    fn scrollback_spill_size(&self) -> usize {
+        self.configuration().scrollback_spill_lines
+    }
+
+    fn scrollback_spill_dir(&self) -> Option<PathBuf> {
+        Some(self.configuration().scrollback_spill_dir.clone())
+    }
+
     fn enable_csi_u_key_encoding(&self) -> bool {
         self.configuration().enable_csi_u_key_encoding
     }
@@ -168,9 +168,36 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    let wezterm_mod = get_or_create_module(lua, "wezterm")?;
+    wezterm_mod.set(
+        "frecent_dirs",
+        lua.create_function(|_, _: ()| {
+            Ok(mux::frecent_dirs::frecent_dirs()
+                .into_iter()
+                .map(|dir| FrecentDirInfo {
+                    score: dir.frecency.score(),
+                    num_accesses: dir.frecency.num_accesses(),
+                    last_accessed: dir.frecency.last_accessed().timestamp(),
+                    host: dir.host,
+                    path: dir.path,
+                })
+                .collect::<Vec<_>>())
+        })?,
+    )?;
+
     Ok(())
 }
 
+#[derive(Debug, FromDynamic, ToDynamic)]
+struct FrecentDirInfo {
+    host: Option<String>,
+    path: String,
+    score: f64,
+    num_accesses: u64,
+    last_accessed: i64,
+}
+impl_lua_conversion_dynamic!(FrecentDirInfo);
+
 #[derive(Debug, Default, FromDynamic, ToDynamic)]
 struct CommandBuilderFrag {
     args: Option<Vec<String>>,
@@ -249,6 +249,21 @@ impl UserData for MuxPane {
             Ok(text)
         });
 
+        methods.add_method("get_lines_as_html", |_, this, nlines: Option<usize>| {
+            let mux = get_mux()?;
+            let pane = this.resolve(&mux)?;
+            let dims = pane.get_dimensions();
+            let nlines = nlines.unwrap_or(dims.viewport_rows);
+            let bottom_row = dims.physical_top + dims.viewport_rows as isize;
+            let top_row = bottom_row.saturating_sub(nlines as isize);
+            let (_first_row, lines) = pane.get_lines(top_row..bottom_row);
+            Ok(mux::export::lines_to_html(
+                &lines,
+                &pane.palette(),
+                &pane.get_title(),
+            ))
+        });
+
         methods.add_method(
             "get_logical_lines_as_text",
             |_, this, nlines: Option<usize>| {
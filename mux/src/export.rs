@@ -0,0 +1,463 @@
+//! Exports the content of a pane as a standalone document
+use crate::pane::Pane;
+use crate::renderable::{RenderableDimensions, StableCursorPosition};
+use crate::tab::Tab;
+use config::keyassignment::ExportFormat;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use termwiz::surface::{CursorShape, CursorVisibility};
+use termwiz_funcs::lines_to_escapes;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::{CellAttributes, Intensity, Line, SemanticType, StableRowIndex, Underline};
+
+/// The number of rows that `export_pane` reads from the pane at a time.
+/// The scrollback may have been spilled to disk and be much larger than
+/// would fit in memory, so it is never loaded all at once.
+const EXPORT_CHUNK_ROWS: StableRowIndex = 1000;
+
+/// Export the entire scrollback and viewport of `pane` to `out`,
+/// a chunk of rows at a time
+pub fn export_pane(
+    pane: &dyn Pane,
+    format: ExportFormat,
+    out: &mut dyn std::io::Write,
+) -> anyhow::Result<()> {
+    let dims = pane.get_dimensions();
+    let end = dims.physical_top + dims.viewport_rows as StableRowIndex;
+    let palette = pane.palette();
+
+    if format == ExportFormat::Html {
+        let mut html = String::new();
+        html_header(&palette, &pane.get_title(), &mut html);
+        out.write_all(html.as_bytes())?;
+    }
+
+    let mut start = dims.scrollback_top;
+    while start < end {
+        let chunk_end = (start + EXPORT_CHUNK_ROWS).min(end);
+        let (_first, lines) = pane.get_lines(start..chunk_end);
+        out.write_all(export_lines(lines, &palette, format)?.as_bytes())?;
+        start = chunk_end;
+    }
+
+    if format == ExportFormat::Html {
+        out.write_all(HTML_FOOTER.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Render `lines` in the requested format, without the surrounding
+/// document in the case of html.
+/// `palette` is used to resolve colors when producing html.
+fn export_lines(
+    lines: Vec<Line>,
+    palette: &ColorPalette,
+    format: ExportFormat,
+) -> anyhow::Result<String> {
+    match format {
+        ExportFormat::Html => {
+            let mut html = String::new();
+            for line in &lines {
+                line_to_html(line, palette, &mut html);
+            }
+            Ok(html)
+        }
+        ExportFormat::Ansi => lines_to_escapes(lines),
+        ExportFormat::Text => {
+            let mut text = String::new();
+            for line in &lines {
+                text.push_str(line.as_str().trim_end());
+                text.push('\n');
+            }
+            Ok(text)
+        }
+    }
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// The schemes of the hyperlinks that are preserved as anchors.
+/// Any program running in the pane can create a hyperlink, so other
+/// schemes, such as `javascript:`, are exported as plain text to
+/// avoid injecting script into the exported document.
+const EXPORTED_LINK_SCHEMES: &[&str] = &["http", "https", "file", "mailto"];
+
+/// Returns the normalized form of `uri` if it is safe to
+/// export it as an anchor
+fn exportable_link(uri: &str) -> Option<String> {
+    let url = url::Url::parse(uri).ok()?;
+    if EXPORTED_LINK_SCHEMES.contains(&url.scheme()) {
+        Some(url.into())
+    } else {
+        None
+    }
+}
+
+fn semantic_class(semantic_type: SemanticType) -> &'static str {
+    match semantic_type {
+        SemanticType::Prompt => "prompt",
+        SemanticType::Input => "input",
+        SemanticType::Output => "output",
+    }
+}
+
+/// Compute the inline css for a run of cells with the specified attributes
+fn cell_style(attrs: &CellAttributes, palette: &ColorPalette) -> String {
+    let mut fg = palette.resolve_fg(attrs.foreground());
+    let mut bg = palette.resolve_bg(attrs.background());
+    if attrs.reverse() {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if attrs.invisible() {
+        fg = bg;
+    }
+
+    let mut style = String::new();
+    if fg != palette.foreground {
+        write!(style, "color:{};", fg.to_rgb_string()).ok();
+    }
+    if bg != palette.background {
+        write!(style, "background-color:{};", bg.to_rgb_string()).ok();
+    }
+    match attrs.intensity() {
+        Intensity::Normal => {}
+        Intensity::Bold => style.push_str("font-weight:bold;"),
+        Intensity::Half => style.push_str("opacity:0.6;"),
+    }
+    if attrs.italic() {
+        style.push_str("font-style:italic;");
+    }
+
+    let mut decorations = vec![];
+    let underline_style = match attrs.underline() {
+        Underline::None => None,
+        Underline::Single => Some("solid"),
+        Underline::Double => Some("double"),
+        Underline::Curly => Some("wavy"),
+        Underline::Dotted => Some("dotted"),
+        Underline::Dashed => Some("dashed"),
+    };
+    if underline_style.is_some() {
+        decorations.push("underline");
+    }
+    if attrs.strikethrough() {
+        decorations.push("line-through");
+    }
+    if attrs.overline() {
+        decorations.push("overline");
+    }
+    if !decorations.is_empty() {
+        write!(style, "text-decoration-line:{};", decorations.join(" ")).ok();
+        if let Some(underline_style) = underline_style {
+            write!(style, "text-decoration-style:{};", underline_style).ok();
+            let underline_color = attrs.underline_color();
+            if underline_color != Default::default() {
+                write!(
+                    style,
+                    "text-decoration-color:{};",
+                    palette.resolve_fg(underline_color).to_rgb_string()
+                )
+                .ok();
+            }
+        }
+    }
+    style
+}
+
+/// Produce a self-contained html document representing `lines`.
+/// Colors are resolved through `palette`, hyperlinks are preserved
+/// as anchors, double width cells are marked with the `wide` class
+/// and OSC 133 semantic zones are marked with the `prompt`, `input`
+/// and `output` classes.
+pub fn lines_to_html(lines: &[Line], palette: &ColorPalette, title: &str) -> String {
+    let mut html = String::new();
+    html_header(palette, title, &mut html);
+    for line in lines {
+        line_to_html(line, palette, &mut html);
+    }
+    html.push_str(HTML_FOOTER);
+    html
+}
+
+const HTML_FOOTER: &str = "</pre>\n</body>\n</html>\n";
+
+/// Appends the start of the html document, up to the first line, to `html`
+fn html_header(palette: &ColorPalette, title: &str, html: &mut String) {
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+    escape_html(title, html);
+    html.push_str("</title>\n<style>\n");
+    write!(
+        html,
+        "body {{ background-color: {bg}; color: {fg}; }}\n\
+         pre {{ font-family: monospace; line-height: 1.2; }}\n\
+         a {{ color: inherit; }}\n\
+         .wide {{ display: inline-block; width: 2ch; }}\n\
+         .prompt {{ font-weight: bold; }}\n",
+        bg = palette.background.to_rgb_string(),
+        fg = palette.foreground.to_rgb_string(),
+    )
+    .ok();
+    html.push_str("</style>\n</head>\n<body>\n<pre>");
+}
+
+/// Appends `line` to `html`
+fn line_to_html(line: &Line, palette: &ColorPalette, html: &mut String) {
+    let mut run_key: Option<(String, Option<String>, SemanticType)> = None;
+    let mut run_text = String::new();
+
+    let flush = |key: &Option<(String, Option<String>, SemanticType)>,
+                 text: &mut String,
+                 html: &mut String| {
+        if let Some((style, link, semantic)) = key {
+            if let Some(link) = link {
+                html.push_str("<a href=\"");
+                escape_html(link, html);
+                html.push_str("\">");
+            }
+            write!(html, "<span class=\"{}\"", semantic_class(*semantic)).ok();
+            if !style.is_empty() {
+                write!(html, " style=\"{}\"", style).ok();
+            }
+            html.push('>');
+            html.push_str(text);
+            html.push_str("</span>");
+            if link.is_some() {
+                html.push_str("</a>");
+            }
+        }
+        text.clear();
+    };
+
+    for cell in line.visible_cells() {
+        let attrs = cell.attrs();
+        let key = (
+            cell_style(attrs, palette),
+            attrs
+                .hyperlink()
+                .and_then(|link| exportable_link(link.uri())),
+            attrs.semantic_type(),
+        );
+        if run_key.as_ref() != Some(&key) {
+            flush(&run_key, &mut run_text, html);
+            run_key = Some(key);
+        }
+        if cell.width() > 1 {
+            run_text.push_str("<span class=\"wide\">");
+            escape_html(cell.str(), &mut run_text);
+            run_text.push_str("</span>");
+        } else {
+            escape_html(cell.str(), &mut run_text);
+        }
+    }
+    flush(&run_key, &mut run_text, html);
+    html.push('\n');
+}
+
+/// The location and shape of the cursor in a `PaneSnapshot`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotCursor {
+    pub x: usize,
+    /// The row, relative to the first of the snapshot lines
+    pub y: usize,
+    pub shape: CursorShape,
+}
+
+/// The visible content of a pane, along with everything needed to
+/// render it without having access to the pane itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaneSnapshot {
+    pub cols: usize,
+    pub rows: usize,
+    pub lines: Vec<Line>,
+    pub palette: ColorPalette,
+    /// None if the cursor is hidden
+    pub cursor: Option<SnapshotCursor>,
+}
+
+impl PaneSnapshot {
+    /// Capture the viewport of `pane`, as it is when scrolled to the bottom
+    pub fn from_pane(pane: &dyn Pane) -> Self {
+        let dims = pane.get_dimensions();
+        let end = dims.physical_top + dims.viewport_rows as StableRowIndex;
+        let (_first, lines) = pane.get_lines(dims.physical_top..end);
+        Self::from_parts(&dims, &pane.get_cursor_position(), lines, pane.palette())
+    }
+
+    /// Build a snapshot from the viewport `lines` that start at
+    /// `dims.physical_top`
+    pub fn from_parts(
+        dims: &RenderableDimensions,
+        cursor: &StableCursorPosition,
+        lines: Vec<Line>,
+        palette: ColorPalette,
+    ) -> Self {
+        let cursor_row = cursor.y - dims.physical_top;
+        let cursor = if cursor.visibility == CursorVisibility::Visible
+            && cursor_row >= 0
+            && (cursor_row as usize) < dims.viewport_rows
+        {
+            Some(SnapshotCursor {
+                x: cursor.x,
+                y: cursor_row as usize,
+                shape: cursor.shape,
+            })
+        } else {
+            None
+        };
+        Self {
+            cols: dims.cols,
+            rows: dims.viewport_rows,
+            lines,
+            palette,
+            cursor,
+        }
+    }
+}
+
+/// A `PaneSnapshot` positioned within its tab
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionedSnapshot {
+    /// The offset from the left of the tab, in cells
+    pub left: usize,
+    /// The offset from the top of the tab, in cells
+    pub top: usize,
+    pub snapshot: PaneSnapshot,
+}
+
+/// The visible content of all of the panes in a tab
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TabSnapshot {
+    pub cols: usize,
+    pub rows: usize,
+    pub panes: Vec<PositionedSnapshot>,
+}
+
+impl TabSnapshot {
+    /// Capture the panes of `tab`, respecting its zoom state
+    pub fn from_tab(tab: &Tab) -> Self {
+        let size = tab.get_size();
+        let panes = tab
+            .iter_panes()
+            .into_iter()
+            .map(|pos| PositionedSnapshot {
+                left: pos.left,
+                top: pos.top,
+                snapshot: PaneSnapshot::from_pane(&*pos.pane),
+            })
+            .collect();
+        Self {
+            cols: size.cols,
+            rows: size.rows,
+            panes,
+        }
+    }
+
+    /// A tab that consists of just `snapshot`
+    pub fn single(snapshot: PaneSnapshot) -> Self {
+        Self {
+            cols: snapshot.cols,
+            rows: snapshot.rows,
+            panes: vec![PositionedSnapshot {
+                left: 0,
+                top: 0,
+                snapshot,
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use termwiz::cell::Cell;
+    use termwiz::color::ColorAttribute;
+    use termwiz::surface::SEQ_ZERO;
+    use wezterm_term::Hyperlink;
+
+    #[test]
+    fn html_export() {
+        let palette = ColorPalette::default();
+        let mut line: Line = "a<b".into();
+
+        let mut red = CellAttributes::default();
+        red.set_foreground(ColorAttribute::PaletteIndex(1))
+            .set_intensity(Intensity::Bold);
+        line.set_cell(3, Cell::new('r', red), SEQ_ZERO);
+
+        let mut link = CellAttributes::default();
+        link.set_hyperlink(Some(Arc::new(Hyperlink::new("https://example.com/?a&b"))))
+            .set_semantic_type(SemanticType::Prompt);
+        line.set_cell(4, Cell::new('l', link), SEQ_ZERO);
+
+        line.set_cell(
+            5,
+            Cell::new_grapheme("\u{4f60}", CellAttributes::default(), None),
+            SEQ_ZERO,
+        );
+
+        let mut script = CellAttributes::default();
+        script.set_hyperlink(Some(Arc::new(Hyperlink::new(
+            " JavaScript:alert(document.cookie)",
+        ))));
+        line.set_cell(7, Cell::new('j', script.clone()), SEQ_ZERO);
+        script.set_hyperlink(Some(Arc::new(Hyperlink::new("data:text/html,<b>x</b>"))));
+        line.set_cell(8, Cell::new('d', script), SEQ_ZERO);
+
+        let html = lines_to_html(&[line], &palette, "<title>");
+        assert!(html.contains("<title>&lt;title&gt;</title>"));
+        assert!(html.contains("<span class=\"output\">a&lt;b</span>"));
+        assert!(html.contains(&format!(
+            "<span class=\"output\" style=\"color:{};font-weight:bold;\">r</span>",
+            palette.colors.0[1].to_rgb_string()
+        )));
+        assert!(html.contains(
+            "<a href=\"https://example.com/?a&amp;b\"><span class=\"prompt\">l</span></a>"
+        ));
+        assert!(html.contains("<span class=\"wide\">\u{4f60}</span>"));
+        assert!(!html.contains("JavaScript"));
+        assert!(!html.contains("data:"));
+        assert_eq!(html.matches("<a href").count(), 1);
+    }
+
+    #[test]
+    fn snapshot_cursor() {
+        let dims = RenderableDimensions {
+            cols: 80,
+            viewport_rows: 24,
+            physical_top: 100,
+            ..Default::default()
+        };
+        let snapshot = |y, visibility| {
+            let cursor = StableCursorPosition {
+                x: 3,
+                y,
+                shape: CursorShape::SteadyBar,
+                visibility,
+            };
+            PaneSnapshot::from_parts(&dims, &cursor, vec![], ColorPalette::default()).cursor
+        };
+
+        assert_eq!(
+            snapshot(102, CursorVisibility::Visible),
+            Some(SnapshotCursor {
+                x: 3,
+                y: 2,
+                shape: CursorShape::SteadyBar
+            })
+        );
+        assert_eq!(snapshot(102, CursorVisibility::Hidden), None);
+        assert_eq!(snapshot(99, CursorVisibility::Visible), None);
+        assert_eq!(snapshot(124, CursorVisibility::Visible), None);
+    }
+}
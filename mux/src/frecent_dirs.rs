@@ -0,0 +1,277 @@
+//! Tracks how frequently and how recently directories are visited, as
+//! reported by the panes through OSC 7, so that the directory jumper
+//! and `wezterm.frecent_dirs()` can offer the most useful ones first.
+use crate::pane::PaneId;
+use chrono::{DateTime, Utc};
+use frecency::Frecency;
+use parking_lot::Mutex;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// The number of directories to remember; the lowest scoring
+/// directories are forgotten when there are more than this
+const MAX_DIRS: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FrecentDir {
+    /// The host reported alongside the directory, if any
+    #[serde(default)]
+    pub host: Option<String>,
+    pub path: String,
+    pub frecency: Frecency,
+}
+
+lazy_static::lazy_static! {
+    /// The most recently recorded directory for each pane.
+    /// Shells typically report their directory at every prompt,
+    /// and we only want to count the first of those reports.
+    static ref LAST_DIR_BY_PANE: Mutex<HashMap<PaneId, (Option<String>, String)>> =
+        Mutex::new(HashMap::new());
+    /// Held while the file is updated, so that concurrent visits
+    /// don't overwrite each other
+    static ref UPDATE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+fn file_name() -> PathBuf {
+    config::DATA_DIR.join("frecent-dirs.json")
+}
+
+/// Loads the directories from `file`; there are none if it
+/// doesn't exist yet
+fn load(file: &Path) -> anyhow::Result<Vec<FrecentDir>> {
+    let f = match std::fs::File::open(file) {
+        Ok(f) => f,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(serde_json::from_reader(f)?)
+}
+
+/// Saves the directories to `file`.  The data is written to a temporary
+/// file that is then renamed over `file`, so that other wezterm processes
+/// never see a partially written file.
+fn save(file: &Path, dirs: &[FrecentDir]) -> anyhow::Result<()> {
+    let json = serde_json::to_string(dirs)?;
+    let mut temp = file.as_os_str().to_owned();
+    temp.push(format!(".{}.tmp", std::process::id()));
+    let temp = PathBuf::from(temp);
+    if let Err(err) = std::fs::write(&temp, json).and_then(|_| std::fs::rename(&temp, file)) {
+        std::fs::remove_file(&temp).ok();
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Extracts the host from the url reported by OSC 7
+fn dir_host(url: &Url) -> Option<String> {
+    url.host_str()
+        .filter(|host| !host.is_empty())
+        .map(|host| host.to_string())
+}
+
+/// Extracts the directory from the url reported by OSC 7
+fn dir_path(url: &Url) -> Option<String> {
+    if url.scheme() != "file" {
+        return None;
+    }
+    let path = percent_decode_str(url.path()).decode_utf8().ok()?;
+    // Windows paths are reported as eg: `/C:/Users`
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+        _ => &path[..],
+    };
+    let trimmed = path.trim_end_matches(|c| c == '/' || c == '\\');
+    if trimmed.is_empty() {
+        if path.is_empty() {
+            None
+        } else {
+            Some(path[..1].to_string())
+        }
+    } else {
+        Some(trimmed.to_string())
+    }
+}
+
+fn register_visit(dirs: &mut Vec<FrecentDir>, host: Option<&str>, path: &str, now: DateTime<Utc>) {
+    match dirs
+        .iter_mut()
+        .find(|dir| dir.host.as_deref() == host && dir.path == path)
+    {
+        Some(dir) => dir.frecency.register_access_at_time(now),
+        None => {
+            let mut frecency = Frecency::new_at_time(now);
+            frecency.register_access_at_time(now);
+            dirs.push(FrecentDir {
+                host: host.map(|host| host.to_string()),
+                path: path.to_string(),
+                frecency,
+            });
+        }
+    }
+
+    if dirs.len() > MAX_DIRS {
+        sort_by_score(dirs, now);
+        dirs.truncate(MAX_DIRS);
+    }
+}
+
+fn sort_by_score(dirs: &mut [FrecentDir], now: DateTime<Utc>) {
+    dirs.sort_by(|a, b| {
+        b.frecency
+            .score_at_time(now)
+            .partial_cmp(&a.frecency.score_at_time(now))
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// Records a visit to `path` on `host` in `file`.
+/// The file is loaded each time, so that we don't lose the visits that
+/// were recorded by other wezterm processes.  If it can't be loaded,
+/// it is left alone rather than replaced with just this visit.
+fn record_visit_in(
+    file: &Path,
+    host: Option<&str>,
+    path: &str,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let mut dirs = load(file)?;
+    register_visit(&mut dirs, host, path, now);
+    save(file, &dirs)
+}
+
+/// Records that `pane_id` has changed its directory to `url`
+pub fn record_visit(pane_id: PaneId, url: &Url) {
+    let path = match dir_path(url) {
+        Some(path) => path,
+        None => return,
+    };
+    let host = dir_host(url);
+
+    {
+        let mut last_dir = LAST_DIR_BY_PANE.lock();
+        let dir = (host.clone(), path.clone());
+        if last_dir.get(&pane_id) == Some(&dir) {
+            return;
+        }
+        last_dir.insert(pane_id, dir);
+    }
+
+    // This is called on the main thread, which shouldn't wait for a
+    // potentially slow filesystem, so update the file in the background
+    let now = Utc::now();
+    smol::unblock(move || {
+        let _lock = UPDATE_LOCK.lock();
+        if let Err(err) = record_visit_in(&file_name(), host.as_deref(), &path, now) {
+            log::error!("Error while recording frecent dir {path}: {err:#}");
+        }
+    })
+    .detach();
+}
+
+/// Forgets the directory that was last recorded for `pane_id`
+pub fn forget_pane(pane_id: PaneId) {
+    LAST_DIR_BY_PANE.lock().remove(&pane_id);
+}
+
+/// Returns the recorded directories, highest scoring first
+pub fn frecent_dirs() -> Vec<FrecentDir> {
+    let mut dirs = match load(&file_name()) {
+        Ok(dirs) => dirs,
+        Err(err) => {
+            log::error!("Error while loading frecent dirs: {err:#}");
+            vec![]
+        }
+    };
+    sort_by_score(&mut dirs, Utc::now());
+    dirs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn paths() {
+        let path = |s: &str| dir_path(&Url::parse(s).unwrap());
+        assert_eq!(
+            path("file://host/home/me/some%20dir/"),
+            Some("/home/me/some dir".to_string())
+        );
+        assert_eq!(path("file:///"), Some("/".to_string()));
+        assert_eq!(path("file:///C:/Users/me"), Some("C:/Users/me".to_string()));
+        assert_eq!(path("https://example.com/"), None);
+
+        let host = |s: &str| dir_host(&Url::parse(s).unwrap());
+        assert_eq!(host("file://host/home/me"), Some("host".to_string()));
+        assert_eq!(host("file:///home/me"), None);
+    }
+
+    #[test]
+    fn scores() {
+        let start = Utc::now();
+        let mut dirs = vec![];
+        register_visit(&mut dirs, None, "/a", start);
+        register_visit(&mut dirs, None, "/b", start);
+        register_visit(&mut dirs, None, "/b", start);
+        register_visit(&mut dirs, None, "/c", start + Duration::days(3));
+        assert_eq!(dirs.len(), 3);
+
+        sort_by_score(&mut dirs, start + Duration::days(3));
+        let order: Vec<&str> = dirs.iter().map(|d| d.path.as_str()).collect();
+        // Two visits that have decayed to half score the same as one new visit;
+        // the sort is stable, so /b stays ahead of /c
+        assert_eq!(order, vec!["/b", "/c", "/a"]);
+
+        for i in 0..MAX_DIRS {
+            register_visit(
+                &mut dirs,
+                None,
+                &format!("/many/{i}"),
+                start + Duration::days(6),
+            );
+        }
+        assert_eq!(dirs.len(), MAX_DIRS);
+        assert!(!dirs.iter().any(|d| d.path == "/a"));
+    }
+
+    #[test]
+    fn hosts() {
+        let now = Utc::now();
+        let mut dirs = vec![];
+        register_visit(&mut dirs, Some("a"), "/src", now);
+        register_visit(&mut dirs, Some("b"), "/src", now);
+        register_visit(&mut dirs, Some("a"), "/src", now);
+        assert_eq!(dirs.len(), 2);
+        assert_eq!(dirs[0].host.as_deref(), Some("a"));
+        assert_eq!(dirs[0].frecency.num_accesses(), 2);
+    }
+
+    #[test]
+    fn persistence() {
+        let dir = std::env::temp_dir().join(format!("wezterm-frecent-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("frecent-dirs.json");
+        let now = Utc::now();
+
+        record_visit_in(&file, Some("host"), "/a", now).unwrap();
+        record_visit_in(&file, None, "/b", now).unwrap();
+        let dirs = load(&file).unwrap();
+        assert_eq!(dirs.len(), 2);
+        assert_eq!(dirs[0].host.as_deref(), Some("host"));
+        // Only the file itself is left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // A file that can't be loaded is not replaced
+        std::fs::write(&file, "not json").unwrap();
+        assert!(record_visit_in(&file, None, "/c", now).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "not json");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
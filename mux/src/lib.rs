@@ -11,7 +11,7 @@ use filedescriptor::{poll, pollfd, socketpair, AsRawSocketDescriptor, FileDescri
 #[cfg(unix)]
 use libc::{c_int, SOL_SOCKET, SO_RCVBUF, SO_SNDBUF};
 use log::error;
-use metrics::histogram;
+use metrics::{counter, gauge, histogram};
 use parking_lot::{
     MappedRwLockReadGuard, MappedRwLockWriteGuard, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
 };
@@ -27,7 +27,7 @@ use std::sync::{Arc, Weak};
 use std::thread;
 use std::time::{Duration, Instant};
 use termwiz::escape::csi::{DecPrivateMode, DecPrivateModeCode, Device, Mode};
-use termwiz::escape::{Action, CSI};
+use termwiz::escape::{Action, Esc, EscCode, CSI};
 use thiserror::*;
 use wezterm_term::{Clipboard, ClipboardSelection, DownloadHandler, TerminalSize};
 #[cfg(windows)]
@@ -37,6 +37,8 @@ pub mod activity;
 pub mod client;
 pub mod connui;
 pub mod domain;
+pub mod export;
+pub mod frecent_dirs;
 pub mod localpane;
 pub mod pane;
 pub mod renderable;
@@ -73,6 +75,10 @@ pub enum MuxNotification {
         selection: ClipboardSelection,
         clipboard: Option<String>,
     },
+    QueryClipboard {
+        pane_id: PaneId,
+        selection: ClipboardSelection,
+    },
     SaveToDownloads {
         name: Option<String>,
         data: Arc<Vec<u8>>,
@@ -137,16 +143,60 @@ fn send_actions_to_mux(pane: &Weak<dyn Pane>, dead: &Arc<AtomicBool>, actions: V
     histogram!("send_actions_to_mux.rate").record(1.);
 }
 
+/// Ends the synchronized update that began at `started`, if any
+fn end_synchronized_update(hold: &mut Option<Instant>) {
+    if let Some(started) = hold.take() {
+        histogram!("synchronized_output.hold.latency").record(started.elapsed());
+        counter!("synchronized_output.frames").increment(1);
+        gauge!("synchronized_output.active").decrement(1.);
+    }
+}
+
 fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: FileDescriptor) {
     let mut buf = vec![0; configuration().mux_output_parser_buffer_size];
     let mut parser = termwiz::escape::parser::Parser::new();
     let mut actions = vec![];
-    let mut hold = false;
+    // When the application has begun a synchronized update, this
+    // holds the time at which it began
+    let mut hold: Option<Instant> = None;
     let mut action_size = 0;
     let mut delay = Duration::from_millis(configuration().mux_output_parser_coalesce_delay_ms);
+    let mut sync_timeout = Duration::from_millis(configuration().synchronized_output_timeout_ms);
     let mut deadline = None;
 
     loop {
+        if let Some(started) = hold {
+            // Don't let an application that never ends its
+            // synchronized update freeze the pane
+            let remaining = (started + sync_timeout).checked_duration_since(Instant::now());
+            let readable = match remaining {
+                Some(remaining) => {
+                    let mut pfd = [pollfd {
+                        fd: rx.as_socket_descriptor(),
+                        events: POLLIN,
+                        revents: 0,
+                    }];
+                    matches!(poll(&mut pfd, Some(remaining)), Ok(1))
+                }
+                None => false,
+            };
+            if !readable {
+                log::trace!(
+                    "synchronized update timed out after {:?}",
+                    started.elapsed()
+                );
+                counter!("synchronized_output.timeout").increment(1);
+                gauge!("synchronized_output.active").decrement(1.);
+                hold = None;
+                if !actions.is_empty() {
+                    send_actions_to_mux(&pane, &dead, std::mem::take(&mut actions));
+                    action_size = 0;
+                }
+                deadline = None;
+                continue;
+            }
+        }
+
         match rx.read(&mut buf) {
             Ok(size) if size == 0 => {
                 dead.store(true, Ordering::Relaxed);
@@ -163,7 +213,10 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
                         Action::CSI(CSI::Mode(Mode::SetDecPrivateMode(DecPrivateMode::Code(
                             DecPrivateModeCode::SynchronizedOutput,
                         )))) => {
-                            hold = true;
+                            if hold.is_none() {
+                                hold.replace(Instant::now());
+                                gauge!("synchronized_output.active").increment(1.);
+                            }
 
                             // Flush prior actions
                             if !actions.is_empty() {
@@ -174,11 +227,15 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
                         Action::CSI(CSI::Mode(Mode::ResetDecPrivateMode(
                             DecPrivateMode::Code(DecPrivateModeCode::SynchronizedOutput),
                         ))) => {
-                            hold = false;
+                            end_synchronized_update(&mut hold);
                             flush = true;
                         }
                         Action::CSI(CSI::Device(dev)) if matches!(**dev, Device::SoftReset) => {
-                            hold = false;
+                            end_synchronized_update(&mut hold);
+                            flush = true;
+                        }
+                        Action::Esc(Esc::Code(EscCode::FullReset)) => {
+                            end_synchronized_update(&mut hold);
                             flush = true;
                         }
                         _ => {}
@@ -191,7 +248,7 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
                     }
                 });
                 action_size += size;
-                if !actions.is_empty() && !hold {
+                if !actions.is_empty() && hold.is_none() {
                     // If we haven't accumulated too much data,
                     // pause for a short while to increase the chances
                     // that we coalesce a full "frame" from an unoptimized
@@ -229,10 +286,15 @@ fn parse_buffered_data(pane: Weak<dyn Pane>, dead: &Arc<AtomicBool>, mut rx: Fil
                 let config = configuration();
                 buf.resize(config.mux_output_parser_buffer_size, 0);
                 delay = Duration::from_millis(config.mux_output_parser_coalesce_delay_ms);
+                sync_timeout = Duration::from_millis(config.synchronized_output_timeout_ms);
             }
         }
     }
 
+    if hold.is_some() {
+        gauge!("synchronized_output.active").decrement(1.);
+    }
+
     // Don't forget to send anything that we might have buffered
     // to be displayed before we return from here; this is important
     // for very short lived commands so that we don't forget to
@@ -815,6 +877,7 @@ impl Mux {
         if let Some(pane) = self.panes.write().remove(&pane_id).clone() {
             log::debug!("killing pane {}", pane_id);
             pane.kill();
+            frecent_dirs::forget_pane(pane_id);
             self.notify(MuxNotification::PaneRemoved(pane_id));
             changed = true;
         }
@@ -1450,6 +1513,16 @@ impl Clipboard for MuxClipboard {
         });
         Ok(())
     }
+
+    fn query_contents(&self, selection: ClipboardSelection) -> anyhow::Result<()> {
+        let mux = Mux::try_get()
+            .ok_or_else(|| anyhow::anyhow!("MuxClipboard::query_contents: no Mux?"))?;
+        mux.notify(MuxNotification::QueryClipboard {
+            pane_id: self.pane_id,
+            selection,
+        });
+        Ok(())
+    }
 }
 
 struct MuxDownloader {}
@@ -1,7 +1,7 @@
 use crate::domain::DomainId;
 use crate::pane::{
-    CachePolicy, CloseReason, ForEachPaneLogicalLine, LogicalLine, Pane, PaneId, Pattern,
-    SearchResult, WithPaneLines,
+    fuzzy_match_spans, CachePolicy, CloseReason, ForEachPaneLogicalLine, LogicalLine, Pane, PaneId,
+    Pattern, SearchResult, WithPaneLines,
 };
 use crate::renderable::*;
 use crate::tmux::{TmuxDomain, TmuxDomainState};
@@ -31,8 +31,9 @@ use url::Url;
 use wezterm_dynamic::Value;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{
-    Alert, AlertHandler, Clipboard, DownloadHandler, KeyCode, KeyModifiers, MouseEvent, Progress,
-    SemanticZone, StableRowIndex, Terminal, TerminalConfiguration, TerminalSize,
+    Alert, AlertHandler, Clipboard, ClipboardSelection, CommandBlock, DownloadHandler, KeyCode,
+    KeyModifiers, MouseEvent, Progress, SemanticZone, StableRowIndex, Terminal,
+    TerminalConfiguration, TerminalSize,
 };
 
 const PROC_INFO_CACHE_TTL: Duration = Duration::from_millis(300);
@@ -183,6 +184,10 @@ impl Pane for LocalPane {
         self.terminal.lock().current_seqno()
     }
 
+    fn get_synchronized_update_seqno(&self) -> SequenceNo {
+        self.terminal.lock().synchronized_update_seqno()
+    }
+
     fn get_changed_since(
         &self,
         lines: Range<StableRowIndex>,
@@ -375,6 +380,10 @@ impl Pane for LocalPane {
         self.terminal.lock().set_clipboard(clipboard);
     }
 
+    fn answer_clipboard_query(&self, selection: ClipboardSelection, text: Option<String>) {
+        self.terminal.lock().answer_clipboard_query(selection, text);
+    }
+
     fn set_download_handler(&self, handler: &Arc<dyn DownloadHandler>) {
         self.terminal.lock().set_download_handler(handler);
     }
@@ -643,19 +652,50 @@ impl Pane for LocalPane {
         term.get_semantic_zones()
     }
 
+    fn get_command_blocks(&self) -> anyhow::Result<Vec<CommandBlock>> {
+        let mut term = self.terminal.lock();
+        Ok(term.get_command_blocks())
+    }
+
+    fn get_command_blocks_in_range(
+        &self,
+        range: Range<StableRowIndex>,
+    ) -> anyhow::Result<Vec<CommandBlock>> {
+        let mut term = self.terminal.lock();
+        Ok(term.get_command_blocks_in_range(range))
+    }
+
+    fn get_command_output(&self, id: usize) -> anyhow::Result<Option<String>> {
+        let term = self.terminal.lock();
+        Ok(term.get_command_output(id))
+    }
+
+    fn get_collapsed_output_ranges(&self) -> anyhow::Result<Vec<Range<StableRowIndex>>> {
+        let term = self.terminal.lock();
+        Ok(term.get_collapsed_output_ranges())
+    }
+
+    fn set_command_block_collapsed(&self, id: usize, collapsed: bool) -> anyhow::Result<()> {
+        let mut term = self.terminal.lock();
+        term.set_command_block_collapsed(id, collapsed)
+    }
+
     async fn search(
         &self,
         pattern: Pattern,
         range: Range<StableRowIndex>,
         limit: Option<u32>,
     ) -> anyhow::Result<Vec<SearchResult>> {
-        let term = self.terminal.lock();
-        let screen = term.screen();
+        // The lines that have been paged out to disk are decoded without
+        // holding the terminal lock, so that the pane can continue to
+        // process output and render while we search the history
+        let (spilled, range) = self.terminal.lock().screen().split_spilled_range(range);
 
         enum CompiledPattern {
             CaseSensitiveString(String),
             CaseInSensitiveString(String),
             Regex(Regex),
+            Fuzzy(String),
         }
 
         let pattern = match pattern {
@@ -665,12 +705,13 @@ impl Pane for LocalPane {
                 CompiledPattern::CaseInSensitiveString(s.to_lowercase())
             }
             Pattern::Regex(r) => CompiledPattern::Regex(Regex::new(&r)?),
+            Pattern::Fuzzy(s) => CompiledPattern::Fuzzy(s),
         };
 
         let mut results = vec![];
         let mut uniq_matches: HashMap<String, usize> = HashMap::new();
 
-        screen.for_each_logical_line_in_stable_range(range, |sr, lines| {
+        let mut search_line = |sr: Range<StableRowIndex>, lines: &[&Line]| -> bool {
             if let Some(limit) = limit {
                 if results.len() == limit as usize {
                     // We've reach the limit, stop iteration.
@@ -718,6 +759,19 @@ impl Pane for LocalPane {
                         );
                     }
                 }
+                CompiledPattern::Fuzzy(s) => {
+                    for span in fuzzy_match_spans(&haystack, s) {
+                        found_match(
+                            &haystack[span.clone()],
+                            span.start,
+                            lines,
+                            stable_idx,
+                            &mut uniq_matches,
+                            &mut coords,
+                            &mut results,
+                        );
+                    }
+                }
                 CompiledPattern::Regex(re) => {
                     // Allow for the regex to contain captures
                     for capture_res in re.captures_iter(&haystack) {
@@ -746,7 +800,23 @@ impl Pane for LocalPane {
 
             // Keep iterating
             true
-        });
+        };
+
+        // A logical line that wraps across the end of the spilled lines
+        // is returned in `tail`, and searched along with the rest of it
+        let mut tail = vec![];
+        let keep_going = match spilled {
+            Some((spill, spilled_range)) => {
+                spill.for_each_logical_line(spilled_range, &mut tail, &mut search_line)
+            }
+            None => true,
+        };
+        if keep_going && (!range.is_empty() || !tail.is_empty()) {
+            self.terminal
+                .lock()
+                .screen()
+                .for_each_logical_line_in_memory(range, tail, &mut search_line);
+        }
 
         #[derive(Copy, Clone, Debug)]
         struct Coord {
@@ -942,6 +1012,14 @@ impl AlertHandler for LocalPaneNotifHandler {
                         }
                     }
                 }
+                Alert::CurrentWorkingDirectoryChanged => {
+                    if let Some(url) = mux
+                        .get_pane(pane_id)
+                        .and_then(|pane| pane.get_current_working_dir(CachePolicy::AllowStale))
+                    {
+                        crate::frecent_dirs::record_visit(pane_id, &url);
+                    }
+                }
                 _ => {}
             }
 
@@ -12,13 +12,13 @@ use std::ops::Range;
 use std::sync::Arc;
 use termwiz::hyperlink::Rule;
 use termwiz::input::KeyboardEncoding;
-use termwiz::surface::{Line, SequenceNo};
+use termwiz::surface::{Line, SequenceNo, SEQ_ZERO};
 use url::Url;
 use wezterm_dynamic::Value;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::{
-    Clipboard, DownloadHandler, KeyCode, KeyModifiers, MouseEvent, Progress, SemanticZone,
-    StableRowIndex, TerminalConfiguration, TerminalSize,
+    Clipboard, ClipboardSelection, CommandBlock, DownloadHandler, KeyCode, KeyModifiers,
+    MouseEvent, Progress, SemanticZone, StableRowIndex, TerminalConfiguration, TerminalSize,
 };
 
 static PANE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
@@ -58,6 +58,11 @@ pub enum Pattern {
     CaseSensitiveString(String),
     CaseInSensitiveString(String),
     Regex(String),
+    /// Matches the characters of the pattern, in order, with any
+    /// number of other characters between them.  The match is
+    /// case-insensitive unless the pattern contains an uppercase
+    /// character.
+    Fuzzy(String),
 }
 
 impl Default for Pattern {
@@ -73,6 +78,7 @@ impl std::ops::Deref for Pattern {
             Pattern::CaseSensitiveString(s) => s,
             Pattern::CaseInSensitiveString(s) => s,
             Pattern::Regex(s) => s,
+            Pattern::Fuzzy(s) => s,
         }
     }
 }
@@ -83,6 +89,7 @@ impl std::ops::DerefMut for Pattern {
             Pattern::CaseSensitiveString(s) => s,
             Pattern::CaseInSensitiveString(s) => s,
             Pattern::Regex(s) => s,
+            Pattern::Fuzzy(s) => s,
         }
     }
 }
@@ -92,6 +99,7 @@ pub enum PatternType {
     CaseSensitiveString,
     CaseInSensitiveString,
     Regex,
+    Fuzzy,
 }
 
 impl From<&Pattern> for PatternType {
@@ -100,10 +108,79 @@ impl From<&Pattern> for PatternType {
             Pattern::CaseSensitiveString(_) => PatternType::CaseSensitiveString,
             Pattern::CaseInSensitiveString(_) => PatternType::CaseInSensitiveString,
             Pattern::Regex(_) => PatternType::Regex,
+            Pattern::Fuzzy(_) => PatternType::Fuzzy,
         }
     }
 }
 
+/// Returns the byte ranges of the fuzzy matches of `needle` in
+/// `haystack`.  Each match is the shortest span that contains the
+/// characters of `needle` in order and that ends at the earliest
+/// possible position; matches do not overlap.  The comparison is
+/// case-insensitive unless `needle` contains an uppercase character.
+pub fn fuzzy_match_spans(haystack: &str, needle: &str) -> Vec<Range<usize>> {
+    let case_sensitive = needle.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let needle: Vec<char> = needle.chars().map(fold).collect();
+    let chars: Vec<(usize, char)> = haystack
+        .char_indices()
+        .map(|(idx, c)| (idx, fold(c)))
+        .collect();
+
+    let mut spans = vec![];
+    if needle.is_empty() {
+        return spans;
+    }
+
+    let mut pos = 0;
+    while pos < chars.len() {
+        // Find the earliest position at which a match can end
+        let mut matched = 0;
+        let mut end = None;
+        for (idx, (_, c)) in chars.iter().enumerate().skip(pos) {
+            if *c == needle[matched] {
+                matched += 1;
+                if matched == needle.len() {
+                    end = Some(idx);
+                    break;
+                }
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => break,
+        };
+
+        // Then walk backwards to find the latest start for that end
+        let mut remaining = needle.len();
+        let mut start = end;
+        for idx in (pos..=end).rev() {
+            if chars[idx].1 == needle[remaining - 1] {
+                remaining -= 1;
+                if remaining == 0 {
+                    start = idx;
+                    break;
+                }
+            }
+        }
+
+        let end_byte = chars
+            .get(end + 1)
+            .map(|(idx, _)| *idx)
+            .unwrap_or(haystack.len());
+        spans.push(chars[start].0..end_byte);
+        pos = end + 1;
+    }
+
+    spans
+}
+
 /// Why a close request is being made
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum CloseReason {
@@ -173,6 +250,14 @@ pub trait Pane: Downcast + Send + Sync {
 
     fn get_current_seqno(&self) -> SequenceNo;
 
+    /// Returns the seqno at which the application most recently
+    /// completed a synchronized update (DECSET 2026).  Changes
+    /// made after a seqno that is older than this should be
+    /// presented together in a single frame.
+    fn get_synchronized_update_seqno(&self) -> SequenceNo {
+        SEQ_ZERO
+    }
+
     /// Returns misc metadata that is pane-specific
     fn get_metadata(&self) -> Value {
         Value::Null
@@ -304,6 +389,48 @@ pub trait Pane: Downcast + Send + Sync {
         Ok(vec![])
     }
 
+    /// Retrieve the commands that have been run in this pane,
+    /// as reported by the shell via OSC 133, oldest first
+    fn get_command_blocks(&self) -> anyhow::Result<Vec<CommandBlock>> {
+        Ok(vec![])
+    }
+
+    /// Retrieve the commands that have rows within `range`
+    fn get_command_blocks_in_range(
+        &self,
+        range: Range<StableRowIndex>,
+    ) -> anyhow::Result<Vec<CommandBlock>> {
+        let mut blocks = self.get_command_blocks()?;
+        blocks.retain(|block| {
+            block.prompt_start < range.end
+                && block
+                    .output_end
+                    .map_or(block.is_running(), |end| end > range.start)
+        });
+        Ok(blocks)
+    }
+
+    /// Retrieve the output text of the command with the specified id
+    fn get_command_output(&self, _id: usize) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Retrieve the rows of command output that have been collapsed,
+    /// and that should be folded into a placeholder row when rendered
+    fn get_collapsed_output_ranges(&self) -> anyhow::Result<Vec<Range<StableRowIndex>>> {
+        Ok(self
+            .get_command_blocks()?
+            .into_iter()
+            .filter(|block| block.collapsed)
+            .filter_map(|block| block.output_rows())
+            .collect())
+    }
+
+    /// Collapse or expand the output of the command with the specified id
+    fn set_command_block_collapsed(&self, _id: usize, _collapsed: bool) -> anyhow::Result<()> {
+        anyhow::bail!("collapsing command output is not supported by this pane");
+    }
+
     /// Returns true if the terminal has grabbed the mouse and wants to
     /// give the embedded application a chance to process events.
     /// In practice this controls whether the gui will perform local
@@ -312,6 +439,9 @@ pub trait Pane: Downcast + Send + Sync {
     fn is_alt_screen_active(&self) -> bool;
 
     fn set_clipboard(&self, _clipboard: &Arc<dyn Clipboard>) {}
+    /// Deliver the outcome of an OSC 52 clipboard query that was
+    /// reported via MuxNotification::QueryClipboard
+    fn answer_clipboard_query(&self, _selection: ClipboardSelection, _text: Option<String>) {}
     fn set_download_handler(&self, _handler: &Arc<dyn DownloadHandler>) {}
     fn set_config(&self, _config: Arc<dyn TerminalConfiguration>) {}
     fn get_config(&self) -> Option<Arc<dyn TerminalConfiguration>> {
@@ -339,6 +469,52 @@ pub trait Pane: Downcast + Send + Sync {
 }
 impl_downcast!(Pane);
 
+/// The results of searching one chunk of rows; produced by `search_stream`
+#[derive(Debug)]
+pub struct SearchChunk {
+    /// The rows that were searched
+    pub range: Range<StableRowIndex>,
+    pub results: Vec<SearchResult>,
+}
+
+/// Searches the scrollback of `pane` for `pattern`, `chunk_rows` rows
+/// at a time, starting at the bottom and working up towards the top.
+/// The results for each chunk are sent to the returned channel as
+/// soon as they are available, so that a large scrollback can be
+/// processed incrementally.  The channel is closed once the top of
+/// the scrollback has been searched, or after an error has been sent.
+/// Dropping the receiver cancels the search.
+/// Must be called on the main thread.
+pub fn search_stream(
+    pane: Arc<dyn Pane>,
+    pattern: Pattern,
+    chunk_rows: StableRowIndex,
+) -> smol::channel::Receiver<anyhow::Result<SearchChunk>> {
+    let (tx, rx) = smol::channel::unbounded();
+    promise::spawn::spawn(async move {
+        let dims = pane.get_dimensions();
+        let top = dims.scrollback_top;
+        let mut end = top + dims.scrollback_rows as StableRowIndex;
+        while end > top && !tx.is_closed() {
+            let start = end.saturating_sub(chunk_rows.max(1)).max(top);
+            let range = start..end;
+            let chunk = pane
+                .search(pattern.clone(), range.clone(), None)
+                .await
+                .map(|results| SearchChunk { range, results });
+            let failed = chunk.is_err();
+            if tx.send(chunk).await.is_err() || failed {
+                break;
+            }
+            end = start;
+            // Let other work run between chunks
+            smol::future::yield_now().await;
+        }
+    })
+    .detach();
+    rx
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CachePolicy {
     FetchImmediate,
@@ -546,7 +722,6 @@ mod test {
     use k9::snapshot;
     use parking_lot::{MappedMutexGuard, Mutex};
     use std::borrow::Cow;
-    use termwiz::surface::SEQ_ZERO;
 
     struct FakePane {
         lines: Mutex<Vec<Line>>,
@@ -1053,6 +1228,31 @@ mod test {
         }
     }
 
+    #[test]
+    fn fuzzy_spans() {
+        fn spans<'a>(haystack: &'a str, needle: &str) -> Vec<&'a str> {
+            fuzzy_match_spans(haystack, needle)
+                .into_iter()
+                .map(|r| &haystack[r])
+                .collect()
+        }
+
+        assert_eq!(
+            spans("cargo build --release", "cbr"),
+            vec!["cargo build --r"]
+        );
+        // The shortest span is preferred
+        assert_eq!(spans("abc abc", "ac"), vec!["abc", "abc"]);
+        assert_eq!(spans("a a b", "ab"), vec!["a b"]);
+        // Smart case
+        assert_eq!(spans("Makefile makefile", "mk"), vec!["Mak", "mak"]);
+        assert_eq!(spans("Makefile makefile", "Mk"), vec!["Mak"]);
+        // Byte ranges respect multi-byte characters
+        assert_eq!(spans("héllo wörld", "éw"), vec!["éllo w"]);
+        assert!(spans("hello", "xyz").is_empty());
+        assert!(spans("hello", "").is_empty());
+    }
+
     #[test]
     fn double_click() {
         let attr = Default::default();
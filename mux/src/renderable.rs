@@ -91,10 +91,7 @@ where
     F: FnMut(StableRowIndex, &[&Line]),
 {
     let screen = term.screen_mut();
-    let phys_range = screen.stable_range(&lines);
-    let first = screen.phys_to_stable_row_index(phys_range.start);
-
-    screen.with_phys_lines(phys_range, |lines| func(first, lines));
+    screen.with_stable_lines(lines, |first, lines| func(first, lines));
 }
 
 /// Implements Pane::with_lines_mut for Terminal
@@ -103,11 +100,26 @@ pub fn terminal_with_lines_mut(
     lines: Range<StableRowIndex>,
     with_lines: &mut dyn WithPaneLines,
 ) {
+    // Kitty image placeholders are resolved into the images that they
+    // display as they are rendered, leaving the screen model untouched
+    let resolver = term.kitty_placeholder_resolver();
     let screen = term.screen_mut();
-    let phys_range = screen.stable_range(&lines);
-    let first = screen.phys_to_stable_row_index(phys_range.start);
-
-    screen.with_phys_lines_mut(phys_range, |lines| with_lines.with_lines_mut(first, lines));
+    screen.with_stable_lines_mut(lines, |first, lines| match &resolver {
+        Some(resolver) => {
+            let mut resolved: Vec<Option<Line>> =
+                lines.iter().map(|line| resolver.resolve(line)).collect();
+            let mut lines: Vec<&mut Line> = lines
+                .iter_mut()
+                .zip(resolved.iter_mut())
+                .map(|(line, resolved)| match resolved {
+                    Some(resolved) => resolved,
+                    None => &mut **line,
+                })
+                .collect();
+            with_lines.with_lines_mut(first, &mut lines)
+        }
+        None => with_lines.with_lines_mut(first, lines),
+    });
 }
 
 /// Implements Pane::get_lines for Terminal
@@ -116,12 +128,7 @@ pub fn terminal_get_lines(
     lines: Range<StableRowIndex>,
 ) -> (StableRowIndex, Vec<Line>) {
     let screen = term.screen_mut();
-    let phys_range = screen.stable_range(&lines);
-
-    let first = screen.phys_to_stable_row_index(phys_range.start);
-    let lines = screen.lines_in_phys_range(phys_range);
-
-    (first, lines)
+    screen.lines_in_stable_range(lines)
 }
 
 /// Implements Pane::get_dimensions for Terminal
@@ -131,9 +138,9 @@ pub fn terminal_get_dimensions(term: &mut Terminal) -> RenderableDimensions {
     RenderableDimensions {
         cols: screen.physical_cols,
         viewport_rows: screen.physical_rows,
-        scrollback_rows: screen.scrollback_rows(),
+        scrollback_rows: screen.scrollback_rows() + screen.spilled_rows(),
         physical_top: screen.visible_row_to_stable_row(0),
-        scrollback_top: screen.phys_to_stable_row_index(0),
+        scrollback_top: screen.first_stable_row(),
         dpi: screen.dpi,
         pixel_width: size.pixel_width,
         pixel_height: size.pixel_height,
@@ -0,0 +1,229 @@
+//! Tracks the commands that have been run in a screen, as delineated
+//! by the OSC 133 semantic prompt markers emitted by a suitably
+//! configured shell.
+//! <https://gitlab.freedesktop.org/Per_Bothner/specifications/blob/master/proposals/semantic-prompts.md>
+use crate::StableRowIndex;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+/// The maximum number of commands that we remember per screen,
+/// regardless of whether their rows are still present in the scrollback
+const MAX_COMMAND_BLOCKS: usize = 10_000;
+
+/// Describes a command that was run in the terminal: the prompt that
+/// preceded it, the input that the user typed, and the output that it
+/// produced, along with timing and status information.
+#[cfg_attr(feature = "use_serde", derive(Deserialize, Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandBlock {
+    /// Uniquely identifies this block within its screen
+    pub id: usize,
+    /// The row on which the prompt was started (OSC 133;A)
+    pub prompt_start: StableRowIndex,
+    /// The row on which the user input was started (OSC 133;B)
+    pub input_start: Option<StableRowIndex>,
+    /// The row on which the command output was started (OSC 133;C)
+    pub output_start: Option<StableRowIndex>,
+    /// The row immediately after the last row of output (OSC 133;D).
+    /// This is None until the command has finished.
+    pub output_end: Option<StableRowIndex>,
+    /// The text of the command, as echoed into the input zone
+    pub command: String,
+    /// When the command was started
+    pub started: Option<SystemTime>,
+    /// When the command finished
+    pub finished: Option<SystemTime>,
+    /// The exit status reported by the shell, if any
+    pub exit_status: Option<i32>,
+    /// When true, the output is folded away into a single placeholder
+    /// row when it is rendered.  The rows themselves remain in the
+    /// screen, so they can still be selected, searched and exported.
+    pub collapsed: bool,
+}
+
+impl CommandBlock {
+    fn new(id: usize, prompt_start: StableRowIndex) -> Self {
+        Self {
+            id,
+            prompt_start,
+            input_start: None,
+            output_start: None,
+            output_end: None,
+            command: String::new(),
+            started: None,
+            finished: None,
+            exit_status: None,
+            collapsed: false,
+        }
+    }
+
+    /// Returns true if the command was submitted to the shell
+    pub fn has_run(&self) -> bool {
+        self.output_start.is_some()
+    }
+
+    /// Returns true if the command is still running
+    pub fn is_running(&self) -> bool {
+        self.started.is_some() && self.finished.is_none()
+    }
+
+    /// Returns how long the command ran for, or how long it has
+    /// been running if it has not yet finished
+    pub fn duration(&self) -> Option<Duration> {
+        let started = self.started?;
+        let finished = self.finished.unwrap_or_else(SystemTime::now);
+        finished.duration_since(started).ok()
+    }
+
+    /// Returns the range of rows holding the output of the command,
+    /// if it has finished
+    pub fn output_rows(&self) -> Option<Range<StableRowIndex>> {
+        let start = self.output_start?;
+        let end = self.output_end?;
+        Some(start..end.max(start))
+    }
+
+    /// Returns the last row that belongs to this block, or None
+    /// if the command is still running and its extent is not yet known
+    pub(crate) fn last_row(&self) -> Option<StableRowIndex> {
+        if self.is_running() {
+            return None;
+        }
+        Some(
+            self.output_end
+                .map(|end| end - 1)
+                .or(self.output_start)
+                .or(self.input_start)
+                .unwrap_or(self.prompt_start)
+                .max(self.prompt_start),
+        )
+    }
+
+    fn map_rows<F: Fn(StableRowIndex) -> StableRowIndex>(&mut self, map: F) {
+        self.prompt_start = map(self.prompt_start);
+        self.input_start = self.input_start.map(&map);
+        self.output_start = self.output_start.map(&map);
+        self.output_end = self.output_end.map(&map);
+    }
+}
+
+/// Maintains the list of command blocks for a screen.
+/// The methods here are driven by the OSC 133 handlers in
+/// the terminal state.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CommandBlocks {
+    blocks: VecDeque<CommandBlock>,
+    next_id: usize,
+}
+
+impl CommandBlocks {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &CommandBlock> {
+        self.blocks.iter()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&CommandBlock> {
+        self.blocks.iter().find(|b| b.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut CommandBlock> {
+        self.blocks.iter_mut().find(|b| b.id == id)
+    }
+
+    fn last_mut(&mut self) -> Option<&mut CommandBlock> {
+        self.blocks.back_mut()
+    }
+
+    /// Called when a primary prompt is started at `row`
+    pub fn start_prompt(&mut self, row: StableRowIndex, now: SystemTime) {
+        if let Some(last) = self.last_mut() {
+            if last.is_running() {
+                // The shell didn't report the status of the prior
+                // command; consider it finished now.
+                last.finished.replace(now);
+                last.output_end.replace(row);
+            } else if !last.has_run() && !last.collapsed {
+                // The prior prompt was abandoned without running
+                // anything (eg: the user pressed CTRL-C), or the shell
+                // sent more than one marker for the same prompt.
+                // Re-use it rather than tracking an empty block.
+                *last = CommandBlock::new(last.id, row);
+                return;
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.blocks.push_back(CommandBlock::new(id, row));
+        while self.blocks.len() > MAX_COMMAND_BLOCKS {
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Called when the user input is started at `row`
+    pub fn start_input(&mut self, row: StableRowIndex) {
+        if let Some(last) = self.last_mut() {
+            if !last.has_run() && last.input_start.is_none() {
+                last.input_start.replace(row);
+            }
+        }
+    }
+
+    /// Called when the command has been submitted and its output
+    /// will start at `row`
+    pub fn start_output(&mut self, row: StableRowIndex, command: String, now: SystemTime) {
+        let needs_block = self.last_mut().map(|b| b.has_run()).unwrap_or(true);
+        if needs_block {
+            // The shell didn't mark up the prompt; synthesize a
+            // block so that we can still track the output
+            self.start_prompt(row, now);
+        }
+        if let Some(last) = self.last_mut() {
+            last.output_start.replace(row);
+            last.command = command;
+            last.started.replace(now);
+        }
+    }
+
+    /// Called when the command has finished with the specified status.
+    /// `end_row` is the row immediately after its output.
+    /// Returns the block that was finished, if any.
+    pub fn finish(
+        &mut self,
+        end_row: StableRowIndex,
+        status: Option<i32>,
+        now: SystemTime,
+    ) -> Option<CommandBlock> {
+        let last = self.last_mut()?;
+        if !last.is_running() {
+            return None;
+        }
+        last.finished.replace(now);
+        last.exit_status = status;
+        last.output_end
+            .replace(end_row.max(last.output_start.unwrap_or(end_row)));
+        Some(last.clone())
+    }
+
+    /// Forget about blocks whose rows have been discarded from
+    /// the scrollback
+    pub fn prune(&mut self, first_row: StableRowIndex) {
+        while let Some(first) = self.blocks.front() {
+            match first.last_row() {
+                Some(last_row) if last_row < first_row => {
+                    self.blocks.pop_front();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Adjust the rows of every block by applying `map`
+    pub fn map_rows<F: Fn(StableRowIndex) -> StableRowIndex>(&mut self, map: F) {
+        for block in &mut self.blocks {
+            block.map_rows(&map);
+        }
+    }
+}
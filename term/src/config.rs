@@ -1,5 +1,6 @@
 use crate::color::ColorPalette;
 use downcast_rs::{impl_downcast, Downcast};
+use std::path::PathBuf;
 use wezterm_bidi::ParagraphDirectionHint;
 use wezterm_cell::UnicodeVersion;
 use wezterm_surface::{Line, SequenceNo};
@@ -147,6 +148,21 @@ pub trait TerminalConfiguration: Downcast + std::fmt::Debug + Send + Sync {
         3500
     }
 
+    /// Returns the maximum number of rows that may be paged out to
+    /// disk once the in-memory scrollback defined by `scrollback_size`
+    /// is full.  Zero disables the on-disk tier.
+    fn scrollback_spill_size(&self) -> usize {
+        0
+    }
+
+    /// Returns the directory in which on-disk scrollback files
+    /// will be created.  The on-disk tier is only used when this
+    /// returns a directory; the embedding application is responsible
+    /// for choosing one that is private to the user.
+    fn scrollback_spill_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
     /// Return true if the embedding application wants to use CSI-u encoding
     /// for keys that would otherwise be ambiguous.
     /// <http://www.leonerd.org.uk/hacks/fixterms/>
@@ -26,6 +26,9 @@ use wezterm_surface::SequenceNo;
 pub mod config;
 pub use config::TerminalConfiguration;
 
+pub mod commandblock;
+pub use crate::commandblock::*;
+
 pub mod input;
 pub use crate::input::*;
 
@@ -35,6 +38,9 @@ pub use wezterm_surface::line::*;
 pub mod screen;
 pub use crate::screen::*;
 
+mod spill;
+pub use crate::spill::SpilledScrollback;
+
 pub mod terminal;
 pub use crate::terminal::*;
 
@@ -1,9 +1,11 @@
 #![allow(clippy::range_plus_one)]
 use super::*;
+use crate::commandblock::CommandBlocks;
 use crate::config::BidiMode;
+use crate::spill::{ScrollbackSpill, SpilledScrollback};
 use log::debug;
 use std::collections::VecDeque;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use termwiz::input::KeyboardEncoding;
 use wezterm_surface::SequenceNo;
 
@@ -44,6 +46,15 @@ pub struct Screen {
     pub dpi: u32,
 
     pub(crate) saved_cursor: Option<SavedCursor>,
+
+    /// Lines that have been paged out of `lines` to disk.
+    /// Only present when the on-disk scrollback tier is enabled,
+    /// and only created once the in-memory scrollback is full.
+    spill: Option<Arc<Mutex<ScrollbackSpill>>>,
+
+    /// The commands that have been run in this screen, as
+    /// reported by OSC 133 markers
+    pub(crate) command_blocks: CommandBlocks,
 }
 
 fn scrollback_size(config: &Arc<dyn TerminalConfiguration>, allow_scrollback: bool) -> usize {
@@ -86,6 +97,8 @@ impl Screen {
             dpi: size.dpi,
             keyboard_stack: vec![],
             saved_cursor: None,
+            spill: None,
+            command_blocks: CommandBlocks::default(),
         }
     }
 
@@ -97,6 +110,146 @@ impl Screen {
         scrollback_size(&self.config, self.allow_scrollback)
     }
 
+    /// Move a line that has been scrolled off the top of the in-memory
+    /// scrollback into the on-disk tier, if that is enabled.
+    /// This must be called before `stable_row_index_offset` is advanced
+    /// past the line, as that is the stable row index of the line.
+    fn spill_line(&mut self, line: Line) {
+        let spill_size = self.config.scrollback_spill_size();
+        let spill_dir = match self.config.scrollback_spill_dir() {
+            Some(dir) if self.allow_scrollback && spill_size > 0 && cfg!(feature = "use_serde") => {
+                dir
+            }
+            _ => {
+                // The on-disk tier may have been disabled by a config change;
+                // any previously spilled lines are no longer reachable.
+                self.spill.take();
+                return;
+            }
+        };
+
+        if self.spill.is_none() {
+            match ScrollbackSpill::new(
+                &spill_dir,
+                spill_size,
+                self.stable_row_index_offset as StableRowIndex,
+            ) {
+                Ok(spill) => {
+                    self.spill.replace(Arc::new(Mutex::new(spill)));
+                }
+                Err(err) => {
+                    log::error!("{:#}", err);
+                    return;
+                }
+            }
+        }
+
+        if let Some(spill) = &self.spill {
+            let mut spill = spill.lock().unwrap();
+            spill.set_max_rows(spill_size);
+            if let Err(err) = spill.push(line) {
+                log::error!("failed to spill scrollback to disk: {:#}", err);
+            }
+        }
+    }
+
+    /// Returns the number of rows that have been paged out to disk
+    pub fn spilled_rows(&self) -> usize {
+        self.spill
+            .as_ref()
+            .map(|spill| spill.lock().unwrap().num_rows())
+            .unwrap_or(0)
+    }
+
+    /// Returns the StableRowIndex of the earliest row that can be
+    /// retrieved, taking into account any rows that were paged out
+    /// to disk.
+    pub fn first_stable_row(&self) -> StableRowIndex {
+        match &self.spill {
+            Some(spill) => spill.lock().unwrap().first_row(),
+            None => self.phys_to_stable_row_index(0),
+        }
+    }
+
+    /// Returns copies of the rows from the on-disk tier that intersect
+    /// with the requested range, along with the StableRowIndex of the
+    /// first of those rows.
+    pub fn spilled_lines(&self, range: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+        match &self.spill {
+            Some(spill) => {
+                let end = range
+                    .end
+                    .min(self.stable_row_index_offset as StableRowIndex);
+                spill.lock().unwrap().get_lines(range.start..end)
+            }
+            None => (range.start, vec![]),
+        }
+    }
+
+    /// Returns true if some portion of `range` lies in the on-disk tier
+    fn range_is_spilled(&self, range: &Range<StableRowIndex>) -> bool {
+        self.spill.is_some() && range.start < self.stable_row_index_offset as StableRowIndex
+    }
+
+    /// Returns copies of the lines in the requested range, including
+    /// those that have been paged out to disk, along with the StableRowIndex
+    /// of the first returned line.
+    pub fn lines_in_stable_range(
+        &self,
+        range: Range<StableRowIndex>,
+    ) -> (StableRowIndex, Vec<Line>) {
+        let mut first = None;
+        let mut result = vec![];
+        self.with_stable_lines(range, |first_row, lines| {
+            first.replace(first_row);
+            result.extend(lines.iter().map(|&line| line.clone()));
+        });
+        (first.unwrap_or(0), result)
+    }
+
+    /// Call `func` with the lines in the requested range, including those
+    /// that have been paged out to disk.
+    pub fn with_stable_lines<F>(&self, range: Range<StableRowIndex>, mut func: F)
+    where
+        F: FnMut(StableRowIndex, &[&Line]),
+    {
+        if !self.range_is_spilled(&range) {
+            let phys_range = self.stable_range(&range);
+            let first = self.phys_to_stable_row_index(phys_range.start);
+            return self.with_phys_lines(phys_range, |lines| func(first, lines));
+        }
+
+        let (first, spilled) = self.spilled_lines(range.clone());
+        let num_mem = (range.end - self.stable_row_index_offset as StableRowIndex)
+            .max(0)
+            .min(self.lines.len() as StableRowIndex) as usize;
+        let mut lines: Vec<&Line> = spilled.iter().collect();
+        lines.extend(self.lines.iter().take(num_mem));
+        func(first, &lines);
+    }
+
+    /// Call `func` with mutable references to the lines in the requested
+    /// range.  Lines that have been paged out to disk are passed as
+    /// temporary copies; changes made to those will not persist.
+    pub fn with_stable_lines_mut<F>(&mut self, range: Range<StableRowIndex>, mut func: F)
+    where
+        F: FnMut(StableRowIndex, &mut [&mut Line]),
+    {
+        if !self.range_is_spilled(&range) {
+            let phys_range = self.stable_range(&range);
+            let first = self.phys_to_stable_row_index(phys_range.start);
+            return self.with_phys_lines_mut(phys_range, |lines| func(first, lines));
+        }
+
+        let (first, mut spilled) = self.spilled_lines(range.clone());
+        let num_mem = (range.end - self.stable_row_index_offset as StableRowIndex)
+            .max(0)
+            .min(self.lines.len() as StableRowIndex) as usize;
+        let mut lines: Vec<&mut Line> = spilled.iter_mut().collect();
+        lines.extend(self.lines.iter_mut().take(num_mem));
+        func(first, &mut lines);
+    }
+
     fn rewrap_lines(
         &mut self,
         physical_cols: usize,
@@ -109,6 +262,11 @@ impl Screen {
         let mut logical_line: Option<Line> = None;
         let mut logical_cursor_x: Option<usize> = None;
         let mut adjusted_cursor = (cursor_x, cursor_y);
+        // Maps the old physical row index to the new physical row index
+        // of the start of the logical line that contained it, so that
+        // we can relocate the command blocks after the rewrap
+        let mut remap = Vec::with_capacity(self.lines.len() + 1);
+        let mut num_wrapped = 0;
 
         for (phys_idx, mut line) in self.lines.drain(..).enumerate() {
             line.update_last_change_seqno(seqno);
@@ -136,9 +294,15 @@ impl Screen {
 
             if was_wrapped {
                 logical_line.replace(line);
+                num_wrapped += 1;
                 continue;
             }
 
+            for _ in 0..=num_wrapped {
+                remap.push(rewrapped.len());
+            }
+            num_wrapped = 0;
+
             if let Some(x) = logical_cursor_x.take() {
                 let num_lines = x / physical_cols;
                 let last_x = x - (num_lines * physical_cols);
@@ -172,6 +336,9 @@ impl Screen {
                 }
             }
         }
+        for _ in 0..=num_wrapped {
+            remap.push(rewrapped.len());
+        }
         self.lines = rewrapped;
 
         // If we resized narrower and generated additional lines,
@@ -186,6 +353,21 @@ impl Screen {
             self.lines.pop_back();
         }
 
+        let offset = self.stable_row_index_offset as StableRowIndex;
+        let num_lines = self.lines.len();
+        self.command_blocks.map_rows(|row| {
+            if row < offset {
+                // Rows that were spilled to disk are not rewrapped
+                return row;
+            }
+            let phys = remap
+                .get((row - offset) as usize)
+                .copied()
+                .unwrap_or(num_lines)
+                .min(num_lines);
+            offset + phys as StableRowIndex
+        });
+
         adjusted_cursor
     }
 
@@ -702,9 +884,13 @@ impl Screen {
         // To avoid thrashing the heap, prefer to move lines that were
         // scrolled off the top and re-use them at the bottom.
         let to_move = lines_removed.min(num_rows);
+        let spill_removed = remove_idx == 0 && scrollback_ok;
         let (to_remove, to_add) = {
             for _ in 0..to_move {
                 let mut line = self.lines.remove(remove_idx).unwrap();
+                if spill_removed {
+                    self.spill_line(line.clone());
+                }
                 let line = if default_blank == blank_attr {
                     Line::new(seqno)
                 } else {
@@ -726,7 +912,11 @@ impl Screen {
 
         // Perform the removal
         for _ in 0..to_remove {
-            self.lines.remove(remove_idx);
+            if let Some(line) = self.lines.remove(remove_idx) {
+                if spill_removed {
+                    self.spill_line(line);
+                }
+            }
         }
 
         if remove_idx == 0 && scrollback_ok {
@@ -760,6 +950,7 @@ impl Screen {
     }
 
     pub fn erase_scrollback(&mut self) {
+        self.spill.take();
         let len = self.lines.len();
         let to_clear = len - self.physical_rows;
         for _ in 0..to_clear {
@@ -768,6 +959,7 @@ impl Screen {
                 self.stable_row_index_offset += 1;
             }
         }
+        self.command_blocks.prune(self.first_stable_row());
     }
 
     /// ```text
@@ -911,6 +1103,14 @@ impl Screen {
         stable_lines: Range<StableRowIndex>,
         seqno: SequenceNo,
     ) -> Vec<StableRowIndex> {
+        // Rows that were paged out to disk are immutable
+        let stable_lines = stable_lines
+            .start
+            .max(self.stable_row_index_offset as StableRowIndex)
+            ..stable_lines.end;
+        if stable_lines.is_empty() {
+            return vec![];
+        }
         let phys = self.stable_range(&stable_lines);
         let mut set = vec![];
         for (idx, line) in self
@@ -1067,6 +1267,31 @@ impl Screen {
         }
     }
 
+    /// Splits `range` into the portion that lies in the on-disk tier,
+    /// and the remainder that is held in memory.  The returned handle
+    /// can be used to iterate the spilled portion after the lock on the
+    /// terminal has been released, which avoids blocking the terminal
+    /// while a potentially large amount of history is decoded.
+    pub fn split_spilled_range(
+        &self,
+        range: Range<StableRowIndex>,
+    ) -> (
+        Option<(SpilledScrollback, Range<StableRowIndex>)>,
+        Range<StableRowIndex>,
+    ) {
+        if !self.range_is_spilled(&range) {
+            return (None, range);
+        }
+        let boundary = self.stable_row_index_offset as StableRowIndex;
+        let spilled = range.start.max(self.first_stable_row())..range.end.min(boundary);
+        let remainder = range.start.max(boundary)..range.end.max(boundary);
+        let spill = self
+            .spill
+            .as_ref()
+            .map(|spill| (SpilledScrollback::new(Arc::clone(spill), boundary), spilled));
+        (spill, remainder)
+    }
+
     pub fn for_each_logical_line_in_stable_range<F>(
         &self,
         stable_range: Range<StableRowIndex>,
@@ -1074,6 +1299,39 @@ impl Screen {
     ) where
         F: FnMut(Range<StableRowIndex>, &[&Line]) -> bool,
     {
+        let (spilled, remainder) = self.split_spilled_range(stable_range);
+        let mut tail = vec![];
+        if let Some((spill, range)) = spilled {
+            if !spill.for_each_logical_line(range, &mut tail, &mut f) {
+                return;
+            }
+        }
+        self.for_each_logical_line_in_memory(remainder, tail, f);
+    }
+
+    /// Iterates the logical lines of the lines held in memory that
+    /// intersect with `stable_range`.
+    /// `spilled_tail` holds the spilled portion of a logical line that
+    /// continues into the first line held in memory, as produced by
+    /// `SpilledScrollback::for_each_logical_line`; it is joined with
+    /// the rest of that line.
+    pub fn for_each_logical_line_in_memory<F>(
+        &self,
+        stable_range: Range<StableRowIndex>,
+        spilled_tail: Vec<Line>,
+        mut f: F,
+    ) where
+        F: FnMut(Range<StableRowIndex>, &[&Line]) -> bool,
+    {
+        let tail_first = self.phys_to_stable_row_index(0) - spilled_tail.len() as StableRowIndex;
+        if stable_range.is_empty() {
+            if !spilled_tail.is_empty() {
+                let refs: Vec<&Line> = spilled_tail.iter().collect();
+                f(tail_first..self.phys_to_stable_row_index(0), &refs);
+            }
+            return;
+        }
+
         let mut phys_range = self.stable_range(&stable_range);
 
         // Avoid pathological cases where we have eg: a really long logical line
@@ -1104,6 +1362,12 @@ impl Screen {
             let mut end_inclusive = phys_row;
             line_vec.clear();
 
+            // The first line continues the spilled tail, if any
+            let joins_tail = phys_row == 0 && !spilled_tail.is_empty();
+            if joins_tail {
+                line_vec.extend(spilled_tail.iter());
+            }
+
             for idx in phys_row.. {
                 if let Some(line) = self.lines.get(idx) {
                     if total_len > 0 && total_len + line.len() > MAX_LOGICAL_LINE_LEN {
@@ -1117,14 +1381,22 @@ impl Screen {
                     }
                 } else if idx == phys_row {
                     // No more rows exist
+                    if joins_tail {
+                        f(tail_first..self.phys_to_stable_row_index(0), &line_vec);
+                    }
                     return;
                 } else {
                     break;
                 }
             }
 
-            let logical_stable_range = self.phys_to_stable_row_index(phys_row)
-                ..self.phys_to_stable_row_index(end_inclusive + 1);
+            let logical_start = if joins_tail {
+                tail_first
+            } else {
+                self.phys_to_stable_row_index(phys_row)
+            };
+            let logical_stable_range =
+                logical_start..self.phys_to_stable_row_index(end_inclusive + 1);
 
             phys_row = end_inclusive + 1;
 
@@ -1142,6 +1414,140 @@ impl Screen {
             }
         }
     }
+
+    /// Returns the commands that are being tracked for this screen,
+    /// discarding any whose rows are no longer present in the scrollback
+    pub fn command_blocks(&mut self) -> Vec<CommandBlock> {
+        self.command_blocks.prune(self.first_stable_row());
+        self.command_blocks.iter().cloned().collect()
+    }
+
+    /// Returns the commands that have rows within `range`
+    pub fn command_blocks_in_range(&mut self, range: Range<StableRowIndex>) -> Vec<CommandBlock> {
+        self.command_blocks.prune(self.first_stable_row());
+        // The blocks are ordered by row, and the range is typically
+        // the viewport, so walk backwards from the most recent
+        let mut blocks: Vec<CommandBlock> = self
+            .command_blocks
+            .iter()
+            .rev()
+            .skip_while(|block| block.prompt_start >= range.end)
+            .take_while(|block| {
+                block
+                    .last_row()
+                    .map_or(true, |last_row| last_row >= range.start)
+            })
+            .cloned()
+            .collect();
+        blocks.reverse();
+        blocks
+    }
+
+    /// Returns the text from the cells in `range` that have the
+    /// specified semantic type.  Wrapped lines are joined together.
+    pub fn semantic_text_in_stable_range(
+        &self,
+        range: Range<StableRowIndex>,
+        semantic_type: SemanticType,
+    ) -> String {
+        let mut text = String::new();
+        self.with_stable_lines(range, |_first_row, lines| {
+            for line in lines {
+                let mut found = false;
+                for cell in line.visible_cells() {
+                    if cell.attrs().semantic_type() == semantic_type {
+                        text.push_str(cell.str());
+                        found = true;
+                    }
+                }
+                if found && !line.last_cell_was_wrapped() {
+                    text.truncate(text.trim_end().len());
+                    text.push('\n');
+                }
+            }
+        });
+        text.trim().to_string()
+    }
+
+    /// Returns the text of the output of the specified command
+    pub fn command_output_text(&self, id: usize) -> Option<String> {
+        let block = self.command_blocks.get(id)?;
+        let start = block.output_start?;
+        let end = block
+            .output_end
+            .unwrap_or_else(|| self.phys_to_stable_row_index(self.lines.len()));
+        let (_, lines) = self.lines_in_stable_range(start..end);
+
+        let mut text = String::new();
+        for line in &lines {
+            if line.last_cell_was_wrapped() {
+                text.push_str(&line.as_str());
+            } else {
+                text.push_str(line.as_str().trim_end());
+                text.push('\n');
+            }
+        }
+        text.truncate(text.trim_end_matches('\n').len());
+        Some(text)
+    }
+
+    /// Returns the rows of output that have been collapsed,
+    /// ordered from oldest to newest.
+    /// The rows remain present in the screen; it is up to the
+    /// renderer to fold each range into a single placeholder row.
+    pub fn collapsed_output_ranges(&self) -> Vec<Range<StableRowIndex>> {
+        let first_row = self.first_stable_row();
+        self.command_blocks
+            .iter()
+            .filter(|block| block.collapsed)
+            .filter_map(|block| block.output_rows())
+            .filter(|rows| rows.end > first_row)
+            .collect()
+    }
+
+    /// Mark the output of the specified command as collapsed, or
+    /// expand it again.  Only the flag on the command is changed;
+    /// the lines and their StableRowIndex values are unaffected.
+    pub fn set_command_block_collapsed(
+        &mut self,
+        id: usize,
+        collapsed: bool,
+        seqno: SequenceNo,
+    ) -> anyhow::Result<()> {
+        let block = self
+            .command_blocks
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("no command with id {id}"))?;
+        if block.collapsed == collapsed {
+            return Ok(());
+        }
+        let output_rows = block
+            .output_rows()
+            .ok_or_else(|| anyhow::anyhow!("command {id} has not finished"))?;
+        if collapsed {
+            // Folding a single row into a placeholder row gains nothing
+            anyhow::ensure!(
+                output_rows.end > output_rows.start + 1,
+                "command {id} has no output to collapse"
+            );
+        }
+        let prompt_start = block.prompt_start;
+
+        if let Some(block) = self.command_blocks.get_mut(id) {
+            block.collapsed = collapsed;
+        }
+
+        // Nothing in the lines has changed, but the renderer needs
+        // to know that it should repaint them
+        let offset = self.stable_row_index_offset as StableRowIndex;
+        let start = (prompt_start - offset).max(0) as usize;
+        let end = (output_rows.end - offset).max(0) as usize;
+        for line in self.lines.iter_mut().take(end).skip(start) {
+            line.update_last_change_seqno(seqno);
+        }
+
+        Ok(())
+    }
 }
 
 fn phys_intersection(r1: &Range<PhysRowIndex>, r2: &Range<PhysRowIndex>) -> Range<PhysRowIndex> {
@@ -0,0 +1,515 @@
+//! An on-disk tier for the primary screen scrollback.
+//!
+//! When enabled via `TerminalConfiguration::scrollback_spill_size`, lines
+//! that would otherwise be discarded from the top of the in-memory
+//! scrollback are batched into blocks, compressed and appended to a
+//! per-screen file.  The blocks are decoded on demand when the lines are
+//! needed for rendering, searching or text extraction, with a small cache
+//! of recently decoded blocks to make scrolling through the spilled region
+//! tolerable.
+use crate::StableRowIndex;
+use anyhow::Context;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use wezterm_surface::Line;
+
+/// How many lines are accumulated before they are compressed
+/// and written out as a block
+const BLOCK_ROWS: usize = 256;
+
+/// How many decoded blocks to keep in memory
+const CACHED_BLOCKS: usize = 8;
+
+/// How many lines are decoded at a time when iterating
+/// over the spilled lines
+const CHUNK_ROWS: StableRowIndex = 4096;
+
+static SPILL_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy)]
+struct SpillBlock {
+    /// The stable row index of the first line in this block
+    first_row: StableRowIndex,
+    /// The number of lines in this block
+    num_rows: usize,
+    /// Byte offset of the compressed data in the file
+    offset: u64,
+    /// Length of the compressed data in the file
+    len: usize,
+}
+
+impl SpillBlock {
+    fn rows(&self) -> Range<StableRowIndex> {
+        self.first_row..self.first_row + self.num_rows as StableRowIndex
+    }
+}
+
+pub(crate) struct ScrollbackSpill {
+    path: PathBuf,
+    file: File,
+    /// The blocks that are currently live, in ascending row order
+    blocks: Vec<SpillBlock>,
+    /// Lines that have been spilled but not yet written out
+    pending: Vec<Line>,
+    /// The stable row index of the first line in `pending`
+    pending_first_row: StableRowIndex,
+    /// Where the next block will be written
+    write_offset: u64,
+    /// The number of bytes in the file that are occupied by
+    /// blocks that have been discarded
+    wasted_bytes: u64,
+    /// The maximum number of lines to retain
+    max_rows: usize,
+    cache: lru::LruCache<u64, Arc<Vec<Line>>>,
+}
+
+impl std::fmt::Debug for ScrollbackSpill {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("ScrollbackSpill")
+            .field("path", &self.path)
+            .field("blocks", &self.blocks.len())
+            .field("pending", &self.pending.len())
+            .field("max_rows", &self.max_rows)
+            .finish()
+    }
+}
+
+impl Drop for ScrollbackSpill {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+impl ScrollbackSpill {
+    /// Create a new, empty, spill file in `dir`.
+    /// `first_row` is the stable row index that will be assigned
+    /// to the first line that is pushed into the spill.
+    pub fn new(dir: &Path, max_rows: usize, first_row: StableRowIndex) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("creating scrollback spill dir {}", dir.display()))?;
+        let path = dir.join(format!(
+            "wezterm-scrollback-{}-{}",
+            std::process::id(),
+            SPILL_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("creating scrollback spill file {}", path.display()))?;
+        log::debug!("spilling scrollback to {}", path.display());
+
+        Ok(Self {
+            path,
+            file,
+            blocks: vec![],
+            pending: vec![],
+            pending_first_row: first_row,
+            write_offset: 0,
+            wasted_bytes: 0,
+            max_rows,
+            cache: lru::LruCache::new(NonZeroUsize::new(CACHED_BLOCKS).unwrap()),
+        })
+    }
+
+    /// Returns the stable row index of the earliest retained line
+    pub fn first_row(&self) -> StableRowIndex {
+        self.blocks
+            .first()
+            .map(|b| b.first_row)
+            .unwrap_or(self.pending_first_row)
+    }
+
+    /// Returns the stable row index one beyond the last spilled line
+    pub fn end_row(&self) -> StableRowIndex {
+        self.pending_first_row + self.pending.len() as StableRowIndex
+    }
+
+    /// Returns the number of lines held by the spill
+    pub fn num_rows(&self) -> usize {
+        (self.end_row() - self.first_row()) as usize
+    }
+
+    pub fn set_max_rows(&mut self, max_rows: usize) {
+        self.max_rows = max_rows;
+        self.prune();
+    }
+
+    /// Append a line that has been scrolled off the top of
+    /// the in-memory scrollback
+    pub fn push(&mut self, mut line: Line) -> anyhow::Result<()> {
+        line.compress_for_scrollback();
+        self.pending.push(line);
+        if self.pending.len() >= BLOCK_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let data = encode_lines(&self.pending)?;
+
+        self.file.seek(SeekFrom::Start(self.write_offset))?;
+        self.file.write_all(&data)?;
+
+        let num_rows = self.pending.len();
+        self.blocks.push(SpillBlock {
+            first_row: self.pending_first_row,
+            num_rows,
+            offset: self.write_offset,
+            len: data.len(),
+        });
+        self.write_offset += data.len() as u64;
+        self.pending_first_row += num_rows as StableRowIndex;
+        self.pending.clear();
+
+        self.prune();
+        Ok(())
+    }
+
+    /// Discard the oldest blocks until we fit within max_rows,
+    /// and compact the file if it has become mostly garbage
+    fn prune(&mut self) {
+        while !self.blocks.is_empty() && self.num_rows() > self.max_rows {
+            let block = self.blocks.remove(0);
+            self.cache.pop(&block.offset);
+            self.wasted_bytes += block.len as u64;
+        }
+
+        if self.wasted_bytes > 0 && self.wasted_bytes * 2 > self.write_offset {
+            if let Err(err) = self.compact() {
+                log::error!("failed to compact {}: {:#}", self.path.display(), err);
+            }
+        }
+    }
+
+    /// Rewrite the live blocks to the start of the file
+    fn compact(&mut self) -> anyhow::Result<()> {
+        let mut write_offset = 0;
+        let mut buf = vec![];
+        for block in &mut self.blocks {
+            buf.resize(block.len, 0);
+            self.file.seek(SeekFrom::Start(block.offset))?;
+            self.file.read_exact(&mut buf)?;
+            self.file.seek(SeekFrom::Start(write_offset))?;
+            self.file.write_all(&buf)?;
+            block.offset = write_offset;
+            write_offset += block.len as u64;
+        }
+        self.file.set_len(write_offset)?;
+        self.write_offset = write_offset;
+        self.wasted_bytes = 0;
+        self.cache.clear();
+        Ok(())
+    }
+
+    fn load_block(&mut self, block: SpillBlock) -> anyhow::Result<Arc<Vec<Line>>> {
+        if let Some(lines) = self.cache.get(&block.offset) {
+            return Ok(Arc::clone(lines));
+        }
+        let mut data = vec![0u8; block.len];
+        self.file.seek(SeekFrom::Start(block.offset))?;
+        self.file.read_exact(&mut data)?;
+        let lines = Arc::new(decode_lines(&data)?);
+        self.cache.put(block.offset, Arc::clone(&lines));
+        Ok(lines)
+    }
+
+    /// Copies the data for the spilled lines that intersect with `range`,
+    /// so that it can be decoded by `SpillChunk::decode` after the lock
+    /// on the spill has been released.
+    fn read_chunk(&mut self, range: Range<StableRowIndex>) -> SpillChunk {
+        let start = range.start.max(self.first_row());
+        let end = range.end.min(self.end_row());
+        let mut chunk = SpillChunk {
+            start,
+            end,
+            blocks: vec![],
+            pending: vec![],
+            pending_first_row: self.pending_first_row,
+        };
+        if start >= end {
+            return chunk;
+        }
+
+        let first_block = self
+            .blocks
+            .partition_point(|b| b.first_row + b.num_rows as StableRowIndex <= start);
+        let blocks: Vec<SpillBlock> = self.blocks[first_block..]
+            .iter()
+            .take_while(|b| b.first_row < end)
+            .copied()
+            .collect();
+
+        for block in blocks {
+            let data = match self.cache.get(&block.offset) {
+                Some(lines) => Ok(ChunkData::Decoded(Arc::clone(lines))),
+                None => {
+                    let mut data = vec![0u8; block.len];
+                    self.file
+                        .seek(SeekFrom::Start(block.offset))
+                        .and_then(|_| self.file.read_exact(&mut data))
+                        .map(|_| ChunkData::Compressed(data))
+                        .map_err(|err| {
+                            anyhow::anyhow!(
+                                "failed to read scrollback from {}: {:#}",
+                                self.path.display(),
+                                err
+                            )
+                        })
+                }
+            };
+            chunk.blocks.push((block, data));
+        }
+
+        if end > self.pending_first_row {
+            let skip = (start.max(self.pending_first_row) - self.pending_first_row) as usize;
+            let take = (end - self.pending_first_row) as usize - skip;
+            chunk
+                .pending
+                .extend(self.pending.iter().skip(skip).take(take).cloned());
+        }
+
+        chunk
+    }
+
+    /// Retrieve copies of the spilled lines that intersect with `range`.
+    /// Returns the stable row index of the first returned line, which
+    /// may be later than `range.start` if those lines are no longer
+    /// retained.
+    pub fn get_lines(&mut self, range: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+        let start = range.start.max(self.first_row());
+        let end = range.end.min(self.end_row());
+        let mut result = vec![];
+        if start >= end {
+            return (start, result);
+        }
+
+        let first_block = self
+            .blocks
+            .partition_point(|b| b.first_row + b.num_rows as StableRowIndex <= start);
+        let blocks: Vec<SpillBlock> = self.blocks[first_block..]
+            .iter()
+            .take_while(|b| b.first_row < end)
+            .copied()
+            .collect();
+
+        for block in blocks {
+            let rows = block.rows();
+            match self.load_block(block) {
+                Ok(lines) => {
+                    let skip = (start.max(rows.start) - rows.start) as usize;
+                    let take = (end.min(rows.end) - rows.start) as usize - skip;
+                    result.extend(lines.iter().skip(skip).take(take).cloned());
+                }
+                Err(err) => {
+                    log::error!(
+                        "failed to load scrollback from {}: {:#}",
+                        self.path.display(),
+                        err
+                    );
+                    // Keep the row accounting intact so that the caller
+                    // can still map indices onto the returned lines
+                    let count = end.min(rows.end) - start.max(rows.start);
+                    for _ in 0..count {
+                        result.push(Line::new(0));
+                    }
+                }
+            }
+        }
+
+        if end > self.pending_first_row {
+            let skip = (start.max(self.pending_first_row) - self.pending_first_row) as usize;
+            let take = (end - self.pending_first_row) as usize - skip;
+            result.extend(self.pending.iter().skip(skip).take(take).cloned());
+        }
+
+        (start, result)
+    }
+}
+
+enum ChunkData {
+    Decoded(Arc<Vec<Line>>),
+    Compressed(Vec<u8>),
+}
+
+/// A copy of a portion of the spill, produced by `read_chunk`
+struct SpillChunk {
+    start: StableRowIndex,
+    end: StableRowIndex,
+    blocks: Vec<(SpillBlock, anyhow::Result<ChunkData>)>,
+    pending: Vec<Line>,
+    pending_first_row: StableRowIndex,
+}
+
+impl SpillChunk {
+    /// Decode the lines in the chunk, returning the stable row index
+    /// of the first of them along with the lines
+    fn decode(self) -> (StableRowIndex, Vec<Line>) {
+        let start = self.start;
+        let end = self.end;
+        let mut result = vec![];
+
+        for (block, data) in self.blocks {
+            let rows = block.rows();
+            let lines = data.and_then(|data| match data {
+                ChunkData::Decoded(lines) => Ok(lines),
+                ChunkData::Compressed(data) => decode_lines(&data).map(Arc::new),
+            });
+            match lines {
+                Ok(lines) => {
+                    let skip = (start.max(rows.start) - rows.start) as usize;
+                    let take = (end.min(rows.end) - rows.start) as usize - skip;
+                    result.extend(lines.iter().skip(skip).take(take).cloned());
+                }
+                Err(err) => {
+                    log::error!("failed to load spilled scrollback: {:#}", err);
+                    let count = end.min(rows.end) - start.max(rows.start);
+                    for _ in 0..count {
+                        result.push(Line::new(0));
+                    }
+                }
+            }
+        }
+
+        if end > self.pending_first_row {
+            result.extend(self.pending);
+        }
+
+        (start, result)
+    }
+}
+
+/// A handle to the lines that a screen has paged out to disk,
+/// which can be used to iterate over them after the lock on the
+/// terminal has been released.  Only a small chunk of the lines
+/// is held in memory at a time, and the lock on the spill is only
+/// held while the compressed data for a chunk is copied; the lines
+/// are decoded without holding any lock.
+#[derive(Clone)]
+pub struct SpilledScrollback {
+    spill: Arc<Mutex<ScrollbackSpill>>,
+    /// The stable row index one beyond the last spilled line,
+    /// at the time that the handle was obtained
+    end: StableRowIndex,
+}
+
+impl std::fmt::Debug for SpilledScrollback {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("SpilledScrollback")
+            .field("end", &self.end)
+            .finish()
+    }
+}
+
+impl SpilledScrollback {
+    pub(crate) fn new(spill: Arc<Mutex<ScrollbackSpill>>, end: StableRowIndex) -> Self {
+        Self { spill, end }
+    }
+
+    fn read(&self, range: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+        let chunk = self.spill.lock().unwrap().read_chunk(range);
+        chunk.decode()
+    }
+
+    /// Iterates the logical lines that intersect with `stable_range`.
+    /// A logical line that is still wrapped at the last spilled line
+    /// continues in the lines that are held in memory, so rather than
+    /// being passed to `f`, its spilled portion is moved into `tail`
+    /// for the caller to join with the rest of it.
+    /// A logical line that continues beyond the end of `stable_range`
+    /// is otherwise passed to `f` as though it ended there.
+    /// Returns false if `f` requested that iteration stop.
+    pub fn for_each_logical_line<F>(
+        &self,
+        stable_range: Range<StableRowIndex>,
+        tail: &mut Vec<Line>,
+        f: &mut F,
+    ) -> bool
+    where
+        F: FnMut(Range<StableRowIndex>, &[&Line]) -> bool,
+    {
+        let end = stable_range.end.min(self.end);
+        let mut chunk_start = stable_range.start;
+        let mut carry: Vec<Line> = vec![];
+        let mut carry_first = chunk_start;
+
+        while chunk_start < end {
+            let chunk_end = (chunk_start + CHUNK_ROWS).min(end);
+            let (first, lines) = self.read(chunk_start..chunk_end);
+            if lines.is_empty() {
+                break;
+            }
+            chunk_start = first + lines.len() as StableRowIndex;
+
+            let mut logical: Vec<Line> = std::mem::take(&mut carry);
+            let mut logical_first = if logical.is_empty() {
+                first
+            } else {
+                carry_first
+            };
+            for (idx, line) in lines.into_iter().enumerate() {
+                let wrapped = line.last_cell_was_wrapped();
+                logical.push(line);
+                if !wrapped {
+                    let refs: Vec<&Line> = logical.iter().collect();
+                    let range = logical_first..logical_first + refs.len() as StableRowIndex;
+                    if !f(range, &refs) {
+                        return false;
+                    }
+                    logical.clear();
+                    logical_first = first + idx as StableRowIndex + 1;
+                }
+            }
+            carry = logical;
+            carry_first = logical_first;
+        }
+
+        if chunk_start == self.end {
+            *tail = carry;
+            return true;
+        }
+        if !carry.is_empty() {
+            let refs: Vec<&Line> = carry.iter().collect();
+            let range = carry_first..carry_first + refs.len() as StableRowIndex;
+            return f(range, &refs);
+        }
+        true
+    }
+}
+
+#[cfg(feature = "use_serde")]
+fn encode_lines(lines: &[Line]) -> anyhow::Result<Vec<u8>> {
+    use serde::Serialize;
+    let mut data = vec![];
+    let mut encode = varbincode::Serializer::new(&mut data);
+    lines.serialize(&mut encode)?;
+    Ok(miniz_oxide::deflate::compress_to_vec(&data, 6))
+}
+
+#[cfg(feature = "use_serde")]
+fn decode_lines(data: &[u8]) -> anyhow::Result<Vec<Line>> {
+    let data = miniz_oxide::inflate::decompress_to_vec(data)
+        .map_err(|err| anyhow::anyhow!("decompressing scrollback block: {err:?}"))?;
+    let mut decode = varbincode::Deserializer::new(data.as_slice());
+    serde::Deserialize::deserialize(&mut decode).map_err(Into::into)
+}
+
+#[cfg(not(feature = "use_serde"))]
+fn encode_lines(_lines: &[Line]) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("spilling scrollback to disk requires the use_serde feature");
+}
+
+#[cfg(not(feature = "use_serde"))]
+fn decode_lines(_data: &[u8]) -> anyhow::Result<Vec<Line>> {
+    anyhow::bail!("spilling scrollback to disk requires the use_serde feature");
+}
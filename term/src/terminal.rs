@@ -16,6 +16,16 @@ pub trait Clipboard: Send + Sync {
         selection: ClipboardSelection,
         data: Option<String>,
     ) -> anyhow::Result<()>;
+
+    /// Called when the application has asked to read the contents
+    /// of the selection via OSC 52.  The implementation is expected
+    /// to arrange for `TerminalState::answer_clipboard_query` to be
+    /// called once the contents are known, or the user has declined
+    /// to share them.  Returning an error causes the query to be
+    /// answered with empty data.
+    fn query_contents(&self, _selection: ClipboardSelection) -> anyhow::Result<()> {
+        anyhow::bail!("reading the clipboard is not supported");
+    }
 }
 
 impl Clipboard for Box<dyn Clipboard> {
@@ -26,6 +36,10 @@ impl Clipboard for Box<dyn Clipboard> {
     ) -> anyhow::Result<()> {
         self.as_ref().set_contents(selection, data)
     }
+
+    fn query_contents(&self, selection: ClipboardSelection) -> anyhow::Result<()> {
+        self.as_ref().query_contents(selection)
+    }
 }
 
 pub trait DeviceControlHandler: Send + Sync {
@@ -71,6 +85,65 @@ pub enum Alert {
     OutputSinceFocusLost,
     /// A change to the progress bar state
     Progress(Progress),
+    /// A command, as delineated by OSC 133 semantic prompt markers,
+    /// has finished running
+    CommandFinished {
+        command: String,
+        exit_status: Option<i32>,
+        duration: std::time::Duration,
+    },
+    /// A desktop notification sent using kitty's OSC 99 protocol
+    DesktopNotification(DesktopNotification),
+    /// The application has asked to close the desktop notification
+    /// with the specified id
+    CloseDesktopNotification {
+        id: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub enum NotificationUrgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+/// Controls when a desktop notification should be displayed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub enum NotificationOccasion {
+    #[default]
+    Always,
+    /// Only when the pane that sent it is not focused
+    Unfocused,
+    /// Only when the pane that sent it is not focused and is
+    /// not visible
+    Invisible,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+pub struct DesktopNotification {
+    /// Identifies the notification in the reports that are
+    /// sent back to the application
+    pub id: Option<String>,
+    pub title: String,
+    pub body: String,
+    pub urgency: Option<NotificationUrgency>,
+    pub occasion: NotificationOccasion,
+    /// Whether activating the notification should focus the pane
+    /// that generated it
+    pub focus: bool,
+    /// Whether activating the notification should be reported
+    /// to the application
+    pub report_activation: bool,
+    /// Whether the closing of the notification should be reported
+    /// to the application
+    pub report_close: bool,
+    /// How long to display the notification before closing it;
+    /// None means that it is not closed automatically
+    pub timeout: Option<std::time::Duration>,
 }
 
 pub trait AlertHandler: Send + Sync {
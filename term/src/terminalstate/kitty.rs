@@ -1,21 +1,283 @@
 use crate::terminalstate::image::*;
 use crate::terminalstate::{ImageAttachParams, PlacementInfo};
-use crate::{StableRowIndex, TerminalState};
+use crate::{Line, StableRowIndex, TerminalState};
 use ::image::{
     DynamicImage, GenericImage, GenericImageView, ImageBuffer, RgbImage, Rgba, RgbaImage,
 };
 use anyhow::Context;
+use ordered_float::NotNan;
 use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::sync::Arc;
 use std::time::Duration;
-use wezterm_cell::image::ImageDataType;
+use wezterm_cell::color::ColorAttribute;
+use wezterm_cell::image::{ImageCell, ImageDataType};
+use wezterm_cell::{Cell, CellAttributes};
 use wezterm_escape_parser::apc::{
     KittyFrameCompositionMode, KittyImage, KittyImageCompression, KittyImageData, KittyImageDelete,
     KittyImageFormat, KittyImageFrame, KittyImageFrameCompose, KittyImagePlacement,
     KittyImageTransmit, KittyImageVerbosity,
 };
 use wezterm_surface::change::ImageData;
+use wezterm_surface::TextureCoordinate;
+
+/// Applications display an image that has a virtual placement (`U=1`)
+/// by printing this character.  The diacritics that follow it encode
+/// the row and column of the slice of the image that is displayed in
+/// that cell.
+/// <https://sw.kovidgoyal.net/kitty/graphics-protocol/#unicode-placeholders>
+const KITTY_PLACEHOLDER: char = '\u{10EEEE}';
+
+/// The combining characters that encode numbers in placeholder cells;
+/// the value of a diacritic is its index in this table.
+const PLACEHOLDER_DIACRITICS: [u32; 297] = [
+    0x0305, 0x030D, 0x030E, 0x0310, 0x0312, 0x033D, 0x033E, 0x033F, 0x0346, 0x034A, 0x034B, 0x034C,
+    0x0350, 0x0351, 0x0352, 0x0357, 0x035B, 0x0363, 0x0364, 0x0365, 0x0366, 0x0367, 0x0368, 0x0369,
+    0x036A, 0x036B, 0x036C, 0x036D, 0x036E, 0x036F, 0x0483, 0x0484, 0x0485, 0x0486, 0x0487, 0x0592,
+    0x0593, 0x0594, 0x0595, 0x0597, 0x0598, 0x0599, 0x059C, 0x059D, 0x059E, 0x059F, 0x05A0, 0x05A1,
+    0x05A8, 0x05A9, 0x05AB, 0x05AC, 0x05AF, 0x05C4, 0x0610, 0x0611, 0x0612, 0x0613, 0x0614, 0x0615,
+    0x0616, 0x0617, 0x0657, 0x0658, 0x0659, 0x065A, 0x065B, 0x065D, 0x065E, 0x06D6, 0x06D7, 0x06D8,
+    0x06D9, 0x06DA, 0x06DB, 0x06DC, 0x06DF, 0x06E0, 0x06E1, 0x06E2, 0x06E4, 0x06E7, 0x06E8, 0x06EB,
+    0x06EC, 0x0730, 0x0732, 0x0733, 0x0735, 0x0736, 0x073A, 0x073D, 0x073F, 0x0740, 0x0741, 0x0743,
+    0x0745, 0x0747, 0x0749, 0x074A, 0x07EB, 0x07EC, 0x07ED, 0x07EE, 0x07EF, 0x07F0, 0x07F1, 0x07F3,
+    0x0816, 0x0817, 0x0818, 0x0819, 0x081B, 0x081C, 0x081D, 0x081E, 0x081F, 0x0820, 0x0821, 0x0822,
+    0x0823, 0x0825, 0x0826, 0x0827, 0x0829, 0x082A, 0x082B, 0x082C, 0x082D, 0x0951, 0x0953, 0x0954,
+    0x0F82, 0x0F83, 0x0F86, 0x0F87, 0x135D, 0x135E, 0x135F, 0x17DD, 0x193A, 0x1A17, 0x1A75, 0x1A76,
+    0x1A77, 0x1A78, 0x1A79, 0x1A7A, 0x1A7B, 0x1A7C, 0x1B6B, 0x1B6D, 0x1B6E, 0x1B6F, 0x1B70, 0x1B71,
+    0x1B72, 0x1B73, 0x1CD0, 0x1CD1, 0x1CD2, 0x1CDA, 0x1CDB, 0x1CE0, 0x1DC0, 0x1DC1, 0x1DC3, 0x1DC4,
+    0x1DC5, 0x1DC6, 0x1DC7, 0x1DC8, 0x1DC9, 0x1DCB, 0x1DCC, 0x1DD1, 0x1DD2, 0x1DD3, 0x1DD4, 0x1DD5,
+    0x1DD6, 0x1DD7, 0x1DD8, 0x1DD9, 0x1DDA, 0x1DDB, 0x1DDC, 0x1DDD, 0x1DDE, 0x1DDF, 0x1DE0, 0x1DE1,
+    0x1DE2, 0x1DE3, 0x1DE4, 0x1DE5, 0x1DE6, 0x1DFE, 0x20D0, 0x20D1, 0x20D4, 0x20D5, 0x20D6, 0x20D7,
+    0x20DB, 0x20DC, 0x20E1, 0x20E7, 0x20E9, 0x20F0, 0x2CEF, 0x2CF0, 0x2CF1, 0x2DE0, 0x2DE1, 0x2DE2,
+    0x2DE3, 0x2DE4, 0x2DE5, 0x2DE6, 0x2DE7, 0x2DE8, 0x2DE9, 0x2DEA, 0x2DEB, 0x2DEC, 0x2DED, 0x2DEE,
+    0x2DEF, 0x2DF0, 0x2DF1, 0x2DF2, 0x2DF3, 0x2DF4, 0x2DF5, 0x2DF6, 0x2DF7, 0x2DF8, 0x2DF9, 0x2DFA,
+    0x2DFB, 0x2DFC, 0x2DFD, 0x2DFE, 0x2DFF, 0xA66F, 0xA67C, 0xA67D, 0xA6F0, 0xA6F1, 0xA8E0, 0xA8E1,
+    0xA8E2, 0xA8E3, 0xA8E4, 0xA8E5, 0xA8E6, 0xA8E7, 0xA8E8, 0xA8E9, 0xA8EA, 0xA8EB, 0xA8EC, 0xA8ED,
+    0xA8EE, 0xA8EF, 0xA8F0, 0xA8F1, 0xAAB0, 0xAAB2, 0xAAB3, 0xAAB7, 0xAAB8, 0xAABE, 0xAABF, 0xAAC1,
+    0xFE20, 0xFE21, 0xFE22, 0xFE23, 0xFE24, 0xFE25, 0xFE26, 0x10A0F, 0x10A38, 0x1D185, 0x1D186,
+    0x1D187, 0x1D188, 0x1D189, 0x1D1AA, 0x1D1AB, 0x1D1AC, 0x1D1AD, 0x1D242, 0x1D243, 0x1D244,
+];
+
+/// A placement created with `U=1`.  Rather than being attached to
+/// the cells at the cursor position, it is displayed wherever the
+/// application prints placeholder characters that reference it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VirtualPlacement {
+    columns: usize,
+    rows: usize,
+    source_x: u32,
+    source_y: u32,
+    source_width: Option<u32>,
+    source_height: Option<u32>,
+    z_index: i32,
+}
+
+/// The image, placement and slice of the image that is referenced
+/// by a placeholder cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PlaceholderCell {
+    x: usize,
+    image_id_low: Option<u32>,
+    placement_id: Option<u32>,
+    row: u32,
+    col: u32,
+    image_id_high: u32,
+}
+
+impl PlaceholderCell {
+    /// Decodes the placeholder `grapheme` in the cell at `x` with `attrs`.
+    /// Missing diacritics are inferred from the placeholder immediately
+    /// to the left, `prior`, if it references the same image.
+    fn decode(
+        grapheme: &str,
+        x: usize,
+        attrs: &CellAttributes,
+        prior: Option<PlaceholderCell>,
+    ) -> Self {
+        let mut diacritics = grapheme.chars().skip(1).map(|c| {
+            PLACEHOLDER_DIACRITICS
+                .binary_search(&(c as u32))
+                .ok()
+                .map(|v| v as u32)
+        });
+        let row = diacritics.next().flatten();
+        let col = diacritics.next().flatten();
+        let image_id_high = diacritics.next().flatten();
+
+        let image_id_low = placeholder_color_to_id(attrs.foreground());
+        let placement_id = placeholder_color_to_id(attrs.underline_color());
+
+        let prior = prior.filter(|prior| {
+            prior.x + 1 == x
+                && prior.image_id_low == image_id_low
+                && prior.placement_id == placement_id
+        });
+        let row = row.or(prior.map(|p| p.row)).unwrap_or(0);
+        let col = col
+            .or_else(|| prior.filter(|p| p.row == row).map(|p| p.col + 1))
+            .unwrap_or(0);
+        let image_id_high = image_id_high
+            .or_else(|| {
+                prior
+                    .filter(|p| p.row == row && p.col + 1 == col)
+                    .map(|p| p.image_id_high)
+            })
+            .unwrap_or(0);
+
+        Self {
+            x,
+            image_id_low,
+            placement_id,
+            row,
+            col,
+            image_id_high,
+        }
+    }
+
+    fn image_id(&self) -> Option<u32> {
+        self.image_id_low
+            .map(|low| (self.image_id_high << 24) | low)
+    }
+}
+
+/// Decodes an id from the foreground or underline color of
+/// a placeholder cell
+fn placeholder_color_to_id(color: ColorAttribute) -> Option<u32> {
+    match color {
+        ColorAttribute::PaletteIndex(idx) => Some(idx as u32),
+        ColorAttribute::TrueColorWithPaletteFallback(c, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(c) => {
+            let component = |v: f32| (v * 255.).round() as u32;
+            Some((component(c.0) << 16) | (component(c.1) << 8) | component(c.2))
+        }
+        ColorAttribute::Default => None,
+    }
+}
+
+/// Resolves the placeholder cells of virtual placements into the
+/// slices of the images that they display.
+/// The placeholder characters remain in the screen model; this is
+/// applied to copies of the lines as they are rendered.
+#[derive(Debug, Clone)]
+pub struct KittyPlaceholderResolver {
+    placements: HashMap<(u32, Option<u32>), (VirtualPlacement, Arc<ImageData>)>,
+    cell_width: f32,
+    cell_height: f32,
+}
+
+impl KittyPlaceholderResolver {
+    /// Returns a copy of `line` in which each placeholder cell is
+    /// replaced by a blank cell that displays its slice of the image,
+    /// or None if `line` doesn't hold any placeholders
+    pub fn resolve(&self, line: &Line) -> Option<Line> {
+        let placeholders: Vec<(usize, Cell)> = line
+            .visible_cells()
+            .filter(|cell| cell.str().starts_with(KITTY_PLACEHOLDER))
+            .map(|cell| (cell.cell_index(), cell.as_cell()))
+            .collect();
+        if placeholders.is_empty() {
+            return None;
+        }
+
+        let mut resolved = line.clone();
+        let seqno = resolved.current_seqno();
+        let mut prior: Option<PlaceholderCell> = None;
+        for (x, cell) in placeholders {
+            let mut attrs = cell.attrs().clone();
+            let placeholder = PlaceholderCell::decode(cell.str(), x, &attrs, prior);
+            prior.replace(placeholder);
+
+            if let Some(image) = placeholder
+                .image_id()
+                .and_then(|image_id| self.image_cell(image_id, &placeholder))
+            {
+                attrs.attach_image(Box::new(image));
+            }
+            // The placeholder is not meant to be seen; the cell
+            // shows a slice of an image in its place
+            resolved.set_cell(x, Cell::new(' ', attrs), seqno);
+        }
+        Some(resolved)
+    }
+
+    /// Computes the slice of the image with the virtual placement
+    /// referenced by `placeholder` that is displayed in its cell.
+    /// The source region of the image is scaled to fit the cells of
+    /// the placement, preserving its aspect ratio, and centered.
+    fn image_cell(&self, image_id: u32, placeholder: &PlaceholderCell) -> Option<ImageCell> {
+        let (placement_id, (placement, data)) = match placeholder.placement_id {
+            Some(p) => (Some(p), self.placements.get(&(image_id, Some(p)))?),
+            None => self
+                .placements
+                .iter()
+                .filter(|((id, _), _)| *id == image_id)
+                .min_by_key(|((_, p), _)| *p)
+                .map(|((_, p), v)| (*p, v))?,
+        };
+        let (row, col) = (placeholder.row, placeholder.col);
+
+        if row as usize >= placement.rows || col as usize >= placement.columns {
+            return None;
+        }
+
+        let (image_width, image_height) = data.data().dimensions().ok()?;
+        let (cell_width, cell_height) = (self.cell_width, self.cell_height);
+
+        let source_x = placement.source_x.min(image_width);
+        let source_y = placement.source_y.min(image_height);
+        let source_width = placement
+            .source_width
+            .unwrap_or(image_width)
+            .min(image_width - source_x) as f32;
+        let source_height = placement
+            .source_height
+            .unwrap_or(image_height)
+            .min(image_height - source_y) as f32;
+        if source_width == 0. || source_height == 0. {
+            return None;
+        }
+
+        let box_width = placement.columns as f32 * cell_width;
+        let box_height = placement.rows as f32 * cell_height;
+        let scale = (box_width / source_width).min(box_height / source_height);
+        let origin_x = (box_width - source_width * scale) / 2.;
+        let origin_y = (box_height - source_height * scale) / 2.;
+
+        let cell_left = col as f32 * cell_width;
+        let cell_top = row as f32 * cell_height;
+        let cell_right = cell_left + cell_width;
+        let cell_bottom = cell_top + cell_height;
+
+        let left = cell_left.max(origin_x);
+        let top = cell_top.max(origin_y);
+        let right = cell_right.min(origin_x + source_width * scale);
+        let bottom = cell_bottom.min(origin_y + source_height * scale);
+        if left >= right || top >= bottom {
+            // This cell is in the letterboxed area
+            return None;
+        }
+
+        let texture_x = |px: f32| {
+            NotNan::new((source_x as f32 + (px - origin_x) / scale) / image_width as f32).ok()
+        };
+        let texture_y = |px: f32| {
+            NotNan::new((source_y as f32 + (px - origin_y) / scale) / image_height as f32).ok()
+        };
+
+        Some(ImageCell::with_z_index(
+            TextureCoordinate::new(texture_x(left)?, texture_y(top)?),
+            TextureCoordinate::new(texture_x(right)?, texture_y(bottom)?),
+            Arc::clone(data),
+            placement.z_index,
+            (left - cell_left).round() as u16,
+            (top - cell_top).round() as u16,
+            (cell_right - right).round() as u16,
+            (cell_bottom - bottom).round() as u16,
+            Some(image_id),
+            placement_id,
+        ))
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct KittyImageState {
@@ -24,6 +286,7 @@ pub struct KittyImageState {
     number_to_id: HashMap<u32, u32>,
     id_to_data: HashMap<u32, Arc<ImageData>>,
     placements: HashMap<(u32, Option<u32>), PlacementInfo>,
+    virtual_placements: HashMap<(u32, Option<u32>), VirtualPlacement>,
     used_memory: usize,
 }
 
@@ -46,7 +309,12 @@ impl KittyImageState {
     fn prune_unreferenced(&mut self) {
         let budget = 320 * 1024 * 1024; // FIXME: make this configurable
         if self.used_memory > budget {
-            let referenced: HashSet<u32> = self.placements.keys().map(|(k, _)| *k).collect();
+            let referenced: HashSet<u32> = self
+                .placements
+                .keys()
+                .chain(self.virtual_placements.keys())
+                .map(|(k, _)| *k)
+                .collect();
             let target = self.used_memory - budget;
             let mut freed = 0;
             self.id_to_data.retain(|id, data| {
@@ -113,6 +381,32 @@ impl TerminalState {
 
         let (image_width, image_height) = img.data().dimensions()?;
 
+        if placement.unicode_placeholder {
+            let (columns, rows) =
+                self.kitty_virtual_placement_size(&placement, image_width, image_height);
+            self.kitty_img.virtual_placements.insert(
+                (image_id, placement.placement_id),
+                VirtualPlacement {
+                    columns,
+                    rows,
+                    source_x: placement.x.unwrap_or(0),
+                    source_y: placement.y.unwrap_or(0),
+                    source_width: placement.w,
+                    source_height: placement.h,
+                    z_index: placement.z_index.unwrap_or(0),
+                },
+            );
+            log::trace!(
+                "record virtual placement for {} {:?}: {}x{} cells",
+                image_id,
+                placement.placement_id,
+                columns,
+                rows
+            );
+            self.kitty_invalidate_placeholders();
+            return Ok(());
+        }
+
         let info = self.assign_image_to_cells(ImageAttachParams {
             image_width,
             image_height,
@@ -145,6 +439,82 @@ impl TerminalState {
         Ok(())
     }
 
+    /// Computes the size in cells of a virtual placement.
+    /// If the application didn't specify it, it is derived from
+    /// the size of the image.
+    fn kitty_virtual_placement_size(
+        &self,
+        placement: &KittyImagePlacement,
+        image_width: u32,
+        image_height: u32,
+    ) -> (usize, usize) {
+        let cell_pixel_width = (self.pixel_width / self.screen().physical_cols).max(1);
+        let cell_pixel_height = (self.pixel_height / self.screen().physical_rows).max(1);
+        let source_width = placement
+            .w
+            .unwrap_or(image_width)
+            .min(image_width.saturating_sub(placement.x.unwrap_or(0)))
+            as usize;
+        let source_height = placement
+            .h
+            .unwrap_or(image_height)
+            .min(image_height.saturating_sub(placement.y.unwrap_or(0)))
+            as usize;
+        let columns = placement
+            .columns
+            .map(|c| c as usize)
+            .unwrap_or_else(|| (source_width + cell_pixel_width - 1) / cell_pixel_width);
+        let rows = placement
+            .rows
+            .map(|r| r as usize)
+            .unwrap_or_else(|| (source_height + cell_pixel_height - 1) / cell_pixel_height);
+        (columns.max(1), rows.max(1))
+    }
+
+    /// Returns a resolver for the placeholder cells of the virtual
+    /// placements, or None if there are no virtual placements
+    pub fn kitty_placeholder_resolver(&self) -> Option<KittyPlaceholderResolver> {
+        if self.kitty_img.virtual_placements.is_empty() {
+            return None;
+        }
+        let cell_width = (self.pixel_width / self.screen().physical_cols) as f32;
+        let cell_height = (self.pixel_height / self.screen().physical_rows) as f32;
+        if cell_width == 0. || cell_height == 0. {
+            return None;
+        }
+        let placements = self
+            .kitty_img
+            .virtual_placements
+            .iter()
+            .filter_map(|(key, placement)| {
+                let data = self.kitty_img.id_to_data.get(&key.0)?;
+                Some((*key, (*placement, Arc::clone(data))))
+            })
+            .collect();
+        Some(KittyPlaceholderResolver {
+            placements,
+            cell_width,
+            cell_height,
+        })
+    }
+
+    /// The placeholder cells are resolved as they are rendered, so when
+    /// a virtual placement changes, the lines that hold placeholders
+    /// need to be marked as changed in order to be rendered again.
+    /// Those lines can be anywhere on the screen, or in the scrollback,
+    /// so we need to examine every line.
+    fn kitty_invalidate_placeholders(&mut self) {
+        let seqno = self.seqno;
+        self.screen_mut().for_each_phys_line_mut(|_, line| {
+            if line
+                .visible_cells()
+                .any(|cell| cell.str().starts_with(KITTY_PLACEHOLDER))
+            {
+                line.update_last_change_seqno(seqno);
+            }
+        });
+    }
+
     fn kitty_img_inner(&mut self, img: KittyImage) -> anyhow::Result<()> {
         match self
             .coalesce_kitty_accumulation(img)
@@ -310,6 +680,14 @@ impl TerminalState {
     }
 
     fn kitty_remove_placement(&mut self, image_id: u32, placement_id: Option<u32>) {
+        let num_virtual = self.kitty_img.virtual_placements.len();
+        self.kitty_img
+            .virtual_placements
+            .retain(|(id, p), _| *id != image_id || (placement_id.is_some() && *p != placement_id));
+        if self.kitty_img.virtual_placements.len() != num_virtual {
+            self.kitty_invalidate_placeholders();
+        }
+
         if placement_id.is_some() {
             if let Some(info) = self.kitty_img.placements.remove(&(image_id, placement_id)) {
                 log::trace!("removed placement {} {:?}", image_id, placement_id);
@@ -341,6 +719,10 @@ impl TerminalState {
         for ((image_id, p), info) in std::mem::take(&mut self.kitty_img.placements).into_iter() {
             self.kitty_remove_placement_from_model(image_id, p, info);
         }
+        if !self.kitty_img.virtual_placements.is_empty() {
+            self.kitty_img.virtual_placements.clear();
+            self.kitty_invalidate_placeholders();
+        }
         if delete {
             self.kitty_img.id_to_data.clear();
             self.kitty_img.used_memory = 0;
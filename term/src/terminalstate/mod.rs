@@ -18,22 +18,32 @@ use wezterm_bidi::ParagraphDirectionHint;
 use wezterm_cell::image::ImageData;
 use wezterm_cell::UnicodeVersion;
 use wezterm_escape_parser::csi::{
-    Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Device, Edit, EraseInDisplay,
-    EraseInLine, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode, Window, XtSmGraphics,
-    XtSmGraphicsAction, XtSmGraphicsItem, XtSmGraphicsStatus, XtermKeyModifierResource,
+    ColorScheme, Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Device, Edit,
+    EraseInDisplay, EraseInLine, Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode,
+    Window, XtSmGraphics, XtSmGraphicsAction, XtSmGraphicsItem, XtSmGraphicsStatus,
+    XtermKeyModifierResource,
 };
+use wezterm_escape_parser::osc::Selection;
 use wezterm_escape_parser::{OneBased, OperatingSystemCommand, CSI};
-use wezterm_surface::{CursorShape, CursorVisibility, SequenceNo};
+use wezterm_surface::{CursorShape, CursorVisibility, SequenceNo, SEQ_ZERO};
 
 mod image;
 mod iterm;
 mod keyboard;
 mod kitty;
 mod mouse;
+mod notification;
 pub(crate) mod performer;
+mod rectangle;
 mod sixel;
+mod stack;
+mod status;
+mod textsize;
 use crate::terminalstate::image::*;
+pub use crate::terminalstate::kitty::KittyPlaceholderResolver;
 use crate::terminalstate::kitty::*;
+use crate::terminalstate::notification::PendingNotification;
+use crate::terminalstate::stack::{ColorStack, SavedSgr};
 
 lazy_static::lazy_static! {
     static ref DB: Database = {
@@ -138,7 +148,6 @@ pub(crate) struct SavedCursor {
     dec_origin_mode: bool,
     g0_charset: CharSet,
     g1_charset: CharSet,
-    // TODO: selective_erase when supported
 }
 
 struct ScreenOrAlt {
@@ -273,6 +282,10 @@ pub struct TerminalState {
     /// Reverse video mode
     reverse_video_mode: bool,
 
+    /// DECSACE; when true, DECCARA and DECRARA apply to a rectangle
+    /// rather than to a stream of character positions
+    rectangular_attribute_change: bool,
+
     /// https://vt100.net/docs/vt510-rm/DECOM.html
     /// When OriginMode is enabled, cursor is constrained to the
     /// scroll region and its position is relative to the scroll
@@ -311,6 +324,21 @@ pub struct TerminalState {
     /// Movement events enabled
     any_event_mouse: bool,
     focus_tracking: bool,
+    /// DECSET 2031: report changes to the color scheme
+    color_scheme_updates: bool,
+    /// DEC private modes saved by XTSAVE, keyed by their number
+    saved_dec_private_modes: HashMap<u16, bool>,
+    /// DECSET 2026: the application is part way through a
+    /// synchronized update.  The output is held back by the mux
+    /// until the update is complete; we only track it so that it
+    /// can be reported.
+    synchronized_output: bool,
+    /// The seqno at which the most recent synchronized update
+    /// was completed
+    synchronized_update_seqno: SequenceNo,
+    /// The color scheme of the effective palette, as of the last
+    /// time that it was checked
+    color_scheme: ColorScheme,
     /// X10 (legacy), SGR, and SGR-Pixels style mouse tracking and
     /// reporting is enabled
     mouse_encoding: MouseEncoding,
@@ -338,6 +366,10 @@ pub struct TerminalState {
     progress: Progress,
 
     palette: Option<ColorPalette>,
+    /// XTPUSHCOLORS/XTPOPCOLORS
+    color_stack: ColorStack,
+    /// XTPUSHSGR/XTPOPSGR
+    sgr_stack: Vec<SavedSgr>,
 
     pixel_width: usize,
     pixel_height: usize,
@@ -360,6 +392,14 @@ pub struct TerminalState {
 
     user_vars: HashMap<String, String>,
 
+    /// OSC 99 notifications that are being received in chunks,
+    /// keyed by their id
+    pending_notifications: HashMap<String, PendingNotification>,
+
+    /// Set once OSC 66 has placed a text sized block, so that printing
+    /// only needs to check for overlapping blocks after that point
+    text_sizing_used: bool,
+
     kitty_img: KittyImageState,
     seqno: SequenceNo,
 
@@ -527,6 +567,7 @@ impl TerminalState {
             dec_auto_wrap: true,
             reverse_wraparound_mode: false,
             reverse_video_mode: false,
+            rectangular_attribute_change: false,
             dec_origin_mode: false,
             insert: false,
             application_cursor_keys: false,
@@ -538,6 +579,11 @@ impl TerminalState {
             application_keypad: false,
             bracketed_paste: false,
             focus_tracking: false,
+            color_scheme_updates: false,
+            color_scheme: ColorScheme::Dark,
+            saved_dec_private_modes: HashMap::new(),
+            synchronized_output: false,
+            synchronized_update_seqno: SEQ_ZERO,
             mouse_encoding: MouseEncoding::X10,
             keyboard_encoding: KeyboardEncoding::Xterm,
             sixel_scrolls_right: false,
@@ -555,6 +601,8 @@ impl TerminalState {
             title: "wezterm".to_string(),
             icon_title: None,
             palette: None,
+            color_stack: ColorStack::default(),
+            sgr_stack: vec![],
             pixel_height: size.pixel_height,
             pixel_width: size.pixel_width,
             dpi: size.dpi,
@@ -568,6 +616,8 @@ impl TerminalState {
             writer,
             image_cache: lru::LruCache::new(NonZeroUsize::new(16).unwrap()),
             user_vars: HashMap::new(),
+            pending_notifications: HashMap::new(),
+            text_sizing_used: false,
             kitty_img: Default::default(),
             seqno,
             unicode_version,
@@ -597,8 +647,17 @@ impl TerminalState {
         self.seqno += 1;
     }
 
+    /// Returns the seqno at which the application most recently
+    /// completed a synchronized update (DECSET 2026), so that
+    /// consumers can tell whether the changes since a given seqno
+    /// should be presented together.
+    pub fn synchronized_update_seqno(&self) -> SequenceNo {
+        self.synchronized_update_seqno
+    }
+
     pub fn set_config(&mut self, config: Arc<dyn TerminalConfiguration>) {
         self.config = config;
+        self.check_color_scheme_change();
     }
 
     pub fn get_config(&self) -> Arc<dyn TerminalConfiguration> {
@@ -715,6 +774,27 @@ impl TerminalState {
         Ok(())
     }
 
+    fn query_clipboard_contents(&self, selection: ClipboardSelection) -> anyhow::Result<()> {
+        match self.clipboard.as_ref() {
+            Some(clip) => clip.query_contents(selection),
+            None => anyhow::bail!("no clipboard is associated with this terminal"),
+        }
+    }
+
+    /// Respond to an OSC 52 clipboard query that was previously passed
+    /// to `Clipboard::query_contents`.  `None` indicates that the
+    /// contents are unavailable, or that the user declined to share
+    /// them, and results in an empty response.
+    pub fn answer_clipboard_query(&mut self, selection: ClipboardSelection, text: Option<String>) {
+        let selection = match selection {
+            ClipboardSelection::Clipboard => Selection::CLIPBOARD,
+            ClipboardSelection::PrimarySelection => Selection::PRIMARY,
+        };
+        let response = OperatingSystemCommand::SetSelection(selection, text.unwrap_or_default());
+        write!(self.writer, "{}", response).ok();
+        self.writer.flush().ok();
+    }
+
     pub fn erase_scrollback_and_viewport(&mut self) {
         // Since we may be called outside of perform_actions,
         // we need to ensure that we increment the seqno in
@@ -929,6 +1009,38 @@ impl TerminalState {
         if let Some(handler) = self.alert_handler.as_mut() {
             handler.alert(Alert::PaletteChanged);
         }
+        self.check_color_scheme_change();
+    }
+
+    /// Classifies the effective palette as dark or light, based on
+    /// the lightness of its background color
+    fn effective_color_scheme(&self) -> ColorScheme {
+        let (lightness, _a, _b, _alpha) = self.palette().background.to_laba();
+        if lightness < 50. {
+            ColorScheme::Dark
+        } else {
+            ColorScheme::Light
+        }
+    }
+
+    /// Called when the effective palette may have changed.
+    /// If its color scheme is different from the last time that we
+    /// checked, report it to the application if it has asked for that
+    /// via DECSET 2031.
+    fn check_color_scheme_change(&mut self) {
+        let scheme = self.effective_color_scheme();
+        if scheme != self.color_scheme {
+            self.color_scheme = scheme;
+            if self.color_scheme_updates {
+                self.report_color_scheme();
+            }
+        }
+    }
+
+    fn report_color_scheme(&mut self) {
+        let dev = Device::ColorSchemeReport(self.color_scheme);
+        write!(self.writer, "\x1b[{}", dev).ok();
+        self.writer.flush().ok();
     }
 
     /// When dealing with selection, mark a range of lines as dirty
@@ -1257,32 +1369,55 @@ impl TerminalState {
                 }
             }
             Device::SoftReset => {
-                // TODO: see https://vt100.net/docs/vt510-rm/DECSTR.html
-                self.pen = CellAttributes::default();
+                // DECSTR resets the state listed in the table at
+                // https://vt100.net/docs/vt510-rm/DECSTR.html
+                // Entries for features that we don't implement (DECNRCM,
+                // KAM, DECAUPSS, DECSASD, DECKPM, DECRLM and DECPCTERM)
+                // have been omitted.
+
+                // DECTCEM: cursor enabled
+                self.cursor_visible = true;
+                // IRM: replace
                 self.insert = false;
+                // DECOM: absolute
                 self.dec_origin_mode = false;
+                // DECAWM: no autowrap.
                 // Note that xterm deviates from the documented DECSTR
                 // setting for dec_auto_wrap, so we do too
                 self.dec_auto_wrap = true;
-                self.application_cursor_keys = false;
-                self.modify_other_keys = None;
+                // DECNKM: numeric characters
                 self.application_keypad = false;
+                // DECCKM: normal
+                self.application_cursor_keys = false;
+                // DECSTBM: top margin = 1; bottom margin = page length
                 self.top_and_bottom_margins = 0..self.screen().physical_rows as i64;
+                // G0, G1, G2, and G3: designated as ASCII, with G0 in GL
+                self.g0_charset = CharSet::Ascii;
+                self.g1_charset = CharSet::Ascii;
+                self.shift_out = false;
+                // SGR: normal rendition, and
+                // DECSCA: normal (erasable by DECSEL and DECSED)
+                self.pen = CellAttributes::default();
+                // DECSC: home position, for both the primary and
+                // alternate screens
+                self.screen.screen.saved_cursor.take();
+                self.screen.alt_screen.saved_cursor.take();
+
+                // The following are not described by the VT510
+                // reference, but are reset by xterm, or are
+                // extensions that an application would expect
+                // to be reset along with the above.
+                self.modify_other_keys = None;
                 self.left_and_right_margins = 0..self.screen().physical_cols;
                 self.left_and_right_margin_mode = false;
-                self.screen.activate_alt_screen(self.seqno);
-                self.screen.saved_cursor().take();
-                self.screen.activate_primary_screen(self.seqno);
-                self.screen.saved_cursor().take();
                 self.kitty_remove_all_placements(true);
-
                 self.reverse_wraparound_mode = false;
                 self.reverse_video_mode = false;
+                self.rectangular_attribute_change = false;
                 self.bidi_enabled.take();
                 self.bidi_hint.take();
-
-                self.g0_charset = CharSet::Ascii;
-                self.g1_charset = CharSet::Ascii;
+                // The mux ends any synchronized update, too
+                self.synchronized_output = false;
             }
             Device::RequestPrimaryDeviceAttributes => {
                 let mut ident = "\x1b[?65".to_string(); // Vt500
@@ -1290,6 +1425,7 @@ impl TerminalState {
                 ident.push_str(";6"); // Selective erase
                 ident.push_str(";18"); // windowing extensions
                 ident.push_str(";22"); // ANSI color, vt525
+                ident.push_str(";28"); // Rectangular editing
                 ident.push_str(";52"); // Clipboard access
                 ident.push('c');
 
@@ -1336,6 +1472,15 @@ impl TerminalState {
                 self.writer.write(b"\x1b[0n").ok();
                 self.writer.flush().ok();
             }
+            Device::RequestColorScheme => {
+                self.color_scheme = self.effective_color_scheme();
+                self.report_color_scheme();
+            }
+            Device::ColorSchemeReport(scheme) => {
+                if self.config.log_unknown_escape_sequences() {
+                    log::warn!("unhandled: ColorSchemeReport({:?})", scheme);
+                }
+            }
             Device::XtSmGraphics(g) => {
                 let response = if matches!(g.item, XtSmGraphicsItem::Unspecified(_)) {
                     XtSmGraphics {
@@ -1573,19 +1718,21 @@ impl TerminalState {
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::SynchronizedOutput,
             )) => {
-                // This is handled in wezterm's mux
+                // Holding back the output is handled in wezterm's mux
+                self.synchronized_output = true;
             }
             Mode::ResetDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::SynchronizedOutput,
             )) => {
-                // This is handled in wezterm's mux
+                if self.synchronized_output {
+                    self.synchronized_output = false;
+                    self.synchronized_update_seqno = self.seqno;
+                }
             }
             Mode::QueryDecPrivateMode(DecPrivateMode::Code(
                 DecPrivateModeCode::SynchronizedOutput,
             )) => {
-                // This is handled in wezterm's mux; if we get here, then it isn't enabled,
-                // so we always report false
-                self.decqrm_response(mode, true, false);
+                self.decqrm_response(mode, true, self.synchronized_output);
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SmoothScroll))
@@ -1815,6 +1962,23 @@ impl TerminalState {
                 self.decqrm_response(mode, true, self.focus_tracking);
             }
 
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::ColorSchemeUpdates,
+            )) => {
+                self.color_scheme_updates = true;
+                self.color_scheme = self.effective_color_scheme();
+            }
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::ColorSchemeUpdates,
+            )) => {
+                self.color_scheme_updates = false;
+            }
+            Mode::QueryDecPrivateMode(DecPrivateMode::Code(
+                DecPrivateModeCode::ColorSchemeUpdates,
+            )) => {
+                self.decqrm_response(mode, true, self.color_scheme_updates);
+            }
+
             Mode::SetDecPrivateMode(DecPrivateMode::Code(DecPrivateModeCode::SGRMouse)) => {
                 self.mouse_encoding = MouseEncoding::SGR;
                 self.last_mouse_move.take();
@@ -1906,9 +2070,11 @@ impl TerminalState {
                     self.dec_restore_cursor();
                 }
             }
-            Mode::SaveDecPrivateMode(DecPrivateMode::Code(n))
-            | Mode::RestoreDecPrivateMode(DecPrivateMode::Code(n)) => {
-                log::warn!("save/restore dec mode {:?} unimplemented", n)
+            Mode::SaveDecPrivateMode(DecPrivateMode::Code(code)) => {
+                self.save_dec_private_mode(code);
+            }
+            Mode::RestoreDecPrivateMode(DecPrivateMode::Code(code)) => {
+                self.restore_dec_private_mode(code);
             }
 
             Mode::SetDecPrivateMode(DecPrivateMode::Code(
@@ -1964,6 +2130,13 @@ impl TerminalState {
                 }
             }
 
+            Mode::QueryDecPrivateMode(DecPrivateMode::Code(ref code)) => {
+                match self.dec_private_mode_state(code) {
+                    Some(enabled) => self.decqrm_response(mode, true, enabled),
+                    None => self.decqrm_response(mode, false, false),
+                }
+            }
+
             Mode::QueryDecPrivateMode(_) | Mode::QueryMode(_) => {
                 self.decqrm_response(mode, false, false);
             }
@@ -2256,6 +2429,43 @@ impl TerminalState {
             Edit::ScrollDown(n) => self.scroll_down(n as usize),
             Edit::ScrollUp(n) => self.scroll_up(n as usize),
             Edit::EraseInDisplay(erase) => self.erase_in_display(erase),
+            Edit::SelectiveEraseInLine(erase) => {
+                let cx = self.cursor.x;
+                let cy = self.cursor.y as usize;
+                let cols = self.screen().physical_cols;
+                let range = match erase {
+                    EraseInLine::EraseToEndOfLine => cx..cols,
+                    EraseInLine::EraseToStartOfLine => 0..cx + 1,
+                    EraseInLine::EraseLine => 0..cols,
+                };
+                self.selective_erase(cy..cy + 1, range);
+            }
+            Edit::SelectiveEraseInDisplay(erase) => {
+                let cy = self.cursor.y as usize;
+                let rows = self.screen().physical_rows;
+                let cols = self.screen().physical_cols;
+                match erase {
+                    EraseInDisplay::EraseToEndOfDisplay => {
+                        self.perform_csi_edit(Edit::SelectiveEraseInLine(
+                            EraseInLine::EraseToEndOfLine,
+                        ));
+                        self.selective_erase(cy + 1..rows, 0..cols);
+                    }
+                    EraseInDisplay::EraseToStartOfDisplay => {
+                        self.perform_csi_edit(Edit::SelectiveEraseInLine(
+                            EraseInLine::EraseToStartOfLine,
+                        ));
+                        self.selective_erase(0..cy, 0..cols);
+                    }
+                    EraseInDisplay::EraseDisplay => self.selective_erase(0..rows, 0..cols),
+                    // There is no selective counterpart to erasing
+                    // the scrollback
+                    EraseInDisplay::EraseScrollback => {}
+                }
+            }
+            Edit::SelectCharacterProtection(protected) => {
+                self.pen.set_protected(protected);
+            }
             Edit::Repeat(n) => {
                 let mut y = self.cursor.y;
                 let mut x = self.cursor.x;
@@ -2653,9 +2863,12 @@ impl TerminalState {
             Sgr::Reset => {
                 let link = self.pen.hyperlink().map(Arc::clone);
                 let semantic_type = self.pen.semantic_type();
+                // DECSCA protection is not a graphic rendition
+                let protected = self.pen.protected();
                 self.pen = CellAttributes::default();
                 self.pen.set_hyperlink(link);
                 self.pen.set_semantic_type(semantic_type);
+                self.pen.set_protected(protected);
             }
             Sgr::Intensity(intensity) => {
                 self.pen.set_intensity(intensity);
@@ -2749,6 +2962,50 @@ impl TerminalState {
         Ok(zones)
     }
 
+    /// Returns the commands that have been run in the current screen,
+    /// as reported by the shell via OSC 133 semantic prompt markers.
+    /// The blocks are ordered from oldest to newest.
+    pub fn get_command_blocks(&mut self) -> Vec<CommandBlock> {
+        self.screen_mut().command_blocks()
+    }
+
+    /// Returns the commands that have rows within `range`
+    pub fn get_command_blocks_in_range(
+        &mut self,
+        range: Range<StableRowIndex>,
+    ) -> Vec<CommandBlock> {
+        self.screen_mut().command_blocks_in_range(range)
+    }
+
+    /// Returns the output text of the command with the specified id
+    pub fn get_command_output(&self, id: usize) -> Option<String> {
+        self.screen().command_output_text(id)
+    }
+
+    /// Returns the rows of command output that have been collapsed
+    pub fn get_collapsed_output_ranges(&self) -> Vec<Range<StableRowIndex>> {
+        self.screen().collapsed_output_ranges()
+    }
+
+    /// Collapse or expand the output of the command with the specified id
+    pub fn set_command_block_collapsed(
+        &mut self,
+        id: usize,
+        collapsed: bool,
+    ) -> anyhow::Result<()> {
+        // Since we may be called outside of perform_actions,
+        // we need to ensure that we increment the seqno in
+        // order to correctly invalidate the display
+        self.increment_seqno();
+        let seqno = self.seqno;
+        self.screen_mut()
+            .set_command_block_collapsed(id, collapsed, seqno)
+    }
+
+    fn cursor_stable_row(&self) -> StableRowIndex {
+        self.screen().visible_row_to_stable_row(self.cursor.y)
+    }
+
     #[inline]
     pub fn get_reverse_video(&self) -> bool {
         self.reverse_video_mode
@@ -0,0 +1,170 @@
+//! Implements kitty's desktop notification protocol (OSC 99)
+//! <https://sw.kovidgoyal.net/kitty/desktop-notifications/>
+use crate::{Alert, DesktopNotification, NotificationOccasion, NotificationUrgency, TerminalState};
+use std::io::Write;
+use std::time::Duration;
+use wezterm_escape_parser::osc::{
+    KittyNotification, KittyNotificationOccasion, KittyNotificationPayload,
+    KittyNotificationUrgency,
+};
+use wezterm_escape_parser::OperatingSystemCommand;
+
+/// Limits the size of the title and body of a notification that is
+/// sent in chunks, so that a misbehaving application cannot consume
+/// an unbounded amount of memory
+const MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+/// Limits the number of notifications that can be in the process
+/// of being assembled from chunks
+const MAX_PENDING_NOTIFICATIONS: usize = 32;
+
+/// What we report in response to a `p=?` query
+const CAPABILITIES: &[u8] =
+    b"a=focus,report:c=1:o=always,unfocused,invisible:p=title,body,?,close:u=0,1,2:w=1";
+
+/// Returns true if `id` is a valid notification identifier.
+/// The protocol restricts identifiers to these characters, so that
+/// they can be safely echoed back in reports.
+fn is_valid_id(id: &str) -> bool {
+    id.chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '.'))
+}
+
+/// A notification that is being assembled from chunks
+#[derive(Debug, Default)]
+pub(crate) struct PendingNotification {
+    metadata: KittyNotification,
+    title: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl PendingNotification {
+    fn merge(&mut self, chunk: &KittyNotification) {
+        let metadata = &mut self.metadata;
+        if chunk.focus.is_some() {
+            metadata.focus = chunk.focus;
+        }
+        if chunk.report.is_some() {
+            metadata.report = chunk.report;
+        }
+        if chunk.occasion.is_some() {
+            metadata.occasion = chunk.occasion;
+        }
+        if chunk.urgency.is_some() {
+            metadata.urgency = chunk.urgency;
+        }
+        if chunk.report_close.is_some() {
+            metadata.report_close = chunk.report_close;
+        }
+        if chunk.expire_ms.is_some() {
+            metadata.expire_ms = chunk.expire_ms;
+        }
+
+        let payload = match chunk.payload_type {
+            KittyNotificationPayload::Title => &mut self.title,
+            KittyNotificationPayload::Body => &mut self.body,
+            // Icons and buttons are not supported
+            _ => return,
+        };
+        let room = MAX_PAYLOAD_SIZE.saturating_sub(payload.len());
+        payload.extend_from_slice(&chunk.payload[..chunk.payload.len().min(room)]);
+    }
+
+    fn into_notification(self, id: Option<String>) -> DesktopNotification {
+        let metadata = self.metadata;
+        DesktopNotification {
+            id,
+            title: String::from_utf8_lossy(&self.title).into_owned(),
+            body: String::from_utf8_lossy(&self.body).into_owned(),
+            urgency: metadata.urgency.map(|urgency| match urgency {
+                KittyNotificationUrgency::Low => NotificationUrgency::Low,
+                KittyNotificationUrgency::Normal => NotificationUrgency::Normal,
+                KittyNotificationUrgency::Critical => NotificationUrgency::Critical,
+            }),
+            occasion: match metadata.occasion.unwrap_or_default() {
+                KittyNotificationOccasion::Always => NotificationOccasion::Always,
+                KittyNotificationOccasion::Unfocused => NotificationOccasion::Unfocused,
+                KittyNotificationOccasion::Invisible => NotificationOccasion::Invisible,
+            },
+            focus: metadata.focus.unwrap_or(true),
+            report_activation: metadata.report.unwrap_or(false),
+            report_close: metadata.report_close.unwrap_or(false),
+            // -1 means the system default and 0 means never expire,
+            // both of which we treat as not closing it ourselves
+            timeout: match metadata.expire_ms {
+                Some(ms) if ms > 0 => Some(Duration::from_millis(ms as u64)),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl TerminalState {
+    pub(crate) fn kitty_notification(&mut self, chunk: KittyNotification) {
+        if let Some(id) = &chunk.id {
+            if !is_valid_id(id) {
+                log::warn!("ignoring OSC 99 notification with invalid id {:?}", id);
+                return;
+            }
+        }
+        let key = chunk.id.clone().unwrap_or_default();
+
+        match chunk.payload_type {
+            KittyNotificationPayload::Query => {
+                let response =
+                    OperatingSystemCommand::KittyNotification(Box::new(KittyNotification {
+                        id: chunk.id,
+                        payload_type: KittyNotificationPayload::Query,
+                        payload: CAPABILITIES.to_vec(),
+                        ..Default::default()
+                    }));
+                write!(self.writer, "{}", response).ok();
+                self.writer.flush().ok();
+                return;
+            }
+            KittyNotificationPayload::Close => {
+                self.pending_notifications.remove(&key);
+                if let (Some(id), Some(handler)) = (chunk.id, self.alert_handler.as_mut()) {
+                    handler.alert(Alert::CloseDesktopNotification { id });
+                }
+                return;
+            }
+            KittyNotificationPayload::Alive => {
+                // We don't advertise support for this query
+                return;
+            }
+            _ => {}
+        }
+
+        if !self.pending_notifications.contains_key(&key)
+            && self.pending_notifications.len() >= MAX_PENDING_NOTIFICATIONS
+        {
+            log::warn!(
+                "too many incomplete OSC 99 notifications; ignoring {:?}",
+                key
+            );
+            return;
+        }
+        self.pending_notifications
+            .entry(key.clone())
+            .or_default()
+            .merge(&chunk);
+
+        if chunk.more_chunks {
+            return;
+        }
+
+        let notification = match self.pending_notifications.remove(&key) {
+            Some(pending) => pending.into_notification(chunk.id),
+            None => return,
+        };
+        if notification.title.is_empty() && notification.body.is_empty() {
+            return;
+        }
+
+        if let Some(handler) = self.alert_handler.as_mut() {
+            handler.alert(Alert::DesktopNotification(notification));
+        } else {
+            log::info!("Application sends DesktopNotification: {:?}", notification);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use crate::terminal::{Alert, Progress};
 use crate::terminalstate::{
     default_color_map, CharSet, MouseEncoding, TabStop, UnicodeVersionStackEntry,
 };
-use crate::{ClipboardSelection, Position, TerminalState, VisibleRowIndex, DCS, ST};
+use crate::{ClipboardSelection, Position, TerminalState, VisibleRowIndex};
 use finl_unicode::grapheme_clusters::Graphemes;
 use log::{debug, error};
 use num_traits::FromPrimitive;
@@ -10,6 +10,7 @@ use ordered_float::NotNan;
 use std::fmt::Write;
 use std::io::Write as _;
 use std::ops::{Deref, DerefMut};
+use std::time::SystemTime;
 use termwiz::input::KeyboardEncoding;
 use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
 use url::Url;
@@ -21,7 +22,7 @@ use wezterm_escape_parser::csi::{
     CharacterPath, EraseInDisplay, Keyboard, KittyKeyboardFlags, KittyKeyboardMode,
 };
 use wezterm_escape_parser::osc::{
-    ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermProprietary,
+    ChangeColorPair, ColorOrQuery, FinalTermPromptKind, FinalTermSemanticPrompt, ITermProprietary,
     ITermUnicodeVersionOp, Selection,
 };
 use wezterm_escape_parser::{
@@ -202,6 +203,8 @@ impl<'a> Performer<'a> {
 
             let wrappable = x + print_width >= width;
 
+            self.clear_text_sized_blocks(y, x..x + print_width);
+
             if self.insert {
                 let margin = self.left_and_right_margins.end;
                 let screen = self.screen_mut();
@@ -249,6 +252,54 @@ impl<'a> Performer<'a> {
         }
     }
 
+    fn start_command_prompt(&mut self) {
+        let row = self.cursor_stable_row();
+        self.screen_mut()
+            .command_blocks
+            .start_prompt(row, SystemTime::now());
+    }
+
+    fn start_command_input(&mut self) {
+        let row = self.cursor_stable_row();
+        self.screen_mut().command_blocks.start_input(row);
+    }
+
+    fn start_command_output(&mut self) {
+        let row = self.cursor_stable_row();
+        let input_start = self
+            .screen()
+            .command_blocks
+            .iter()
+            .last()
+            .filter(|block| !block.has_run())
+            .map(|block| block.input_start.unwrap_or(block.prompt_start))
+            .unwrap_or(row);
+        let command = self
+            .screen()
+            .semantic_text_in_stable_range(input_start..row + 1, SemanticType::Input);
+        self.screen_mut()
+            .command_blocks
+            .start_output(row, command, SystemTime::now());
+    }
+
+    fn finish_command(&mut self, status: i32) {
+        let row = self.cursor_stable_row();
+        // The output ends at the cursor, unless the cursor is part way
+        // along a line that holds output
+        let end_row = if self.cursor.x == 0 { row } else { row + 1 };
+        let block =
+            self.screen_mut()
+                .command_blocks
+                .finish(end_row, Some(status), SystemTime::now());
+        if let (Some(block), Some(handler)) = (block, self.alert_handler.as_mut()) {
+            handler.alert(Alert::CommandFinished {
+                duration: block.duration().unwrap_or_default(),
+                command: block.command,
+                exit_status: block.exit_status,
+            });
+        }
+    }
+
     pub fn perform(&mut self, action: Action) {
         debug!("perform {:?}", action);
         if self.suppress_initial_title_change {
@@ -294,55 +345,7 @@ impl<'a> Performer<'a> {
                 match (s.byte, s.intermediates.as_slice()) {
                     (b'q', &[b'$']) => {
                         // DECRQSS - Request Status String
-                        // https://vt100.net/docs/vt510-rm/DECRQSS.html
-                        // The response is described here:
-                        // https://vt100.net/docs/vt510-rm/DECRPSS.html
-                        // but note that *that* text has the validity value
-                        // inverted; there's a note about this in the xterm
-                        // ctlseqs docs.
-                        match s.data.as_slice() {
-                            &[b'"', b'p'] => {
-                                // DECSCL - select conformance level
-                                write!(self.writer, "{}1$r65;1\"p{}", DCS, ST).ok();
-                                self.writer.flush().ok();
-                            }
-                            &[b'r'] => {
-                                // DECSTBM - top and bottom margins
-                                let margins = self.top_and_bottom_margins.clone();
-                                write!(
-                                    self.writer,
-                                    "{}1$r{};{}r{}",
-                                    DCS,
-                                    margins.start + 1,
-                                    margins.end,
-                                    ST
-                                )
-                                .ok();
-                                self.writer.flush().ok();
-                            }
-                            &[b's'] => {
-                                // DECSLRM - left and right margins
-                                let margins = self.left_and_right_margins.clone();
-                                write!(
-                                    self.writer,
-                                    "{}1$r{};{}s{}",
-                                    DCS,
-                                    margins.start + 1,
-                                    margins.end,
-                                    ST
-                                )
-                                .ok();
-                                self.writer.flush().ok();
-                            }
-                            _ => {
-                                if self.config.log_unknown_escape_sequences() {
-                                    log::warn!("unhandled DECRQSS {:?}", s);
-                                }
-                                // Reply that the request is invalid
-                                write!(self.writer, "{}0$r{}", DCS, ST).ok();
-                                self.writer.flush().ok();
-                            }
-                        }
+                        self.request_status_string(&s.data);
                     }
                     _ => {
                         if self.config.log_unknown_escape_sequences() {
@@ -507,6 +510,8 @@ impl<'a> Performer<'a> {
             CSI::Device(dev) => self.state.perform_device(*dev),
             CSI::Mouse(mouse) => error!("mouse report sent by app? {:?}", mouse),
             CSI::Window(window) => self.state.perform_csi_window(*window),
+            CSI::RectangularArea(area) => self.state.perform_csi_rectangular_area(*area),
+            CSI::Stack(stack) => self.state.perform_csi_stack(*stack),
             CSI::SelectCharacterPath(CharacterPath::ImplementationDefault, _) => {
                 self.state.bidi_hint.take();
             }
@@ -686,6 +691,7 @@ impl<'a> Performer<'a> {
                 self.dec_auto_wrap = true;
                 self.reverse_wraparound_mode = false;
                 self.reverse_video_mode = false;
+                self.rectangular_attribute_change = false;
                 self.dec_origin_mode = false;
                 self.use_private_color_registers_for_each_graphic = false;
                 self.color_map = default_color_map();
@@ -708,7 +714,11 @@ impl<'a> Performer<'a> {
                 self.shift_out = false;
                 self.newline_mode = false;
                 self.tabs = TabStop::new(self.screen().physical_cols, 8);
+                self.saved_dec_private_modes.clear();
+                self.synchronized_output = false;
                 self.palette.take();
+                self.color_stack = Default::default();
+                self.sgr_stack.clear();
                 self.top_and_bottom_margins = 0..self.screen().physical_rows as VisibleRowIndex;
                 self.left_and_right_margins = 0..self.screen().physical_cols;
                 self.unicode_version = self.config.unicode_version();
@@ -785,7 +795,13 @@ impl<'a> Performer<'a> {
                 let selection = selection_to_selection(selection);
                 self.set_clipboard_contents(selection, None).ok();
             }
-            OperatingSystemCommand::QuerySelection(_) => {}
+            OperatingSystemCommand::QuerySelection(selection) => {
+                let selection = selection_to_selection(selection);
+                if let Err(err) = self.query_clipboard_contents(selection) {
+                    log::debug!("declining OSC 52 clipboard query: {:#}", err);
+                    self.answer_clipboard_query(selection, None);
+                }
+            }
             OperatingSystemCommand::SetSelection(selection, selection_data) => {
                 let selection = selection_to_selection(selection);
                 match self.set_clipboard_contents(selection, Some(selection_data)) {
@@ -868,38 +884,48 @@ impl<'a> Performer<'a> {
             ) => {
                 self.fresh_line();
                 self.pen.set_semantic_type(SemanticType::Prompt);
+                self.start_command_prompt();
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
-                FinalTermSemanticPrompt::StartPrompt(_),
+                FinalTermSemanticPrompt::StartPrompt(kind),
             ) => {
                 self.pen.set_semantic_type(SemanticType::Prompt);
+                if kind == FinalTermPromptKind::Initial {
+                    self.start_command_prompt();
+                }
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::MarkEndOfCommandWithFreshLine { .. },
             ) => {
                 self.fresh_line();
                 self.pen.set_semantic_type(SemanticType::Prompt);
+                self.start_command_prompt();
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker { .. },
             ) => {
                 self.pen.set_semantic_type(SemanticType::Input);
+                self.start_command_input();
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilEndOfLine { .. },
             ) => {
                 self.pen.set_semantic_type(SemanticType::Input);
                 self.clear_semantic_attribute_on_newline = true;
+                self.start_command_input();
             }
             OperatingSystemCommand::FinalTermSemanticPrompt(
                 FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { .. },
             ) => {
                 self.pen.set_semantic_type(SemanticType::Output);
+                self.start_command_output();
             }
 
             OperatingSystemCommand::FinalTermSemanticPrompt(
-                FinalTermSemanticPrompt::CommandStatus { .. },
-            ) => {}
+                FinalTermSemanticPrompt::CommandStatus { status, .. },
+            ) => {
+                self.finish_command(status);
+            }
 
             OperatingSystemCommand::SystemNotification(message) => {
                 if let Some(handler) = self.alert_handler.as_mut() {
@@ -912,6 +938,12 @@ impl<'a> Performer<'a> {
                     log::info!("Application sends SystemNotification: {}", message);
                 }
             }
+            OperatingSystemCommand::KittyNotification(notif) => {
+                self.kitty_notification(*notif);
+            }
+            OperatingSystemCommand::TextSizing(sizing) => {
+                self.text_sizing(*sizing);
+            }
             OperatingSystemCommand::RxvtExtension(params) => {
                 if let Some("notify") = params.get(0).map(String::as_str) {
                     let title = params.get(1);
@@ -0,0 +1,293 @@
+//! Implements the VT400-series rectangular area operations
+//! (DECCRA, DECFRA, DECERA, DECSERA, DECCARA and DECRARA)
+//! along with selective erase of cells that are not protected
+//! by DECSCA.
+use crate::{TerminalState, VisibleRowIndex};
+use std::ops::Range;
+use wezterm_cell::{Blink, Cell, CellAttributes, Intensity, Underline};
+use wezterm_escape_parser::csi::{Rectangle, RectangularArea, Sgr};
+
+/// Resolve a pair of 1-based, inclusive coordinates to a range
+/// that is offset by and clipped to `limit`
+fn clip(limit: &Range<usize>, start: u32, end: u32) -> (usize, usize) {
+    let start = (limit.start + start as usize).min(limit.end);
+    let end = (limit.start + end as usize + 1).min(limit.end);
+    (start, end)
+}
+
+impl TerminalState {
+    /// Returns the rows and columns that rectangle coordinates are
+    /// relative to; in origin mode they are relative to, and
+    /// constrained by, the margins.
+    fn rectangle_limits(&self) -> (Range<usize>, Range<usize>) {
+        if self.dec_origin_mode {
+            (
+                self.top_and_bottom_margins.start as usize
+                    ..self.top_and_bottom_margins.end as usize,
+                self.left_and_right_margins.clone(),
+            )
+        } else {
+            (
+                0..self.screen().physical_rows,
+                0..self.screen().physical_cols,
+            )
+        }
+    }
+
+    /// Resolve `rect` to the rows and columns of the screen that it covers
+    pub(crate) fn rectangle_ranges(&self, rect: &Rectangle) -> (Range<usize>, Range<usize>) {
+        let (row_limit, col_limit) = self.rectangle_limits();
+        let (top, bottom) = clip(
+            &row_limit,
+            rect.top.as_zero_based(),
+            rect.bottom.as_zero_based(),
+        );
+        let (left, right) = clip(
+            &col_limit,
+            rect.left.as_zero_based(),
+            rect.right.as_zero_based(),
+        );
+        (top..bottom.max(top), left..right.max(left))
+    }
+
+    /// Returns the columns in each row that are affected by DECCARA
+    /// or DECRARA, taking DECSACE into account
+    fn attribute_change_extent(&self, rect: &Rectangle) -> Vec<(usize, Range<usize>)> {
+        let (rows, cols) = self.rectangle_ranges(rect);
+        if self.rectangular_attribute_change || rows.len() < 2 {
+            return rows.map(|y| (y, cols.clone())).collect();
+        }
+
+        // Stream mode: the extent runs from the top left position
+        // to the bottom right position, wrapping at the margins
+        let (_, col_limit) = self.rectangle_limits();
+        let (left, right) = clip(
+            &col_limit,
+            rect.left.as_zero_based(),
+            rect.right.as_zero_based(),
+        );
+        let first = rows.start;
+        let last = rows.end - 1;
+        rows.map(|y| {
+            let start = if y == first { left } else { col_limit.start };
+            let end = if y == last { right } else { col_limit.end };
+            (y, start..end.max(start))
+        })
+        .collect()
+    }
+
+    pub(crate) fn perform_csi_rectangular_area(&mut self, area: RectangularArea) {
+        match area {
+            RectangularArea::Copy {
+                source,
+                dest_top,
+                dest_left,
+                ..
+            } => self.copy_rectangle(&source, dest_top.as_zero_based(), dest_left.as_zero_based()),
+            RectangularArea::Fill { ch, area } => self.fill_rectangle(&area, ch),
+            RectangularArea::Erase(area) => {
+                let (rows, cols) = self.rectangle_ranges(&area);
+                let seqno = self.seqno;
+                let blank = Cell::blank_with_attrs(self.pen.clone_sgr_only());
+                let screen = self.screen_mut();
+                for y in rows {
+                    let line_idx = screen.phys_row(y as VisibleRowIndex);
+                    screen
+                        .line_mut(line_idx)
+                        .fill_range(cols.clone(), &blank, seqno);
+                }
+            }
+            RectangularArea::SelectiveErase(area) => {
+                let (rows, cols) = self.rectangle_ranges(&area);
+                self.selective_erase(rows, cols);
+            }
+            RectangularArea::ChangeAttributes { area, attributes } => {
+                self.change_rectangle_attributes(&area, &attributes, false)
+            }
+            RectangularArea::ReverseAttributes { area, attributes } => {
+                self.change_rectangle_attributes(&area, &attributes, true)
+            }
+            RectangularArea::SelectAttributeChangeExtent(rectangle) => {
+                self.rectangular_attribute_change = rectangle;
+            }
+        }
+    }
+
+    /// Erase the cells in the specified rows and columns, skipping
+    /// those that are protected by DECSCA
+    pub(crate) fn selective_erase(&mut self, rows: Range<usize>, cols: Range<usize>) {
+        let seqno = self.seqno;
+        let screen = self.screen_mut();
+        for y in rows {
+            let line_idx = screen.phys_row(y as VisibleRowIndex);
+            let line = screen.line_mut(line_idx);
+            let to_erase: Vec<(usize, CellAttributes)> = line
+                .visible_cells()
+                .filter(|cell| cols.contains(&cell.cell_index()) && !cell.attrs().protected())
+                .map(|cell| (cell.cell_index(), cell.attrs().clone_sgr_only()))
+                .collect();
+            for (x, attrs) in to_erase {
+                line.set_cell(x, Cell::blank_with_attrs(attrs), seqno);
+            }
+            line.prune_trailing_blanks(seqno);
+        }
+    }
+
+    fn copy_rectangle(&mut self, source: &Rectangle, dest_top: u32, dest_left: u32) {
+        let (rows, cols) = self.rectangle_ranges(source);
+        let (row_limit, col_limit) = self.rectangle_limits();
+        let seqno = self.seqno;
+        let screen = self.screen_mut();
+
+        // Take a copy of the source first, so that overlapping
+        // source and destination areas are handled correctly.
+        // None represents the trailing portion of a double-width cell.
+        let copied: Vec<Vec<Option<Cell>>> = rows
+            .map(|y| {
+                let mut row = vec![Some(Cell::blank()); cols.len()];
+                let line_idx = screen.phys_row(y as VisibleRowIndex);
+                for cell in screen.line_mut(line_idx).visible_cells() {
+                    let x = cell.cell_index();
+                    if !cols.contains(&x) {
+                        continue;
+                    }
+                    row[x - cols.start] = Some(cell.as_cell());
+                    for trailing in x + 1..(x + cell.width()).min(cols.end) {
+                        row[trailing - cols.start] = None;
+                    }
+                }
+                row
+            })
+            .collect();
+
+        let top = row_limit.start + dest_top as usize;
+        let left = col_limit.start + dest_left as usize;
+        for (dy, row) in copied.into_iter().enumerate() {
+            let y = top + dy;
+            if y >= row_limit.end {
+                break;
+            }
+            let line_idx = screen.phys_row(y as VisibleRowIndex);
+            let line = screen.line_mut(line_idx);
+            for (dx, cell) in row.into_iter().enumerate() {
+                let x = left + dx;
+                if x >= col_limit.end {
+                    break;
+                }
+                if let Some(cell) = cell {
+                    line.set_cell(x, cell, seqno);
+                }
+            }
+        }
+    }
+
+    fn fill_rectangle(&mut self, area: &Rectangle, ch: char) {
+        let cell = Cell::new(ch, self.pen.clone());
+        if ch.is_control() || cell.width() != 1 {
+            return;
+        }
+        let (rows, cols) = self.rectangle_ranges(area);
+        let seqno = self.seqno;
+        let screen = self.screen_mut();
+        for y in rows {
+            let line_idx = screen.phys_row(y as VisibleRowIndex);
+            let line = screen.line_mut(line_idx);
+            for x in cols.clone() {
+                line.set_cell(x, cell.clone(), seqno);
+            }
+        }
+    }
+
+    fn change_rectangle_attributes(&mut self, area: &Rectangle, attributes: &[Sgr], reverse: bool) {
+        let extent = self.attribute_change_extent(area);
+        let seqno = self.seqno;
+        let screen = self.screen_mut();
+        for (y, cols) in extent {
+            if cols.is_empty() {
+                continue;
+            }
+            let line_idx = screen.phys_row(y as VisibleRowIndex);
+            let line = screen.line_mut(line_idx);
+            if line.len() < cols.end {
+                // Materialize the implicit blanks so that their
+                // attributes can be changed
+                line.resize(cols.end, seqno);
+            }
+            for cell in &mut line.cells_mut_for_attr_changes_only()[cols] {
+                let attrs = cell.attrs_mut();
+                for sgr in attributes {
+                    if reverse {
+                        reverse_attribute(attrs, sgr);
+                    } else {
+                        change_attribute(attrs, sgr);
+                    }
+                }
+            }
+            line.update_last_change_seqno(seqno);
+            line.prune_trailing_blanks(seqno);
+        }
+    }
+}
+
+/// Apply a DECCARA attribute to a cell
+fn change_attribute(attrs: &mut CellAttributes, sgr: &Sgr) {
+    match sgr {
+        Sgr::Reset => {
+            attrs
+                .set_intensity(Intensity::Normal)
+                .set_underline(Underline::None)
+                .set_blink(Blink::None)
+                .set_reverse(false)
+                .set_invisible(false);
+        }
+        Sgr::Intensity(intensity) => {
+            attrs.set_intensity(*intensity);
+        }
+        Sgr::Underline(underline) => {
+            attrs.set_underline(*underline);
+        }
+        Sgr::Blink(blink) => {
+            attrs.set_blink(*blink);
+        }
+        Sgr::Inverse(inverse) => {
+            attrs.set_reverse(*inverse);
+        }
+        Sgr::Invisible(invisible) => {
+            attrs.set_invisible(*invisible);
+        }
+        _ => {}
+    }
+}
+
+/// Apply a DECRARA attribute to a cell; the attribute is toggled.
+/// Reset toggles all of the attributes.
+fn reverse_attribute(attrs: &mut CellAttributes, sgr: &Sgr) {
+    let all = matches!(sgr, Sgr::Reset);
+    if all || *sgr == Sgr::Intensity(Intensity::Bold) {
+        attrs.set_intensity(if attrs.intensity() == Intensity::Bold {
+            Intensity::Normal
+        } else {
+            Intensity::Bold
+        });
+    }
+    if all || *sgr == Sgr::Underline(Underline::Single) {
+        attrs.set_underline(if attrs.underline() == Underline::None {
+            Underline::Single
+        } else {
+            Underline::None
+        });
+    }
+    if all || *sgr == Sgr::Blink(Blink::Slow) {
+        attrs.set_blink(if attrs.blink() == Blink::None {
+            Blink::Slow
+        } else {
+            Blink::None
+        });
+    }
+    if all || *sgr == Sgr::Inverse(true) {
+        attrs.set_reverse(!attrs.reverse());
+    }
+    if all || *sgr == Sgr::Invisible(true) {
+        attrs.set_invisible(!attrs.invisible());
+    }
+}
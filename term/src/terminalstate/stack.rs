@@ -0,0 +1,255 @@
+//! Implements xterm's stacks of graphic renditions (XTPUSHSGR,
+//! XTPOPSGR and XTREPORTSGR) and of color palettes (XTPUSHCOLORS,
+//! XTPOPCOLORS and XTREPORTCOLORS)
+use crate::color::{ColorAttribute, ColorPalette};
+use crate::{TerminalState, VisibleRowIndex};
+use std::io::Write;
+use wezterm_cell::{Blink, CellAttributes, Intensity, Underline};
+use wezterm_escape_parser::csi::{SgrStackAttribute, Stack};
+
+/// The maximum depth of each of the stacks; this matches xterm
+const MAX_STACK_DEPTH: usize = 10;
+
+/// The pen as it was when XTPUSHSGR was used
+#[derive(Debug)]
+pub(crate) struct SavedSgr {
+    pen: CellAttributes,
+    /// The attributes that will be restored by XTPOPSGR.
+    /// Empty means all of them.
+    attributes: Vec<SgrStackAttribute>,
+}
+
+impl SavedSgr {
+    fn restore(self, pen: &mut CellAttributes) {
+        if self.attributes.is_empty() {
+            *pen = self.pen;
+            return;
+        }
+
+        fn restore_intensity(pen: &mut CellAttributes, saved: Intensity, which: Intensity) {
+            if saved == which {
+                pen.set_intensity(which);
+            } else if pen.intensity() == which {
+                pen.set_intensity(Intensity::Normal);
+            }
+        }
+
+        let saved = &self.pen;
+        for attr in &self.attributes {
+            match attr {
+                SgrStackAttribute::Bold => {
+                    restore_intensity(pen, saved.intensity(), Intensity::Bold)
+                }
+                SgrStackAttribute::Faint => {
+                    restore_intensity(pen, saved.intensity(), Intensity::Half)
+                }
+                SgrStackAttribute::Italic => {
+                    pen.set_italic(saved.italic());
+                }
+                // We don't distinguish between the underline styles here
+                SgrStackAttribute::Underline | SgrStackAttribute::DoubleUnderline => {
+                    pen.set_underline(saved.underline());
+                    pen.set_underline_color(saved.underline_color());
+                }
+                SgrStackAttribute::Blink => {
+                    pen.set_blink(saved.blink());
+                }
+                SgrStackAttribute::Inverse => {
+                    pen.set_reverse(saved.reverse());
+                }
+                SgrStackAttribute::Invisible => {
+                    pen.set_invisible(saved.invisible());
+                }
+                SgrStackAttribute::StrikeThrough => {
+                    pen.set_strikethrough(saved.strikethrough());
+                }
+                SgrStackAttribute::Foreground => {
+                    pen.set_foreground(saved.foreground());
+                }
+                SgrStackAttribute::Background => {
+                    pen.set_background(saved.background());
+                }
+            }
+        }
+    }
+}
+
+/// The stack of color palettes.  As well as being pushed and popped,
+/// its slots can be stored into and restored from directly, so it
+/// is represented as an array of slots along with the number that
+/// are considered to be in use.
+#[derive(Debug, Default)]
+pub(crate) struct ColorStack {
+    slots: Vec<Option<ColorPalette>>,
+    used: usize,
+    /// The 1-based slot that was most recently stored or restored
+    last: usize,
+}
+
+impl ColorStack {
+    fn store(&mut self, slot: usize, palette: ColorPalette) {
+        let idx = match slot.checked_sub(1) {
+            Some(idx) => idx,
+            None => return,
+        };
+        if self.slots.len() < slot {
+            self.slots.resize(slot, None);
+        }
+        self.slots[idx] = Some(palette);
+        self.used = self.used.max(slot);
+        self.last = slot;
+    }
+
+    fn get(&mut self, slot: usize) -> Option<ColorPalette> {
+        let palette = self.slots.get(slot.checked_sub(1)?)?.clone()?;
+        self.last = slot;
+        Some(palette)
+    }
+}
+
+/// Returns the SGR parameters that select the graphic rendition of
+/// `attrs`, in the form used by the response to XTREPORTSGR
+fn sgr_report_params(attrs: &CellAttributes) -> Vec<String> {
+    fn color(color: ColorAttribute, base: u8, bright_base: u8) -> Option<String> {
+        match color {
+            ColorAttribute::Default => None,
+            ColorAttribute::PaletteIndex(idx) if idx < 8 => Some((base + idx).to_string()),
+            ColorAttribute::PaletteIndex(idx) if idx < 16 => {
+                Some((bright_base + idx - 8).to_string())
+            }
+            ColorAttribute::PaletteIndex(idx) => Some(format!("{}:5:{}", base + 8, idx)),
+            ColorAttribute::TrueColorWithDefaultFallback(rgb)
+            | ColorAttribute::TrueColorWithPaletteFallback(rgb, _) => {
+                let (r, g, b, _) = rgb.to_srgb_u8();
+                Some(format!("{}:2::{}:{}:{}", base + 8, r, g, b))
+            }
+        }
+    }
+
+    let mut params = vec![];
+    match attrs.intensity() {
+        Intensity::Normal => {}
+        Intensity::Bold => params.push("1".to_string()),
+        Intensity::Half => params.push("2".to_string()),
+    }
+    if attrs.italic() {
+        params.push("3".to_string());
+    }
+    match attrs.underline() {
+        Underline::None => {}
+        Underline::Double => params.push("21".to_string()),
+        _ => params.push("4".to_string()),
+    }
+    match attrs.blink() {
+        Blink::None => {}
+        Blink::Slow => params.push("5".to_string()),
+        Blink::Rapid => params.push("6".to_string()),
+    }
+    if attrs.reverse() {
+        params.push("7".to_string());
+    }
+    if attrs.invisible() {
+        params.push("8".to_string());
+    }
+    if attrs.strikethrough() {
+        params.push("9".to_string());
+    }
+    params.extend(color(attrs.foreground(), 30, 90));
+    params.extend(color(attrs.background(), 40, 100));
+    params
+}
+
+impl TerminalState {
+    pub(crate) fn perform_csi_stack(&mut self, stack: Stack) {
+        match stack {
+            Stack::PushSgr(attributes) => {
+                if self.sgr_stack.len() < MAX_STACK_DEPTH {
+                    self.sgr_stack.push(SavedSgr {
+                        pen: self.pen.clone(),
+                        attributes,
+                    });
+                }
+            }
+            Stack::PopSgr => {
+                if let Some(saved) = self.sgr_stack.pop() {
+                    saved.restore(&mut self.pen);
+                }
+            }
+            Stack::PushColors(slots) => {
+                let palette = self.palette();
+                if slots.is_empty() {
+                    let slot = self.color_stack.used + 1;
+                    if slot <= MAX_STACK_DEPTH {
+                        self.color_stack.store(slot, palette);
+                    }
+                } else {
+                    for slot in slots {
+                        self.color_stack.store(slot as usize, palette.clone());
+                    }
+                }
+            }
+            Stack::PopColors(slots) => {
+                let palette = if slots.is_empty() {
+                    let slot = self.color_stack.used;
+                    if slot == 0 {
+                        return;
+                    }
+                    let palette = self.color_stack.get(slot);
+                    self.color_stack.used -= 1;
+                    palette
+                } else {
+                    // Restoring from several slots is the same as
+                    // restoring from the last of them
+                    slots
+                        .into_iter()
+                        .filter_map(|slot| self.color_stack.get(slot as usize))
+                        .last()
+                };
+                if let Some(palette) = palette {
+                    self.palette.replace(palette);
+                    self.implicit_palette_reset_if_same_as_configured();
+                    self.palette_did_change();
+                }
+            }
+            Stack::ReportColors => {
+                write!(
+                    self.writer,
+                    "\x1b[{};{}#Q",
+                    self.color_stack.last, self.color_stack.used
+                )
+                .ok();
+                self.writer.flush().ok();
+            }
+            Stack::ReportSgr(area) => {
+                // Only the attributes that are shared by every cell
+                // in the rectangle are reported
+                let (rows, cols) = self.rectangle_ranges(&area);
+                let mut common: Option<Vec<String>> = None;
+                let screen = self.screen_mut();
+                for y in rows {
+                    let line_idx = screen.phys_row(y as VisibleRowIndex);
+                    let line = screen.line_mut(line_idx);
+                    for x in cols.clone() {
+                        let params = match line.get_cell(x) {
+                            Some(cell) => sgr_report_params(cell.attrs()),
+                            None => vec![],
+                        };
+                        match &mut common {
+                            Some(common) => common.retain(|param| params.contains(param)),
+                            None => common = Some(params),
+                        }
+                    }
+                }
+
+                let mut response = "\x1b[0".to_string();
+                for param in common.unwrap_or_default() {
+                    response.push(';');
+                    response.push_str(&param);
+                }
+                response.push('m');
+                self.writer.write_all(response.as_bytes()).ok();
+                self.writer.flush().ok();
+            }
+        }
+    }
+}
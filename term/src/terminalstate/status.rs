@@ -0,0 +1,217 @@
+//! Implements saving and restoring DEC private modes (XTSAVE and
+//! XTRESTORE), and reporting settings via DECRQSS
+use crate::terminalstate::MouseEncoding;
+use crate::{TerminalState, DCS, ST};
+use num_traits::ToPrimitive;
+use std::io::Write;
+use termwiz::input::KeyboardEncoding;
+use wezterm_cell::color::ColorAttribute;
+use wezterm_cell::{Blink, CellAttributes};
+use wezterm_escape_parser::color::ColorSpec;
+use wezterm_escape_parser::csi::{DecPrivateMode, DecPrivateModeCode, Mode, Sgr, VerticalAlign};
+use wezterm_surface::CursorShape;
+
+impl TerminalState {
+    /// Returns the current state of a DEC private mode, or None for modes
+    /// that have no state that we track.
+    pub(crate) fn dec_private_mode_state(&self, code: &DecPrivateModeCode) -> Option<bool> {
+        use DecPrivateModeCode::*;
+        Some(match code {
+            ApplicationCursorKeys => self.application_cursor_keys,
+            DecAnsiMode => self.dec_ansi_mode,
+            ReverseVideo => self.reverse_video_mode,
+            OriginMode => self.dec_origin_mode,
+            AutoWrap => self.dec_auto_wrap,
+            ShowCursor => self.cursor_visible,
+            ReverseWraparound => self.reverse_wraparound_mode,
+            LeftRightMarginMode => self.left_and_right_margin_mode,
+            SixelDisplayMode => self.sixel_display_mode,
+            MouseTracking => self.mouse_tracking,
+            ButtonEventMouse => self.button_event_mouse,
+            AnyEventMouse => self.any_event_mouse,
+            FocusTracking => self.focus_tracking,
+            Utf8Mouse => self.mouse_encoding == MouseEncoding::Utf8,
+            SGRMouse => self.mouse_encoding == MouseEncoding::SGR,
+            SGRPixelsMouse => self.mouse_encoding == MouseEncoding::SgrPixels,
+            EnableAlternateScreen | OptEnableAlternateScreen | ClearAndEnableAlternateScreen => {
+                self.screen.is_alt_screen_active()
+            }
+            BracketedPaste => self.bracketed_paste,
+            UsePrivateColorRegistersForEachGraphic => {
+                self.use_private_color_registers_for_each_graphic
+            }
+            ColorSchemeUpdates => self.color_scheme_updates,
+            SixelScrollsRight => self.sixel_scrolls_right,
+            Win32InputMode => self.keyboard_encoding == KeyboardEncoding::Win32,
+            // Select132Columns clears the screen as a side effect of
+            // being set or reset, SynchronizedOutput only makes sense
+            // for the duration of an update, and the remaining modes
+            // are ignored
+            Select132Columns
+            | SmoothScroll
+            | AutoRepeat
+            | StartBlinkingCursor
+            | HighlightMouseTracking
+            | XTermMetaSendsEscape
+            | XTermAltSendsEscape
+            | SaveCursor
+            | GraphemeClustering
+            | SynchronizedOutput
+            | MinTTYApplicationEscapeKeyMode => return None,
+        })
+    }
+
+    /// XTSAVE
+    pub(crate) fn save_dec_private_mode(&mut self, code: DecPrivateModeCode) {
+        if let Some(enabled) = self.dec_private_mode_state(&code) {
+            if let Some(number) = code.to_u16() {
+                self.saved_dec_private_modes.insert(number, enabled);
+            }
+        }
+    }
+
+    /// XTRESTORE.  The mode is set or reset in the same way as it
+    /// would be if the application had done so directly, so that
+    /// any side effects are applied.
+    pub(crate) fn restore_dec_private_mode(&mut self, code: DecPrivateModeCode) {
+        let enabled = match code
+            .to_u16()
+            .and_then(|number| self.saved_dec_private_modes.get(&number))
+        {
+            Some(enabled) => *enabled,
+            None => return,
+        };
+        if self.dec_private_mode_state(&code) == Some(enabled) {
+            return;
+        }
+        let mode = DecPrivateMode::Code(code);
+        self.perform_csi_mode(if enabled {
+            Mode::SetDecPrivateMode(mode)
+        } else {
+            Mode::ResetDecPrivateMode(mode)
+        });
+    }
+
+    /// DECRQSS - Request Status String
+    /// <https://vt100.net/docs/vt510-rm/DECRQSS.html>
+    /// The response is described here:
+    /// <https://vt100.net/docs/vt510-rm/DECRPSS.html>
+    /// but note that *that* text has the validity value
+    /// inverted; there's a note about this in the xterm
+    /// ctlseqs docs.
+    pub(crate) fn request_status_string(&mut self, request: &[u8]) {
+        let status = match request {
+            // DECSCL - select conformance level
+            b"\"p" => "65;1\"p".to_string(),
+            // DECSTBM - top and bottom margins
+            b"r" => {
+                let margins = self.top_and_bottom_margins.clone();
+                format!("{};{}r", margins.start + 1, margins.end)
+            }
+            // DECSLRM - left and right margins
+            b"s" => {
+                let margins = self.left_and_right_margins.clone();
+                format!("{};{}s", margins.start + 1, margins.end)
+            }
+            // SGR - the current graphic rendition
+            b"m" => format!("{}m", sgr_status(&self.pen)),
+            // DECSCA - select character protection attribute
+            b"\"q" => format!("{}\"q", if self.pen.protected() { 1 } else { 0 }),
+            // DECSCUSR - set cursor style
+            b" q" => {
+                let style = match self.cursor.shape {
+                    CursorShape::Default => 0,
+                    CursorShape::BlinkingBlock => 1,
+                    CursorShape::SteadyBlock => 2,
+                    CursorShape::BlinkingUnderline => 3,
+                    CursorShape::SteadyUnderline => 4,
+                    CursorShape::BlinkingBar => 5,
+                    CursorShape::SteadyBar => 6,
+                };
+                format!("{} q", style)
+            }
+            // DECSACE - select attribute change extent
+            b"*x" => format!(
+                "{}*x",
+                if self.rectangular_attribute_change {
+                    2
+                } else {
+                    1
+                }
+            ),
+            _ => {
+                if self.config.log_unknown_escape_sequences() {
+                    log::warn!("unhandled DECRQSS {:?}", String::from_utf8_lossy(request));
+                }
+                // Reply that the request is invalid
+                write!(self.writer, "{}0$r{}", DCS, ST).ok();
+                self.writer.flush().ok();
+                return;
+            }
+        };
+        write!(self.writer, "{}1$r{}{}", DCS, status, ST).ok();
+        self.writer.flush().ok();
+    }
+}
+
+fn color_spec(color: ColorAttribute) -> ColorSpec {
+    match color {
+        ColorAttribute::TrueColorWithPaletteFallback(color, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(color) => ColorSpec::TrueColor(color),
+        ColorAttribute::PaletteIndex(idx) => ColorSpec::PaletteIndex(idx),
+        ColorAttribute::Default => ColorSpec::Default,
+    }
+}
+
+/// Produces the SGR parameters that would select the specified
+/// attributes, starting from the normal rendition
+fn sgr_status(pen: &CellAttributes) -> String {
+    let mut sgr = vec![Sgr::Reset];
+    if pen.intensity() != Default::default() {
+        sgr.push(Sgr::Intensity(pen.intensity()));
+    }
+    if pen.italic() {
+        sgr.push(Sgr::Italic(true));
+    }
+    if pen.underline() != Default::default() {
+        sgr.push(Sgr::Underline(pen.underline()));
+    }
+    if pen.blink() != Blink::None {
+        sgr.push(Sgr::Blink(pen.blink()));
+    }
+    if pen.reverse() {
+        sgr.push(Sgr::Inverse(true));
+    }
+    if pen.invisible() {
+        sgr.push(Sgr::Invisible(true));
+    }
+    if pen.strikethrough() {
+        sgr.push(Sgr::StrikeThrough(true));
+    }
+    if pen.overline() {
+        sgr.push(Sgr::Overline(true));
+    }
+    if pen.vertical_align() != VerticalAlign::BaseLine {
+        sgr.push(Sgr::VerticalAlign(pen.vertical_align()));
+    }
+    if pen.foreground() != ColorAttribute::Default {
+        sgr.push(Sgr::Foreground(color_spec(pen.foreground())));
+    }
+    if pen.background() != ColorAttribute::Default {
+        sgr.push(Sgr::Background(color_spec(pen.background())));
+    }
+    if pen.underline_color() != ColorAttribute::Default {
+        sgr.push(Sgr::UnderlineColor(color_spec(pen.underline_color())));
+    }
+
+    // Each Sgr formats itself as a complete sequence; we want
+    // just the parameters, separated by semicolons
+    sgr.iter()
+        .map(|sgr| {
+            let mut params = sgr.to_string();
+            params.pop();
+            params
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
@@ -0,0 +1,174 @@
+//! Implements kitty's text sizing protocol (OSC 66)
+//! <https://sw.kovidgoyal.net/kitty/text-sizing-protocol/>
+use crate::{TerminalState, VisibleRowIndex};
+use finl_unicode::grapheme_clusters::Graphemes;
+use std::ops::Range;
+use wezterm_cell::{grapheme_column_width, Cell, TextSize};
+use wezterm_escape_parser::osc::TextSizing;
+
+impl TerminalState {
+    pub(crate) fn text_sizing(&mut self, sizing: TextSizing) {
+        let scale = sizing.scale.max(1) as usize;
+
+        // When the width is specified, the text occupies a single
+        // block of that width.  Otherwise each grapheme is placed
+        // in its own block, sized according to its natural width.
+        let blocks: Vec<(String, usize)> = if sizing.width > 0 {
+            vec![(sizing.text.clone(), sizing.width as usize * scale)]
+        } else {
+            Graphemes::new(&sizing.text)
+                .filter_map(|g| {
+                    let width = grapheme_column_width(g, Some(&self.unicode_version));
+                    if width == 0 {
+                        None
+                    } else {
+                        Some((g.to_string(), width * scale))
+                    }
+                })
+                .collect()
+        };
+
+        for (text, width) in blocks {
+            self.print_text_sized_block(&text, width, scale, &sizing);
+        }
+    }
+
+    /// Places a block of `width` columns and `scale` lines at the
+    /// cursor position, wrapping and scrolling as needed to fit it.
+    fn print_text_sized_block(
+        &mut self,
+        text: &str,
+        width: usize,
+        scale: usize,
+        sizing: &TextSizing,
+    ) {
+        let seqno = self.seqno;
+        let margins = self.left_and_right_margins.clone();
+        let width = width.min(margins.end - margins.start).max(1);
+        let scale = scale.min(self.screen().physical_rows).max(1);
+
+        if self.wrap_next {
+            self.new_line(true);
+            self.wrap_next = false;
+        }
+        if self.cursor.x + width > margins.end {
+            if self.dec_auto_wrap {
+                self.new_line(true);
+            } else {
+                self.cursor.x = margins.end - width;
+            }
+        }
+
+        // Scroll so that the lines below the first are visible
+        let last_row = self.cursor.y + scale as i64 - 1;
+        let bottom = self.top_and_bottom_margins.end;
+        if last_row >= bottom {
+            let excess = (last_row - bottom + 1) as usize;
+            self.scroll_up(excess);
+            self.cursor.y = (self.cursor.y - excess as i64).max(0);
+            self.cursor.seqno = seqno;
+        }
+
+        let x = self.cursor.x;
+        let y = self.cursor.y;
+        for row in 0..scale {
+            self.clear_text_sized_blocks(y + row as VisibleRowIndex, x..x + width);
+        }
+        self.text_sizing_used = true;
+        for row in 0..scale {
+            for col in 0..width {
+                let mut attrs = self.pen.clone();
+                attrs.set_text_size(Some(TextSize {
+                    scale: scale as u8,
+                    width: width as u8,
+                    numerator: sizing.numerator,
+                    denominator: sizing.denominator,
+                    vertical_align: sizing.vertical_align,
+                    horizontal_align: sizing.horizontal_align,
+                    x: col as u8,
+                    y: row as u8,
+                }));
+
+                let screen = self.screen_mut();
+                if row == 0 {
+                    // The text is held by a single cell whose width
+                    // spans the whole block
+                    screen.set_cell_grapheme(x, y, text, width, attrs, seqno);
+                    break;
+                }
+                screen.set_cell(
+                    x + col,
+                    y + row as i64,
+                    &Cell::blank_with_attrs(attrs),
+                    seqno,
+                );
+            }
+        }
+
+        if x + width >= margins.end {
+            self.wrap_next = self.dec_auto_wrap;
+        } else {
+            self.cursor.x += width;
+            self.wrap_next = false;
+        }
+    }
+
+    /// Clears every text sized block that overlaps columns `cols` of
+    /// row `y`.  A block is displayed as a single unit, so overwriting
+    /// any part of it removes the whole block.
+    pub(crate) fn clear_text_sized_blocks(&mut self, y: VisibleRowIndex, cols: Range<usize>) {
+        if !self.text_sizing_used {
+            return;
+        }
+        let seqno = self.seqno;
+        let screen = self.screen_mut();
+        let physical_rows = screen.physical_rows as VisibleRowIndex;
+
+        let line_idx = screen.phys_row(y);
+        let mut origins: Vec<(usize, VisibleRowIndex, TextSize)> = vec![];
+        for cell in screen.line_mut(line_idx).visible_cells() {
+            let idx = cell.cell_index();
+            if idx >= cols.end || idx + cell.width() <= cols.start {
+                continue;
+            }
+            let size = match cell.attrs().text_size() {
+                Some(size) => size,
+                None => continue,
+            };
+            let origin = match idx.checked_sub(size.x as usize) {
+                Some(x) => (x, y - size.y as VisibleRowIndex, size),
+                None => continue,
+            };
+            if !origins.contains(&origin) {
+                origins.push(origin);
+            }
+        }
+
+        for (x, origin_y, size) in origins {
+            for row in 0..size.scale {
+                let y = origin_y + row as VisibleRowIndex;
+                if y < 0 || y >= physical_rows {
+                    continue;
+                }
+                // Only clear the cells that still belong to this block
+                let line_idx = screen.phys_row(y);
+                let line = screen.line_mut(line_idx);
+                let ranges: Vec<Range<usize>> = line
+                    .visible_cells()
+                    .filter(|cell| {
+                        cell.attrs().text_size().map_or(false, |s| {
+                            s.scale == size.scale
+                                && s.width == size.width
+                                && s.y == row
+                                && cell.cell_index().checked_sub(s.x as usize) == Some(x)
+                        })
+                    })
+                    .map(|cell| cell.cell_index()..cell.cell_index() + cell.width())
+                    .collect();
+                for range in ranges {
+                    line.fill_range(range, &Cell::blank(), seqno);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Tables of requests for the state of the terminal, checking that
+//! each setting is reported correctly, survives XTSAVE/XTRESTORE
+//! and is reset by DECSTR as described by the VT510 reference.
+use super::*;
+use k9::assert_equal as assert_eq;
+use std::sync::mpsc::Receiver;
+
+fn term_with_responses() -> (TestTerm, Receiver<Vec<u8>>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let term = TestTerm::with_writer(5, 10, 0, Box::new(ChannelWriter(tx)));
+    (term, rx)
+}
+
+/// Issue DECRQSS and return the response
+fn decrqss(term: &mut TestTerm, rx: &Receiver<Vec<u8>>, request: &str) -> String {
+    term.print(format!("\x1bP$q{}\x1b\\", request));
+    read_response(rx)
+}
+
+/// Issue DECRQM for a DEC private mode and return the status
+/// value from the response
+fn decrqm(term: &mut TestTerm, rx: &Receiver<Vec<u8>>, mode: u16) -> u8 {
+    term.print(format!("\x1b[?{}$p", mode));
+    let response = String::from_utf8(rx.recv().unwrap()).unwrap();
+    let prefix = format!("\x1b[?{};", mode);
+    assert!(response.starts_with(&prefix), "{:?}", response);
+    response[prefix.len()..]
+        .trim_end_matches("$y")
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn test_decrqss() {
+    let cases: &[(&str, &str, &str)] = &[
+        // (setup, request, expected response)
+        ("", "\"p", "\x1bP1$r65;1\"p\x1b\\"),
+        ("", "r", "\x1bP1$r1;5r\x1b\\"),
+        ("\x1b[2;4r", "r", "\x1bP1$r2;4r\x1b\\"),
+        ("", "s", "\x1bP1$r1;10s\x1b\\"),
+        ("\x1b[?69h\x1b[3;7s", "s", "\x1bP1$r3;7s\x1b\\"),
+        ("", "m", "\x1bP1$r0m\x1b\\"),
+        ("\x1b[1;3;4;7m", "m", "\x1bP1$r0;1;3;4;7m\x1b\\"),
+        ("\x1b[2;31;44m", "m", "\x1bP1$r0;2;31;44m\x1b\\"),
+        (
+            "\x1b[4:3;38:2::1:2:3m",
+            "m",
+            "\x1bP1$r0;4:3;38:2::1:2:3m\x1b\\",
+        ),
+        ("", "\"q", "\x1bP1$r0\"q\x1b\\"),
+        ("\x1b[1\"q", "\"q", "\x1bP1$r1\"q\x1b\\"),
+        ("", " q", "\x1bP1$r0 q\x1b\\"),
+        ("\x1b[5 q", " q", "\x1bP1$r5 q\x1b\\"),
+        ("", "*x", "\x1bP1$r1*x\x1b\\"),
+        ("\x1b[2*x", "*x", "\x1bP1$r2*x\x1b\\"),
+        ("", "bogus", "\x1bP0$r\x1b\\"),
+    ];
+
+    for (setup, request, expected) in cases {
+        let (mut term, rx) = term_with_responses();
+        term.print(*setup);
+        assert_eq!(
+            decrqss(&mut term, &rx, request),
+            expected.to_string(),
+            "setup={:?} request={:?}",
+            setup,
+            request
+        );
+    }
+}
+
+/// Modes that can be set and reset without side effects that would
+/// interfere with the test, along with their default state
+const MODES: &[(u16, bool)] = &[
+    (1, false),    // DECCKM
+    (2, false),    // DECANM
+    (5, false),    // DECSCNM
+    (6, false),    // DECOM
+    (7, true),     // DECAWM
+    (25, true),    // DECTCEM
+    (45, false),   // reverse wraparound
+    (69, false),   // DECLRMM
+    (80, false),   // DECSDM
+    (1000, false), // mouse tracking
+    (1002, false), // button event mouse
+    (1003, false), // any event mouse
+    (1004, false), // focus tracking
+    (1006, false), // SGR mouse
+    (1049, false), // alternate screen
+    (1070, false), // private color registers
+    (2004, false), // bracketed paste
+    (2031, false), // color scheme updates
+    (8452, false), // sixel scrolls right
+];
+
+fn status(enabled: bool) -> u8 {
+    if enabled {
+        1
+    } else {
+        2
+    }
+}
+
+#[test]
+fn test_decrqm() {
+    for &(mode, default) in MODES {
+        let (mut term, rx) = term_with_responses();
+        assert_eq!(
+            decrqm(&mut term, &rx, mode),
+            status(default),
+            "mode {}",
+            mode
+        );
+
+        let toggle = if default { 'l' } else { 'h' };
+        term.print(format!("\x1b[?{}{}", mode, toggle));
+        assert_eq!(
+            decrqm(&mut term, &rx, mode),
+            status(!default),
+            "mode {}",
+            mode
+        );
+    }
+
+    // Unrecognized modes
+    let (mut term, rx) = term_with_responses();
+    assert_eq!(decrqm(&mut term, &rx, 4242), 0);
+}
+
+#[test]
+fn test_xtsave_xtrestore() {
+    for &(mode, default) in MODES {
+        let (mut term, rx) = term_with_responses();
+        let toggle = if default { 'l' } else { 'h' };
+        let untoggle = if default { 'h' } else { 'l' };
+
+        // Save the non-default state, then go back to the default
+        term.print(format!("\x1b[?{}{}\x1b[?{}s", mode, toggle, mode));
+        term.print(format!("\x1b[?{}{}", mode, untoggle));
+        assert_eq!(
+            decrqm(&mut term, &rx, mode),
+            status(default),
+            "mode {}",
+            mode
+        );
+
+        term.print(format!("\x1b[?{}r", mode));
+        assert_eq!(
+            decrqm(&mut term, &rx, mode),
+            status(!default),
+            "mode {}",
+            mode
+        );
+    }
+
+    // Restoring a mode that was never saved has no effect
+    let (mut term, rx) = term_with_responses();
+    term.print("\x1b[?2004h\x1b[?2004r");
+    assert_eq!(decrqm(&mut term, &rx, 2004), 1);
+}
+
+#[test]
+fn test_decstr() {
+    let (mut term, rx) = term_with_responses();
+    term.print("\x1b[3;3H\x1b7");
+    term.print("\x1b[?25l\x1b[4h\x1b[?6h\x1b[?7l\x1b=\x1b[?1h\x1b[2;4r");
+    term.print("\x1b[?69h\x1b[3;7s\x1b[?45h\x1b[1;31m\x1b[1\"q\x1b[2*x\x0e");
+    term.soft_reset();
+
+    // DECTCEM: cursor enabled
+    assert_eq!(decrqm(&mut term, &rx, 25), 1);
+    // IRM: replace
+    term.print("\x1b[4$p");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[4;2$y".to_vec());
+    // DECOM: absolute
+    assert_eq!(decrqm(&mut term, &rx, 6), 2);
+    // DECAWM: we follow xterm and enable it
+    assert_eq!(decrqm(&mut term, &rx, 7), 1);
+    // DECCKM: normal
+    assert_eq!(decrqm(&mut term, &rx, 1), 2);
+    // DECSTBM: the whole page
+    assert_eq!(decrqss(&mut term, &rx, "r"), "\x1bP1$r1;5r\x1b\\");
+    // SGR: normal rendition
+    assert_eq!(decrqss(&mut term, &rx, "m"), "\x1bP1$r0m\x1b\\");
+    // DECSCA: normal
+    assert_eq!(decrqss(&mut term, &rx, "\"q"), "\x1bP1$r0\"q\x1b\\");
+    // DECSC: home position
+    term.print("\x1b[4;4H\x1b8");
+    term.assert_cursor_pos(0, 0, None, None);
+
+    // Not part of the VT510 table, but reset by xterm
+    assert_eq!(decrqm(&mut term, &rx, 69), 2);
+    assert_eq!(decrqss(&mut term, &rx, "s"), "\x1bP1$r1;10s\x1b\\");
+    assert_eq!(decrqm(&mut term, &rx, 45), 2);
+    assert_eq!(decrqss(&mut term, &rx, "*x"), "\x1bP1$r1*x\x1b\\");
+}
+
+#[test]
+fn test_synchronized_output() {
+    let (mut term, rx) = term_with_responses();
+    assert_eq!(decrqm(&mut term, &rx, 2026), 2);
+    let seqno = term.synchronized_update_seqno();
+
+    term.print("\x1b[?2026h");
+    assert_eq!(decrqm(&mut term, &rx, 2026), 1);
+    assert_eq!(term.synchronized_update_seqno(), seqno);
+
+    term.print("hello\x1b[?2026l");
+    assert_eq!(decrqm(&mut term, &rx, 2026), 2);
+    assert!(term.synchronized_update_seqno() > seqno);
+
+    // DECSTR ends the update, as does the mux
+    term.print("\x1b[?2026h");
+    term.soft_reset();
+    assert_eq!(decrqm(&mut term, &rx, 2026), 2);
+}
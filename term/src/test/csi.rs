@@ -1,4 +1,5 @@
 use super::*;
+use k9::assert_equal as assert_eq;
 
 /// In this issue, the `CSI 2 P` sequence incorrectly removed two
 /// cells from the line, leaving them effectively blank, when those
@@ -401,3 +402,102 @@ fn test_ed_erase_scrollback() {
     term.print("b");
     assert_all_contents(&term, file!(), line!(), &["111", "222", "ab"]);
 }
+
+#[test]
+fn test_decera_decfra_deccra() {
+    let mut term = TestTerm::new(3, 4, 0);
+    term.print("abcd\r\nefgh\r\nijkl");
+
+    term.print("\x1b[2;2;3;3$z");
+    assert_visible_contents(&term, file!(), line!(), &["abcd", "e  h", "i  l"]);
+
+    term.print("\x1b[35;1;2;2;3$x");
+    assert_visible_contents(&term, file!(), line!(), &["a##d", "e##h", "i  l"]);
+
+    // Copy the top left 2x2 cells so that they start at row 2, col 3
+    term.print("\x1b[1;1;2;2;1;2;3;1$v");
+    assert_visible_contents(&term, file!(), line!(), &["a##d", "e#a#", "i e#"]);
+
+    // In origin mode, the coordinates are relative to the margins
+    // and are clipped to them
+    term.print("\x1b[2;3r\x1b[?6h");
+    term.print("\x1b[42;1;1;5;1$x");
+    assert_visible_contents(&term, file!(), line!(), &["a##d", "*#a#", "* e#"]);
+}
+
+#[test]
+fn test_decsca_selective_erase() {
+    // Erasing a whole line may leave blanks in place of its cells,
+    // so compare the text without them
+    fn visible_text(term: &TestTerm) -> String {
+        let lines: Vec<String> = term
+            .screen()
+            .visible_lines()
+            .iter()
+            .map(|line| line.as_str().trim_end().to_string())
+            .collect();
+        lines.join("\n")
+    }
+
+    let mut term = TestTerm::new(2, 4, 0);
+    // SGR 0 must not clear the protection
+    term.print("ab\x1b[1\"q\x1b[0mc\x1b[0\"qd\r\nefgh");
+
+    // DECSEL and DECSED skip the protected cell
+    term.cup(0, 0);
+    term.print("\x1b[?K");
+    assert_eq!(visible_text(&term), "  c\nefgh");
+    term.print("\x1b[?2J");
+    assert_eq!(visible_text(&term), "  c\n");
+
+    // ... as does DECSERA
+    term.cup(0, 1);
+    term.print("wxyz");
+    term.print("\x1b[1;1;2;4${");
+    assert_eq!(visible_text(&term), "  c\n");
+
+    // but the regular erase functions do not
+    term.print("\x1b[2J");
+    assert_eq!(visible_text(&term), "\n");
+}
+
+#[test]
+fn test_deccara_decrara() {
+    fn assert_bold(term: &TestTerm, expect: &[&str]) {
+        let bold: Vec<String> = term
+            .screen()
+            .visible_lines()
+            .iter()
+            .map(|line| {
+                line.visible_cells()
+                    .map(|cell| {
+                        if cell.attrs().intensity() == Intensity::Bold {
+                            'B'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let bold: Vec<&str> = bold.iter().map(String::as_str).collect();
+        assert_eq!(bold, expect.to_vec());
+    }
+
+    let mut term = TestTerm::new(2, 4, 0);
+    term.print("abcd\r\nefgh");
+
+    // The default extent is the stream of positions from the
+    // start to the end position
+    term.print("\x1b[1;3;2;2;1$r");
+    assert_bold(&term, &["..BB", "BB.."]);
+
+    // DECSACE 2 selects the exact rectangle
+    term.print("\x1b[2*x");
+    term.print("\x1b[1;2;2;3;0$r");
+    assert_bold(&term, &["...B", "B..."]);
+
+    // DECRARA toggles the attribute
+    term.print("\x1b[1;1;2;2;1$t");
+    assert_bold(&term, &["BB.B", ".B.."]);
+}
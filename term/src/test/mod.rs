@@ -5,7 +5,9 @@ use super::*;
 mod c0;
 use bitflags::bitflags;
 mod c1;
+mod conformance;
 mod csi;
+mod sixel;
 // mod selection; FIXME: port to render layer
 use crate::color::ColorPalette;
 use k9::assert_equal as assert_eq;
@@ -58,6 +60,15 @@ impl TerminalConfiguration for TestTermConfig {
 
 impl TestTerm {
     fn new(height: usize, width: usize, scrollback: usize) -> Self {
+        Self::with_writer(height, width, scrollback, Box::new(Vec::new()))
+    }
+
+    fn with_writer(
+        height: usize,
+        width: usize,
+        scrollback: usize,
+        writer: Box<dyn std::io::Write + Send>,
+    ) -> Self {
         let _ = env_logger::Builder::new()
             .is_test(true)
             .filter_level(log::LevelFilter::Trace)
@@ -74,7 +85,7 @@ impl TestTerm {
             Arc::new(TestTermConfig { scrollback }),
             "WezTerm",
             "O_o",
-            Box::new(Vec::new()),
+            writer,
         );
         let clip: Arc<dyn Clipboard> = Arc::new(LocalClip::new());
         term.set_clipboard(&clip);
@@ -479,6 +490,120 @@ fn test_semantic() {
     );
 }
 
+#[test]
+fn test_command_blocks() {
+    use wezterm_escape_parser::osc::FinalTermSemanticPrompt;
+    let mut term = TestTerm::new(10, 20, 0);
+
+    let run_command = |term: &mut TestTerm, command: &str, output: &str, status: i32| {
+        term.print(format!(
+            "{}$ {}{}\r\n{}{}{}",
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::FreshLineAndStartPrompt {
+                    aid: None,
+                    cl: None
+                }
+            ),
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker
+            ),
+            command,
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { aid: None }
+            ),
+            output,
+            OperatingSystemCommand::FinalTermSemanticPrompt(
+                FinalTermSemanticPrompt::CommandStatus { status, aid: None }
+            ),
+        ));
+    };
+
+    run_command(&mut term, "echo hi", "hi\r\n", 1);
+    run_command(&mut term, "ls", "a\r\nb\r\nc\r\n", 0);
+    term.print(format!(
+        "{}$ ",
+        OperatingSystemCommand::FinalTermSemanticPrompt(
+            FinalTermSemanticPrompt::FreshLineAndStartPrompt {
+                aid: None,
+                cl: None
+            }
+        )
+    ));
+
+    let rows = |term: &mut TestTerm| -> Vec<_> {
+        term.get_command_blocks()
+            .into_iter()
+            .map(|b| (b.prompt_start, b.output_start, b.output_end))
+            .collect()
+    };
+
+    let blocks = term.get_command_blocks();
+    assert_eq!(blocks.len(), 3);
+    assert_eq!(blocks[0].command, "echo hi");
+    assert_eq!(blocks[0].exit_status, Some(1));
+    assert_eq!(blocks[1].command, "ls");
+    assert_eq!(blocks[1].exit_status, Some(0));
+    assert!(!blocks[1].is_running());
+    assert!(blocks[1].duration().is_some());
+    assert!(!blocks[2].has_run());
+    assert_eq!(
+        rows(&mut term),
+        vec![
+            (0, Some(1), Some(2)),
+            (2, Some(3), Some(6)),
+            (6, None, None)
+        ]
+    );
+    assert_eq!(
+        term.get_command_output(blocks[1].id),
+        Some("a\nb\nc".to_string())
+    );
+
+    let ids_in_range =
+        |term: &mut TestTerm, range: std::ops::Range<StableRowIndex>| -> Vec<usize> {
+            term.get_command_blocks_in_range(range)
+                .into_iter()
+                .map(|b| b.id)
+                .collect()
+        };
+    assert_eq!(ids_in_range(&mut term, 3..5), vec![blocks[1].id]);
+    assert_eq!(
+        ids_in_range(&mut term, 1..3),
+        vec![blocks[0].id, blocks[1].id]
+    );
+    assert_eq!(ids_in_range(&mut term, 6..7), vec![blocks[2].id]);
+
+    // Collapsing only affects rendering; the screen and the
+    // rows of the blocks are left untouched
+    let contents = ["$ echo hi", "hi", "$ ls", "a", "b", "c", "$ ", "", "", ""];
+    term.set_command_block_collapsed(blocks[1].id, true)
+        .unwrap();
+    assert_visible_contents(&term, file!(), line!(), &contents);
+    assert_eq!(
+        rows(&mut term),
+        vec![
+            (0, Some(1), Some(2)),
+            (2, Some(3), Some(6)),
+            (6, None, None)
+        ]
+    );
+    assert!(term.get_command_blocks()[1].collapsed);
+    assert_eq!(term.screen().collapsed_output_ranges(), vec![3..6]);
+    assert_eq!(
+        term.get_command_output(blocks[1].id),
+        Some("a\nb\nc".to_string())
+    );
+    // There is nothing to gain by folding a single row
+    assert!(term
+        .set_command_block_collapsed(blocks[0].id, true)
+        .is_err());
+
+    term.set_command_block_collapsed(blocks[1].id, false)
+        .unwrap();
+    assert_visible_contents(&term, file!(), line!(), &contents);
+    assert!(term.screen().collapsed_output_ranges().is_empty());
+}
+
 #[test]
 fn issue_1161() {
     let mut term = TestTerm::new(1, 5, 0);
@@ -1289,6 +1414,111 @@ fn test_region_scrollback_limit() {
     assert_eq!(term.screen().visible_row_to_stable_row(4), 7);
 }
 
+#[derive(Debug)]
+struct SpillConfig;
+impl TerminalConfiguration for SpillConfig {
+    fn scrollback_size(&self) -> usize {
+        2
+    }
+
+    fn scrollback_spill_size(&self) -> usize {
+        1000
+    }
+
+    fn scrollback_spill_dir(&self) -> Option<std::path::PathBuf> {
+        Some(std::env::temp_dir())
+    }
+
+    fn color_palette(&self) -> ColorPalette {
+        ColorPalette::default()
+    }
+}
+
+/// Returns a 10x2 terminal with 2 rows of scrollback held in
+/// memory, beyond which lines are spilled to disk
+fn spill_term() -> Terminal {
+    Terminal::new(
+        TerminalSize {
+            rows: 2,
+            cols: 10,
+            pixel_width: 80,
+            pixel_height: 32,
+            dpi: 0,
+        },
+        Arc::new(SpillConfig),
+        "WezTerm",
+        "O_o",
+        Box::new(Vec::new()),
+    )
+}
+
+#[test]
+fn test_scrollback_spill() {
+    let mut term = spill_term();
+    for i in 0..600 {
+        term.advance_bytes(format!("line {i}\r\n"));
+    }
+
+    let screen = term.screen();
+    assert_eq!(screen.scrollback_rows(), 4);
+    assert_eq!(screen.spilled_rows(), 597);
+    assert_eq!(screen.first_stable_row(), 0);
+
+    let text = |lines: &[Line]| -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| line.as_str().trim_end().to_string())
+            .collect()
+    };
+
+    let (first, lines) = screen.lines_in_stable_range(0..2);
+    assert_eq!(first, 0);
+    assert_eq!(text(&lines), vec!["line 0", "line 1"]);
+
+    // Straddle the boundary between the disk and memory tiers
+    let (first, lines) = screen.lines_in_stable_range(595..599);
+    assert_eq!(first, 595);
+    assert_eq!(
+        text(&lines),
+        vec!["line 595", "line 596", "line 597", "line 598"]
+    );
+
+    let mut num_logical = 0;
+    screen.for_each_logical_line_in_stable_range(0..601, |_, _| {
+        num_logical += 1;
+        true
+    });
+    assert_eq!(num_logical, 601);
+}
+
+#[test]
+fn test_scrollback_spill_wrapped_line() {
+    let mut term = spill_term();
+    for i in 0..596 {
+        term.advance_bytes(format!("line {i}\r\n"));
+    }
+    // This wraps over rows 596..599, the first of which is spilled
+    term.advance_bytes("abcdefghijklmnopqrstuvwxy\r\nend\r\n");
+
+    let screen = term.screen();
+    assert_eq!(screen.spilled_rows(), 597);
+
+    let mut logical = vec![];
+    screen.for_each_logical_line_in_stable_range(590..601, |range, lines| {
+        let text: String = lines.iter().map(|line| line.as_str().to_string()).collect();
+        logical.push((range, text.trim_end().to_string()));
+        true
+    });
+    assert!(
+        logical.contains(&(596..599, "abcdefghijklmnopqrstuvwxy".to_string())),
+        "{logical:?}"
+    );
+    assert!(
+        logical.contains(&(599..600, "end".to_string())),
+        "{logical:?}"
+    );
+}
+
 #[test]
 fn test_hyperlinks() {
     let mut term = TestTerm::new(3, 5, 0);
@@ -1373,3 +1603,375 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn test_kitty_unicode_placeholders() {
+    #[derive(Debug)]
+    struct KittyConfig;
+    impl TerminalConfiguration for KittyConfig {
+        fn color_palette(&self) -> ColorPalette {
+            ColorPalette::default()
+        }
+
+        fn enable_kitty_graphics(&self) -> bool {
+            true
+        }
+    }
+
+    // Cells are 8x16 pixels, so this 16x32 image exactly fills
+    // the 2x2 cells of its virtual placement
+    let mut term = Terminal::new(
+        TerminalSize {
+            rows: 4,
+            cols: 10,
+            pixel_width: 80,
+            pixel_height: 64,
+            dpi: 0,
+        },
+        Arc::new(KittyConfig),
+        "WezTerm",
+        "O_o",
+        Box::new(Vec::new()),
+    );
+    term.advance_bytes(format!(
+        "\x1b_Ga=T,f=32,s=16,v=32,i=1,U=1,c=2,r=2,q=2;{}AAA=\x1b\\",
+        "A".repeat(2728)
+    ));
+
+    // Select image 1 via the foreground color.  The first row specifies
+    // both the row and column diacritics and then relies on inheritance,
+    // while the second row only specifies the row.  The third row
+    // references a cell outside of the placement.
+    term.advance_bytes("\x1b[38;5;1m\u{10EEEE}\u{305}\u{305}\u{10EEEE}\r\n");
+    term.advance_bytes("\u{10EEEE}\u{30D}\u{10EEEE}\u{30D}\r\n");
+    term.advance_bytes("\u{10EEEE}\u{305}\u{30E}\x1b[m");
+
+    // The placeholders remain in the screen
+    let (_, lines) = term.screen().lines_in_stable_range(0..3);
+    for line in &lines {
+        assert!(line
+            .visible_cells()
+            .all(|cell| cell.attrs().images().is_none()));
+    }
+    assert_eq!(
+        lines[0].visible_cells().next().unwrap().str(),
+        "\u{10EEEE}\u{305}\u{305}"
+    );
+
+    // and are resolved into slices of the image when rendered
+    let resolver = term.kitty_placeholder_resolver().unwrap();
+    let mut slices = vec![];
+    for line in &lines[0..2] {
+        let resolved = resolver.resolve(line).unwrap();
+        for x in 0..2 {
+            let cell = resolved.get_cell(x).unwrap();
+            assert_eq!(cell.str(), " ");
+            let images = cell.attrs().images().unwrap();
+            assert_eq!(images.len(), 1);
+            assert_eq!(images[0].image_id(), Some(1));
+            let tl = images[0].top_left();
+            let br = images[0].bottom_right();
+            slices.push((
+                (tl.x.into_inner(), tl.y.into_inner()),
+                (br.x.into_inner(), br.y.into_inner()),
+            ));
+        }
+    }
+    assert_eq!(
+        slices,
+        vec![
+            ((0.0, 0.0), (0.5, 0.5)),
+            ((0.5, 0.0), (1.0, 0.5)),
+            ((0.0, 0.5), (0.5, 1.0)),
+            ((0.5, 0.5), (1.0, 1.0)),
+        ]
+    );
+
+    // Cells outside of the placement don't display anything
+    let resolved = resolver.resolve(&lines[2]).unwrap();
+    assert!(resolved.get_cell(0).unwrap().attrs().images().is_none());
+
+    // Deleting the placement leaves nothing to resolve
+    term.advance_bytes("\x1b_Ga=d,d=i,i=1,q=2\x1b\\");
+    assert!(term.kitty_placeholder_resolver().is_none());
+}
+
+/// Collects the responses that the terminal sends to the application
+struct ChannelWriter(std::sync::mpsc::Sender<Vec<u8>>);
+
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.send(buf.to_vec()).ok();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn read_response(rx: &std::sync::mpsc::Receiver<Vec<u8>>) -> String {
+    let mut response = vec![];
+    while !response.ends_with(b"\x1b\\") {
+        match rx.recv_timeout(std::time::Duration::from_secs(5)) {
+            Ok(data) => response.extend_from_slice(&data),
+            Err(_) => break,
+        }
+    }
+    String::from_utf8(response).unwrap()
+}
+
+#[derive(Default)]
+struct QueryClip {
+    queries: Mutex<Vec<ClipboardSelection>>,
+}
+
+impl Clipboard for QueryClip {
+    fn set_contents(
+        &self,
+        _selection: ClipboardSelection,
+        _clip: Option<String>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn query_contents(&self, selection: ClipboardSelection) -> anyhow::Result<()> {
+        self.queries.lock().unwrap().push(selection);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_osc52_query() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut term = TestTerm::with_writer(3, 10, 0, Box::new(ChannelWriter(tx)));
+
+    // LocalClip doesn't support reads, so the query is answered
+    // immediately with empty data
+    term.print("\x1b]52;c;?\x1b\\");
+    assert_eq!(read_response(&rx), "\x1b]52;c;\x1b\\".to_string());
+
+    let clip = Arc::new(QueryClip::default());
+    let dyn_clip: Arc<dyn Clipboard> = clip.clone();
+    term.set_clipboard(&dyn_clip);
+
+    term.print("\x1b]52;c;?\x1b\\");
+    term.print("\x1b]52;p;?\x1b\\");
+    assert_eq!(
+        clip.queries.lock().unwrap().clone(),
+        vec![
+            ClipboardSelection::Clipboard,
+            ClipboardSelection::PrimarySelection
+        ]
+    );
+
+    term.answer_clipboard_query(ClipboardSelection::Clipboard, Some("hello".to_string()));
+    assert_eq!(read_response(&rx), "\x1b]52;c;aGVsbG8=\x1b\\".to_string());
+
+    // Declining the query produces an empty response
+    term.answer_clipboard_query(ClipboardSelection::PrimarySelection, None);
+    assert_eq!(read_response(&rx), "\x1b]52;p;\x1b\\".to_string());
+}
+
+/// Collects the alerts that the terminal generates
+#[derive(Clone, Default)]
+struct AlertCollector(Arc<Mutex<Vec<Alert>>>);
+
+impl AlertHandler for AlertCollector {
+    fn alert(&mut self, alert: Alert) {
+        self.0.lock().unwrap().push(alert);
+    }
+}
+
+#[test]
+fn test_kitty_notification() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut term = TestTerm::with_writer(3, 10, 0, Box::new(ChannelWriter(tx)));
+    let alerts = AlertCollector::default();
+    term.set_notification_handler(Box::new(alerts.clone()));
+
+    term.print("\x1b]99;i=q:p=?;\x1b\\");
+    assert!(read_response(&rx).starts_with("\x1b]99;i=q:p=?;a=focus,report:"));
+
+    // The title and body are assembled from several chunks
+    term.print("\x1b]99;i=1:d=0:u=2:o=unfocused;Hello\x1b\\");
+    term.print("\x1b]99;i=1:d=0; world\x1b\\");
+    assert!(alerts.0.lock().unwrap().is_empty());
+    term.print("\x1b]99;i=1:p=body:e=1:a=report:c=1;Ym9keQ==\x1b\\");
+    term.print("\x1b]99;i=1:p=close;\x1b\\");
+
+    // Identifiers are restricted to [a-zA-Z0-9-_+.]
+    term.print("\x1b]99;i=a/b;ignored\x1b\\");
+    term.print("\x1b]99;i=a b;ignored\x1b\\");
+
+    assert_eq!(
+        alerts.0.lock().unwrap().clone(),
+        vec![
+            Alert::DesktopNotification(DesktopNotification {
+                id: Some("1".to_string()),
+                title: "Hello world".to_string(),
+                body: "body".to_string(),
+                urgency: Some(NotificationUrgency::Critical),
+                occasion: NotificationOccasion::Unfocused,
+                focus: true,
+                report_activation: true,
+                report_close: true,
+                timeout: None,
+            }),
+            Alert::CloseDesktopNotification {
+                id: "1".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_color_scheme_updates() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut term = TestTerm::with_writer(3, 10, 0, Box::new(ChannelWriter(tx)));
+
+    term.print("\x1b[?996n");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[?997;1n".to_vec());
+
+    term.print("\x1b[?2031h");
+    term.print("\x1b]11;#ffffff\x1b\\");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[?997;2n".to_vec());
+
+    // Changing the background without changing its lightness
+    // class doesn't produce a report
+    term.print("\x1b]11;#eeeeee\x1b\\");
+    term.print("\x1b[?2031l");
+    term.print("\x1b]11;#000000\x1b\\");
+    term.print("\x1b[?996n");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[?997;1n".to_vec());
+}
+
+#[test]
+fn test_text_sizing() {
+    let mut term = TestTerm::new(4, 10, 0);
+    term.print("a\x1b]66;s=2;BC\x1b\\d");
+
+    // Each grapheme occupies a 2x2 block, the text of which is held
+    // by the cell at its top left
+    let geometry = |term: &mut TestTerm, x: usize, y: i64| {
+        let cell = term.screen_mut().get_cell(x, y).unwrap().clone();
+        let size = cell
+            .attrs()
+            .text_size()
+            .map(|size| (size.scale, size.width, size.x, size.y));
+        (cell.str().to_string(), size)
+    };
+    assert_eq!(geometry(&mut term, 0, 0), ("a".to_string(), None));
+    assert_eq!(
+        geometry(&mut term, 1, 0),
+        ("B".to_string(), Some((2, 2, 0, 0)))
+    );
+    assert_eq!(
+        geometry(&mut term, 3, 0),
+        ("C".to_string(), Some((2, 2, 0, 0)))
+    );
+    assert_eq!(
+        geometry(&mut term, 1, 1),
+        (" ".to_string(), Some((2, 2, 0, 1)))
+    );
+    assert_eq!(
+        geometry(&mut term, 4, 1),
+        (" ".to_string(), Some((2, 2, 1, 1)))
+    );
+    assert_eq!(geometry(&mut term, 5, 0), ("d".to_string(), None));
+    // Printing doesn't change the cursor seqno
+    term.assert_cursor_pos(6, 0, None, Some(0));
+
+    // Overwriting part of a block clears the whole of it
+    term.cup(2, 1);
+    term.print("X");
+    assert_eq!(geometry(&mut term, 1, 0), (" ".to_string(), None));
+    assert_eq!(geometry(&mut term, 1, 1), (" ".to_string(), None));
+    assert_eq!(geometry(&mut term, 2, 1), ("X".to_string(), None));
+    assert_eq!(
+        geometry(&mut term, 3, 0),
+        ("C".to_string(), Some((2, 2, 0, 0)))
+    );
+    assert_eq!(
+        geometry(&mut term, 4, 1),
+        (" ".to_string(), Some((2, 2, 1, 1)))
+    );
+}
+
+#[test]
+fn test_sgr_stack() {
+    use crate::color::ColorAttribute;
+
+    let mut term = TestTerm::new(3, 10, 0);
+
+    // Restore all of the attributes
+    term.print("\x1b[1;31m\x1b[#{\x1b[0;4;32mA\x1b[#}B");
+    let attrs = term.screen_mut().get_cell(1, 0).unwrap().attrs().clone();
+    assert_eq!(attrs.intensity(), Intensity::Bold);
+    assert_eq!(attrs.underline(), Underline::None);
+    assert_eq!(attrs.foreground(), ColorAttribute::PaletteIndex(1));
+
+    // Restore only the foreground color
+    term.print("\x1b[m\x1b[31m\x1b[30#{\x1b[1;32mC\x1b[#}D");
+    let attrs = term.screen_mut().get_cell(3, 0).unwrap().attrs().clone();
+    assert_eq!(attrs.intensity(), Intensity::Bold);
+    assert_eq!(attrs.foreground(), ColorAttribute::PaletteIndex(1));
+
+    // Popping an empty stack has no effect
+    term.print("\x1b[#}E");
+    let attrs = term.screen_mut().get_cell(4, 0).unwrap().attrs().clone();
+    assert_eq!(attrs.intensity(), Intensity::Bold);
+}
+
+#[test]
+fn test_report_sgr() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut term = TestTerm::with_writer(3, 10, 0, Box::new(ChannelWriter(tx)));
+
+    term.print("\x1b[1;4;31mAB\x1b[22;38:5:100mC\x1b[m");
+
+    term.print("\x1b[1;1;1;2#|");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[0;1;4;31m".to_vec());
+
+    // Only the underline is common to all three cells
+    term.print("\x1b[1;1;1;3#|");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[0;4m".to_vec());
+
+    term.print("\x1b[1;3;1;3#|");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[0;4;38:5:100m".to_vec());
+
+    // Blank cells have no attributes
+    term.print("\x1b[1;1;2;3#|");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[0m".to_vec());
+}
+
+#[test]
+fn test_color_stack() {
+    use crate::color::{RgbColor, SrgbaTuple};
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut term = TestTerm::with_writer(3, 10, 0, Box::new(ChannelWriter(tx)));
+    let red: SrgbaTuple = RgbColor::new_8bpc(0xff, 0, 0).into();
+    let green: SrgbaTuple = RgbColor::new_8bpc(0, 0xff, 0).into();
+
+    term.print("\x1b[#R");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[0;0#Q".to_vec());
+
+    term.print("\x1b]4;1;#ff0000\x1b\\\x1b[#P");
+    term.print("\x1b]4;1;#00ff00\x1b\\\x1b[#P");
+    term.print("\x1b[#R");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[2;2#Q".to_vec());
+
+    term.print("\x1b]4;1;#0000ff\x1b\\\x1b[#Q");
+    assert_eq!(term.palette().colors.0[1], green);
+    term.print("\x1b[#Q");
+    assert_eq!(term.palette().colors.0[1], red);
+    term.print("\x1b[#R");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[1;0#Q".to_vec());
+
+    // Restoring from a slot doesn't pop it
+    term.print("\x1b]4;1;#0000ff\x1b\\\x1b[2#Q");
+    assert_eq!(term.palette().colors.0[1], green);
+    term.print("\x1b[#R");
+    assert_eq!(rx.recv().unwrap(), b"\x1b[2;0#Q".to_vec());
+}
@@ -0,0 +1,105 @@
+//! Round trips images through termwiz's sixel encoder and the
+//! terminal's sixel decoder
+use super::*;
+use k9::assert_equal as assert_eq;
+use termwiz::sixel::SixelEncoder;
+use wezterm_cell::image::ImageDataType;
+
+/// Returns the dimensions and RGBA data of the image in the top
+/// left cell
+fn decoded_image(term: &mut TestTerm) -> (u32, u32, Vec<u8>) {
+    let cell = term.screen_mut().get_cell(0, 0).unwrap().clone();
+    let images = cell.attrs().images().unwrap();
+    let data = images[0].image_data().data();
+    let (width, height, pixels) = match &*data {
+        ImageDataType::Rgba8 {
+            width,
+            height,
+            data,
+            ..
+        } => (*width, *height, data.clone()),
+        other => panic!("unexpected image data {:?}", other),
+    };
+    (width, height, pixels)
+}
+
+#[test]
+fn test_sixel_round_trip() {
+    let colors = [[255, 0, 0], [0, 128, 255], [30, 200, 60], [250, 250, 250]];
+    let (width, height) = (10, 8);
+
+    // The top left corner is transparent
+    let mut data = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            if x < 2 && y < 2 {
+                data.extend_from_slice(&[0, 0, 0, 0]);
+            } else {
+                data.extend_from_slice(&colors[((x / 3 + y / 3) % 4) as usize]);
+                data.push(0xff);
+            }
+        }
+    }
+
+    let sixel = SixelEncoder::default()
+        .encode(width, height, &data)
+        .unwrap();
+    assert!(sixel.background_is_transparent);
+
+    let mut term = TestTerm::new(4, 10, 0);
+    term.print(format!("{}\x1b\\", sixel));
+
+    let (decoded_width, decoded_height, decoded) = decoded_image(&mut term);
+    assert_eq!((decoded_width, decoded_height), (width, height));
+
+    // The color components are sent as percentages, so the
+    // decoded colors may be off by a little
+    for (idx, (expected, actual)) in data.chunks(4).zip(decoded.chunks(4)).enumerate() {
+        if expected[3] == 0 {
+            assert_eq!(actual[3], 0, "pixel {}", idx);
+            continue;
+        }
+        assert_eq!(actual[3], 0xff, "pixel {}", idx);
+        for (e, a) in expected.iter().zip(actual.iter()).take(3) {
+            assert!(
+                (*e as i32 - *a as i32).abs() <= 2,
+                "pixel {} expected {:?} got {:?}",
+                idx,
+                expected,
+                actual
+            );
+        }
+    }
+}
+
+#[test]
+fn test_sixel_quantized_round_trip() {
+    let (width, height) = (32, 13);
+    let mut data = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            data.extend_from_slice(&[(x * 8) as u8, (y * 20) as u8, 128, 0xff]);
+        }
+    }
+
+    let encoder = SixelEncoder::new(8);
+    let sixel = encoder.encode(width, height, &data).unwrap();
+    assert!(!sixel.background_is_transparent);
+
+    let mut term = TestTerm::new(4, 10, 0);
+    term.print(format!("{}\x1b\\", sixel));
+
+    let (decoded_width, decoded_height, decoded) = decoded_image(&mut term);
+    assert_eq!((decoded_width, decoded_height), (width, height));
+
+    let mut distinct = decoded
+        .chunks(4)
+        .map(|pixel| {
+            assert_eq!(pixel[3], 0xff);
+            [pixel[0], pixel[1], pixel[2]]
+        })
+        .collect::<Vec<_>>();
+    distinct.sort();
+    distinct.dedup();
+    assert!(distinct.len() <= 8, "{} colors", distinct.len());
+}
@@ -93,6 +93,10 @@ builder! {
         /// Configure whether sixel graphics are supported.
         sixel: Option<bool>,
 
+        /// The number of sixel color registers, as reported by
+        /// the terminal in response to XTSMGRAPHICS.
+        sixel_color_registers: Option<usize>,
+
         /// Configure whether iTerm2 style graphics embedding is supported
         /// See <https://www.iterm2.com/documentation-images.html>
         iterm2_image: Option<bool>,
@@ -173,6 +177,7 @@ pub struct Capabilities {
     color_level: ColorLevel,
     hyperlinks: bool,
     sixel: bool,
+    sixel_color_registers: usize,
     iterm2_image: bool,
     bce: bool,
     terminfo_db: Option<terminfo::Database>,
@@ -262,6 +267,9 @@ impl Capabilities {
         // I don't know of a way to detect SIXEL support, so we
         // assume no by default.
         let sixel = hints.sixel.unwrap_or(false);
+        let sixel_color_registers = hints
+            .sixel_color_registers
+            .unwrap_or(crate::sixel::DEFAULT_COLOR_REGISTERS);
 
         // The use of OSC 8 for hyperlinks means that it is generally
         // safe to assume yes: if the terminal doesn't support it,
@@ -316,6 +324,7 @@ impl Capabilities {
         Ok(Self {
             color_level,
             sixel,
+            sixel_color_registers,
             hyperlinks,
             iterm2_image,
             bce,
@@ -336,6 +345,11 @@ impl Capabilities {
         self.sixel
     }
 
+    /// How many color registers are available for SIXEL graphics?
+    pub fn sixel_color_registers(&self) -> usize {
+        self.sixel_color_registers
+    }
+
     /// Does the terminal support hyperlinks?
     /// See <https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda>
     pub fn hyperlinks(&self) -> bool {
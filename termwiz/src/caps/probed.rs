@@ -1,4 +1,4 @@
-use crate::escape::csi::{Device, Window};
+use crate::escape::csi::{Device, Window, XtSmGraphics, XtSmGraphicsItem, XtSmGraphicsStatus};
 use crate::escape::parser::Parser;
 use crate::escape::{Action, DeviceControlMode, Esc, EscCode, CSI};
 use crate::terminal::ScreenSize;
@@ -124,6 +124,49 @@ impl<'a> ProbeCapabilities<'a> {
         Ok(XtVersion(String::from_utf8_lossy(&term).into()))
     }
 
+    /// Probe for the number of sixel color registers using XTSMGRAPHICS.
+    /// Returns None if the terminal doesn't report it.
+    pub fn sixel_color_registers(&mut self) -> Result<Option<usize>> {
+        let query = CSI::Device(Box::new(Device::XtSmGraphics(XtSmGraphics {
+            item: XtSmGraphicsItem::NumberOfColorRegisters,
+            action_or_status: 1,
+            value: vec![],
+        })));
+        let dev_attributes = CSI::Device(Box::new(Device::RequestPrimaryDeviceAttributes));
+
+        write!(self.write, "{query}{dev_attributes}")?;
+        self.write.flush()?;
+
+        let mut parser = Parser::new();
+        let mut done = false;
+        let mut registers = None;
+
+        while !done {
+            let mut byte = [0u8];
+            self.read.read(&mut byte)?;
+
+            parser.parse(&byte, |action| match action {
+                Action::CSI(CSI::Device(dev)) => match *dev {
+                    Device::XtSmGraphics(g) => {
+                        if g.item == XtSmGraphicsItem::NumberOfColorRegisters
+                            && g.status() == Some(XtSmGraphicsStatus::Success)
+                        {
+                            registers = g.value.first().filter(|&&n| n > 0).map(|&n| n as usize);
+                        }
+                    }
+                    _ => {
+                        done = true;
+                    }
+                },
+                _ => {
+                    done = true;
+                }
+            });
+        }
+
+        Ok(registers)
+    }
+
     /// Probe the terminal and determine the ScreenSize.
     pub fn screen_size(&mut self) -> Result<ScreenSize> {
         let xt_version = self.xt_version()?;
@@ -56,6 +56,7 @@ mod macros;
 pub use wezterm_char_props::nerdfonts;
 mod readbuf;
 pub mod render;
+pub mod sixel;
 pub use wezterm_surface as surface;
 pub mod terminal;
 #[cfg(feature = "tmux_cc")]
@@ -7,10 +7,14 @@ use crate::escape::esc::EscCode;
 use crate::escape::osc::OperatingSystemCommand;
 #[cfg(feature = "use_image")]
 use crate::escape::osc::{ITermDimension, ITermFileData, ITermProprietary};
+#[cfg(feature = "use_image")]
+use crate::escape::Sixel;
 use crate::escape::{Esc, OneBased};
 #[cfg(feature = "use_image")]
 use crate::image::{ImageDataType, TextureCoordinate};
 use crate::render::RenderTty;
+#[cfg(feature = "use_image")]
+use crate::sixel::SixelEncoder;
 use crate::surface::{Change, CursorShape, CursorVisibility, LineAttribute, Position};
 use crate::Result;
 use std::io::Write;
@@ -620,8 +624,21 @@ impl TerminfoRenderer {
                         ));
 
                         write!(out, "{}", osc)?;
+                    } else if self.caps.sixel() {
+                        let sixel = image_to_sixel(image, self.caps.sixel_color_registers())?;
 
-                    // TODO: } else if self.caps.sixel() {
+                        // Terminals differ in where they leave the cursor
+                        // after drawing sixel graphics, so put it back where
+                        // it was and then move past the image ourselves
+                        write!(
+                            out,
+                            "{}{}{}{}",
+                            Esc::Code(EscCode::DecSaveCursorPosition),
+                            sixel,
+                            Esc::Code(EscCode::StringTerminator),
+                            Esc::Code(EscCode::DecRestoreCursorPosition)
+                        )?;
+                        self.cursor_right(image.width as u32, out)?;
                     } else {
                         // Blank out the cells and move the cursor to the right spot
                         for y in 0..image.height {
@@ -725,6 +742,47 @@ impl TerminfoRenderer {
     }
 }
 
+/// Encodes the region of the image that is covered by the texture
+/// coordinates as sixel.  The image is drawn at its native resolution,
+/// as we don't know the pixel dimensions of the cells.
+#[cfg(feature = "use_image")]
+fn image_to_sixel(image: &crate::surface::Image, max_colors: usize) -> Result<Sixel> {
+    let data = match &*image.image.data() {
+        ImageDataType::EncodedLease(lease) => ImageDataType::EncodedFile(lease.get_data()?),
+        data => data.clone(),
+    };
+    let (width, height, pixels) = match data.decode() {
+        ImageDataType::Rgba8 {
+            data,
+            width,
+            height,
+            ..
+        } => (width, height, data),
+        ImageDataType::AnimRgba8 {
+            width,
+            height,
+            mut frames,
+            ..
+        } if !frames.is_empty() => (width, height, frames.swap_remove(0)),
+        _ => crate::bail!("unable to decode image data for sixel"),
+    };
+
+    let to_pixel = |coord: f32, size: u32| ((coord * size as f32).round() as u32).min(size);
+    let left = to_pixel(image.top_left.x.into_inner(), width);
+    let top = to_pixel(image.top_left.y.into_inner(), height);
+    let right = to_pixel(image.bottom_right.x.into_inner(), width).max(left);
+    let bottom = to_pixel(image.bottom_right.y.into_inner(), height).max(top);
+
+    let mut region = Vec::with_capacity(((right - left) * (bottom - top) * 4) as usize);
+    for y in top..bottom {
+        let start = ((y * width + left) * 4) as usize;
+        let end = ((y * width + right) * 4) as usize;
+        region.extend_from_slice(&pixels[start..end]);
+    }
+
+    Ok(SixelEncoder::new(max_colors).encode(right - left, bottom - top, &region)?)
+}
+
 #[cfg(all(test, unix))]
 mod test {
     use super::*;
@@ -0,0 +1,445 @@
+//! Encodes images as sixel graphics.
+//! <https://vt100.net/docs/vt3xx-gp/chapter14.html>
+//!
+//! Sixel images are drawn using a limited number of color registers,
+//! so the image is first reduced to a palette using the median cut
+//! algorithm, optionally applying Floyd-Steinberg dithering to hide
+//! the banding that comes from using fewer colors.
+use crate::color::RgbColor;
+use crate::escape::{Sixel, SixelData};
+use std::collections::{BTreeMap, HashMap};
+
+/// The number of color registers to use when the terminal hasn't
+/// reported how many it has.  This is the number that xterm provides
+/// in its default configuration.
+pub const DEFAULT_COLOR_REGISTERS: usize = 256;
+
+/// The color registers are addressed by a 16-bit number
+const MAX_COLOR_REGISTERS: usize = 65536;
+
+type Rgb = [u8; 3];
+
+/// Configures how an image is encoded as sixel
+#[derive(Debug, Clone)]
+pub struct SixelEncoder {
+    /// The maximum number of colors to use.  This should be no larger
+    /// than the number of color registers that the terminal reports
+    /// via XTSMGRAPHICS.
+    pub max_colors: usize,
+    /// Whether to apply Floyd-Steinberg dithering when the image has
+    /// more colors than can be represented
+    pub dither: bool,
+    /// Pixels whose alpha channel is below this value are transparent;
+    /// they are left unpainted and the image is sent with P2=1 so that
+    /// the terminal leaves whatever is behind them visible.
+    pub alpha_threshold: u8,
+}
+
+impl Default for SixelEncoder {
+    fn default() -> Self {
+        Self {
+            max_colors: DEFAULT_COLOR_REGISTERS,
+            dither: true,
+            alpha_threshold: 128,
+        }
+    }
+}
+
+impl SixelEncoder {
+    /// Create an encoder that uses up to `max_colors` color registers
+    pub fn new(max_colors: usize) -> Self {
+        Self {
+            max_colors,
+            ..Self::default()
+        }
+    }
+
+    /// Encode an image of the specified dimensions.  `data` holds the
+    /// pixels in RGBA order, row by row.
+    /// The returned `Sixel` formats as the DCS sequence, but without
+    /// the ST that terminates it.
+    /// Returns an error if `data` doesn't hold exactly `width * height`
+    /// pixels.
+    pub fn encode(&self, width: u32, height: u32, data: &[u8]) -> anyhow::Result<Sixel> {
+        anyhow::ensure!(
+            width as usize * height as usize * 4 == data.len(),
+            "invalid dimensions {}x{} for pixel data of length {}",
+            width,
+            height,
+            data.len()
+        );
+
+        let mut histogram: HashMap<Rgb, u32> = HashMap::new();
+        for pixel in data.chunks_exact(4) {
+            if pixel[3] >= self.alpha_threshold {
+                *histogram.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+            }
+        }
+
+        let max_colors = self.max_colors.clamp(1, MAX_COLOR_REGISTERS);
+        let palette = median_cut(histogram.into_iter().collect(), max_colors);
+        let indices = self.map_pixels(width as usize, height as usize, data, &palette);
+
+        let mut sixel_data = vec![];
+        for (color_number, &[r, g, b]) in palette.iter().enumerate() {
+            sixel_data.push(SixelData::DefineColorMapRGB {
+                color_number: color_number as u16,
+                rgb: RgbColor::new_8bpc(r, g, b),
+            });
+        }
+
+        let width = width as usize;
+        let height = height as usize;
+        for top in (0..height).step_by(6) {
+            // Build up the sixel values for each color that is used
+            // in this band of 6 rows
+            let mut band: BTreeMap<u16, Vec<u8>> = BTreeMap::new();
+            for bit in 0..6.min(height - top) {
+                let row = (top + bit) * width;
+                for x in 0..width {
+                    if let Some(color) = indices[row + x] {
+                        band.entry(color).or_insert_with(|| vec![0; width])[x] |= 1 << bit;
+                    }
+                }
+            }
+
+            for (i, (color, values)) in band.into_iter().enumerate() {
+                if i > 0 {
+                    sixel_data.push(SixelData::CarriageReturn);
+                }
+                sixel_data.push(SixelData::SelectColorMapEntry(color));
+                run_length_encode(&values, &mut sixel_data);
+            }
+
+            if top + 6 < height {
+                sixel_data.push(SixelData::NewLine);
+            }
+        }
+
+        Ok(Sixel {
+            pan: 1,
+            pad: 1,
+            pixel_width: Some(width as u32),
+            pixel_height: Some(height as u32),
+            background_is_transparent: indices.iter().any(Option::is_none),
+            horizontal_grid_size: None,
+            data: sixel_data,
+        })
+    }
+
+    /// Assigns each pixel to its closest palette entry, or None
+    /// if it is transparent
+    fn map_pixels(
+        &self,
+        width: usize,
+        height: usize,
+        data: &[u8],
+        palette: &[Rgb],
+    ) -> Vec<Option<u16>> {
+        let mut pixels: Vec<Option<[f32; 3]>> = data
+            .chunks_exact(4)
+            .map(|pixel| {
+                if pixel[3] >= self.alpha_threshold {
+                    Some([pixel[0] as f32, pixel[1] as f32, pixel[2] as f32])
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let mut cache: HashMap<Rgb, u16> = HashMap::new();
+        let mut indices = vec![None; pixels.len()];
+
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * width + x;
+                let color = match pixels[idx] {
+                    Some(color) => color,
+                    None => continue,
+                };
+                let rgb = color.map(|c| c.round().clamp(0., 255.) as u8);
+                let nearest = *cache
+                    .entry(rgb)
+                    .or_insert_with(|| nearest_color(palette, rgb));
+                indices[idx] = Some(nearest);
+
+                if self.dither {
+                    let chosen = palette[nearest as usize];
+                    let error = [
+                        color[0] - chosen[0] as f32,
+                        color[1] - chosen[1] as f32,
+                        color[2] - chosen[2] as f32,
+                    ];
+                    let mut diffuse = |x: usize, y: usize, weight: f32| {
+                        if x < width && y < height {
+                            if let Some(pixel) = pixels[y * width + x].as_mut() {
+                                for (p, e) in pixel.iter_mut().zip(error.iter()) {
+                                    *p += e * weight;
+                                }
+                            }
+                        }
+                    };
+                    diffuse(x + 1, y, 7. / 16.);
+                    if x > 0 {
+                        diffuse(x - 1, y + 1, 3. / 16.);
+                    }
+                    diffuse(x, y + 1, 5. / 16.);
+                    diffuse(x + 1, y + 1, 1. / 16.);
+                }
+            }
+        }
+
+        indices
+    }
+}
+
+fn nearest_color(palette: &[Rgb], rgb: Rgb) -> u16 {
+    let distance = |candidate: &Rgb| -> i32 {
+        candidate
+            .iter()
+            .zip(rgb.iter())
+            .map(|(a, b)| {
+                let delta = *a as i32 - *b as i32;
+                delta * delta
+            })
+            .sum()
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, candidate)| distance(candidate))
+        .map(|(idx, _)| idx as u16)
+        .unwrap_or(0)
+}
+
+/// Appends the sixel values, compressing runs of the same value.
+/// Trailing empty values are omitted, as the next band or color
+/// starts from the left edge anyway.
+fn run_length_encode(values: &[u8], sixel_data: &mut Vec<SixelData>) {
+    let len = values
+        .iter()
+        .rposition(|&v| v != 0)
+        .map_or(0, |pos| pos + 1);
+    let mut x = 0;
+    while x < len {
+        let data = values[x];
+        let run = values[x..len].iter().take_while(|&&v| v == data).count();
+        // A repeat introducer is only shorter than the values
+        // themselves when there are more than 3 of them
+        if run > 3 {
+            sixel_data.push(SixelData::Repeat {
+                repeat_count: run as u32,
+                data,
+            });
+        } else {
+            for _ in 0..run {
+                sixel_data.push(SixelData::Data(data));
+            }
+        }
+        x += run;
+    }
+}
+
+/// A set of colors, with the number of pixels of each, that is
+/// represented by a single palette entry
+struct ColorBox {
+    colors: Vec<(Rgb, u32)>,
+}
+
+impl ColorBox {
+    fn population(&self) -> u64 {
+        self.colors.iter().map(|(_, count)| *count as u64).sum()
+    }
+
+    /// Returns the channel with the largest range of values,
+    /// along with that range
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+            .map(|c| {
+                let min = self.colors.iter().map(|(rgb, _)| rgb[c]).min().unwrap_or(0);
+                let max = self.colors.iter().map(|(rgb, _)| rgb[c]).max().unwrap_or(0);
+                (c, max - min)
+            })
+            .max_by_key(|(_, range)| *range)
+            .unwrap_or((0, 0))
+    }
+
+    /// Boxes that cover a wide range of colors used by many
+    /// pixels are the most worthwhile to split
+    fn priority(&self) -> u64 {
+        self.widest_channel().1 as u64 * self.population()
+    }
+
+    /// Splits the box at the median pixel along its widest channel.
+    /// The box must hold at least two colors.
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.colors.sort_by_key(|(rgb, _)| rgb[channel]);
+
+        let half = self.population() / 2;
+        let mut seen = 0;
+        let mut median = self.colors.len() - 1;
+        for (i, (_, count)) in self.colors.iter().enumerate() {
+            seen += *count as u64;
+            if seen >= half {
+                median = i + 1;
+                break;
+            }
+        }
+        let median = median.clamp(1, self.colors.len() - 1);
+
+        let upper = self.colors.split_off(median);
+        (self, ColorBox { colors: upper })
+    }
+
+    /// The average of the colors, weighted by the number of pixels
+    fn average(&self) -> Rgb {
+        let population = self.population().max(1);
+        let mut sum = [0u64; 3];
+        for (rgb, count) in &self.colors {
+            for (s, c) in sum.iter_mut().zip(rgb.iter()) {
+                *s += *c as u64 * *count as u64;
+            }
+        }
+        sum.map(|s| ((s + population / 2) / population) as u8)
+    }
+}
+
+/// Reduces the colors to a palette of at most `max_colors` entries.
+/// If there are few enough colors, they are used as-is.
+fn median_cut(colors: Vec<(Rgb, u32)>, max_colors: usize) -> Vec<Rgb> {
+    if colors.len() <= max_colors {
+        return colors.into_iter().map(|(rgb, _)| rgb).collect();
+    }
+
+    let mut boxes = vec![ColorBox { colors }];
+    while boxes.len() < max_colors {
+        let candidate = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.colors.len() > 1)
+            .max_by_key(|(_, b)| b.priority())
+            .map(|(idx, _)| idx);
+        match candidate {
+            Some(idx) => {
+                let (lower, upper) = boxes.swap_remove(idx).split();
+                boxes.push(lower);
+                boxes.push(upper);
+            }
+            None => break,
+        }
+    }
+
+    boxes.iter().map(ColorBox::average).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::escape::parser::Parser;
+    use crate::escape::Action;
+
+    /// A horizontal gradient from red to blue, with a vertical
+    /// gradient of green
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        let mut data = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&[
+                    (255 * (width - 1 - x) / (width - 1)) as u8,
+                    (255 * y / (height - 1)) as u8,
+                    (255 * x / (width - 1)) as u8,
+                    0xff,
+                ]);
+            }
+        }
+        data
+    }
+
+    fn palette_size(sixel: &Sixel) -> usize {
+        sixel
+            .data
+            .iter()
+            .filter(|d| matches!(d, SixelData::DefineColorMapRGB { .. }))
+            .count()
+    }
+
+    #[test]
+    fn honours_max_colors() {
+        let data = gradient(64, 20);
+        for max_colors in [1, 2, 16, 256] {
+            for dither in [false, true] {
+                let encoder = SixelEncoder {
+                    max_colors,
+                    dither,
+                    ..SixelEncoder::default()
+                };
+                let sixel = encoder.encode(64, 20, &data).unwrap();
+                assert!(palette_size(&sixel) <= max_colors);
+                assert!(!sixel.background_is_transparent);
+            }
+        }
+    }
+
+    #[test]
+    fn exact_palette() {
+        // Fewer colors than registers are used exactly
+        let data = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 0, 0],
+            [255, 0, 0, 255],
+        ]
+        .concat();
+        let sixel = SixelEncoder::default().encode(2, 2, &data).unwrap();
+        assert_eq!(palette_size(&sixel), 2);
+        assert!(sixel.background_is_transparent);
+    }
+
+    #[test]
+    fn invalid_dimensions() {
+        let data = gradient(4, 4);
+        assert!(SixelEncoder::default().encode(4, 5, &data).is_err());
+        assert!(SixelEncoder::default().encode(3, 4, &data).is_err());
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let data = gradient(40, 15);
+        let sixel = SixelEncoder::new(16).encode(40, 15, &data).unwrap();
+        let encoded = format!("{}\x1b\\", sixel);
+
+        let parsed = match Parser::new().parse_as_vec(encoded.as_bytes()).first() {
+            Some(Action::Sixel(parsed)) => parsed.clone(),
+            other => panic!("expected sixel, got {:?}", other),
+        };
+        assert_eq!(parsed.dimensions(), (40, 15));
+        assert!(!parsed.background_is_transparent);
+        assert_eq!(parsed.data.len(), sixel.data.len());
+
+        // The color components are sent as percentages, so they
+        // may be off by a little
+        for (parsed, expected) in parsed.data.iter().zip(sixel.data.iter()) {
+            match (parsed, expected) {
+                (
+                    SixelData::DefineColorMapRGB {
+                        color_number: a,
+                        rgb: a_rgb,
+                    },
+                    SixelData::DefineColorMapRGB {
+                        color_number: b,
+                        rgb: b_rgb,
+                    },
+                ) => {
+                    assert_eq!(a, b);
+                    let (ar, ag, ab) = a_rgb.to_tuple_rgb8();
+                    let (br, bg, bb) = b_rgb.to_tuple_rgb8();
+                    for (a, b) in [(ar, br), (ag, bg), (ab, bb)] {
+                        assert!((a as i32 - b as i32).abs() <= 2, "{a_rgb:?} {b_rgb:?}");
+                    }
+                }
+                _ => assert_eq!(parsed, expected),
+            }
+        }
+    }
+}
@@ -13,7 +13,7 @@ pub use wezterm_char_props::emoji::Presentation;
 use wezterm_char_props::emoji_variation::WCWIDTH_TABLE;
 use wezterm_char_props::widechar_width::WcWidth;
 use wezterm_dynamic::{FromDynamic, ToDynamic};
-pub use wezterm_escape_parser::osc::Hyperlink;
+pub use wezterm_escape_parser::osc::{Hyperlink, TextSizingAlign};
 
 extern crate alloc;
 use crate::alloc::string::ToString;
@@ -99,6 +99,8 @@ struct FatAttributes {
     underline_color: ColorAttribute,
     foreground: ColorAttribute,
     background: ColorAttribute,
+    /// The geometry of multi-cell scaled text, if any
+    text_size: Option<TextSize>,
 }
 
 impl FatAttributes {
@@ -113,6 +115,51 @@ impl FatAttributes {
         self.underline_color.hash(hasher);
         self.foreground.hash(hasher);
         self.background.hash(hasher);
+        self.text_size.hash(hasher);
+    }
+}
+
+/// Describes a cell that is part of a block of cells occupied by
+/// text that was sized using kitty's text sizing protocol (OSC 66).
+/// The text is held by the top left cell of the block; the other
+/// cells of the block are blank, and are marked with their position
+/// in the block.
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextSize {
+    /// The number of lines occupied by the block; the text is
+    /// scaled by this factor
+    pub scale: u8,
+    /// The number of columns occupied by the block
+    pub width: u8,
+    /// The fractional scale that is applied to the text, in
+    /// addition to `scale`.  Ignored unless `denominator`
+    /// is greater than `numerator`.
+    pub numerator: u8,
+    pub denominator: u8,
+    pub vertical_align: TextSizingAlign,
+    pub horizontal_align: TextSizingAlign,
+    /// The column of this cell within the block
+    pub x: u8,
+    /// The line of this cell within the block
+    pub y: u8,
+}
+
+impl TextSize {
+    /// Returns the factor by which the font size is to be scaled
+    pub fn font_scale(&self) -> f32 {
+        let fraction = if self.denominator > self.numerator && self.numerator > 0 {
+            self.numerator as f32 / self.denominator as f32
+        } else {
+            1.0
+        };
+        self.scale as f32 * fraction
+    }
+
+    /// Returns true if this is the top left cell of the block,
+    /// which is the one that holds the text
+    pub fn is_origin(&self) -> bool {
+        self.x == 0 && self.y == 0
     }
 }
 
@@ -210,6 +257,8 @@ impl CellAttributes {
     bitfield!(overline, set_overline, 12);
     bitfield!(semantic_type, set_semantic_type, SemanticType, 0b11, 13);
     bitfield!(vertical_align, set_vertical_align, VerticalAlign, 0b11, 15);
+    // The cell is protected from selective erase by DECSCA
+    bitfield!(protected, set_protected, 17);
 
     pub const fn blank() -> Self {
         Self {
@@ -323,6 +372,7 @@ impl CellAttributes {
                 underline_color: ColorAttribute::Default,
                 foreground: ColorAttribute::Default,
                 background: ColorAttribute::Default,
+                text_size: None,
             }));
         }
     }
@@ -342,6 +392,7 @@ impl CellAttributes {
                     && fat.underline_color == ColorAttribute::Default
                     && fat.foreground == ColorAttribute::Default
                     && fat.background == ColorAttribute::Default
+                    && fat.text_size.is_none()
             })
             .unwrap_or(false);
         if deallocate {
@@ -458,6 +509,8 @@ impl CellAttributes {
         res.set_underline(Underline::None);
         res.set_overline(false);
         res.set_strikethrough(false);
+        // Blank cells are never protected from selective erase
+        res.set_protected(false);
         res
     }
 
@@ -465,6 +518,21 @@ impl CellAttributes {
         self.fat.as_ref().and_then(|fat| fat.hyperlink.as_ref())
     }
 
+    pub fn set_text_size(&mut self, text_size: Option<TextSize>) -> &mut Self {
+        if text_size.is_none() && self.fat.is_none() {
+            self
+        } else {
+            self.allocate_fat_attributes();
+            self.fat.as_mut().unwrap().text_size = text_size;
+            self.deallocate_fat_attributes_if_none();
+            self
+        }
+    }
+
+    pub fn text_size(&self) -> Option<TextSize> {
+        self.fat.as_ref().and_then(|fat| fat.text_size)
+    }
+
     /// Returns the list of attached images in z-index order.
     /// Returns None if there are no attached images; will
     /// never return Some(vec![]).
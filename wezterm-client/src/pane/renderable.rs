@@ -70,6 +70,11 @@ pub struct RenderableInner {
     pub working_dir: Option<Url>,
     pub seqno: SequenceNo,
 
+    /// Lines from a synchronized update that are being held back
+    /// until the fetch that was started at the associated time
+    /// completes, so that the update is presented in one frame
+    pending_synchronized_lines: Option<(Instant, Vec<(StableRowIndex, Line)>)>,
+
     fetch_limiter: RateLimiter,
 
     last_send_time: Instant,
@@ -117,6 +122,7 @@ impl RenderableInner {
             last_input_rtt: 0,
             input_serial: InputSerial::empty(),
             seqno: SEQ_ZERO,
+            pending_synchronized_lines: None,
         }
     }
 
@@ -361,17 +367,47 @@ impl RenderableInner {
         self.seqno = delta.seqno;
 
         let config = configuration();
-        for (stable_row, line) in bonus_lines {
-            log::trace!("bonus line {} seqno={}", stable_row, line.current_seqno());
-            self.put_line(stable_row, line, &config, None);
-            dirty.remove(stable_row);
+
+        // A prior synchronized update is still waiting for its fetch
+        // to complete; it is superseded by this one, so apply its
+        // lines now rather than risk them replacing newer lines later
+        if let Some((_, lines)) = self.pending_synchronized_lines.take() {
+            for (stable_row, line) in lines {
+                self.put_line(stable_row, line, &config, None);
+            }
         }
 
-        log::trace!(
-            "apply_changes_to_surface: Generate PaneOutput event for local={}",
-            self.local_pane_id
-        );
-        Mux::get().notify(mux::MuxNotification::PaneOutput(self.local_pane_id));
+        for (stable_row, _) in &bonus_lines {
+            dirty.remove(*stable_row);
+        }
+
+        // If this delta completes a synchronized update and we need
+        // to fetch some of its lines, then hold back the bonus lines
+        // and the notification until the fetch completes, so that
+        // the update isn't rendered partially applied
+        let defer = delta.synchronized
+            && dirty.iter().any(|r| r.end > delta.dimensions.physical_top)
+            && self.fetch_limiter.non_blocking_admittance_check(1);
+
+        if defer {
+            log::trace!(
+                "apply_changes_to_surface: holding {} bonus lines of synchronized update for local={}",
+                bonus_lines.len(),
+                self.local_pane_id
+            );
+            self.pending_synchronized_lines = Some((now, bonus_lines));
+        } else {
+            for (stable_row, line) in bonus_lines {
+                log::trace!("bonus line {} seqno={}", stable_row, line.current_seqno());
+                self.put_line(stable_row, line, &config, None);
+            }
+
+            log::trace!(
+                "apply_changes_to_surface: Generate PaneOutput event for local={}",
+                self.local_pane_id
+            );
+            Mux::get().notify(mux::MuxNotification::PaneOutput(self.local_pane_id));
+        }
 
         let mut to_fetch = RangeSet::new();
         log::trace!("dirty as of seq {} -> {:?}", delta.seqno, dirty);
@@ -406,7 +442,9 @@ impl RenderableInner {
             }
         }
         if !to_fetch.is_empty() {
-            if self.fetch_limiter.non_blocking_admittance_check(1) {
+            // If we deferred the bonus lines, then we already have
+            // admittance for this fetch
+            if defer || self.fetch_limiter.non_blocking_admittance_check(1) {
                 self.schedule_fetch_lines(to_fetch, now);
             } else {
                 log::warn!(
@@ -544,6 +582,18 @@ impl RenderableInner {
             let renderable = client_tab.renderable.lock();
             let mut inner = renderable.inner.borrow_mut();
 
+            // Apply the rest of the synchronized update that this
+            // fetch completes, if any.  We do this even if the fetch
+            // failed, so that the update isn't lost.
+            if matches!(&inner.pending_synchronized_lines, Some((then, _)) if *then == now) {
+                if let Some((_, lines)) = inner.pending_synchronized_lines.take() {
+                    let config = configuration();
+                    for (stable_row, line) in lines {
+                        inner.put_line(stable_row, line, &config, None);
+                    }
+                }
+            }
+
             match result {
                 Ok(lines) => {
                     let config = configuration();
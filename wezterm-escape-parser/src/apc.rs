@@ -609,6 +609,10 @@ pub struct KittyImagePlacement {
     pub placement_id: Option<u32>,
     /// z=...
     pub z_index: Option<i32>,
+    /// Create a virtual placement that is displayed by printing
+    /// U+10EEEE placeholder characters, rather than at the cursor.
+    /// U=0, U=1
+    pub unicode_placeholder: bool,
 }
 
 impl KittyImagePlacement {
@@ -629,6 +633,11 @@ impl KittyImagePlacement {
                 _ => return None,
             },
             z_index: geti(keys, "z"),
+            unicode_placeholder: match get(keys, "U") {
+                None | Some("0") => false,
+                Some("1") => true,
+                _ => return None,
+            },
         })
     }
 
@@ -648,6 +657,10 @@ impl KittyImagePlacement {
         }
 
         set(keys, "z", &self.z_index);
+
+        if self.unicode_placeholder {
+            keys.insert("U", "1".to_string());
+        }
     }
 }
 
@@ -1265,5 +1278,28 @@ mod test {
                 },
             }
         );
+
+        assert_eq!(
+            KittyImage::parse_apc("Ga=p,U=1,i=42,c=10,r=2,q=2".as_bytes()).unwrap(),
+            KittyImage::Display {
+                image_id: Some(42),
+                image_number: None,
+                placement: KittyImagePlacement {
+                    x: None,
+                    y: None,
+                    w: None,
+                    h: None,
+                    x_offset: None,
+                    y_offset: None,
+                    columns: Some(10),
+                    rows: Some(2),
+                    do_not_move_cursor: false,
+                    placement_id: None,
+                    z_index: None,
+                    unicode_placeholder: true,
+                },
+                verbosity: KittyImageVerbosity::Quiet,
+            }
+        );
     }
 }
@@ -130,6 +130,12 @@ pub enum CSI {
 
     Window(Box<Window>),
 
+    /// Operations on rectangular areas of the screen
+    RectangularArea(Box<RectangularArea>),
+
+    /// xterm's stacks of graphic renditions and color palettes
+    Stack(Box<Stack>),
+
     Keyboard(Keyboard),
 
     /// ECMA-48 SCP
@@ -225,6 +231,8 @@ impl Display for CSI {
             CSI::Mouse(mouse) => mouse.fmt(f)?,
             CSI::Device(dev) => dev.fmt(f)?,
             CSI::Window(window) => window.fmt(f)?,
+            CSI::RectangularArea(area) => area.fmt(f)?,
+            CSI::Stack(stack) => stack.fmt(f)?,
             CSI::Keyboard(Keyboard::SetKittyState { flags, mode }) => {
                 write!(f, "={};{}u", flags.bits(), *mode as u16)?
             }
@@ -281,6 +289,7 @@ pub enum DeviceAttributeCodes {
     UserWindows = 18,
     HorizontalScrolling = 21,
     AnsiColor = 22,
+    RectangularEditing = 28,
     AnsiTextLocator = 29,
 }
 
@@ -462,6 +471,21 @@ pub enum Device {
     RequestTerminalNameAndVersion,
     RequestTerminalParameters(i64),
     XtSmGraphics(XtSmGraphics),
+    /// DSR 996: query the current color scheme.
+    /// <https://contour-terminal.org/vt-extensions/color-palette-update-notifications/>
+    RequestColorScheme,
+    /// DSR 997: reports the current color scheme, either in response
+    /// to `RequestColorScheme` or when it changes while
+    /// `DecPrivateModeCode::ColorSchemeUpdates` is enabled.
+    ColorSchemeReport(ColorScheme),
+}
+
+/// Whether the color palette is considered to be dark or light,
+/// as reported by DSR 997
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum ColorScheme {
+    Dark = 1,
+    Light = 2,
 }
 
 impl Display for Device {
@@ -482,6 +506,8 @@ impl Display for Device {
             Device::RequestTerminalNameAndVersion => write!(f, ">q")?,
             Device::RequestTerminalParameters(n) => write!(f, "{};1;1;128;128;1;0x", n + 2)?,
             Device::StatusReport => write!(f, "5n")?,
+            Device::RequestColorScheme => write!(f, "?996n")?,
+            Device::ColorSchemeReport(scheme) => write!(f, "?997;{}n", *scheme as u8)?,
             Device::XtSmGraphics(g) => {
                 write!(f, "?{};{}", g.item, g.action_or_status)?;
                 for v in &g.value {
@@ -663,6 +689,251 @@ impl Display for Window {
     }
 }
 
+/// The bounds of a rectangular area of the screen, as used by the
+/// VT400-series rectangular area operations.
+/// The bounds are inclusive; a bottom or right value that was
+/// omitted or specified as 0 is represented as `u32::MAX` and
+/// refers to the last row or column of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rectangle {
+    pub top: OneBased,
+    pub left: OneBased,
+    pub bottom: OneBased,
+    pub right: OneBased,
+}
+
+impl Rectangle {
+    fn parse(params: &Cracked, idx: usize) -> Result<Self, ()> {
+        Ok(Self {
+            top: OneBased::from_optional_esc_param(params.get(idx))?,
+            left: OneBased::from_optional_esc_param(params.get(idx + 1))?,
+            bottom: OneBased::from_optional_esc_param_with_big_default(params.get(idx + 2))?,
+            right: OneBased::from_optional_esc_param_with_big_default(params.get(idx + 3))?,
+        })
+    }
+}
+
+impl Display for Rectangle {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        fn big(v: OneBased) -> String {
+            if v.as_one_based() == u32::max_value() {
+                "".to_owned()
+            } else {
+                v.to_string()
+            }
+        }
+        write!(
+            f,
+            "{};{};{};{}",
+            self.top,
+            self.left,
+            big(self.bottom),
+            big(self.right)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RectangularArea {
+    /// DECCRA - Copy Rectangular Area.
+    /// Copies the source rectangle such that its top left corner
+    /// is placed at dest_top, dest_left.
+    Copy {
+        source: Rectangle,
+        source_page: OneBased,
+        dest_top: OneBased,
+        dest_left: OneBased,
+        dest_page: OneBased,
+    },
+    /// DECFRA - Fill Rectangular Area.
+    /// Fills the area with the specified character, using the
+    /// current graphic rendition.
+    Fill { ch: char, area: Rectangle },
+    /// DECERA - Erase Rectangular Area
+    Erase(Rectangle),
+    /// DECSERA - Selective Erase Rectangular Area.
+    /// Only erases cells that have not been protected by DECSCA.
+    SelectiveErase(Rectangle),
+    /// DECCARA - Change Attributes in Rectangular Area.
+    /// Only the subset of Sgr that is expressible by this sequence
+    /// (bold, underline, blink, inverse and invisible, along with
+    /// Reset) is produced by the parser.
+    ChangeAttributes {
+        area: Rectangle,
+        attributes: Vec<Sgr>,
+    },
+    /// DECRARA - Reverse Attributes in Rectangular Area.
+    /// Toggles the specified attributes; Reset means all of them.
+    ReverseAttributes {
+        area: Rectangle,
+        attributes: Vec<Sgr>,
+    },
+    /// DECSACE - Select Attribute Change Extent.
+    /// When true, DECCARA and DECRARA apply to the exact rectangle
+    /// rather than to the stream of character positions that runs
+    /// from its top left to its bottom right corner.
+    SelectAttributeChangeExtent(bool),
+}
+
+/// Map a DECCARA/DECRARA attribute parameter to its Sgr equivalent
+fn rectangle_attribute(p: Option<i64>) -> Option<Sgr> {
+    Some(match p {
+        None | Some(0) => Sgr::Reset,
+        Some(1) => Sgr::Intensity(Intensity::Bold),
+        Some(4) => Sgr::Underline(Underline::Single),
+        Some(5) => Sgr::Blink(Blink::Slow),
+        Some(7) => Sgr::Inverse(true),
+        Some(8) => Sgr::Invisible(true),
+        Some(22) => Sgr::Intensity(Intensity::Normal),
+        Some(24) => Sgr::Underline(Underline::None),
+        Some(25) => Sgr::Blink(Blink::None),
+        Some(27) => Sgr::Inverse(false),
+        Some(28) => Sgr::Invisible(false),
+        _ => return None,
+    })
+}
+
+/// The inverse of rectangle_attribute
+fn rectangle_attribute_code(sgr: &Sgr) -> Option<u8> {
+    Some(match sgr {
+        Sgr::Reset => 0,
+        Sgr::Intensity(Intensity::Bold) => 1,
+        Sgr::Underline(Underline::Single) => 4,
+        Sgr::Blink(Blink::Slow) => 5,
+        Sgr::Inverse(true) => 7,
+        Sgr::Invisible(true) => 8,
+        Sgr::Intensity(Intensity::Normal) => 22,
+        Sgr::Underline(Underline::None) => 24,
+        Sgr::Blink(Blink::None) => 25,
+        Sgr::Inverse(false) => 27,
+        Sgr::Invisible(false) => 28,
+        _ => return None,
+    })
+}
+
+impl Display for RectangularArea {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        fn attributes(f: &mut Formatter, attributes: &[Sgr]) -> Result<(), FmtError> {
+            for code in attributes.iter().filter_map(rectangle_attribute_code) {
+                write!(f, ";{}", code)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            RectangularArea::Copy {
+                source,
+                source_page,
+                dest_top,
+                dest_left,
+                dest_page,
+            } => write!(
+                f,
+                "{};{};{};{};{}$v",
+                source, source_page, dest_top, dest_left, dest_page
+            ),
+            RectangularArea::Fill { ch, area } => write!(f, "{};{}$x", *ch as u32, area),
+            RectangularArea::Erase(area) => write!(f, "{}$z", area),
+            RectangularArea::SelectiveErase(area) => write!(f, "{}${{", area),
+            RectangularArea::ChangeAttributes {
+                area,
+                attributes: a,
+            } => {
+                write!(f, "{}", area)?;
+                attributes(f, a)?;
+                write!(f, "$r")
+            }
+            RectangularArea::ReverseAttributes {
+                area,
+                attributes: a,
+            } => {
+                write!(f, "{}", area)?;
+                attributes(f, a)?;
+                write!(f, "$t")
+            }
+            RectangularArea::SelectAttributeChangeExtent(rectangle) => {
+                write!(f, "{}*x", if *rectangle { 2 } else { 0 })
+            }
+        }
+    }
+}
+
+/// The attributes that can be selected when pushing onto the
+/// SGR stack with XTPUSHSGR.  The values are the parameters of
+/// that sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum SgrStackAttribute {
+    Bold = 1,
+    Faint = 2,
+    Italic = 3,
+    Underline = 4,
+    Blink = 5,
+    Inverse = 7,
+    Invisible = 8,
+    StrikeThrough = 9,
+    DoubleUnderline = 21,
+    Foreground = 30,
+    Background = 31,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stack {
+    /// XTPUSHSGR - save the specified attributes of the current
+    /// graphic rendition.  An empty list saves all of them.
+    PushSgr(Vec<SgrStackAttribute>),
+    /// XTPOPSGR - restore the attributes saved by the most
+    /// recent XTPUSHSGR
+    PopSgr,
+    /// XTPUSHCOLORS - save the color palette.
+    /// An empty list pushes it onto the stack, otherwise the
+    /// palette is stored into each of the listed (1-based)
+    /// stack slots without pushing.
+    PushColors(Vec<u8>),
+    /// XTPOPCOLORS - restore the color palette.
+    /// An empty list pops it from the stack, otherwise it is
+    /// restored from the listed stack slot without popping.
+    PopColors(Vec<u8>),
+    /// XTREPORTCOLORS - report the current stack slot and the
+    /// number of palettes on the stack
+    ReportColors,
+    /// XTREPORTSGR - report the graphic rendition that is common
+    /// to all of the cells in the rectangle
+    ReportSgr(Rectangle),
+}
+
+impl Display for Stack {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        fn list<T: Display>(f: &mut Formatter, items: &[T]) -> Result<(), FmtError> {
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    write!(f, ";")?;
+                }
+                write!(f, "{}", item)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Stack::PushSgr(attributes) => {
+                let codes: Vec<u8> = attributes.iter().filter_map(|attr| attr.to_u8()).collect();
+                list(f, &codes)?;
+                write!(f, "#{{")
+            }
+            Stack::PopSgr => write!(f, "#}}"),
+            Stack::PushColors(slots) => {
+                list(f, slots)?;
+                write!(f, "#P")
+            }
+            Stack::PopColors(slots) => {
+                list(f, slots)?;
+                write!(f, "#Q")
+            }
+            Stack::ReportColors => write!(f, "#R"),
+            Stack::ReportSgr(area) => write!(f, "{}#|", area),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MouseReport {
     SGR1006 {
@@ -954,6 +1225,10 @@ pub enum DecPrivateModeCode {
     /// <https://gist.github.com/christianparpart/d8a62cc1ab659194337d73e399004036>
     SynchronizedOutput = 2026,
 
+    /// Report changes to the color scheme using DSR 997
+    /// <https://contour-terminal.org/vt-extensions/color-palette-update-notifications/>
+    ColorSchemeUpdates = 2031,
+
     MinTTYApplicationEscapeKeyMode = 7727,
 
     /// xterm: adjust cursor positioning after emitting sixel
@@ -1229,6 +1504,19 @@ pub enum Edit {
 
     /// REP - Repeat the preceding character n times
     Repeat(u32),
+
+    /// DECSED - Selective Erase in Display.
+    /// Like ED, but cells protected by DECSCA are left intact
+    SelectiveEraseInDisplay(EraseInDisplay),
+
+    /// DECSEL - Selective Erase in Line.
+    /// Like EL, but cells protected by DECSCA are left intact
+    SelectiveEraseInLine(EraseInLine),
+
+    /// DECSCA - Select Character Protection Attribute.
+    /// When true, subsequently printed characters cannot be
+    /// erased by DECSED, DECSEL or DECSERA.
+    SelectCharacterProtection(bool),
 }
 
 trait EncodeCSIParam {
@@ -1279,6 +1567,17 @@ impl Display for Edit {
             Edit::ScrollUp(n) => n.write_csi(f, "S")?,
             Edit::EraseInDisplay(n) => n.write_csi(f, "J")?,
             Edit::Repeat(n) => n.write_csi(f, "b")?,
+            Edit::SelectiveEraseInDisplay(n) => {
+                write!(f, "?")?;
+                n.write_csi(f, "J")?
+            }
+            Edit::SelectiveEraseInLine(n) => {
+                write!(f, "?")?;
+                n.write_csi(f, "K")?
+            }
+            Edit::SelectCharacterProtection(protected) => {
+                write!(f, "{}\"q", if *protected { 1 } else { 0 })?
+            }
         }
         Ok(())
     }
@@ -1832,6 +2131,23 @@ impl<'a> CSIParser<'a> {
             ('k', [.., CsiParam::P(b' ')]) => self.select_character_path(params),
             ('q', [.., CsiParam::P(b' ')]) => self.cursor_style(params),
             ('y', [.., CsiParam::P(b'*')]) => self.checksum_area(params),
+            ('q', [.., CsiParam::P(b'"')]) => self.select_character_protection(params),
+            ('x', [.., CsiParam::P(b'*')]) => self.select_attribute_change_extent(params),
+            ('v' | 'x' | 'z' | '{' | 'r' | 't', [.., CsiParam::P(b'$')]) => {
+                self.rectangular_area(params)
+            }
+            // `CSI # p` and `CSI # q` are aliases for XTPUSHSGR and XTPOPSGR
+            ('{' | 'p', [.., CsiParam::P(b'#')]) => self.push_sgr(params),
+            ('}' | 'q', [CsiParam::P(b'#')]) => Ok(CSI::Stack(Box::new(Stack::PopSgr))),
+            ('P' | 'Q', [.., CsiParam::P(b'#')]) => self.color_stack(params),
+            ('R', [CsiParam::P(b'#')]) => Ok(CSI::Stack(Box::new(Stack::ReportColors))),
+            ('|', [.., CsiParam::P(b'#')]) => self.report_sgr(params),
+            ('J', [CsiParam::P(b'?'), ..]) => {
+                parse!(Edit, SelectiveEraseInDisplay, self.focus(params, 1, 0))
+            }
+            ('K', [CsiParam::P(b'?'), ..]) => {
+                parse!(Edit, SelectiveEraseInLine, self.focus(params, 1, 0))
+            }
 
             ('c', [CsiParam::P(b'='), ..]) => self
                 .req_tertiary_device_attributes(params)
@@ -1936,6 +2252,16 @@ impl<'a> CSIParser<'a> {
             )),
             ('u', [CsiParam::P(b'<')]) => Ok(CSI::Keyboard(Keyboard::PopKittyState(1))),
 
+            ('n', [CsiParam::P(b'?'), CsiParam::Integer(996)]) => {
+                Ok(CSI::Device(Box::new(Device::RequestColorScheme)))
+            }
+            (
+                'n',
+                [CsiParam::P(b'?'), CsiParam::Integer(997), CsiParam::P(b';'), CsiParam::Integer(scheme)],
+            ) => Ok(CSI::Device(Box::new(Device::ColorSchemeReport(
+                ColorScheme::from_i64(*scheme).ok_or(())?,
+            )))),
+
             _ => match self.control {
                 'c' => self
                     .req_primary_device_attributes(params)
@@ -2082,6 +2408,128 @@ impl<'a> CSIParser<'a> {
         })))
     }
 
+    fn select_character_protection(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let protected = match params {
+            [CsiParam::P(b'"')] => false,
+            [CsiParam::Integer(p), CsiParam::P(b'"')] => match p {
+                0 | 2 => false,
+                1 => true,
+                _ => return Err(()),
+            },
+            _ => return Err(()),
+        };
+        Ok(self.advance_by(
+            params.len(),
+            params,
+            CSI::Edit(Edit::SelectCharacterProtection(protected)),
+        ))
+    }
+
+    fn select_attribute_change_extent(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let rectangle = match params {
+            [CsiParam::P(b'*')] => false,
+            [CsiParam::Integer(p), CsiParam::P(b'*')] => match p {
+                0 | 1 => false,
+                2 => true,
+                _ => return Err(()),
+            },
+            _ => return Err(()),
+        };
+        Ok(self.advance_by(
+            params.len(),
+            params,
+            CSI::RectangularArea(Box::new(RectangularArea::SelectAttributeChangeExtent(
+                rectangle,
+            ))),
+        ))
+    }
+
+    fn push_sgr(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let params = Cracked::parse(&params[..params.len() - 1])?;
+        let attributes = (0..params.len())
+            .map(|idx| {
+                params
+                    .opt_int(idx)
+                    .and_then(SgrStackAttribute::from_i64)
+                    .ok_or(())
+            })
+            .collect::<Result<Vec<_>, ()>>()?;
+        Ok(CSI::Stack(Box::new(Stack::PushSgr(attributes))))
+    }
+
+    fn color_stack(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let params = Cracked::parse(&params[..params.len() - 1])?;
+        let mut slots = vec![];
+        for idx in 0..params.len() {
+            match params.opt_int(idx) {
+                // Zero selects the default behavior
+                None | Some(0) => {}
+                // The stack holds at most 10 palettes
+                Some(slot @ 1..=10) => slots.push(slot as u8),
+                Some(_) => return Err(()),
+            }
+        }
+        Ok(CSI::Stack(Box::new(match self.control {
+            'P' => Stack::PushColors(slots),
+            _ => Stack::PopColors(slots),
+        })))
+    }
+
+    fn report_sgr(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let params = Cracked::parse(&params[..params.len() - 1])?;
+        if params.len() > 4 {
+            return Err(());
+        }
+        Ok(CSI::Stack(Box::new(Stack::ReportSgr(Rectangle::parse(
+            &params, 0,
+        )?))))
+    }
+
+    fn rectangular_area(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
+        let params = Cracked::parse(&params[..params.len() - 1])?;
+
+        let attributes = |params: &Cracked| {
+            if params.len() <= 4 {
+                vec![Sgr::Reset]
+            } else {
+                (4..params.len())
+                    .filter_map(|idx| rectangle_attribute(params.opt_int(idx)))
+                    .collect()
+            }
+        };
+
+        let area = match self.control {
+            'v' => RectangularArea::Copy {
+                source: Rectangle::parse(&params, 0)?,
+                source_page: OneBased::from_optional_esc_param(params.get(4))?,
+                dest_top: OneBased::from_optional_esc_param(params.get(5))?,
+                dest_left: OneBased::from_optional_esc_param(params.get(6))?,
+                dest_page: OneBased::from_optional_esc_param(params.get(7))?,
+            },
+            'x' => RectangularArea::Fill {
+                ch: params
+                    .int(0)?
+                    .try_into()
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(())?,
+                area: Rectangle::parse(&params, 1)?,
+            },
+            'z' => RectangularArea::Erase(Rectangle::parse(&params, 0)?),
+            '{' => RectangularArea::SelectiveErase(Rectangle::parse(&params, 0)?),
+            'r' => RectangularArea::ChangeAttributes {
+                area: Rectangle::parse(&params, 0)?,
+                attributes: attributes(&params),
+            },
+            't' => RectangularArea::ReverseAttributes {
+                area: Rectangle::parse(&params, 0)?,
+                attributes: attributes(&params),
+            },
+            _ => return Err(()),
+        };
+        Ok(CSI::RectangularArea(Box::new(area)))
+    }
+
     fn dsr(&mut self, params: &'a [CsiParam]) -> Result<CSI, ()> {
         match params {
             [CsiParam::Integer(5)] => {
@@ -3194,6 +3642,196 @@ mod test {
         );
     }
 
+    #[test]
+    fn selective_erase() {
+        let res: Vec<_> = CSI::parse(&[CsiParam::P(b'?')], false, 'J').collect();
+        assert_eq!(encode(&res), "\x1b[?J");
+        assert_eq!(
+            res,
+            vec![CSI::Edit(Edit::SelectiveEraseInDisplay(
+                EraseInDisplay::EraseToEndOfDisplay,
+            ))]
+        );
+
+        let res: Vec<_> =
+            CSI::parse(&[CsiParam::P(b'?'), CsiParam::Integer(2)], false, 'K').collect();
+        assert_eq!(encode(&res), "\x1b[?2K");
+        assert_eq!(
+            res,
+            vec![CSI::Edit(Edit::SelectiveEraseInLine(
+                EraseInLine::EraseLine
+            ))]
+        );
+
+        let res: Vec<_> =
+            CSI::parse(&[CsiParam::Integer(1), CsiParam::P(b'"')], false, 'q').collect();
+        assert_eq!(encode(&res), "\x1b[1\"q");
+        assert_eq!(res, vec![CSI::Edit(Edit::SelectCharacterProtection(true))]);
+
+        let res: Vec<_> = CSI::parse(&[CsiParam::P(b'"')], false, 'q').collect();
+        assert_eq!(encode(&res), "\x1b[0\"q");
+        assert_eq!(res, vec![CSI::Edit(Edit::SelectCharacterProtection(false))]);
+    }
+
+    fn parse_rect(control: char, params: &[i64], expected: &str) -> Vec<CSI> {
+        let mut cparams = vec![];
+        for &p in params {
+            if !cparams.is_empty() {
+                cparams.push(CsiParam::P(b';'));
+            }
+            cparams.push(CsiParam::Integer(p));
+        }
+        cparams.push(CsiParam::P(b'$'));
+        let res = CSI::parse(&cparams, false, control).collect();
+        assert_eq!(encode(&res), expected, "parsed -> {res:?}");
+        res
+    }
+
+    #[test]
+    fn rectangular_area() {
+        let rect = |top, left, bottom, right| Rectangle {
+            top: OneBased::new(top),
+            left: OneBased::new(left),
+            bottom: OneBased::new(bottom),
+            right: OneBased::new(right),
+        };
+
+        assert_eq!(
+            parse_rect('v', &[1, 2, 3, 4, 1, 5, 6, 1], "\x1b[1;2;3;4;1;5;6;1$v"),
+            vec![CSI::RectangularArea(Box::new(RectangularArea::Copy {
+                source: rect(1, 2, 3, 4),
+                source_page: OneBased::new(1),
+                dest_top: OneBased::new(5),
+                dest_left: OneBased::new(6),
+                dest_page: OneBased::new(1),
+            }))]
+        );
+        assert_eq!(
+            parse_rect('x', &[0x41, 2, 3, 4, 5], "\x1b[65;2;3;4;5$x"),
+            vec![CSI::RectangularArea(Box::new(RectangularArea::Fill {
+                ch: 'A',
+                area: rect(2, 3, 4, 5),
+            }))]
+        );
+        // Omitted bounds default to the whole screen
+        assert_eq!(
+            parse_rect('z', &[], "\x1b[1;1;;$z"),
+            vec![CSI::RectangularArea(Box::new(RectangularArea::Erase(
+                rect(1, 1, u32::MAX, u32::MAX)
+            )))]
+        );
+        assert_eq!(
+            parse_rect('{', &[2, 2, 0, 0], "\x1b[2;2;;${"),
+            vec![CSI::RectangularArea(Box::new(
+                RectangularArea::SelectiveErase(rect(2, 2, u32::MAX, u32::MAX))
+            ))]
+        );
+        assert_eq!(
+            parse_rect('r', &[1, 1, 2, 2, 1, 4, 27], "\x1b[1;1;2;2;1;4;27$r"),
+            vec![CSI::RectangularArea(Box::new(
+                RectangularArea::ChangeAttributes {
+                    area: rect(1, 1, 2, 2),
+                    attributes: vec![
+                        Sgr::Intensity(Intensity::Bold),
+                        Sgr::Underline(Underline::Single),
+                        Sgr::Inverse(false),
+                    ],
+                }
+            ))]
+        );
+        let res: Vec<_> =
+            CSI::parse(&[CsiParam::Integer(2), CsiParam::P(b'*')], false, 'x').collect();
+        assert_eq!(encode(&res), "\x1b[2*x");
+        assert_eq!(
+            res,
+            vec![CSI::RectangularArea(Box::new(
+                RectangularArea::SelectAttributeChangeExtent(true)
+            ))]
+        );
+        assert_eq!(
+            parse_rect('t', &[1, 1, 2, 2], "\x1b[1;1;2;2;0$t"),
+            vec![CSI::RectangularArea(Box::new(
+                RectangularArea::ReverseAttributes {
+                    area: rect(1, 1, 2, 2),
+                    attributes: vec![Sgr::Reset],
+                }
+            ))]
+        );
+    }
+
+    fn parse_stack(control: char, params: &[i64], expected: &str) -> Vec<CSI> {
+        let mut cparams = vec![];
+        for &p in params {
+            if !cparams.is_empty() {
+                cparams.push(CsiParam::P(b';'));
+            }
+            cparams.push(CsiParam::Integer(p));
+        }
+        cparams.push(CsiParam::P(b'#'));
+        let res = CSI::parse(&cparams, false, control).collect();
+        assert_eq!(encode(&res), expected, "parsed -> {res:?}");
+        res
+    }
+
+    #[test]
+    fn stacks() {
+        assert_eq!(
+            parse_stack('{', &[], "\x1b[#{"),
+            vec![CSI::Stack(Box::new(Stack::PushSgr(vec![])))]
+        );
+        assert_eq!(
+            parse_stack('p', &[1, 30, 31], "\x1b[1;30;31#{"),
+            vec![CSI::Stack(Box::new(Stack::PushSgr(vec![
+                SgrStackAttribute::Bold,
+                SgrStackAttribute::Foreground,
+                SgrStackAttribute::Background,
+            ])))]
+        );
+        assert_eq!(
+            parse_stack('}', &[], "\x1b[#}"),
+            vec![CSI::Stack(Box::new(Stack::PopSgr))]
+        );
+        assert_eq!(
+            parse_stack('q', &[], "\x1b[#}"),
+            vec![CSI::Stack(Box::new(Stack::PopSgr))]
+        );
+        assert_eq!(
+            parse_stack('P', &[0], "\x1b[#P"),
+            vec![CSI::Stack(Box::new(Stack::PushColors(vec![])))]
+        );
+        assert_eq!(
+            parse_stack('P', &[2, 3], "\x1b[2;3#P"),
+            vec![CSI::Stack(Box::new(Stack::PushColors(vec![2, 3])))]
+        );
+        assert_eq!(
+            parse_stack('Q', &[4], "\x1b[4#Q"),
+            vec![CSI::Stack(Box::new(Stack::PopColors(vec![4])))]
+        );
+        assert_eq!(
+            parse_stack('R', &[], "\x1b[#R"),
+            vec![CSI::Stack(Box::new(Stack::ReportColors))]
+        );
+        assert_eq!(
+            parse_stack('|', &[2, 3, 4, 5], "\x1b[2;3;4;5#|"),
+            vec![CSI::Stack(Box::new(Stack::ReportSgr(Rectangle {
+                top: OneBased::new(2),
+                left: OneBased::new(3),
+                bottom: OneBased::new(4),
+                right: OneBased::new(5),
+            })))]
+        );
+
+        // Out of range parameters are not recognized
+        assert!(matches!(
+            parse_stack('P', &[11], "\x1b[11#P")[0],
+            CSI::Unspecified(_)
+        ));
+        assert!(matches!(
+            parse_stack('{', &[6], "\x1b[6#{")[0],
+            CSI::Unspecified(_)
+        ));
+    }
+
     #[test]
     fn window() {
         assert_eq!(
@@ -3350,6 +3988,33 @@ mod test {
         assert_eq!(res, vec![CSI::Device(Box::new(Device::SoftReset))],);
     }
 
+    #[test]
+    fn color_scheme() {
+        let res: Vec<_> =
+            CSI::parse(&[CsiParam::P(b'?'), CsiParam::Integer(996)], false, 'n').collect();
+        assert_eq!(encode(&res), "\x1b[?996n");
+        assert_eq!(res, vec![CSI::Device(Box::new(Device::RequestColorScheme))]);
+
+        let res: Vec<_> = CSI::parse(
+            &[
+                CsiParam::P(b'?'),
+                CsiParam::Integer(997),
+                CsiParam::P(b';'),
+                CsiParam::Integer(2),
+            ],
+            false,
+            'n',
+        )
+        .collect();
+        assert_eq!(encode(&res), "\x1b[?997;2n");
+        assert_eq!(
+            res,
+            vec![CSI::Device(Box::new(Device::ColorSchemeReport(
+                ColorScheme::Light
+            )))]
+        );
+    }
+
     #[test]
     fn device_attr() {
         let res: Vec<_> = CSI::parse(
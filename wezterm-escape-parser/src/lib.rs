@@ -11,7 +11,6 @@
 use crate::tmux_cc::Event;
 use core::fmt::{Display, Formatter, Result as FmtResult, Write as FmtWrite};
 use num_derive::*;
-use wezterm_color_types::LinearRgba;
 
 #[cfg_attr(not(feature = "std"), macro_use)]
 extern crate alloc;
@@ -409,7 +408,7 @@ pub enum SixelData {
     Repeat { repeat_count: u32, data: SixelValue },
 
     /// Set the specified color map entry to the specified
-    /// linear RGB color value
+    /// RGB color value
     DefineColorMapRGB {
         color_number: u16,
         rgb: crate::color::RgbColor,
@@ -445,14 +444,16 @@ impl Display for SixelData {
                 write!(f, "!{}{}", repeat_count, (data + 0x3f) as char)
             }
             Self::DefineColorMapRGB { color_number, rgb } => {
-                let LinearRgba(r, g, b, _) = rgb.to_linear_tuple_rgba();
+                // The components are percentages of the sRGB range
+                let (r, g, b) = rgb.to_tuple_rgb8();
+                let percent = |c: u8| (c as u32 * 100 + 127) / 255;
                 write!(
                     f,
                     "#{};2;{};{};{}",
                     color_number,
-                    (r * 100.) as u8,
-                    (g * 100.) as u8,
-                    (b * 100.0) as u8
+                    percent(r),
+                    percent(g),
+                    percent(b)
                 )
             }
             Self::DefineColorMapHSL {
@@ -594,6 +595,14 @@ impl OneBased {
         Self::from_esc_param(o.unwrap_or(&CsiParam::Integer(1)))
     }
 
+    /// Map a value from an optional escape sequence parameter.
+    /// Both 0 and an omitted parameter are equivalent to max_value.
+    pub fn from_optional_esc_param_with_big_default(
+        o: Option<&CsiParam>,
+    ) -> core::result::Result<Self, ()> {
+        Self::from_esc_param_with_big_default(o.unwrap_or(&CsiParam::Integer(0)))
+    }
+
     /// Return the underlying value as a 0-based value
     pub fn as_zero_based(self) -> u32 {
         self.value.saturating_sub(1)
@@ -9,6 +9,8 @@ use core::str::FromStr;
 use num_derive::*;
 use num_traits::FromPrimitive;
 use ordered_float::NotNan;
+#[cfg(feature = "use_serde")]
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "std")]
 use std::sync::LazyLock;
 
@@ -50,6 +52,8 @@ pub enum OperatingSystemCommand {
     ResetColors(Vec<u8>),
     RxvtExtension(Vec<String>),
     ConEmuProgress(Progress),
+    KittyNotification(Box<KittyNotification>),
+    TextSizing(Box<TextSizing>),
 
     Unspecified(Vec<Vec<u8>>),
 }
@@ -361,6 +365,10 @@ impl OperatingSystemCommand {
             }
             FinalTermSemanticPrompt => self::FinalTermSemanticPrompt::parse(osc)
                 .map(OperatingSystemCommand::FinalTermSemanticPrompt),
+            KittyNotification => self::KittyNotification::parse(osc)
+                .map(|notif| OperatingSystemCommand::KittyNotification(Box::new(notif))),
+            TextSizing => self::TextSizing::parse(osc)
+                .map(|sizing| OperatingSystemCommand::TextSizing(Box::new(sizing))),
             ChangeColorNumber => Self::parse_change_color_number(osc),
             ResetColors => Self::parse_reset_colors(osc),
 
@@ -482,6 +490,10 @@ osc_entries!(
     SetFont = "50",
     EmacsShell = "51",
     ManipulateSelectionData = "52",
+    /// <https://sw.kovidgoyal.net/kitty/text-sizing-protocol/>
+    TextSizing = "66",
+    /// <https://sw.kovidgoyal.net/kitty/desktop-notifications/>
+    KittyNotification = "99",
     ResetColors = "104",
     ResetSpecialColor = "105",
     ResetTextForegroundColor = "110",
@@ -583,6 +595,8 @@ impl Display for OperatingSystemCommand {
             SystemNotification(s) => write!(f, "9;{}", s)?,
             ITermProprietary(i) => i.fmt(f)?,
             FinalTermSemanticPrompt(i) => i.fmt(f)?,
+            KittyNotification(n) => n.fmt(f)?,
+            TextSizing(t) => t.fmt(f)?,
             ResetColors(colors) => {
                 write!(f, "104")?;
                 for c in colors {
@@ -1378,6 +1392,386 @@ impl Display for ITermProprietary {
     }
 }
 
+/// The kind of payload carried by a kitty desktop notification
+/// escape sequence; the `p` key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KittyNotificationPayload {
+    #[default]
+    Title,
+    Body,
+    /// Close the identified notification, or, when sent by the
+    /// terminal, report that it was closed
+    Close,
+    /// Query the capabilities of the terminal
+    Query,
+    /// Query which notifications are still displayed
+    Alive,
+    Icon,
+    Buttons,
+}
+
+impl KittyNotificationPayload {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Body => "body",
+            Self::Close => "close",
+            Self::Query => "?",
+            Self::Alive => "alive",
+            Self::Icon => "icon",
+            Self::Buttons => "buttons",
+        }
+    }
+}
+
+impl core::convert::TryFrom<&str> for KittyNotificationPayload {
+    type Error = crate::Error;
+    fn try_from(s: &str) -> Result<Self> {
+        match s {
+            "title" => Ok(Self::Title),
+            "body" => Ok(Self::Body),
+            "close" => Ok(Self::Close),
+            "?" => Ok(Self::Query),
+            "alive" => Ok(Self::Alive),
+            "icon" => Ok(Self::Icon),
+            "buttons" => Ok(Self::Buttons),
+            _ => bail!("invalid kitty notification payload type {}", s),
+        }
+    }
+}
+
+/// When a kitty desktop notification should be displayed; the `o` key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KittyNotificationOccasion {
+    #[default]
+    Always,
+    /// Only when the originating window does not have focus
+    Unfocused,
+    /// Only when the originating window is both unfocused and
+    /// not visible, eg: because it is in an inactive tab
+    Invisible,
+}
+
+impl KittyNotificationOccasion {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::Unfocused => "unfocused",
+            Self::Invisible => "invisible",
+        }
+    }
+}
+
+impl core::convert::TryFrom<&str> for KittyNotificationOccasion {
+    type Error = crate::Error;
+    fn try_from(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(Self::Always),
+            "unfocused" => Ok(Self::Unfocused),
+            "invisible" => Ok(Self::Invisible),
+            _ => bail!("invalid kitty notification occasion {}", s),
+        }
+    }
+}
+
+/// The urgency of a kitty desktop notification; the `u` key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum KittyNotificationUrgency {
+    Low = 0,
+    Normal = 1,
+    Critical = 2,
+}
+
+/// Kitty's desktop notification protocol, OSC 99.
+/// The metadata is a colon separated list of `key=value` pairs;
+/// only the keys that were present are represented here, so that
+/// the metadata of a notification that is sent in chunks can be
+/// merged together.
+/// <https://sw.kovidgoyal.net/kitty/desktop-notifications/>
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KittyNotification {
+    /// `i`: identifies the notification across chunks and in the
+    /// reports sent back to the application
+    pub id: Option<String>,
+    /// `d=0`: more chunks of this notification follow
+    pub more_chunks: bool,
+    /// `p`: what the payload represents
+    pub payload_type: KittyNotificationPayload,
+    /// `e=1`: the payload is base64 encoded
+    pub base64: bool,
+    /// `a`: whether activating the notification should focus the
+    /// window that sent it
+    pub focus: Option<bool>,
+    /// `a`: whether activating the notification should be reported
+    /// back to the application
+    pub report: Option<bool>,
+    /// `o`: when to display the notification
+    pub occasion: Option<KittyNotificationOccasion>,
+    /// `u`: the urgency of the notification
+    pub urgency: Option<KittyNotificationUrgency>,
+    /// `c=1`: report back to the application when the notification
+    /// is closed
+    pub report_close: Option<bool>,
+    /// `w`: the number of milliseconds after which the notification
+    /// should be closed.  -1 means that the system default applies.
+    pub expire_ms: Option<i64>,
+    /// The payload, decoded from base64 if `base64` is set
+    pub payload: Vec<u8>,
+}
+
+impl KittyNotification {
+    fn parse(osc: &[&[u8]]) -> Result<Self> {
+        ensure!(osc.len() > 1, "not enough args");
+        let mut notif = Self::default();
+
+        let metadata = str::from_utf8(osc[1])?;
+        for item in metadata.split(':') {
+            if item.is_empty() {
+                continue;
+            }
+            let (key, value) = match item.split_once('=') {
+                Some(pair) => pair,
+                None => bail!("malformed kitty notification metadata {:?}", item),
+            };
+            match key {
+                "i" => notif.id = Some(value.to_string()),
+                "d" => notif.more_chunks = value == "0",
+                "p" => notif.payload_type = value.try_into()?,
+                "e" => notif.base64 = value == "1",
+                "a" => {
+                    for action in value.split(',') {
+                        let (enable, action) = match action.strip_prefix('-') {
+                            Some(action) => (false, action),
+                            None => (true, action),
+                        };
+                        match action {
+                            "focus" => notif.focus = Some(enable),
+                            "report" => notif.report = Some(enable),
+                            _ => {}
+                        }
+                    }
+                }
+                "o" => notif.occasion = Some(value.try_into()?),
+                "u" => {
+                    notif.urgency = Some(
+                        KittyNotificationUrgency::from_u8(value.parse()?)
+                            .ok_or_else(|| format_err!("invalid urgency {}", value))?,
+                    )
+                }
+                "c" => notif.report_close = Some(value == "1"),
+                "w" => notif.expire_ms = Some(value.parse()?),
+                // Application name, icons, sounds and so on are
+                // not supported
+                _ => {}
+            }
+        }
+
+        // The payload may itself contain semicolons
+        let mut payload = vec![];
+        for (idx, chunk) in osc.iter().skip(2).enumerate() {
+            if idx > 0 {
+                payload.push(b';');
+            }
+            payload.extend_from_slice(chunk);
+        }
+        notif.payload = if notif.base64 {
+            base64_decode(&payload)?
+        } else {
+            payload
+        };
+
+        Ok(notif)
+    }
+}
+
+impl Display for KittyNotification {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "99;")?;
+        let mut sep = "";
+        macro_rules! item {
+            ($($arg:tt)*) => {{
+                write!(f, "{}", sep)?;
+                write!(f, $($arg)*)?;
+                sep = ":";
+            }};
+        }
+        if let Some(id) = &self.id {
+            item!("i={}", id);
+        }
+        if self.more_chunks {
+            item!("d=0");
+        }
+        if self.payload_type != KittyNotificationPayload::Title {
+            item!("p={}", self.payload_type.as_str());
+        }
+        if self.base64 {
+            item!("e=1");
+        }
+        match (self.focus, self.report) {
+            (None, None) => {}
+            (focus, report) => {
+                let action = |name: &str, enable: Option<bool>| match enable {
+                    Some(true) => Some(name.to_string()),
+                    Some(false) => Some(format!("-{}", name)),
+                    None => None,
+                };
+                let actions: Vec<String> = [action("focus", focus), action("report", report)]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                item!("a={}", actions.join(","));
+            }
+        }
+        if let Some(occasion) = self.occasion {
+            item!("o={}", occasion.as_str());
+        }
+        if let Some(urgency) = self.urgency {
+            item!("u={}", urgency as u8);
+        }
+        if let Some(report_close) = self.report_close {
+            item!("c={}", if report_close { 1 } else { 0 });
+        }
+        if let Some(expire_ms) = self.expire_ms {
+            item!("w={}", expire_ms);
+        }
+        let _ = sep;
+        write!(f, ";")?;
+        if self.base64 {
+            write!(f, "{}", base64_encode(&self.payload))
+        } else {
+            f.write_str(&String::from_utf8_lossy(&self.payload))
+        }
+    }
+}
+
+/// How text is aligned within the block of cells that it occupies
+/// when using the text sizing protocol
+#[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, FromPrimitive)]
+pub enum TextSizingAlign {
+    /// Top or left
+    #[default]
+    Start = 0,
+    /// Bottom or right
+    End = 1,
+    Center = 2,
+}
+
+/// Kitty's text sizing protocol, OSC 66.
+/// The metadata is a colon separated list of `key=value` pairs.
+/// <https://sw.kovidgoyal.net/kitty/text-sizing-protocol/>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSizing {
+    /// `s`: the number of lines the text occupies, 1-7.
+    /// The text is scaled by the same factor.
+    pub scale: u8,
+    /// `w`: the width of the text in units of the scale, 0-7.
+    /// 0 means that the width is computed from the text itself.
+    pub width: u8,
+    /// `n`: the numerator of a fractional scale, 0-15
+    pub numerator: u8,
+    /// `d`: the denominator of a fractional scale, 0-15.
+    /// The fractional scale is only applied when this is
+    /// greater than the numerator.
+    pub denominator: u8,
+    /// `v`: the vertical alignment of fractionally scaled text
+    pub vertical_align: TextSizingAlign,
+    /// `h`: the horizontal alignment of fractionally scaled text
+    pub horizontal_align: TextSizingAlign,
+    pub text: String,
+}
+
+impl Default for TextSizing {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            width: 0,
+            numerator: 0,
+            denominator: 0,
+            vertical_align: TextSizingAlign::Start,
+            horizontal_align: TextSizingAlign::Start,
+            text: String::new(),
+        }
+    }
+}
+
+impl TextSizing {
+    fn parse(osc: &[&[u8]]) -> Result<Self> {
+        ensure!(osc.len() > 2, "not enough args");
+        let mut sizing = Self::default();
+
+        fn number(value: &str, max: u8) -> Result<u8> {
+            let n: u8 = value.parse()?;
+            ensure!(n <= max, "{} is out of range", n);
+            Ok(n)
+        }
+        fn align(value: &str) -> Result<TextSizingAlign> {
+            TextSizingAlign::from_u8(value.parse()?)
+                .ok_or_else(|| format_err!("invalid alignment {}", value))
+        }
+
+        let metadata = str::from_utf8(osc[1])?;
+        for item in metadata.split(':') {
+            if item.is_empty() {
+                continue;
+            }
+            let (key, value) = match item.split_once('=') {
+                Some(pair) => pair,
+                None => bail!("malformed text sizing metadata {:?}", item),
+            };
+            match key {
+                "s" => sizing.scale = number(value, 7)?.max(1),
+                "w" => sizing.width = number(value, 7)?,
+                "n" => sizing.numerator = number(value, 15)?,
+                "d" => sizing.denominator = number(value, 15)?,
+                "v" => sizing.vertical_align = align(value)?,
+                "h" => sizing.horizontal_align = align(value)?,
+                // The protocol requires that unknown keys are ignored,
+                // so that it can be extended in the future
+                _ => {}
+            }
+        }
+
+        // The text may itself contain semicolons
+        let mut text = vec![];
+        for (idx, chunk) in osc.iter().skip(2).enumerate() {
+            if idx > 0 {
+                text.push(b';');
+            }
+            text.extend_from_slice(chunk);
+        }
+        sizing.text = String::from_utf8(text)?;
+
+        Ok(sizing)
+    }
+}
+
+impl Display for TextSizing {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "66;")?;
+        let mut items = vec![];
+        if self.scale != 1 {
+            items.push(format!("s={}", self.scale));
+        }
+        if self.width != 0 {
+            items.push(format!("w={}", self.width));
+        }
+        if self.numerator != 0 {
+            items.push(format!("n={}", self.numerator));
+        }
+        if self.denominator != 0 {
+            items.push(format!("d={}", self.denominator));
+        }
+        if self.vertical_align != TextSizingAlign::Start {
+            items.push(format!("v={}", self.vertical_align as u8));
+        }
+        if self.horizontal_align != TextSizingAlign::Start {
+            items.push(format!("h={}", self.horizontal_align as u8));
+        }
+        write!(f, "{};{}", items.join(":"), self.text)
+    }
+}
+
 fn not_nan_err(err: ordered_float::FloatIsNan) -> crate::Error {
     format_err!("{:#}", err)
 }
@@ -1997,4 +2391,120 @@ mod test {
             )))
         );
     }
+
+    #[test]
+    fn kitty_notification() {
+        assert_eq!(
+            parse(&["99", "", "Hello world"], "\x1b]99;;Hello world\x1b\\"),
+            OperatingSystemCommand::KittyNotification(Box::new(KittyNotification {
+                payload: b"Hello world".to_vec(),
+                ..Default::default()
+            }))
+        );
+
+        assert_eq!(
+            parse(
+                &[
+                    "99",
+                    "i=1:d=0:a=-focus,report:o=unfocused:u=2:c=1:w=500",
+                    "Title"
+                ],
+                "\x1b]99;i=1:d=0:a=-focus,report:o=unfocused:u=2:c=1:w=500;Title\x1b\\"
+            ),
+            OperatingSystemCommand::KittyNotification(Box::new(KittyNotification {
+                id: Some("1".into()),
+                more_chunks: true,
+                focus: Some(false),
+                report: Some(true),
+                occasion: Some(KittyNotificationOccasion::Unfocused),
+                urgency: Some(KittyNotificationUrgency::Critical),
+                report_close: Some(true),
+                expire_ms: Some(500),
+                payload: b"Title".to_vec(),
+                ..Default::default()
+            }))
+        );
+
+        // The payload may contain semicolons, or be base64 encoded
+        assert_eq!(
+            parse(
+                &["99", "i=1:p=body", "one", "two"],
+                "\x1b]99;i=1:p=body;one;two\x1b\\"
+            ),
+            OperatingSystemCommand::KittyNotification(Box::new(KittyNotification {
+                id: Some("1".into()),
+                payload_type: KittyNotificationPayload::Body,
+                payload: b"one;two".to_vec(),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse(
+                &["99", "i=1:p=body:e=1", "aGVsbG8="],
+                "\x1b]99;i=1:p=body:e=1;aGVsbG8=\x1b\\"
+            ),
+            OperatingSystemCommand::KittyNotification(Box::new(KittyNotification {
+                id: Some("1".into()),
+                payload_type: KittyNotificationPayload::Body,
+                base64: true,
+                payload: b"hello".to_vec(),
+                ..Default::default()
+            }))
+        );
+
+        assert_eq!(
+            parse(&["99", "i=x:p=?", ""], "\x1b]99;i=x:p=?;\x1b\\"),
+            OperatingSystemCommand::KittyNotification(Box::new(KittyNotification {
+                id: Some("x".into()),
+                payload_type: KittyNotificationPayload::Query,
+                ..Default::default()
+            }))
+        );
+    }
+
+    #[test]
+    fn text_sizing() {
+        assert_eq!(
+            parse(&["66", "s=2", "Hello"], "\x1b]66;s=2;Hello\x1b\\"),
+            OperatingSystemCommand::TextSizing(Box::new(TextSizing {
+                scale: 2,
+                text: "Hello".into(),
+                ..Default::default()
+            }))
+        );
+
+        assert_eq!(
+            parse(
+                &["66", "w=3:n=1:d=2:v=2:h=1", "a;b"],
+                "\x1b]66;w=3:n=1:d=2:v=2:h=1;a;b\x1b\\"
+            ),
+            OperatingSystemCommand::TextSizing(Box::new(TextSizing {
+                width: 3,
+                numerator: 1,
+                denominator: 2,
+                vertical_align: TextSizingAlign::Center,
+                horizontal_align: TextSizingAlign::End,
+                text: "a;b".into(),
+                ..Default::default()
+            }))
+        );
+
+        assert_eq!(
+            parse(&["66", "s=2:z=1", "x"], "\x1b]66;s=2;x\x1b\\"),
+            OperatingSystemCommand::TextSizing(Box::new(TextSizing {
+                scale: 2,
+                text: "x".into(),
+                ..Default::default()
+            }))
+        );
+
+        assert_eq!(
+            parse(&["66", "s=8", "x"], "\x1b]66;s=8;x\x1b\\"),
+            OperatingSystemCommand::Unspecified(vec![
+                b"66".to_vec(),
+                b"s=8".to_vec(),
+                b"x".to_vec()
+            ])
+        );
+    }
 }
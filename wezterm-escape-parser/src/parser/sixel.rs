@@ -111,10 +111,11 @@ impl SixelBuilder {
                         saturation: c,
                     });
                 } else {
-                    let r = a as f32 * 255.0 / 100.;
-                    let g = b as f32 * 255.0 / 100.;
-                    let b = c as f32 * 255.0 / 100.;
-                    let rgb = RgbColor::new_8bpc(r as u8, g as u8, b as u8); // FIXME: from linear
+                    // The components are percentages of the sRGB range
+                    let r = (a as f32 * 255.0 / 100.).round();
+                    let g = (b as f32 * 255.0 / 100.).round();
+                    let b = (c as f32 * 255.0 / 100.).round();
+                    let rgb = RgbColor::new_8bpc(r as u8, g as u8, b as u8);
                     self.sixel
                         .data
                         .push(SixelData::DefineColorMapRGB { color_number, rgb });
@@ -95,6 +95,55 @@ pub fn compute_load_flags_from_config(
     (load_flags as i32, render)
 }
 
+/// One of the design axes of a variable font
+#[derive(Debug, Clone)]
+pub struct VariationAxis {
+    pub tag: FT_ULong,
+    pub minimum: f64,
+    pub default: f64,
+    pub maximum: f64,
+}
+
+impl std::fmt::Display for VariationAxis {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{}={}..{} (default {})",
+            tag_to_string(self.tag),
+            self.minimum,
+            self.maximum,
+            self.default
+        )
+    }
+}
+
+/// Parses a variation axis setting such as `wght=450` or `CASL=0.5`
+/// into the axis tag and its value.
+/// Tags shorter than 4 characters are padded with spaces.
+pub fn variation_from_string(s: &str) -> anyhow::Result<(FT_ULong, f64)> {
+    let (tag, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected an axis setting like wght=450, but got {s}"))?;
+    let tag = tag.trim();
+    anyhow::ensure!(
+        !tag.is_empty() && tag.len() <= 4 && tag.bytes().all(|b| b.is_ascii_graphic()),
+        "invalid variation axis tag {tag:?} in {s}"
+    );
+    let value: f64 = value
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid variation axis value in {s}"))?;
+
+    let mut bytes = [b' '; 4];
+    bytes[..tag.len()].copy_from_slice(tag.as_bytes());
+    Ok((ft_make_tag(bytes[0], bytes[1], bytes[2], bytes[3]), value))
+}
+
+fn tag_to_string(tag: FT_ULong) -> String {
+    let bytes = (tag as u32).to_be_bytes();
+    String::from_utf8_lossy(&bytes).trim_end().to_string()
+}
+
 pub struct Face {
     pub face: FT_Face,
     source: FontDataHandle,
@@ -208,6 +257,86 @@ impl Face {
         }
     }
 
+    /// Returns the design axes of a variable font, or an empty
+    /// list for other fonts
+    pub fn variation_axes(&self) -> Vec<VariationAxis> {
+        let mut mm = std::ptr::null_mut();
+
+        unsafe {
+            if !succeeded(FT_Get_MM_Var(self.face, &mut mm)) {
+                return vec![];
+            }
+
+            let axes = from_raw_parts((*mm).axis, (*mm).num_axis as usize)
+                .iter()
+                .map(|axis| VariationAxis {
+                    tag: axis.tag,
+                    minimum: axis.minimum.to_num::<f64>(),
+                    default: axis.def.to_num::<f64>(),
+                    maximum: axis.maximum.to_num::<f64>(),
+                })
+                .collect();
+
+            FT_Done_MM_Var(self.lib, mm);
+
+            axes
+        }
+    }
+
+    /// Applies variation axis settings on top of the design coordinates
+    /// of the current named instance.
+    /// Values are clamped to the range of their axis, and settings for
+    /// axes that are not present in the font are ignored.
+    pub fn set_variations(&mut self, variations: &[(FT_ULong, f64)]) -> anyhow::Result<()> {
+        if variations.is_empty() {
+            return Ok(());
+        }
+
+        let mut mm = std::ptr::null_mut();
+
+        unsafe {
+            if !succeeded(FT_Get_MM_Var(self.face, &mut mm)) {
+                log::warn!(
+                    "{} is not a variable font; ignoring its variations",
+                    self.source.diagnostic_string()
+                );
+                return Ok(());
+            }
+
+            let axes = from_raw_parts((*mm).axis, (*mm).num_axis as usize);
+            let mut coords = vec![FT_Fixed::from_num(0); axes.len()];
+            let mut res =
+                FT_Get_Var_Design_Coordinates(self.face, coords.len() as _, coords.as_mut_ptr());
+            if succeeded(res) {
+                for (tag, value) in variations {
+                    match axes.iter().position(|axis| axis.tag == *tag) {
+                        Some(idx) => {
+                            let axis = &axes[idx];
+                            let value = value
+                                .max(axis.minimum.to_num::<f64>())
+                                .min(axis.maximum.to_num::<f64>());
+                            coords[idx] = FT_Fixed::from_num(value);
+                        }
+                        None => log::warn!(
+                            "{} has no {} variation axis",
+                            self.source.diagnostic_string(),
+                            tag_to_string(*tag)
+                        ),
+                    }
+                }
+                res = FT_Set_Var_Design_Coordinates(
+                    self.face,
+                    coords.len() as _,
+                    coords.as_mut_ptr(),
+                );
+            }
+
+            FT_Done_MM_Var(self.lib, mm);
+
+            ft_result(res, ()).context("FT_Set_Var_Design_Coordinates")
+        }
+    }
+
     pub fn get_glyph_name(&self, glyph_index: u32) -> Option<String> {
         let mut buf = [0u8; 128];
         let res = unsafe {
@@ -1533,3 +1662,32 @@ pub fn composite_mode_to_operator(mode: FT_Composite_Mode) -> cairo::Operator {
 fn ft_make_tag(a: u8, b: u8, c: u8, d: u8) -> FT_ULong {
     (a as FT_ULong) << 24 | (b as FT_ULong) << 16 | (c as FT_ULong) << 8 | (d as FT_ULong)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_variations() {
+        assert_eq!(
+            variation_from_string("wght=450").unwrap(),
+            (ft_make_tag(b'w', b'g', b'h', b't'), 450.)
+        );
+        assert_eq!(
+            variation_from_string(" slnt = -5.5 ").unwrap(),
+            (ft_make_tag(b's', b'l', b'n', b't'), -5.5)
+        );
+        assert_eq!(
+            variation_from_string("MON=1").unwrap(),
+            (ft_make_tag(b'M', b'O', b'N', b' '), 1.)
+        );
+        assert_eq!(
+            tag_to_string(variation_from_string("CASL=0.5").unwrap().0),
+            "CASL"
+        );
+        assert!(variation_from_string("wght").is_err());
+        assert!(variation_from_string("wght=bold").is_err());
+        assert!(variation_from_string("toolong=1").is_err());
+        assert!(variation_from_string("=1").is_err());
+    }
+}
@@ -33,6 +33,30 @@ pub fn language_from_string(s: &str) -> Result<hb_language_t, Error> {
     }
 }
 
+/// Returns the ISO 15924 tag of the script that `c` belongs to
+pub fn script_tag_for_codepoint(c: char) -> TagString {
+    let tag = unsafe {
+        let script = hb_unicode_script(hb_unicode_funcs_get_default(), c as hb_codepoint_t);
+        hb_script_to_iso15924_tag(script)
+    };
+    hb_tag_to_string(tag)
+}
+
+/// Returns true if `c` is assigned and is neither a control,
+/// format nor surrogate codepoint
+pub fn is_visible_codepoint(c: char) -> bool {
+    use hb_unicode_general_category_t::*;
+    let category =
+        unsafe { hb_unicode_general_category(hb_unicode_funcs_get_default(), c as hb_codepoint_t) };
+    !matches!(
+        category,
+        HB_UNICODE_GENERAL_CATEGORY_CONTROL
+            | HB_UNICODE_GENERAL_CATEGORY_FORMAT
+            | HB_UNICODE_GENERAL_CATEGORY_UNASSIGNED
+            | HB_UNICODE_GENERAL_CATEGORY_SURROGATE
+    )
+}
+
 pub fn feature_from_string(s: &str) -> Result<hb_feature_t, Error> {
     unsafe {
         let mut feature = mem::zeroed();
@@ -316,6 +340,15 @@ impl Font {
         }
     }
 
+    /// Applies variation axis settings; see `ftwrap::variation_from_string`
+    pub fn set_variations(&mut self, variations: &[(freetype::FT_ULong, f64)]) {
+        for (tag, value) in variations {
+            unsafe {
+                hb_font_set_variation(self.font, *tag as hb_tag_t, *value as f32);
+            }
+        }
+    }
+
     pub fn set_synthetic_bold(&mut self, x_embolden: f32, y_embolden: f32, in_place: bool) {
         unsafe {
             hb_font_set_synthetic_bold(
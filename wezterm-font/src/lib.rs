@@ -38,6 +38,18 @@ pub mod fcwrap;
 pub use crate::rasterizer::RasterizedGlyph;
 pub use crate::shaper::{FallbackIdx, FontMetrics, GlyphInfo};
 
+/// Returns the ISO 15924 tag, such as `Latn` or `Hani`, of the
+/// script that `c` belongs to
+pub fn script_tag_for_codepoint(c: char) -> String {
+    hbwrap::script_tag_for_codepoint(c).to_string()
+}
+
+/// Returns true if `c` is an assigned codepoint that is neither
+/// a control, format nor surrogate codepoint
+pub fn is_visible_codepoint(c: char) -> bool {
+    hbwrap::is_visible_codepoint(c)
+}
+
 #[derive(Debug, Error)]
 #[error("Font fallback recalculated")]
 pub struct ClearShapeCache {}
@@ -100,7 +112,13 @@ impl LoadedFont {
         {
             let mut handles = self.handles.borrow_mut();
             for h in extra_handles {
-                if !handles.iter().any(|existing| *existing == h) {
+                // A font that was loaded for a font_range_rules entry
+                // can't be used for other codepoints, so it doesn't
+                // count as already being present
+                if !handles
+                    .iter()
+                    .any(|existing| existing.range_rule.is_none() && *existing == h)
+                {
                     handles.push(h);
                     loaded = true;
                 }
@@ -440,6 +458,7 @@ impl FallbackResolveInfo {
                     ),
                     url: Some(url.to_string()),
                     timeout: Some(Duration::from_secs(15)),
+                    ..Default::default()
                 }
                 .show();
             } else {
@@ -462,6 +481,9 @@ enum Entity {
 
 struct FontConfigInner {
     fonts: RefCell<HashMap<TextStyle, Rc<LoadedFont>>>,
+    /// Fonts that are scaled relative to the configured size,
+    /// keyed by style and the bits of the scale factor
+    scaled_fonts: RefCell<HashMap<(TextStyle, u64), Rc<LoadedFont>>>,
     metrics: RefCell<Option<FontMetrics>>,
     dpi: RefCell<usize>,
     font_scale: RefCell<f64>,
@@ -488,6 +510,7 @@ impl FontConfigInner {
         let locator = new_locator(config.font_locator);
         Ok(Self {
             fonts: RefCell::new(HashMap::new()),
+            scaled_fonts: RefCell::new(HashMap::new()),
             locator,
             metrics: RefCell::new(None),
             title_font: RefCell::new(None),
@@ -508,6 +531,7 @@ impl FontConfigInner {
         *self.config.borrow_mut() = config.clone();
         // Config was reloaded, invalidate our caches
         fonts.clear();
+        self.scaled_fonts.borrow_mut().clear();
         self.title_font.borrow_mut().take();
         self.pane_select_font.borrow_mut().take();
         self.char_select_font.borrow_mut().take();
@@ -805,7 +829,7 @@ impl FontConfigInner {
     ) -> anyhow::Result<(Box<dyn FontShaper>, Vec<ParsedFont>)> {
         let attributes = style.font_with_fallback();
 
-        let (handles, loaded) = self.resolve_font_helper_impl(&attributes, pixel_size)?;
+        let (mut handles, loaded) = self.resolve_font_helper_impl(&attributes, pixel_size)?;
 
         for attr in &attributes {
             if !attr.is_synthetic && !attr.is_fallback && !loaded.contains(attr) {
@@ -855,9 +879,61 @@ impl FontConfigInner {
             }
         }
 
+        handles.append(&mut self.resolve_range_rule_fonts(
+            config,
+            pixel_size,
+            *style == config.font,
+        )?);
+
         Ok((new_shaper(&*config, &handles)?, handles))
     }
 
+    /// Loads the fonts for the `font_range_rules` entries.
+    /// They are placed after the fonts of the style, ahead of the
+    /// general fallback, and the shaper only uses them to render
+    /// the codepoints from the ranges of their rule.
+    fn resolve_range_rule_fonts(
+        &self,
+        config: &ConfigHandle,
+        pixel_size: u16,
+        report_errors: bool,
+    ) -> anyhow::Result<Vec<ParsedFont>> {
+        let mut handles = vec![];
+
+        for (idx, rule) in config.font_range_rules.iter().enumerate() {
+            let mut ranges = RangeSet::new();
+            for range in &rule.ranges {
+                ranges.add_range(range.first..range.last + 1);
+            }
+
+            let (rule_handles, loaded) =
+                self.resolve_font_helper_impl(&rule.font.font, pixel_size)?;
+
+            if report_errors {
+                for attr in &rule.font.font {
+                    if !attr.is_fallback && !loaded.contains(attr) {
+                        config::show_error(&format!(
+                            "Unable to load a font specified by font_range_rules[{}]: {}. \
+                            The general font fallback is being used for its codepoints \
+                            instead. See https://wezterm.org/config/fonts.html for more \
+                            information",
+                            idx + 1,
+                            attr
+                        ));
+                    }
+                }
+            }
+
+            for mut handle in rule_handles {
+                handle.range_rule = Some(idx);
+                handle.codepoint_ranges = Some(ranges.clone());
+                handles.push(handle);
+            }
+        }
+
+        Ok(handles)
+    }
+
     /// Given a text style, load (with caching) the font that best
     /// matches according to the fontconfig pattern.
     fn resolve_font(&self, myself: &Rc<Self>, style: &TextStyle) -> anyhow::Result<Rc<LoadedFont>> {
@@ -875,11 +951,57 @@ impl FontConfigInner {
             return Ok(Rc::clone(entry));
         }
 
-        let mut font_size = config.font_size * *self.font_scale.borrow();
+        let loaded = self.load_font(myself, style, &config, def_font, 1.0)?;
+        fonts.insert(style.clone(), Rc::clone(&loaded));
+
+        Ok(loaded)
+    }
+
+    fn resolve_font_scaled(
+        &self,
+        myself: &Rc<Self>,
+        style: &TextStyle,
+        scale: f64,
+    ) -> anyhow::Result<Rc<LoadedFont>> {
+        if scale == 1.0 {
+            return self.resolve_font(myself, style);
+        }
+
+        let config = self.config.borrow();
+        let is_default = *style == config.font;
+        let def_font = if !is_default && config.use_cap_height_to_scale_fallback_fonts {
+            Some(self.default_font(myself)?)
+        } else {
+            None
+        };
+
+        let key = (style.clone(), scale.to_bits());
+        let mut scaled_fonts = self.scaled_fonts.borrow_mut();
+
+        if let Some(entry) = scaled_fonts.get(&key) {
+            return Ok(Rc::clone(entry));
+        }
+
+        let loaded = self.load_font(myself, style, &config, def_font, scale)?;
+        scaled_fonts.insert(key, Rc::clone(&loaded));
+
+        Ok(loaded)
+    }
+
+    /// Loads the font for `style` at `size_scale` times the configured size
+    fn load_font(
+        &self,
+        myself: &Rc<Self>,
+        style: &TextStyle,
+        config: &ConfigHandle,
+        def_font: Option<Rc<LoadedFont>>,
+        size_scale: f64,
+    ) -> anyhow::Result<Rc<LoadedFont>> {
+        let mut font_size = config.font_size * *self.font_scale.borrow() * size_scale;
         let dpi = *self.dpi.borrow() as u32;
         let pixel_size = (font_size * dpi as f64 / 72.0) as u16;
 
-        let (mut shaper, mut handles) = self.resolve_font_helper(style, &config, pixel_size)?;
+        let (mut shaper, mut handles) = self.resolve_font_helper(style, config, pixel_size)?;
 
         let mut metrics = shaper.metrics(font_size, dpi).with_context(|| {
             format!(
@@ -895,7 +1017,7 @@ impl FontConfigInner {
                     // Scale by the ratio of the pixel heights of the default
                     // and this font; this causes the `I` glyphs to appear to
                     // have the same height.
-                    let scale = d.get() / m.get();
+                    let scale = d.get() * size_scale / m.get();
                     if scale != 1.0 {
                         let scaled_pixel_size = (pixel_size as f64 * scale) as u16;
                         let scaled_font_size = font_size * scale;
@@ -908,7 +1030,7 @@ impl FontConfigInner {
                             metrics,
                         );
                         let (alt_shaper, alt_handles) =
-                            self.resolve_font_helper(style, &config, scaled_pixel_size)?;
+                            self.resolve_font_helper(style, config, scaled_pixel_size)?;
                         shaper = alt_shaper;
                         handles = alt_handles;
 
@@ -941,8 +1063,6 @@ impl FontConfigInner {
             pixel_geometry: config.display_pixel_geometry,
         });
 
-        fonts.insert(style.clone(), Rc::clone(&loaded));
-
         Ok(loaded)
     }
 
@@ -953,6 +1073,7 @@ impl FontConfigInner {
         *self.dpi.borrow_mut() = dpi;
         *self.font_scale.borrow_mut() = font_scale;
         self.fonts.borrow_mut().clear();
+        self.scaled_fonts.borrow_mut().clear();
         self.metrics.borrow_mut().take();
         self.title_font.borrow_mut().take();
 
@@ -1085,6 +1206,17 @@ impl FontConfiguration {
         self.inner.resolve_font(&self.inner, style)
     }
 
+    /// Like `resolve_font`, but loads the font at `scale` times the
+    /// configured size, so that its glyphs are rasterized at that size
+    /// rather than being scaled up from the regular size.
+    pub fn resolve_font_scaled(
+        &self,
+        style: &TextStyle,
+        scale: f64,
+    ) -> anyhow::Result<Rc<LoadedFont>> {
+        self.inner.resolve_font_scaled(&self.inner, style, scale)
+    }
+
     pub fn change_scaling(&self, font_scale: f64, dpi: usize) -> (f64, usize) {
         self.inner.change_scaling(font_scale, dpi)
     }
@@ -322,6 +322,7 @@ impl FontLocator for GdiFontLocator {
                         is_fallback: true,
                         is_synthetic: true,
                         harfbuzz_features: None,
+                        variations: None,
                         freetype_load_target: None,
                         freetype_render_target: None,
                         freetype_load_flags: None,
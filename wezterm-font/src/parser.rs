@@ -1,3 +1,4 @@
+use crate::ftwrap::VariationAxis;
 use crate::locator::{FontDataHandle, FontDataSource, FontOrigin};
 use crate::shaper::GlyphInfo;
 use config::{FontAttributes, FontStyle, FreeTypeLoadFlags, FreeTypeLoadTarget};
@@ -34,10 +35,16 @@ pub struct ParsedFont {
     pub synthesize_dim: bool,
     pub assume_emoji_presentation: bool,
     pub pixel_sizes: Vec<u16>,
+    pub axes: Vec<VariationAxis>,
     pub is_built_in_fallback: bool,
     pub palettes: Vec<FontPaletteInfo>,
+    /// When this font was loaded for an entry in `font_range_rules`,
+    /// the index of that entry and the codepoints that it may render
+    pub range_rule: Option<usize>,
+    pub codepoint_ranges: Option<RangeSet<u32>>,
 
     pub harfbuzz_features: Option<Vec<String>>,
+    pub variations: Option<Vec<String>>,
     pub freetype_load_target: Option<FreeTypeLoadTarget>,
     pub freetype_render_target: Option<FreeTypeLoadTarget>,
     pub freetype_load_flags: Option<FreeTypeLoadFlags>,
@@ -58,11 +65,15 @@ impl std::fmt::Debug for ParsedFont {
             .field("synthesize_dim", &self.synthesize_dim)
             .field("assume_emoji_presentation", &self.assume_emoji_presentation)
             .field("pixel_sizes", &self.pixel_sizes)
+            .field("axes", &self.axes)
             .field("harfbuzz_features", &self.harfbuzz_features)
+            .field("variations", &self.variations)
             .field("freetype_load_target", &self.freetype_load_target)
             .field("freetype_render_target", &self.freetype_render_target)
             .field("freetype_load_flags", &self.freetype_load_flags)
             .field("scale", &self.scale)
+            .field("range_rule", &self.range_rule)
+            .field("codepoint_ranges", &self.codepoint_ranges)
             .finish()
     }
 }
@@ -82,13 +93,17 @@ impl Clone for ParsedFont {
             cap_height: self.cap_height,
             coverage: Mutex::new(self.coverage.lock().unwrap().clone()),
             pixel_sizes: self.pixel_sizes.clone(),
+            axes: self.axes.clone(),
             harfbuzz_features: self.harfbuzz_features.clone(),
+            variations: self.variations.clone(),
             freetype_load_target: self.freetype_load_target,
             freetype_render_target: self.freetype_render_target,
             freetype_load_flags: self.freetype_load_flags,
             is_built_in_fallback: self.is_built_in_fallback,
             scale: self.scale,
             palettes: self.palettes.clone(),
+            range_rule: self.range_rule,
+            codepoint_ranges: self.codepoint_ranges.clone(),
         }
     }
 }
@@ -289,6 +304,43 @@ impl ParsedFont {
         )
     }
 
+    /// Describes the `font_range_rules` entry that this font was loaded for,
+    /// using the lua index of that entry
+    pub fn range_rule_description(&self) -> Option<String> {
+        let rule = self.range_rule?;
+        let ranges: Vec<String> = self
+            .codepoint_ranges
+            .as_ref()?
+            .iter()
+            .map(|r| {
+                if r.end - r.start == 1 {
+                    format!("U+{:04X}", r.start)
+                } else {
+                    format!("U+{:04X}-U+{:04X}", r.start, r.end - 1)
+                }
+            })
+            .collect();
+        Some(format!(
+            "{} by font_range_rules[{}]",
+            ranges.join(", "),
+            rule + 1
+        ))
+    }
+
+    /// Returns true if this font may be used to render `text`.
+    /// Fonts that were loaded for a `font_range_rules` entry may only
+    /// render the codepoints from the ranges of that entry.
+    /// The shaper splits text into runs of graphemes that either all
+    /// start with a codepoint from the same rule, or that all start with
+    /// codepoints that aren't covered by any rule, so only the first
+    /// codepoint needs to be considered.
+    pub fn permits_text(&self, text: &str) -> bool {
+        match (&self.codepoint_ranges, text.chars().next()) {
+            (Some(ranges), Some(c)) => ranges.contains(c as u32),
+            _ => true,
+        }
+    }
+
     pub fn lua_fallback(handles: &[Self]) -> String {
         let mut code = "wezterm.font_with_fallback({\n".to_string();
 
@@ -305,9 +357,16 @@ impl ParsedFont {
             if p.assume_emoji_presentation {
                 code.push_str("  -- Assumed to have Emoji Presentation\n");
             }
+            if let Some(rule) = p.range_rule_description() {
+                code.push_str(&format!("  -- Only used for {}\n", rule));
+            }
             if !p.pixel_sizes.is_empty() {
                 code.push_str(&format!("  -- Pixel sizes: {:?}\n", p.pixel_sizes));
             }
+            if !p.axes.is_empty() {
+                let axes: Vec<String> = p.axes.iter().map(|axis| axis.to_string()).collect();
+                code.push_str(&format!("  -- Variation axes: {}\n", axes.join(", ")));
+            }
             if !p.palettes.is_empty() {
                 for pal in &p.palettes {
                     let mut info = format!(
@@ -336,6 +395,7 @@ impl ParsedFont {
                 && p.freetype_load_target.is_none()
                 && p.freetype_load_flags.is_none()
                 && p.harfbuzz_features.is_none()
+                && p.variations.is_none()
                 && p.scale.is_none()
             {
                 code.push_str(&format!("  \"{}\",\n", p.names.family));
@@ -374,6 +434,18 @@ impl ParsedFont {
                     }
                     code.push('}');
                 }
+                if let Some(variations) = &p.variations {
+                    code.push_str(", variations={");
+                    for (idx, v) in variations.iter().enumerate() {
+                        if idx > 0 {
+                            code.push_str(", ");
+                        }
+                        code.push('"');
+                        code.push_str(v);
+                        code.push('"');
+                    }
+                    code.push('}');
+                }
                 code.push_str("},\n")
             }
             code.push_str("\n");
@@ -393,6 +465,7 @@ impl ParsedFont {
         let stretch = FontStretch::from_opentype_stretch(width);
         let cap_height = face.cap_height();
         let pixel_sizes = face.pixel_sizes();
+        let axes = face.variation_axes();
 
         let palettes = match face.get_palette_data() {
             Ok(info) => info
@@ -524,12 +597,16 @@ impl ParsedFont {
             coverage: Mutex::new(RangeSet::new()),
             cap_height,
             pixel_sizes,
+            axes,
             harfbuzz_features: None,
+            variations: None,
             freetype_render_target: None,
             freetype_load_target: None,
             freetype_load_flags: None,
             scale: None,
             palettes,
+            range_rule: None,
+            codepoint_ranges: None,
         })
     }
 
@@ -555,6 +632,19 @@ impl ParsedFont {
         Ok(wanted.intersection(&cov))
     }
 
+    /// Returns the parsed axis settings from `variations`.
+    /// Invalid settings are logged and skipped.
+    pub fn variation_settings(&self) -> Vec<(crate::ftwrap::FT_ULong, f64)> {
+        let mut settings = vec![];
+        for s in self.variations.iter().flatten() {
+            match crate::ftwrap::variation_from_string(s) {
+                Ok(setting) => settings.push(setting),
+                Err(err) => log::warn!("{}: {:#}", self.names.full_name, err),
+            }
+        }
+        settings
+    }
+
     pub fn names(&self) -> &Names {
         &self.names
     }
@@ -777,6 +867,7 @@ impl ParsedFont {
     /// italic for this font.
     pub fn synthesize(mut self, attr: &FontAttributes) -> Self {
         self.harfbuzz_features = attr.harfbuzz_features.clone();
+        self.variations = attr.variations.clone();
         self.freetype_render_target = attr.freetype_render_target;
         self.freetype_load_target = attr.freetype_load_target;
         self.freetype_load_flags = attr.freetype_load_flags;
@@ -157,9 +157,11 @@ impl HarfbuzzShaper {
                 if opt_pair.is_none() {
                     let handle = &self.handles[font_idx];
                     log::trace!("shaper wants {} {:?}", font_idx, handle);
-                    let face = self.lib.face_from_locator(&handle.handle)?;
+                    let mut face = self.lib.face_from_locator(&handle.handle)?;
+                    let variations = handle.variation_settings();
+                    face.set_variations(&variations)?;
 
-                    let font = if USE_OT_FACE {
+                    let mut font = if USE_OT_FACE {
                         harfbuzz::Font::from_locator(&handle.handle)?
                     } else {
                         let (load_flags, _) = ftwrap::compute_load_flags_from_config(
@@ -172,6 +174,7 @@ impl HarfbuzzShaper {
                         font.set_load_flags(load_flags);
                         font
                     };
+                    font.set_variations(&variations);
 
                     let features = match &handle.harfbuzz_features {
                         Some(features) => features
@@ -202,6 +205,81 @@ impl HarfbuzzShaper {
         }
     }
 
+    /// Returns the index of the font that `font_range_rules` assigns
+    /// to `c`, if any
+    fn range_rule_font(&self, c: char) -> Option<FallbackIdx> {
+        self.handles.iter().position(|h| {
+            h.codepoint_ranges
+                .as_ref()
+                .map_or(false, |ranges| ranges.contains(c as u32))
+        })
+    }
+
+    /// Returns the index of the font to try for the clusters that
+    /// `font_idx` has no glyphs for.  The remaining fonts of a
+    /// `font_range_rules` entry are tried first, followed by the regular
+    /// fonts from the start, so that the glyphs of the fonts that precede
+    /// the rule fonts are still found.
+    fn next_fallback_idx(&self, font_idx: FallbackIdx) -> FallbackIdx {
+        let rule = |idx: FallbackIdx| self.handles.get(idx).and_then(|h| h.range_rule);
+        match rule(font_idx) {
+            Some(r) if rule(font_idx + 1) == Some(r) => font_idx + 1,
+            Some(_) => 0,
+            None => font_idx + 1,
+        }
+    }
+
+    /// Splits `range` into runs of graphemes that use the same
+    /// `font_range_rules` font, or no such font, and shapes each run
+    /// starting with its font.
+    fn shape_range_rule_runs(
+        &self,
+        s: &str,
+        font_size: f64,
+        dpi: u32,
+        no_glyphs: &mut Vec<char>,
+        presentation: Option<Presentation>,
+        direction: Direction,
+        range: Range<usize>,
+        presentation_width: Option<&PresentationWidth>,
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        let mut runs: Vec<(Option<FallbackIdx>, Range<usize>)> = vec![];
+        let mut start = range.start;
+        for grapheme in Graphemes::new(&s[range]) {
+            let end = start + grapheme.len();
+            let font_idx = grapheme
+                .chars()
+                .next()
+                .and_then(|c| self.range_rule_font(c));
+            match runs.last_mut() {
+                Some((run_font_idx, run)) if *run_font_idx == font_idx => run.end = end,
+                _ => runs.push((font_idx, start..end)),
+            }
+            start = end;
+        }
+
+        // The glyphs for right-to-left text are produced in visual order
+        if direction == Direction::RightToLeft {
+            runs.reverse();
+        }
+
+        let mut glyphs = vec![];
+        for (font_idx, run) in runs {
+            glyphs.append(&mut self.do_shape(
+                font_idx.unwrap_or(0),
+                s,
+                font_size,
+                dpi,
+                no_glyphs,
+                presentation,
+                direction,
+                run,
+                presentation_width,
+            )?);
+        }
+        Ok(glyphs)
+    }
+
     fn do_shape(
         &self,
         mut font_idx: FallbackIdx,
@@ -240,11 +318,30 @@ impl HarfbuzzShaper {
         // will use the notdef glyph from the base font.
         let mut no_more_fallbacks = false;
 
+        // The fonts of a font_range_rules entry are only used when we
+        // start with one of them; they are skipped when falling back
+        // from the other fonts
+        let range_rule = self.handles.get(font_idx).and_then(|h| h.range_rule);
+
         loop {
+            if let Some(handle) = self.handles.get(font_idx) {
+                let other_rule = handle.range_rule.is_some() && handle.range_rule != range_rule;
+                if other_rule || !handle.permits_text(&s[range.clone()]) {
+                    log::trace!(
+                        "font_idx={font_idx} is reserved for other codepoints \
+                         by font_range_rules, so skip it"
+                    );
+                    font_idx += 1;
+                    continue;
+                }
+            }
             match self.load_fallback(font_idx, dpi).context("load_fallback")? {
                 Some(mut pair) => {
+                    // An explicit font_range_rules font is used regardless
+                    // of its presentation
+                    let is_range_rule = self.handles[font_idx].range_rule.is_some();
                     if let Some(p) = presentation {
-                        if pair.presentation != p {
+                        if pair.presentation != p && !is_range_rule {
                             log::trace!(
                                 "wanted presentation is {p:?} != font \
                                      presentation {:?} so skip \
@@ -480,7 +577,7 @@ impl HarfbuzzShaper {
                 let first_info = &infos[0];
 
                 let mut shape = match self.do_shape(
-                    font_idx + 1,
+                    self.next_fallback_idx(font_idx),
                     s,
                     font_size,
                     dpi,
@@ -582,17 +679,30 @@ impl FontShaper for HarfbuzzShaper {
             text.escape_debug()
         );
         let start = std::time::Instant::now();
-        let result = self.do_shape(
-            0,
-            text,
-            size,
-            dpi,
-            no_glyphs,
-            presentation,
-            direction,
-            range,
-            presentation_width,
-        );
+        let result = if self.handles.iter().any(|h| h.codepoint_ranges.is_some()) {
+            self.shape_range_rule_runs(
+                text,
+                size,
+                dpi,
+                no_glyphs,
+                presentation,
+                direction,
+                range,
+                presentation_width,
+            )
+        } else {
+            self.do_shape(
+                0,
+                text,
+                size,
+                dpi,
+                no_glyphs,
+                presentation,
+                direction,
+                range,
+                presentation_width,
+            )
+        };
         metrics::histogram!("shape.harfbuzz").record(start.elapsed());
         /*
         if let Ok(glyphs) = &result {
@@ -838,6 +948,80 @@ mod test {
     use super::*;
     use crate::FontDatabase;
     use config::FontAttributes;
+    use rangeset::RangeSet;
+
+    fn range_rule_font(db: &FontDatabase, family: &str, chars: &str) -> ParsedFont {
+        let mut handle = db
+            .resolve(&FontAttributes::new(family), 14)
+            .unwrap()
+            .clone();
+        let mut ranges = RangeSet::new();
+        for c in chars.chars() {
+            ranges.add(c as u32);
+        }
+        handle.range_rule = Some(0);
+        handle.codepoint_ranges = Some(ranges);
+        handle
+    }
+
+    fn shaped_font_indices(shaper: &HarfbuzzShaper, text: &str) -> Vec<(String, FallbackIdx)> {
+        let mut no_glyphs = vec![];
+        shaper
+            .shape(
+                text,
+                10.,
+                72,
+                &mut no_glyphs,
+                None,
+                Direction::LeftToRight,
+                None,
+                None,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|info| (info.text.to_string(), info.font_idx))
+            .collect()
+    }
+
+    #[test]
+    fn range_rules() {
+        let db = FontDatabase::with_built_in().unwrap();
+        let config = config::configuration();
+        let jetbrains = db
+            .resolve(&FontAttributes::new("JetBrains Mono"), 14)
+            .unwrap()
+            .clone();
+
+        // JetBrains Mono has a glyph for `a` too, but the rule font wins
+        let shaper = HarfbuzzShaper::new(
+            &config,
+            &[jetbrains.clone(), range_rule_font(&db, "Fira Code", "a")],
+        )
+        .unwrap();
+        assert_eq!(
+            shaped_font_indices(&shaper, "bab"),
+            vec![
+                ("b".to_string(), 0),
+                ("a".to_string(), 1),
+                ("b".to_string(), 0)
+            ]
+        );
+
+        // The symbols font has no glyph for `a`, so the fonts that
+        // precede it are used instead of the notdef glyph
+        let shaper = HarfbuzzShaper::new(
+            &config,
+            &[
+                jetbrains,
+                range_rule_font(&db, "Symbols Nerd Font Mono", "a"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            shaped_font_indices(&shaper, "a"),
+            vec![("a".to_string(), 0)]
+        );
+    }
 
     #[test]
     fn ligatures() {
@@ -860,6 +1044,7 @@ mod test {
                     freetype_load_target: None,
                     freetype_render_target: None,
                     harfbuzz_features: None,
+                    variations: None,
                     scale: None,
                     assume_emoji_presentation: None,
                 },
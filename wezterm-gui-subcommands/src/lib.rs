@@ -260,6 +260,44 @@ pub struct LsFontsCommand {
     /// Show rasterized glyphs for the text in --text or --codepoints using ascii blocks.
     #[arg(long, requires = "text")]
     pub rasterize_ascii: bool,
+
+    /// Audit the coverage of the font configuration for the text from
+    /// --text, --codepoints, --text-file, --range or --script: report the
+    /// font that renders each cluster, and the clusters that fall back to
+    /// a last resort placeholder or that are synthesized.
+    #[arg(long, conflicts_with_all = &["list_system", "rasterize_ascii"])]
+    pub coverage: bool,
+
+    /// Audit the text in the specified file with --coverage.
+    #[arg(
+        long,
+        requires = "coverage",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = &["text", "codepoints"]
+    )]
+    pub text_file: Option<PathBuf>,
+
+    /// Audit each assigned codepoint in a unicode block, or other range
+    /// of codepoints, with --coverage. eg: `U+0400-U+04FF`.
+    #[arg(
+        long,
+        requires = "coverage",
+        conflicts_with_all = &["text", "codepoints", "text_file"]
+    )]
+    pub range: Option<String>,
+
+    /// Audit each assigned codepoint in the script with the specified
+    /// ISO 15924 code, such as `Cyrl` or `Hani`, with --coverage.
+    #[arg(
+        long,
+        requires = "coverage",
+        conflicts_with_all = &["text", "codepoints", "text_file", "range"]
+    )]
+    pub script: Option<String>,
+
+    /// Output the --coverage report as JSON.
+    #[arg(long, requires = "coverage")]
+    pub json: bool,
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -271,3 +309,11 @@ pub struct ShowKeysCommand {
     #[arg(long)]
     pub key_table: Option<String>,
 }
+
+#[derive(Debug, Parser, Clone)]
+pub struct RenderSnapshotCommand {
+    /// The png file to write.
+    /// The snapshot to render is read as JSON from stdin.
+    #[arg(value_hint = ValueHint::FilePath)]
+    pub output: PathBuf,
+}
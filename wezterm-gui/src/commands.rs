@@ -673,6 +673,26 @@ pub fn derive_command_from_key_assignment(action: &KeyAssignment) -> Option<Comm
             menubar: &["Edit"],
             icon: Some("md_content_paste"),
         },
+        CopyTextTo {
+            text: _,
+            destination: ClipboardCopyDestination::Register(name),
+        }
+        | CopyTo(ClipboardCopyDestination::Register(name)) => CommandDef {
+            brief: format!("Copy to register {name}").into(),
+            doc: format!("Copies text to the register named {name}").into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &[],
+            icon: Some("md_content_copy"),
+        },
+        PasteFrom(ClipboardPasteSource::Register(name)) => CommandDef {
+            brief: format!("Paste from register {name}").into(),
+            doc: format!("Pastes text from the register named {name}").into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &[],
+            icon: Some("md_content_paste"),
+        },
         ToggleFullScreen => CommandDef {
             brief: "Toggle full screen mode".into(),
             doc: "Switch between normal and full screen mode".into(),
@@ -774,6 +794,24 @@ pub fn derive_command_from_key_assignment(action: &KeyAssignment) -> Option<Comm
             menubar: &["Edit"],
             icon: Some("cod_clear_all"),
         },
+        ExportScrollback(args) => CommandDef {
+            brief: format!(
+                "Export scrollback as {}",
+                match args.format {
+                    ExportFormat::Html => "HTML",
+                    ExportFormat::Ansi => "ANSI text",
+                    ExportFormat::Text => "plain text",
+                }
+            )
+            .into(),
+            doc: "Saves the scrollback and viewport of the current pane \
+              to a file in the downloads directory"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Edit"],
+            icon: Some("cod_save"),
+        },
         Search(Pattern::CurrentSelectionOrEmptyString) => CommandDef {
             brief: "Search pane output".into(),
             doc: "Enters the search mode UI for the current pane".into(),
@@ -790,6 +828,36 @@ pub fn derive_command_from_key_assignment(action: &KeyAssignment) -> Option<Comm
             menubar: &[],
             icon: Some("oct_search"),
         },
+        SearchAllPanes(_) => CommandDef {
+            brief: "Search all panes".into(),
+            doc: "Searches the output of all of the panes in the workspace, \
+                  and allows activating one of the matches"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Edit"],
+            icon: Some("md_text_search"),
+        },
+        JumpToDirectory(DirectoryJumpTarget::ChangeDirectory) => CommandDef {
+            brief: "Jump to a recently visited directory".into(),
+            doc: "Chooses from the most frequently and recently visited \
+                  directories, and changes the current pane to it"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Shell"],
+            icon: Some("md_folder_clock"),
+        },
+        JumpToDirectory(DirectoryJumpTarget::SpawnTab) => CommandDef {
+            brief: "New tab in a recently visited directory".into(),
+            doc: "Chooses from the most frequently and recently visited \
+                  directories, and spawns a new tab in it"
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Shell"],
+            icon: Some("md_folder_plus"),
+        },
         ShowDebugOverlay => CommandDef {
             brief: "Show debug overlay".into(),
             doc: "Activates the debug overlay and Lua REPL".into(),
@@ -1410,6 +1478,52 @@ pub fn derive_command_from_key_assignment(action: &KeyAssignment) -> Option<Comm
                 icon: Some("oct_terminal"),
             }
         }
+        CopyCommandOutput(dest) => CommandDef {
+            brief: match dest {
+                ClipboardCopyDestination::PrimarySelection => {
+                    "Copy command output to primary selection".to_string()
+                }
+                ClipboardCopyDestination::Clipboard => {
+                    "Copy command output to clipboard".to_string()
+                }
+                ClipboardCopyDestination::ClipboardAndPrimarySelection => {
+                    "Copy command output to clipboard and primary selection".to_string()
+                }
+                ClipboardCopyDestination::Register(name) => {
+                    format!("Copy command output to register {name}")
+                }
+            }
+            .into(),
+            doc: "Copies the output of the most recent command at or above \
+                  the bottom of the viewport.  Requires shell integration."
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Edit"],
+            icon: Some("cod_copy"),
+        },
+        ToggleCommandOutputCollapse => CommandDef {
+            brief: "Collapse or expand command output".into(),
+            doc: "Collapses the output of the most recent command at or above \
+                  the bottom of the viewport into a single line, or expands it \
+                  again.  Requires shell integration."
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Edit"],
+            icon: Some("cod_fold"),
+        },
+        ShowCommandHistory => CommandDef {
+            brief: "Show command history".into(),
+            doc: "Shows a searchable list of the commands that have been run \
+                  in all panes, and allows re-running one of them in the \
+                  active pane.  Requires shell integration."
+                .into(),
+            keys: vec![],
+            args: &[ArgType::ActivePane],
+            menubar: &["Shell"],
+            icon: Some("cod_history"),
+        },
         ScrollByCurrentEventWheelDelta => CommandDef {
             brief: "Scrolls based on the mouse wheel position \
                 in the current mouse event"
@@ -2049,6 +2163,9 @@ fn compute_default_actions() -> Vec<KeyAssignment> {
         PasteFrom(ClipboardPasteSource::Clipboard),
         ClearScrollback(ScrollbackEraseMode::ScrollbackOnly),
         ClearScrollback(ScrollbackEraseMode::ScrollbackAndViewport),
+        ExportScrollback(ExportScrollbackArguments::default()),
+        CopyCommandOutput(ClipboardCopyDestination::Clipboard),
+        ToggleCommandOutputCollapse,
         QuickSelect,
         CharSelect(CharSelectArguments::default()),
         ActivateCopyMode,
@@ -2072,6 +2189,9 @@ fn compute_default_actions() -> Vec<KeyAssignment> {
         SetWindowLevel(WindowLevel::AlwaysOnTop),
         Hide,
         Search(Pattern::CurrentSelectionOrEmptyString),
+        SearchAllPanes(Pattern::CurrentSelectionOrEmptyString),
+        JumpToDirectory(DirectoryJumpTarget::ChangeDirectory),
+        JumpToDirectory(DirectoryJumpTarget::SpawnTab),
         PaneSelect(PaneSelectArguments {
             alphabet: String::new(),
             mode: PaneSelectMode::Activate,
@@ -2136,6 +2256,7 @@ fn compute_default_actions() -> Vec<KeyAssignment> {
         ActivateLastTab,
         ShowLauncher,
         ShowTabNavigator,
+        ShowCommandHistory,
         // ----------------- Help
         OpenUri("https://wezterm.org/".to_string()),
         OpenUri("https://github.com/wezterm/wezterm/discussions/".to_string()),
@@ -57,9 +57,23 @@ fn resolve_file_name(name: Option<&str>) -> anyhow::Result<(PathBuf, File)> {
 }
 
 pub fn save_to_downloads(orig_name: Option<String>, data: &[u8]) -> anyhow::Result<()> {
-    let (name, mut file) = resolve_file_name(orig_name.as_deref())?;
-    file.write_all(data)
-        .with_context(|| format!("writing {} of data to {}", data.len(), name.display()))?;
+    save_to_downloads_with(orig_name, |file| {
+        file.write_all(data)
+            .with_context(|| format!("writing {} of data", data.len()))
+    })
+}
+
+/// Like `save_to_downloads`, but the data is written to the file by
+/// `write`, so that it doesn't need to be held in memory all at once
+pub fn save_to_downloads_with<F>(orig_name: Option<String>, write: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> anyhow::Result<()>,
+{
+    let (name, file) = resolve_file_name(orig_name.as_deref())?;
+    let mut file = std::io::BufWriter::new(file);
+    write(&mut file)
+        .and_then(|_| Ok(file.flush()?))
+        .with_context(|| format!("writing to {}", name.display()))?;
 
     let url = format!("file://{}", name.display());
     wezterm_toast_notification::persistent_toast_notification_with_click_to_open_url(
@@ -0,0 +1,366 @@
+//! Implements `wezterm ls-fonts --coverage`, which reports how the
+//! configured fonts render some text, a range of codepoints or a
+//! whole script, so that a font configuration can be checked ahead
+//! of time rather than by noticing missing glyphs at runtime.
+use crate::customglyph::BlockKey;
+use anyhow::{anyhow, Context};
+use config::{CodepointRange, ConfigHandle};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use termwiz::cell::CellAttributes;
+use termwiz::surface::{Line, SEQ_ZERO};
+use unicode_normalization::UnicodeNormalization;
+use wezterm_font::parser::ParsedFont;
+use wezterm_font::shaper::PresentationWidth;
+use wezterm_font::{FontConfiguration, GlyphInfo};
+use wezterm_gui_subcommands::LsFontsCommand;
+
+/// The families of the fonts that only contain placeholder glyphs
+const LAST_RESORT_FAMILIES: &[&str] = &["Last Resort High-Efficiency", ".LastResort"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterStatus {
+    /// Rendered using a glyph from a font
+    Font,
+    /// Rendered using a glyph from a font with synthesized styling,
+    /// or drawn by wezterm itself
+    Synthesized,
+    /// No font has a glyph for it, so a placeholder is rendered
+    LastResort,
+}
+
+impl ClusterStatus {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Font => "font",
+            Self::Synthesized => "synthesized",
+            Self::LastResort => "LAST RESORT",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterCoverage {
+    pub text: String,
+    pub codepoints: Vec<String>,
+    pub status: ClusterStatus,
+    /// The font that renders the cluster, as a `wezterm.font` expression
+    pub font: Option<String>,
+    /// Where that font was loaded from
+    pub source: Option<String>,
+    /// The `font_range_rules` entry that selected the font
+    pub range_rule: Option<String>,
+    /// How the cluster is synthesized
+    pub synthesized: Vec<&'static str>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CoverageSummary {
+    pub clusters: usize,
+    pub synthesized: usize,
+    pub last_resort: usize,
+    /// The number of clusters that are rendered by each font
+    pub fonts: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CoverageReport {
+    pub summary: CoverageSummary,
+    pub clusters: Vec<ClusterCoverage>,
+}
+
+impl CoverageReport {
+    fn push(&mut self, cluster: ClusterCoverage) {
+        self.summary.clusters += 1;
+        match cluster.status {
+            ClusterStatus::Font => {}
+            ClusterStatus::Synthesized => self.summary.synthesized += 1,
+            ClusterStatus::LastResort => self.summary.last_resort += 1,
+        }
+        if let Some(font) = &cluster.font {
+            *self.summary.fonts.entry(font.clone()).or_insert(0) += 1;
+        }
+        self.clusters.push(cluster);
+    }
+
+    fn print(&self) {
+        for cluster in &self.clusters {
+            let mut detail = match (&cluster.font, &cluster.source) {
+                (Some(font), Some(source)) => format!("{font} {source}"),
+                _ => String::new(),
+            };
+            if let Some(rule) = &cluster.range_rule {
+                detail.push_str(&format!(", matched {rule}"));
+            }
+            if !cluster.synthesized.is_empty() {
+                if !detail.is_empty() {
+                    detail.push_str(", ");
+                }
+                detail.push_str(&format!("synthesized {}", cluster.synthesized.join("+")));
+            }
+            println!(
+                "{:<24} {:4} {:<11} {}",
+                cluster.codepoints.join(" "),
+                cluster.text,
+                cluster.status.label(),
+                detail
+            );
+        }
+
+        let summary = &self.summary;
+        println!();
+        println!(
+            "{} clusters: {} rendered by fonts, {} synthesized, {} last resort",
+            summary.clusters,
+            summary.clusters - summary.synthesized - summary.last_resort,
+            summary.synthesized,
+            summary.last_resort
+        );
+        for (font, count) in &summary.fonts {
+            println!("{count:>8} {font}");
+        }
+    }
+}
+
+/// Returns `tag` with the capitalization used by ISO 15924, eg: `Cyrl`
+fn normalize_script_tag(tag: &str) -> anyhow::Result<String> {
+    anyhow::ensure!(
+        tag.len() == 4 && tag.chars().all(|c| c.is_ascii_alphabetic()),
+        "{tag} is not an ISO 15924 script code such as Latn, Cyrl or Hani"
+    );
+    Ok(tag
+        .chars()
+        .enumerate()
+        .map(|(idx, c)| {
+            if idx == 0 {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .collect())
+}
+
+/// Returns the text to audit, as a list of lines that are shaped
+/// independently of each other.
+/// Each codepoint from a range or script is placed on its own line,
+/// so that combining marks are checked in isolation.
+fn audit_lines(
+    config: &ConfigHandle,
+    cmd: &LsFontsCommand,
+    text: Option<String>,
+) -> anyhow::Result<Vec<String>> {
+    let text = match (text, &cmd.text_file) {
+        (Some(text), _) => Some(text),
+        (None, Some(path)) => Some(
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?,
+        ),
+        (None, None) => None,
+    };
+
+    if let Some(text) = text {
+        // Emulate the effect of output normalization
+        let text: String = if config.normalize_output_to_unicode_nfc {
+            text.nfc().collect()
+        } else {
+            text
+        };
+        return Ok(text.lines().map(|line| line.to_string()).collect());
+    }
+
+    if let Some(range) = &cmd.range {
+        let range = CodepointRange::try_from(range.to_string()).map_err(|err| anyhow!(err))?;
+        return Ok((range.first..=range.last)
+            .filter_map(char::from_u32)
+            .filter(|&c| wezterm_font::is_visible_codepoint(c))
+            .map(|c| c.to_string())
+            .collect());
+    }
+
+    if let Some(script) = &cmd.script {
+        let tag = normalize_script_tag(script)?;
+        let lines: Vec<String> = (0..=0x10FFFF)
+            .filter_map(char::from_u32)
+            .filter(|&c| {
+                wezterm_font::is_visible_codepoint(c)
+                    && wezterm_font::script_tag_for_codepoint(c) == tag
+            })
+            .map(|c| c.to_string())
+            .collect();
+        anyhow::ensure!(!lines.is_empty(), "no codepoints belong to script {tag}");
+        return Ok(lines);
+    }
+
+    anyhow::bail!("--coverage requires --text, --codepoints, --text-file, --range or --script");
+}
+
+fn classify(
+    config: &ConfigHandle,
+    text: &str,
+    glyphs: &[&GlyphInfo],
+    handles: &[ParsedFont],
+) -> ClusterCoverage {
+    let mut coverage = ClusterCoverage {
+        text: text.to_string(),
+        codepoints: text
+            .chars()
+            .map(|c| format!("U+{:04X}", c as u32))
+            .collect(),
+        status: ClusterStatus::Font,
+        font: None,
+        source: None,
+        range_rule: None,
+        synthesized: vec![],
+    };
+
+    let first = glyphs[0];
+    if config.custom_block_glyphs
+        && glyphs.len() == 1
+        && first.only_char.and_then(BlockKey::from_char).is_some()
+    {
+        coverage.status = ClusterStatus::Synthesized;
+        coverage.synthesized.push("custom_block_glyph");
+        return coverage;
+    }
+
+    // The notdef glyph is used when no font has a glyph for the cluster
+    if glyphs.iter().any(|info| info.glyph_pos == 0) {
+        coverage.status = ClusterStatus::LastResort;
+        return coverage;
+    }
+
+    let parsed = match handles.get(first.font_idx) {
+        Some(parsed) => parsed,
+        None => {
+            coverage.status = ClusterStatus::LastResort;
+            return coverage;
+        }
+    };
+    coverage.font.replace(parsed.lua_name());
+    coverage.source.replace(parsed.handle.diagnostic_string());
+    coverage.range_rule = parsed.range_rule_description();
+
+    if LAST_RESORT_FAMILIES.contains(&parsed.names().family.as_str()) {
+        coverage.status = ClusterStatus::LastResort;
+        return coverage;
+    }
+
+    if parsed.synthesize_bold {
+        coverage.synthesized.push("bold");
+    }
+    if parsed.synthesize_dim {
+        coverage.synthesized.push("dim");
+    }
+    if parsed.synthesize_italic {
+        coverage.synthesized.push("italic");
+    }
+    if !coverage.synthesized.is_empty() {
+        coverage.status = ClusterStatus::Synthesized;
+    }
+
+    coverage
+}
+
+fn audit_line(
+    config: &ConfigHandle,
+    font_config: &FontConfiguration,
+    text: &str,
+    report: &mut CoverageReport,
+) -> anyhow::Result<()> {
+    let bidi_hint = if config.bidi_enabled {
+        Some(config.bidi_direction)
+    } else {
+        None
+    };
+    let unicode_version = config.unicode_version();
+
+    let line = Line::from_text(
+        text,
+        &CellAttributes::default(),
+        SEQ_ZERO,
+        Some(&unicode_version),
+    );
+
+    for cluster in line.cluster(bidi_hint) {
+        let style = font_config.match_style(config, &cluster.attrs);
+        let font = font_config.resolve_font(style)?;
+        let presentation_width = PresentationWidth::with_cluster(&cluster);
+        let infos = font.blocking_shape(
+            &cluster.text,
+            Some(cluster.presentation),
+            cluster.direction,
+            None,
+            Some(&presentation_width),
+        )?;
+
+        // We must grab the handles after shaping, so that we get the
+        // revised list that includes system fallbacks!
+        let handles = font.clone_handles();
+
+        // Group the glyphs by the offset of the text that produced them
+        let mut by_offset: BTreeMap<usize, Vec<&GlyphInfo>> = BTreeMap::new();
+        for info in &infos {
+            by_offset
+                .entry(info.cluster as usize)
+                .or_insert_with(Vec::new)
+                .push(info);
+        }
+        let offsets: Vec<usize> = by_offset.keys().copied().collect();
+
+        for (idx, (start, glyphs)) in by_offset.iter().enumerate() {
+            let end = offsets.get(idx + 1).copied().unwrap_or(cluster.text.len());
+            let text = &cluster.text[*start..end];
+            if text.chars().all(char::is_whitespace) {
+                continue;
+            }
+            report.push(classify(config, text, glyphs, &handles));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run_coverage(
+    config: &ConfigHandle,
+    font_config: &FontConfiguration,
+    cmd: &LsFontsCommand,
+    text: Option<String>,
+) -> anyhow::Result<()> {
+    let mut report = CoverageReport::default();
+
+    for line in audit_lines(config, cmd, text)? {
+        audit_line(config, font_config, &line, &mut report)?;
+    }
+
+    if cmd.json {
+        let stdout = std::io::stdout();
+        serde_json::to_writer_pretty(stdout.lock(), &report)?;
+        println!();
+    } else {
+        report.print();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn script_tags() {
+        assert_eq!(normalize_script_tag("cyrl").unwrap(), "Cyrl");
+        assert_eq!(normalize_script_tag("HANI").unwrap(), "Hani");
+        assert!(normalize_script_tag("Cyrillic").is_err());
+        assert!(normalize_script_tag("Cy1l").is_err());
+
+        assert_eq!(wezterm_font::script_tag_for_codepoint('a'), "Latn");
+        assert_eq!(wezterm_font::script_tag_for_codepoint('\u{416}'), "Cyrl");
+        assert_eq!(wezterm_font::script_tag_for_codepoint('\u{4e16}'), "Hani");
+        assert!(wezterm_font::is_visible_codepoint('\u{e0b0}'));
+        assert!(!wezterm_font::is_visible_codepoint('\u{7}'));
+        assert!(!wezterm_font::is_visible_codepoint('\u{378}'));
+    }
+}
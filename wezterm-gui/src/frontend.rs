@@ -5,7 +5,7 @@ use crate::TermWindow;
 use ::window::*;
 use anyhow::{Context, Error};
 use config::keyassignment::{KeyAssignment, SpawnCommand};
-use config::{ConfigSubscription, NotificationHandling};
+use config::ConfigSubscription;
 use mux::client::ClientId;
 use mux::window::WindowId as MuxWindowId;
 use mux::{Mux, MuxNotification};
@@ -90,10 +90,16 @@ impl GuiFrontEnd {
                 MuxNotification::WindowTitleChanged { .. } => {}
                 MuxNotification::TabResized(_) => {}
                 MuxNotification::TabAddedToWindow { .. } => {}
-                MuxNotification::PaneRemoved(_) => {}
+                MuxNotification::PaneRemoved(pane_id) => {
+                    crate::overlay::copy::forget_marks(pane_id);
+                }
                 MuxNotification::WindowInvalidated(_) => {}
                 MuxNotification::PaneOutput(_) => {}
                 MuxNotification::PaneAdded(_) => {}
+                MuxNotification::QueryClipboard { .. } => {
+                    // Handled via TermWindowNotif, as the user may
+                    // need to be prompted in the window
+                }
                 MuxNotification::Alert {
                     pane_id,
                     alert:
@@ -103,35 +109,27 @@ impl GuiFrontEnd {
                             focus: _,
                         },
                 } => {
-                    let mux = Mux::get();
-
-                    if let Some((_domain, window_id, tab_id)) = mux.resolve_pane_id(pane_id) {
-                        let config = config::configuration();
-
-                        if let Some((_fdomain, f_window, f_tab, f_pane)) =
-                            mux.resolve_focused_pane(&client_id)
-                        {
-                            let show = match config.notification_handling {
-                                NotificationHandling::NeverShow => false,
-                                NotificationHandling::AlwaysShow => true,
-                                NotificationHandling::SuppressFromFocusedPane => f_pane != pane_id,
-                                NotificationHandling::SuppressFromFocusedTab => f_tab != tab_id,
-                                NotificationHandling::SuppressFromFocusedWindow => {
-                                    f_window != window_id
-                                }
-                            };
-
-                            if show {
-                                let message = if title.is_none() { "" } else { &body };
-                                let title = title.as_ref().unwrap_or(&body);
-                                // FIXME: if notification.focus is true, we should do
-                                // something here to arrange to focus pane_id when the
-                                // notification is clicked
-                                persistent_toast_notification(title, message);
-                            }
-                        }
+                    if crate::notification::notification_handling_allows(&client_id, pane_id) {
+                        let message = if title.is_none() { "" } else { &body };
+                        let title = title.as_ref().unwrap_or(&body);
+                        // FIXME: if notification.focus is true, we should do
+                        // something here to arrange to focus pane_id when the
+                        // notification is clicked
+                        persistent_toast_notification(title, message);
                     }
                 }
+                MuxNotification::Alert {
+                    pane_id,
+                    alert: Alert::DesktopNotification(notif),
+                } => {
+                    crate::notification::show_desktop_notification(&client_id, pane_id, notif);
+                }
+                MuxNotification::Alert {
+                    pane_id,
+                    alert: Alert::CloseDesktopNotification { id },
+                } => {
+                    crate::notification::close_desktop_notification(pane_id, id);
+                }
                 MuxNotification::Alert {
                     pane_id: _,
                     alert: Alert::Bell | Alert::Progress(_),
@@ -147,7 +145,8 @@ impl GuiFrontEnd {
                         | Alert::WindowTitleChanged(_)
                         | Alert::TabTitleChanged(_)
                         | Alert::IconTitleChanged(_)
-                        | Alert::SetUserVar { .. },
+                        | Alert::SetUserVar { .. }
+                        | Alert::CommandFinished { .. },
                 } => {}
                 MuxNotification::Empty => {
                     if config::configuration().quit_when_all_windows_are_closed {
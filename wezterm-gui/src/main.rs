@@ -38,9 +38,12 @@ mod colorease;
 mod commands;
 mod customglyph;
 mod download;
+mod font_coverage;
 mod frontend;
 mod glyphcache;
 mod inputmap;
+mod notification;
+mod offscreen;
 mod overlay;
 mod quad;
 mod renderstate;
@@ -131,6 +134,10 @@ enum SubCommand {
 
     #[command(name = "show-keys", about = "Show key assignments")]
     ShowKeys(ShowKeysCommand),
+
+    /// Render a pane snapshot to a png; used by `wezterm cli screenshot`
+    #[command(name = "render-snapshot", hide = true)]
+    RenderSnapshot(RenderSnapshotCommand),
 }
 
 async fn async_run_ssh(opts: SshCommand) -> anyhow::Result<()> {
@@ -858,6 +865,24 @@ fn run_show_keys(config: config::ConfigHandle, cmd: &ShowKeysCommand) -> anyhow:
     Ok(())
 }
 
+fn run_render_snapshot(
+    config: config::ConfigHandle,
+    cmd: &RenderSnapshotCommand,
+) -> anyhow::Result<()> {
+    // Disable the normal config error UI window, as we don't have
+    // a fully baked GUI environment running
+    config::assign_error_callback(|err| eprintln!("{}", err));
+
+    let snapshot: mux::export::TabSnapshot =
+        serde_json::from_reader(std::io::stdin().lock()).context("parsing snapshot")?;
+    let mut renderer = crate::offscreen::OffscreenRenderer::with_config(&config)?;
+    let image = renderer.render_tab(&snapshot)?;
+    image
+        .save(&cmd.output)
+        .with_context(|| format!("writing {}", cmd.output.display()))?;
+    Ok(())
+}
+
 pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyhow::Result<()> {
     use wezterm_font::parser::ParsedFont;
 
@@ -901,6 +926,10 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
         _ => None,
     };
 
+    if cmd.coverage {
+        return font_coverage::run_coverage(&config, &font_config, cmd, text);
+    }
+
     if let Some(text) = &text {
         // Emulate the effect of output normalization
         let text = if config.normalize_output_to_unicode_nfc {
@@ -1021,6 +1050,9 @@ pub fn run_ls_fonts(config: config::ConfigHandle, cmd: &LsFontsCommand) -> anyho
                         "",
                         parsed.handle.diagnostic_string()
                     );
+                    if let Some(rule) = parsed.range_rule_description() {
+                        println!("{:38}matched {}", "", rule);
+                    }
                 }
 
                 if cmd.rasterize_ascii {
@@ -1274,5 +1306,6 @@ fn run() -> anyhow::Result<()> {
         ),
         SubCommand::LsFonts(cmd) => run_ls_fonts(config, &cmd),
         SubCommand::ShowKeys(cmd) => run_show_keys(config, &cmd),
+        SubCommand::RenderSnapshot(cmd) => run_render_snapshot(config, &cmd),
     }
 }
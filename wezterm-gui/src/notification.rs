@@ -0,0 +1,271 @@
+//! Displays the desktop notifications that applications send using
+//! kitty's OSC 99 protocol, and reports their activation and closing
+//! back to the application when it has asked for that.
+use crate::termwindow::TermWindowNotif;
+use config::NotificationHandling;
+use mux::client::ClientId;
+use mux::pane::PaneId;
+use mux::Mux;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use termwiz::escape::osc::{KittyNotification, KittyNotificationPayload};
+use termwiz::escape::OperatingSystemCommand;
+use wezterm_term::{DesktopNotification, NotificationOccasion, NotificationUrgency};
+use wezterm_toast_notification::{ToastCallback, ToastHandle, ToastNotification, ToastUrgency};
+use window::WindowOps;
+
+type NotificationKey = (PaneId, String);
+
+/// Limits the number of displayed notifications that we track
+const MAX_DISPLAYED: usize = 128;
+
+thread_local! {
+    /// The notifications that are currently displayed, keyed by the
+    /// pane that sent them and their id, so that the application can
+    /// close or replace them.  The generation distinguishes a
+    /// notification from a later one that replaced it.
+    static DISPLAYED: RefCell<HashMap<NotificationKey, (usize, ToastHandle)>> =
+        RefCell::new(HashMap::new());
+    static GENERATION: RefCell<usize> = RefCell::new(0);
+}
+
+/// Returns true if the notification_handling configuration permits
+/// showing a notification that was generated by the specified pane
+pub fn notification_handling_allows(client_id: &ClientId, pane_id: PaneId) -> bool {
+    let mux = Mux::get();
+    let (_domain, window_id, tab_id) = match mux.resolve_pane_id(pane_id) {
+        Some(tuple) => tuple,
+        None => return false,
+    };
+    let (_fdomain, f_window, f_tab, f_pane) = match mux.resolve_focused_pane(client_id) {
+        Some(tuple) => tuple,
+        None => return false,
+    };
+    match config::configuration().notification_handling {
+        NotificationHandling::NeverShow => false,
+        NotificationHandling::AlwaysShow => true,
+        NotificationHandling::SuppressFromFocusedPane => f_pane != pane_id,
+        NotificationHandling::SuppressFromFocusedTab => f_tab != tab_id,
+        NotificationHandling::SuppressFromFocusedWindow => f_window != window_id,
+    }
+}
+
+/// Called on the main thread when a pane has sent a desktop notification
+pub fn show_desktop_notification(
+    client_id: &ClientId,
+    pane_id: PaneId,
+    notif: DesktopNotification,
+) {
+    if !notification_handling_allows(client_id, pane_id) {
+        return;
+    }
+    if notif.occasion == NotificationOccasion::Always {
+        display(pane_id, notif);
+        return;
+    }
+
+    let mux = Mux::get();
+    let window_id = match mux.resolve_pane_id(pane_id) {
+        Some((_domain, window_id, _tab_id)) => window_id,
+        None => return,
+    };
+    match crate::frontend::front_end().gui_window_for_mux_window(window_id) {
+        Some(gui_win) => {
+            gui_win
+                .window
+                .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                    let window_focused = term_window.focused.is_some();
+                    let mux = Mux::get();
+                    let (pane_focused, pane_visible) =
+                        match mux.get_active_tab_for_window(window_id) {
+                            Some(tab) => (
+                                window_focused
+                                    && tab.get_active_pane().map(|pane| pane.pane_id())
+                                        == Some(pane_id),
+                                window_focused && tab.contains_pane(pane_id),
+                            ),
+                            None => (false, false),
+                        };
+                    let show = match notif.occasion {
+                        NotificationOccasion::Always => true,
+                        NotificationOccasion::Unfocused => !pane_focused,
+                        NotificationOccasion::Invisible => !pane_visible,
+                    };
+                    if show {
+                        display(pane_id, notif);
+                    }
+                })));
+        }
+        // A pane that isn't in a gui window is neither focused nor visible
+        None => display(pane_id, notif),
+    }
+}
+
+/// Called on the main thread when a pane has asked to close one
+/// of its desktop notifications
+pub fn close_desktop_notification(pane_id: PaneId, id: String) {
+    let handle = DISPLAYED.with(|displayed| {
+        displayed
+            .borrow()
+            .get(&(pane_id, id))
+            .map(|(_generation, handle)| handle.clone())
+    });
+    if let Some(handle) = handle {
+        handle.close();
+    }
+}
+
+fn display(pane_id: PaneId, notif: DesktopNotification) {
+    let generation = GENERATION.with(|generation| {
+        let mut generation = generation.borrow_mut();
+        *generation += 1;
+        *generation
+    });
+
+    let on_activate = if notif.focus || notif.report_activation || notif.id.is_some() {
+        let id = notif.id.clone();
+        let focus = notif.focus;
+        let report_activation = notif.report_activation;
+        let report_close = notif.report_close;
+        Some(ToastCallback::new(move || {
+            let id = id.clone();
+            promise::spawn::spawn_into_main_thread(async move {
+                if focus {
+                    activate_pane(pane_id);
+                }
+                if report_activation {
+                    report(
+                        pane_id,
+                        KittyNotification {
+                            // kitty reports 0 for notifications
+                            // that have no identifier
+                            id: Some(id.clone().unwrap_or_else(|| "0".to_string())),
+                            ..Default::default()
+                        },
+                    );
+                }
+                // Activating a notification dismisses it, and not every
+                // platform reports that it was closed after that
+                if let Some(id) = id {
+                    if forget(&(pane_id, id.clone()), generation) && report_close {
+                        report_closed(pane_id, id);
+                    }
+                }
+            })
+            .detach();
+        }))
+    } else {
+        None
+    };
+
+    let on_close = notif.id.clone().map(|id| {
+        let report_close = notif.report_close;
+        ToastCallback::new(move || {
+            let id = id.clone();
+            promise::spawn::spawn_into_main_thread(async move {
+                if forget(&(pane_id, id.clone()), generation) && report_close {
+                    report_closed(pane_id, id);
+                }
+            })
+            .detach();
+        })
+    });
+
+    let (title, message) = if notif.title.is_empty() {
+        (notif.body, String::new())
+    } else {
+        (notif.title, notif.body)
+    };
+
+    let handle = ToastNotification {
+        title,
+        message,
+        url: None,
+        timeout: notif.timeout,
+        urgency: notif.urgency.map(|urgency| match urgency {
+            NotificationUrgency::Low => ToastUrgency::Low,
+            NotificationUrgency::Normal => ToastUrgency::Normal,
+            NotificationUrgency::Critical => ToastUrgency::Critical,
+        }),
+        on_activate,
+        on_close,
+    }
+    .show();
+
+    if let Some(id) = notif.id {
+        // A notification with the same id replaces the earlier one
+        let prior = DISPLAYED.with(|displayed| {
+            let mut displayed = displayed.borrow_mut();
+            let prior = displayed.insert((pane_id, id), (generation, handle));
+            // Forget about the oldest notification if there are too
+            // many, rather than accumulating entries for notifications
+            // whose closing is never reported by the system
+            if displayed.len() > MAX_DISPLAYED {
+                let oldest = displayed
+                    .iter()
+                    .min_by_key(|(_key, (generation, _handle))| *generation)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    displayed.remove(&oldest);
+                }
+            }
+            prior
+        });
+        if let Some((_generation, prior)) = prior {
+            prior.close();
+        }
+    }
+}
+
+/// Stop tracking the notification with the specified key, provided
+/// that it hasn't been replaced by a later notification with the same
+/// id.  Returns true if it was being tracked.
+fn forget(key: &NotificationKey, generation: usize) -> bool {
+    DISPLAYED.with(|displayed| {
+        let mut displayed = displayed.borrow_mut();
+        match displayed.get(key) {
+            Some((g, _)) if *g == generation => {
+                displayed.remove(key);
+                true
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Focus the pane that generated a notification, along with its
+/// containing tab and window
+fn activate_pane(pane_id: PaneId) {
+    let mux = Mux::get();
+    if let Err(err) = mux.focus_pane_and_containing_tab(pane_id) {
+        log::error!("Unable to activate pane {}: {:#}", pane_id, err);
+        return;
+    }
+    if let Some((_domain, window_id, _tab_id)) = mux.resolve_pane_id(pane_id) {
+        if let Some(gui_win) = crate::frontend::front_end().gui_window_for_mux_window(window_id) {
+            gui_win.window.focus();
+        }
+    }
+}
+
+/// Report to the application that a notification was closed
+fn report_closed(pane_id: PaneId, id: String) {
+    report(
+        pane_id,
+        KittyNotification {
+            id: Some(id),
+            payload_type: KittyNotificationPayload::Close,
+            ..Default::default()
+        },
+    );
+}
+
+/// Send a report about a notification to the application running in the pane
+fn report(pane_id: PaneId, response: KittyNotification) {
+    if let Some(pane) = Mux::get().get_pane(pane_id) {
+        let response = OperatingSystemCommand::KittyNotification(Box::new(response));
+        let mut writer = pane.writer();
+        write!(writer, "{}", response).ok();
+    }
+}
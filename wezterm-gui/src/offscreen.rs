@@ -0,0 +1,462 @@
+//! Renders terminal content into an image using the CPU, without
+//! requiring a window or a GPU.
+//! The glyphs, custom block glyphs, cursors and line decorations are
+//! produced by the same `GlyphCache` that feeds the GPU; this module
+//! composites those sprites in place of the quad pipeline.
+use crate::customglyph::BlockKey;
+use crate::glyphcache::GlyphCache;
+use crate::termwindow::render::resolve_fg_color_attr;
+use crate::utilsprites::RenderMetrics;
+use ::window::bitmaps::atlas::{OutOfTextureSpace, Sprite};
+use ::window::bitmaps::{BitmapImage, ImageTexture};
+use ::window::color::LinearRgba;
+use anyhow::Context;
+use config::{ConfigHandle, TextStyle};
+use mux::export::{PaneSnapshot, TabSnapshot};
+use std::rc::Rc;
+use termwiz::surface::CursorShape;
+use wezterm_font::shaper::PresentationWidth;
+use wezterm_font::FontConfiguration;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::Line;
+
+/// The initial size of the glyph atlas; it is grown as needed
+const INITIAL_ATLAS_SIZE: usize = 1024;
+
+/// An RGB canvas in linear color space
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<LinearRgba>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize, color: LinearRgba) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![color; width * height],
+        }
+    }
+
+    fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: LinearRgba) {
+        for py in y.max(0)..(y + height as isize).min(self.height as isize) {
+            for px in x.max(0)..(x + width as isize).min(self.width as isize) {
+                self.blend(px as usize, py as usize, color, color.3);
+            }
+        }
+    }
+
+    /// Blend `color`, which is not premultiplied, with the given coverage
+    fn blend(&mut self, x: usize, y: usize, color: LinearRgba, coverage: f32) {
+        let dest = &mut self.pixels[y * self.width + x];
+        let keep = 1.0 - coverage;
+        *dest = LinearRgba::with_components(
+            color.0 * coverage + dest.0 * keep,
+            color.1 * coverage + dest.1 * keep,
+            color.2 * coverage + dest.2 * keep,
+            1.0,
+        );
+    }
+
+    /// Blend a color that has already been multiplied by its alpha
+    fn blend_premultiplied(&mut self, x: usize, y: usize, color: LinearRgba) {
+        let dest = &mut self.pixels[y * self.width + x];
+        let keep = 1.0 - color.3;
+        *dest = LinearRgba::with_components(
+            color.0 + dest.0 * keep,
+            color.1 + dest.1 * keep,
+            color.2 + dest.2 * keep,
+            1.0,
+        );
+    }
+
+    /// Composite `sprite` with its top left corner at `x`, `y`.
+    /// When `color` is None, the sprite is drawn with its own colors,
+    /// otherwise its alpha channel is used as the coverage for `color`.
+    fn draw_sprite(&mut self, sprite: &Sprite, x: isize, y: isize, color: Option<LinearRgba>) {
+        let texture = match sprite.texture.downcast_ref::<ImageTexture>() {
+            Some(texture) => texture,
+            None => return,
+        };
+        let image = texture.image.borrow();
+        let (tex_width, _) = image.image_dimensions();
+        let data = image.pixel_data_slice();
+
+        for sy in 0..sprite.coords.size.height.max(0) {
+            let py = y + sy;
+            if py < 0 || py >= self.height as isize {
+                continue;
+            }
+            for sx in 0..sprite.coords.size.width.max(0) {
+                let px = x + sx;
+                if px < 0 || px >= self.width as isize {
+                    continue;
+                }
+                let tx = (sprite.coords.min_x() + sx) as usize;
+                let ty = (sprite.coords.min_y() + sy) as usize;
+                let offset = (ty * tex_width + tx) * 4;
+                let (r, g, b, a) = (
+                    data[offset],
+                    data[offset + 1],
+                    data[offset + 2],
+                    data[offset + 3],
+                );
+                if a == 0 {
+                    continue;
+                }
+                match color {
+                    Some(color) => self.blend(px as usize, py as usize, color, a as f32 / 255.0),
+                    None => self.blend_premultiplied(
+                        px as usize,
+                        py as usize,
+                        LinearRgba::with_srgba(r, g, b, a),
+                    ),
+                }
+            }
+        }
+    }
+
+    fn into_image(self) -> image::RgbaImage {
+        let mut data = Vec::with_capacity(self.width * self.height * 4);
+        for pixel in self.pixels {
+            let (r, g, b, _a) = pixel.to_srgb().to_srgb_u8();
+            data.extend_from_slice(&[r, g, b, 0xff]);
+        }
+        image::RgbaImage::from_raw(self.width as u32, self.height as u32, data)
+            .expect("buffer to match the image dimensions")
+    }
+}
+
+/// Renders snapshots of panes and tabs into images
+pub struct OffscreenRenderer {
+    config: ConfigHandle,
+    fonts: Rc<FontConfiguration>,
+    render_metrics: RenderMetrics,
+    glyph_cache: GlyphCache,
+}
+
+impl OffscreenRenderer {
+    pub fn new(fonts: &Rc<FontConfiguration>) -> anyhow::Result<Self> {
+        let render_metrics = RenderMetrics::new(fonts)?;
+        let glyph_cache = GlyphCache::new_in_memory(fonts, INITIAL_ATLAS_SIZE)?;
+        Ok(Self {
+            config: fonts.config(),
+            fonts: Rc::clone(fonts),
+            render_metrics,
+            glyph_cache,
+        })
+    }
+
+    /// Create a renderer that uses the fonts from the current configuration
+    pub fn with_config(config: &ConfigHandle) -> anyhow::Result<Self> {
+        let fonts = Rc::new(FontConfiguration::new(
+            Some(config.clone()),
+            config.dpi.unwrap_or_else(|| ::window::default_dpi()) as usize,
+        )?);
+        Self::new(&fonts)
+    }
+
+    pub fn render_pane(&mut self, snapshot: &PaneSnapshot) -> anyhow::Result<image::RgbaImage> {
+        self.render_tab(&TabSnapshot::single(snapshot.clone()))
+    }
+
+    pub fn render_tab(&mut self, tab: &TabSnapshot) -> anyhow::Result<image::RgbaImage> {
+        loop {
+            match self.render_tab_impl(tab) {
+                Ok(canvas) => return Ok(canvas.into_image()),
+                Err(err) => {
+                    if let Some(&OutOfTextureSpace {
+                        size: Some(size), ..
+                    }) = err.root_cause().downcast_ref::<OutOfTextureSpace>()
+                    {
+                        log::trace!("grow offscreen texture atlas to {size}");
+                        self.glyph_cache = GlyphCache::new_in_memory(&self.fonts, size)?;
+                        continue;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    fn render_tab_impl(&mut self, tab: &TabSnapshot) -> anyhow::Result<Canvas> {
+        let cell_width = self.render_metrics.cell_size.width as usize;
+        let cell_height = self.render_metrics.cell_size.height as usize;
+
+        let background = tab
+            .panes
+            .first()
+            .map(|pos| pos.snapshot.palette.background.to_linear())
+            .unwrap_or_else(|| LinearRgba::with_components(0., 0., 0., 1.));
+        let mut canvas = Canvas::new(tab.cols * cell_width, tab.rows * cell_height, background);
+
+        for pos in &tab.panes {
+            let left = pos.left * cell_width;
+            let top = pos.top * cell_height;
+            self.render_pane_at(&mut canvas, &pos.snapshot, left as isize, top as isize)?;
+
+            // Draw the split dividers in the cell that separates
+            // this pane from its neighbors
+            let split = pos.snapshot.palette.split.to_linear();
+            let thickness = self.render_metrics.underline_height.max(1) as usize;
+            let right_col = pos.left + pos.snapshot.cols;
+            if right_col < tab.cols {
+                canvas.fill_rect(
+                    (right_col * cell_width + cell_width / 2) as isize,
+                    top as isize,
+                    thickness,
+                    (pos.snapshot.rows + 1) * cell_height,
+                    split,
+                );
+            }
+            let bottom_row = pos.top + pos.snapshot.rows;
+            if bottom_row < tab.rows {
+                canvas.fill_rect(
+                    left as isize,
+                    (bottom_row * cell_height + cell_height / 2) as isize,
+                    (pos.snapshot.cols + 1) * cell_width,
+                    thickness,
+                    split,
+                );
+            }
+        }
+
+        Ok(canvas)
+    }
+
+    fn render_pane_at(
+        &mut self,
+        canvas: &mut Canvas,
+        snapshot: &PaneSnapshot,
+        left: isize,
+        top: isize,
+    ) -> anyhow::Result<()> {
+        let cell_width = self.render_metrics.cell_size.width as usize;
+        let cell_height = self.render_metrics.cell_size.height as usize;
+        let palette = &snapshot.palette;
+
+        canvas.fill_rect(
+            left,
+            top,
+            snapshot.cols * cell_width,
+            snapshot.rows * cell_height,
+            palette.background.to_linear(),
+        );
+
+        for (row, line) in snapshot.lines.iter().take(snapshot.rows).enumerate() {
+            let cursor = snapshot
+                .cursor
+                .filter(|cursor| cursor.y == row)
+                .map(|cursor| (cursor.x, cursor.shape));
+            self.render_line(
+                canvas,
+                line,
+                palette,
+                cursor,
+                snapshot.cols,
+                left,
+                top + (row * cell_height) as isize,
+            )?;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_line(
+        &mut self,
+        canvas: &mut Canvas,
+        line: &Line,
+        palette: &ColorPalette,
+        cursor: Option<(usize, CursorShape)>,
+        cols: usize,
+        left: isize,
+        top: isize,
+    ) -> anyhow::Result<()> {
+        let cell_width = self.render_metrics.cell_size.width as isize;
+        let cell_height = self.render_metrics.cell_size.height as isize;
+        let block_cursor = cursor.and_then(|(x, shape)| match shape {
+            CursorShape::Default | CursorShape::BlinkingBlock | CursorShape::SteadyBlock => Some(x),
+            _ => None,
+        });
+
+        // Backgrounds and decorations are per cell
+        for cell in line.visible_cells() {
+            let idx = cell.cell_index();
+            if idx >= cols {
+                break;
+            }
+            let attrs = cell.attrs();
+            let x = left + idx as isize * cell_width;
+            let width = cell.width().max(1);
+
+            let bg = if block_cursor == Some(idx) {
+                palette.cursor_bg.to_linear()
+            } else if attrs.reverse() {
+                self.fg_color(attrs, palette)
+            } else {
+                palette.resolve_bg(attrs.background()).to_linear()
+            };
+            canvas.fill_rect(
+                x,
+                top,
+                width * cell_width as usize,
+                cell_height as usize,
+                bg,
+            );
+        }
+
+        let bidi_hint = if self.config.bidi_enabled {
+            Some(self.config.bidi_direction)
+        } else {
+            None
+        };
+
+        for cluster in line.cluster(bidi_hint) {
+            let attrs = &cluster.attrs;
+            if attrs.invisible() {
+                continue;
+            }
+            let style = self.fonts.match_style(&self.config, attrs);
+            let fg = if attrs.reverse() {
+                palette.resolve_bg(attrs.background()).to_linear()
+            } else {
+                self.styled_fg_color(attrs, palette, style)
+            };
+            let font = self.fonts.resolve_font(style)?;
+            let presentation_width = PresentationWidth::with_cluster(&cluster);
+            let infos = font
+                .blocking_shape(
+                    &cluster.text,
+                    Some(cluster.presentation),
+                    cluster.direction,
+                    None,
+                    Some(&presentation_width),
+                )
+                .context("shaping")?;
+
+            // The glyphs of a ligature or combining sequence share the
+            // starting cell of their cluster; each is placed after the
+            // advance of the glyphs that precede it, as the GPU path does
+            let mut prior_cluster = None;
+            let mut cluster_advance = 0.;
+
+            for info in &infos {
+                let idx = cluster.byte_to_cell_idx(info.cluster as usize);
+                if idx >= cols {
+                    continue;
+                }
+                let followed_by_space = match line.get_cell(idx + 1) {
+                    Some(cell) => cell.str() == " ",
+                    None => false,
+                };
+                let glyph = self.glyph_cache.cached_glyph(
+                    info,
+                    style,
+                    followed_by_space,
+                    &font,
+                    &self.render_metrics,
+                    info.num_cells,
+                )?;
+                let color = if block_cursor == Some(idx) {
+                    palette.cursor_fg.to_linear()
+                } else {
+                    fg
+                };
+                let x = left + idx as isize * cell_width;
+                if prior_cluster != Some(info.cluster) {
+                    prior_cluster = Some(info.cluster);
+                    cluster_advance = 0.;
+                }
+                let advance = cluster_advance;
+                cluster_advance += glyph.x_advance.get();
+
+                if self.config.custom_block_glyphs {
+                    if let Some(block) = info.only_char.and_then(BlockKey::from_char) {
+                        // Custom glyphs are rendered relative to the cell
+                        // top left, rather than the baseline
+                        let sprite = self.glyph_cache.cached_block(block, &self.render_metrics)?;
+                        canvas.draw_sprite(&sprite, x, top, Some(color));
+                        continue;
+                    }
+                }
+
+                if let Some(sprite) = &glyph.texture {
+                    let x = x + (advance + (glyph.x_offset + glyph.bearing_x).get()) as isize;
+                    let y = top
+                        + cell_height
+                        + (self.render_metrics.descender - (glyph.y_offset + glyph.bearing_y)).get()
+                            as isize;
+                    canvas.draw_sprite(
+                        sprite,
+                        x,
+                        y,
+                        if glyph.has_color { None } else { Some(color) },
+                    );
+                }
+            }
+        }
+
+        for cell in line.visible_cells() {
+            let idx = cell.cell_index();
+            if idx >= cols {
+                break;
+            }
+            let attrs = cell.attrs();
+            if !attrs.strikethrough()
+                && !attrs.overline()
+                && attrs.underline() == wezterm_term::Underline::None
+            {
+                continue;
+            }
+            let sprite = self.glyph_cache.cached_line_sprite(
+                false,
+                attrs.strikethrough(),
+                attrs.underline(),
+                attrs.overline(),
+                &self.render_metrics,
+            )?;
+            let color = match attrs.underline_color() {
+                wezterm_term::color::ColorAttribute::Default => self.fg_color(attrs, palette),
+                color => palette.resolve_fg(color).to_linear(),
+            };
+            for i in 0..cell.width().max(1) {
+                let x = left + (idx + i) as isize * cell_width;
+                canvas.draw_sprite(&sprite, x, top, Some(color));
+            }
+        }
+
+        if let Some((x, shape)) = cursor {
+            if block_cursor.is_none() && x < cols {
+                let width = line.get_cell(x).map(|cell| cell.width()).unwrap_or(1);
+                let sprite = self.glyph_cache.cursor_sprite(
+                    Some(shape),
+                    &self.render_metrics,
+                    width as u8,
+                )?;
+                canvas.draw_sprite(
+                    &sprite,
+                    left + x as isize * cell_width,
+                    top,
+                    Some(palette.cursor_bg.to_linear()),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fg_color(&self, attrs: &wezterm_term::CellAttributes, palette: &ColorPalette) -> LinearRgba {
+        let style = self.fonts.match_style(&self.config, attrs);
+        self.styled_fg_color(attrs, palette, style)
+    }
+
+    fn styled_fg_color(
+        &self,
+        attrs: &wezterm_term::CellAttributes,
+        palette: &ColorPalette,
+        style: &TextStyle,
+    ) -> LinearRgba {
+        resolve_fg_color_attr(attrs, attrs.foreground(), palette, &self.config, style)
+    }
+}
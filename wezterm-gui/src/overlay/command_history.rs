@@ -0,0 +1,381 @@
+//! The command history overlay presents the commands that have been run
+//! in all panes, as reported by the shell via OSC 133 semantic prompt
+//! markers, and allows re-running one of them in the active pane.
+use crate::overlay::selector::{matcher_pattern, matcher_score};
+use crate::termwindow::TermWindowNotif;
+use config::keyassignment::KeyAssignment;
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity};
+use termwiz::color::{AnsiColor, ColorAttribute};
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use termwiz_funcs::truncate_right;
+use wezterm_term::unicode_column_width;
+use window::WindowOps;
+
+const ROW_OVERHEAD: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct CommandHistoryEntry {
+    pub command: String,
+    pub pane_title: String,
+    pub exit_status: Option<i32>,
+    pub started: Option<SystemTime>,
+    pub duration: Option<Duration>,
+    pub running: bool,
+}
+
+impl CommandHistoryEntry {
+    /// Collect the commands from all panes, most recent first.
+    /// Repeated invocations of the same command are collapsed into
+    /// the most recent of them.
+    /// Must be called on the Mux thread!
+    pub fn collect() -> Vec<Self> {
+        let mux = Mux::get();
+        let mut entries = vec![];
+        for pane in mux.iter_panes() {
+            let blocks = match pane.get_command_blocks() {
+                Ok(blocks) => blocks,
+                Err(err) => {
+                    log::error!("getting commands for pane {}: {:#}", pane.pane_id(), err);
+                    continue;
+                }
+            };
+            let pane_title = pane.get_title();
+            for block in blocks {
+                if !block.has_run() || block.command.is_empty() {
+                    continue;
+                }
+                entries.push(Self {
+                    duration: block.duration(),
+                    command: block.command,
+                    pane_title: pane_title.clone(),
+                    exit_status: block.exit_status,
+                    started: block.started,
+                    running: block.is_running(),
+                });
+            }
+        }
+
+        entries.sort_by(|a, b| b.started.cmp(&a.started));
+        let mut seen = HashSet::new();
+        entries.retain(|entry| seen.insert(entry.command.clone()));
+        entries
+    }
+
+    fn detail(&self) -> String {
+        let status = match self.exit_status {
+            Some(0) => "\u{2714}".to_string(),
+            Some(status) => format!("\u{2718} {status}"),
+            None if self.running => "\u{2026}".to_string(),
+            None => "?".to_string(),
+        };
+        let duration = self
+            .duration
+            .map(format_duration)
+            .map(|d| format!(" {d}"))
+            .unwrap_or_default();
+        format!("{status}{duration} {}", self.pane_title)
+    }
+}
+
+/// Format a duration compactly, eg: `350ms`, `12.3s`, `4m05s` or `1h02m`
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs == 0 {
+        format!("{}ms", d.as_millis())
+    } else if secs < 60 {
+        format!("{:.1}s", d.as_secs_f32())
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+struct CommandHistoryState {
+    active_idx: usize,
+    max_items: usize,
+    top_row: usize,
+    entries: Vec<CommandHistoryEntry>,
+    filter_term: String,
+    filtered_entries: Vec<CommandHistoryEntry>,
+    pane_id: PaneId,
+    window: ::window::Window,
+}
+
+impl CommandHistoryState {
+    fn update_filter(&mut self) {
+        if self.filter_term.is_empty() {
+            self.filtered_entries = self.entries.clone();
+            return;
+        }
+
+        self.filtered_entries.clear();
+
+        let pattern = matcher_pattern(&self.filter_term);
+
+        struct MatchResult {
+            row_idx: usize,
+            score: u32,
+        }
+
+        let mut scores: Vec<MatchResult> = self
+            .entries
+            .par_iter()
+            .enumerate()
+            .filter_map(|(row_idx, entry)| {
+                let score = matcher_score(&pattern, &entry.command)?;
+                Some(MatchResult { row_idx, score })
+            })
+            .collect();
+
+        scores.sort_by(|a, b| a.score.cmp(&b.score).reverse());
+
+        for result in scores {
+            self.filtered_entries
+                .push(self.entries[result.row_idx].clone());
+        }
+
+        self.active_idx = 0;
+        self.top_row = 0;
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let max_width = size.cols.saturating_sub(6);
+        self.max_items = size.rows.saturating_sub(ROW_OVERHEAD);
+
+        let header = if self.filter_term.is_empty() {
+            "Type to search  Enter=run  Tab=insert  Esc=cancel".to_string()
+        } else {
+            format!("Search: {}", self.filter_term)
+        };
+
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(format!("{}\r\n", truncate_right(&header, max_width))),
+            Change::AllAttributes(CellAttributes::default()),
+        ];
+
+        if self.filtered_entries.is_empty() {
+            changes.push(Change::Text(if self.entries.is_empty() {
+                " No commands have been recorded; \
+                 this requires shell integration"
+                    .to_string()
+            } else {
+                " No matching commands".to_string()
+            }));
+        }
+
+        for (row_num, (entry_idx, entry)) in self
+            .filtered_entries
+            .iter()
+            .enumerate()
+            .skip(self.top_row)
+            .enumerate()
+        {
+            if row_num > self.max_items {
+                break;
+            }
+
+            if entry_idx == self.active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+
+            let detail = entry.detail();
+            let detail_width = unicode_column_width(&detail, None) + 2;
+            // Only show the first line of multi-line commands
+            let command = entry.command.lines().next().unwrap_or("");
+            let command = truncate_right(command, max_width.saturating_sub(detail_width));
+            let padding = max_width
+                .saturating_sub(detail_width)
+                .saturating_sub(unicode_column_width(&command, None));
+
+            changes.push(Change::Text(format!(" {command}{} ", " ".repeat(padding))));
+            match entry.exit_status {
+                Some(0) => {
+                    changes.push(AttributeChange::Foreground(AnsiColor::Green.into()).into())
+                }
+                Some(_) => changes.push(AttributeChange::Foreground(AnsiColor::Red.into()).into()),
+                None => changes.push(AttributeChange::Intensity(Intensity::Half).into()),
+            }
+            changes.push(Change::Text(format!("{detail} ")));
+            changes.push(Change::AllAttributes(CellAttributes::default()));
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        term.render(&changes)
+    }
+
+    /// Send the selected command to the pane.
+    /// When `run` is true, it is followed by a carriage return
+    /// so that the shell will execute it.
+    fn send(&self, active_idx: usize, run: bool) -> bool {
+        if let Some(entry) = self.filtered_entries.get(active_idx) {
+            let mut text = entry.command.clone();
+            if run {
+                text.push('\r');
+            }
+            self.window.notify(TermWindowNotif::PerformAssignment {
+                pane_id: self.pane_id,
+                assignment: KeyAssignment::SendString(text),
+                tx: None,
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    fn move_up(&mut self) {
+        self.active_idx = self.active_idx.saturating_sub(1);
+        if self.active_idx < self.top_row {
+            self.top_row = self.active_idx;
+        }
+    }
+
+    fn move_down(&mut self) {
+        self.active_idx = (self.active_idx + 1).min(self.filtered_entries.len().saturating_sub(1));
+        if self.active_idx > self.top_row + self.max_items {
+            self.top_row = self.active_idx.saturating_sub(self.max_items);
+        }
+    }
+
+    fn run_loop(&mut self, term: &mut TermWizTerminal) -> anyhow::Result<()> {
+        while let Ok(Some(event)) = term.poll_input(None) {
+            match event {
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('G') | KeyCode::Char('['),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::Escape,
+                    ..
+                }) => {
+                    break;
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('P' | 'K'),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::UpArrow,
+                    ..
+                }) => {
+                    self.move_up();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('N' | 'J'),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::DownArrow,
+                    ..
+                }) => {
+                    self.move_down();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('U'),
+                    modifiers: Modifiers::CTRL,
+                }) => {
+                    self.filter_term.clear();
+                    self.update_filter();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Backspace,
+                    ..
+                }) => {
+                    self.filter_term.pop();
+                    self.update_filter();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Enter,
+                    ..
+                }) => {
+                    if self.send(self.active_idx, true) {
+                        break;
+                    }
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Tab, ..
+                }) => {
+                    if self.send(self.active_idx, false) {
+                        break;
+                    }
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char(c),
+                    modifiers: Modifiers::NONE | Modifiers::SHIFT,
+                }) => {
+                    self.filter_term.push(c);
+                    self.update_filter();
+                }
+                InputEvent::Mouse(MouseEvent { mouse_buttons, .. })
+                    if mouse_buttons.contains(MouseButtons::VERT_WHEEL) =>
+                {
+                    if mouse_buttons.contains(MouseButtons::WHEEL_POSITIVE) {
+                        self.move_up();
+                    } else {
+                        self.move_down();
+                    }
+                }
+                InputEvent::Mouse(MouseEvent {
+                    y, mouse_buttons, ..
+                }) => {
+                    if y > 0 && self.top_row + y as usize - 1 < self.filtered_entries.len() {
+                        self.active_idx = self.top_row + y as usize - 1;
+
+                        if mouse_buttons == MouseButtons::LEFT {
+                            if self.send(self.active_idx, true) {
+                                break;
+                            }
+                        }
+                    }
+                    if mouse_buttons != MouseButtons::NONE {
+                        // Treat any other mouse button as cancel
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            self.render(term)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn command_history(
+    entries: Vec<CommandHistoryEntry>,
+    mut term: TermWizTerminal,
+    window: ::window::Window,
+    pane_id: PaneId,
+) -> anyhow::Result<()> {
+    let mut state = CommandHistoryState {
+        active_idx: 0,
+        max_items: 0,
+        top_row: 0,
+        entries,
+        filter_term: String::new(),
+        filtered_entries: vec![],
+        pane_id,
+        window,
+    };
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Command History".to_string())])?;
+    state.update_filter();
+    state.render(&mut term)?;
+    state.run_loop(&mut term)
+}
@@ -0,0 +1,41 @@
+use super::confirm;
+use crate::TermWindow;
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use wezterm_term::ClipboardSelection;
+
+/// Ask the user whether the application running in `pane_id` may
+/// read the clipboard, then answer its OSC 52 query accordingly.
+/// If `answered` has already been set, the query was denied when a
+/// newer prompt replaced this one, and nothing more is done.
+pub fn confirm_clipboard_read(
+    pane_id: PaneId,
+    selection: ClipboardSelection,
+    message: String,
+    answered: Arc<AtomicBool>,
+    mut term: TermWizTerminal,
+    window: ::window::Window,
+) -> anyhow::Result<()> {
+    let allow = confirm::run_confirmation(&message, &mut term);
+    let allowed = *allow.as_ref().unwrap_or(&false);
+
+    if answered.swap(true, Ordering::SeqCst) {
+        return allow.map(|_| ());
+    }
+
+    let clipboard_window = window.clone();
+    promise::spawn::spawn_into_main_thread(async move {
+        if allowed {
+            TermWindow::answer_clipboard_query(clipboard_window, pane_id, selection);
+        } else if let Some(pane) = Mux::get().get_pane(pane_id) {
+            pane.answer_clipboard_query(selection, None);
+        }
+    })
+    .detach();
+    TermWindow::schedule_cancel_overlay_for_pane(window, pane_id);
+
+    allow.map(|_| ())
+}
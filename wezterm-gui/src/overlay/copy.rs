@@ -1,9 +1,11 @@
+use crate::overlay::textobject::{LineBuffer, Position};
+use crate::overlay::vimgrammar::{GrammarResult, KeyGrammar};
 use crate::selection::{SelectionCoordinate, SelectionRange, SelectionX};
 use crate::termwindow::keyevent::KeyTableArgs;
 use crate::termwindow::{TermWindow, TermWindowNotif};
 use config::keyassignment::{
     ClipboardCopyDestination, CopyModeAssignment, KeyAssignment, KeyTable, KeyTableEntry,
-    ScrollbackEraseMode, SelectionMode,
+    ScrollbackEraseMode, SelectionMode, TextObject,
 };
 use mux::domain::DomainId;
 use mux::pane::{
@@ -34,10 +36,27 @@ use window::{KeyCode as WKeyCode, Modifiers, WindowOps};
 
 lazy_static::lazy_static! {
     static ref SAVED_PATTERN: Mutex<HashMap<TabId, Pattern>> = Mutex::new(HashMap::new());
+    /// Marks are remembered for the lifetime of the pane, so that
+    /// they can be used in later copy mode sessions
+    static ref SAVED_MARKS: Mutex<HashMap<PaneId, HashMap<char, Position>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Forget the marks that were set in a pane, once it has been removed
+pub fn forget_marks(pane_id: PaneId) {
+    SAVED_MARKS.lock().remove(&pane_id);
 }
 
 const SEARCH_CHUNK_SIZE: StableRowIndex = 1000;
 
+/// How many rows either side of the cursor are considered when
+/// looking for text objects and matching brackets
+const TEXT_OBJECT_CONTEXT: StableRowIndex = 500;
+
+/// The mark that remembers the position prior to the most recent
+/// jump, as `''` does in vim
+const PRIOR_POSITION_MARK: char = '\'';
+
 pub struct CopyOverlay {
     delegate: Arc<dyn Pane>,
     render: Arc<Mutex<CopyRenderable>>,
@@ -48,6 +67,7 @@ pub struct CopyOverlay {
 struct PendingJump {
     forward: bool,
     prev_char: bool,
+    count: usize,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -63,8 +83,9 @@ struct CopyRenderable {
     start: Option<SelectionCoordinate>,
     selection_mode: SelectionMode,
     viewport: Option<StableRowIndex>,
-    /// We use this to cancel ourselves later
-    window: ::window::Window,
+    /// We use this to cancel ourselves later.
+    /// It is None when the overlay is being tested without a window.
+    window: Option<::window::Window>,
 
     /// The text that the user entered
     pattern_type: PatternType,
@@ -85,6 +106,13 @@ struct CopyRenderable {
     searching: Option<Searching>,
     pending_jump: Option<PendingJump>,
     last_jump: Option<Jump>,
+    /// Counts and multi-key commands entered via key_down
+    grammar: KeyGrammar,
+    /// Set when the key bound to MoveToStartOfLine was routed to
+    /// key_down because it might be the `0` in a count
+    routed_start_of_line: bool,
+    /// The register that the next copy will be stored in
+    register: Option<char>,
 }
 
 struct Searching {
@@ -115,10 +143,6 @@ impl CopyOverlay {
         pane: &Arc<dyn Pane>,
         params: CopyModeParams,
     ) -> anyhow::Result<Arc<dyn Pane>> {
-        let mut cursor = pane.get_cursor_position();
-        cursor.shape = termwiz::surface::CursorShape::SteadyBlock;
-        cursor.visibility = CursorVisibility::Visible;
-
         let (_domain, _window, tab_id) = mux::Mux::get()
             .resolve_pane_id(pane.pane_id())
             .ok_or_else(|| anyhow::anyhow!("no tab contains the current pane"))?;
@@ -127,7 +151,6 @@ impl CopyOverlay {
             .window
             .clone()
             .ok_or_else(|| anyhow::anyhow!("failed to clone window handle"))?;
-        let dims = pane.get_dimensions();
         let pattern = if params.pattern.is_empty() {
             SAVED_PATTERN
                 .lock()
@@ -137,6 +160,32 @@ impl CopyOverlay {
         } else {
             params.pattern
         };
+
+        Ok(Arc::new(Self::new(
+            pane,
+            Some(window),
+            term_window.get_viewport(pane.pane_id()),
+            tab_id,
+            CopyModeParams {
+                pattern,
+                editing_search: params.editing_search,
+            },
+        )))
+    }
+
+    fn new(
+        pane: &Arc<dyn Pane>,
+        window: Option<::window::Window>,
+        viewport: Option<StableRowIndex>,
+        tab_id: TabId,
+        params: CopyModeParams,
+    ) -> Self {
+        let mut cursor = pane.get_cursor_position();
+        cursor.shape = termwiz::surface::CursorShape::SteadyBlock;
+        cursor.visibility = CursorVisibility::Visible;
+
+        let dims = pane.get_dimensions();
+        let pattern = params.pattern;
         let search_line = LineEditBuffer::new(&pattern, pattern.len());
 
         let mut render = CopyRenderable {
@@ -144,7 +193,7 @@ impl CopyOverlay {
             window,
             delegate: Arc::clone(pane),
             start: None,
-            viewport: term_window.get_viewport(pane.pane_id()),
+            viewport,
             results: vec![],
             by_line: HashMap::new(),
             dirty_results: RangeSet::default(),
@@ -162,6 +211,9 @@ impl CopyOverlay {
             searching: None,
             pending_jump: None,
             last_jump: None,
+            grammar: KeyGrammar::default(),
+            routed_start_of_line: false,
+            register: None,
         };
 
         let search_row = render.compute_search_row();
@@ -173,11 +225,11 @@ impl CopyOverlay {
             render: Arc::clone(&shared_render),
         };
 
-        Ok(Arc::new(CopyOverlay {
+        CopyOverlay {
             delegate: Arc::clone(pane),
             render: shared_render,
             writer: Mutex::new(writer),
-        }))
+        }
     }
 
     pub fn get_params(&self) -> CopyModeParams {
@@ -217,6 +269,19 @@ impl CopyOverlay {
 }
 
 impl CopyRenderable {
+    /// Arrange for `func` to be called with the TermWindow
+    fn notify<F: FnOnce(&mut TermWindow) + Send + Sync + 'static>(&self, func: F) {
+        if let Some(window) = &self.window {
+            window.notify(TermWindowNotif::Apply(Box::new(func)));
+        }
+    }
+
+    fn invalidate(&self) {
+        if let Some(window) = &self.window {
+            window.invalidate();
+        }
+    }
+
     fn compute_search_row(&self) -> StableRowIndex {
         let dims = self.delegate.get_dimensions();
         let top = self.viewport.unwrap_or_else(|| dims.physical_top);
@@ -276,7 +341,10 @@ impl CopyRenderable {
         self.typing_cookie += 1;
         let cookie = self.typing_cookie;
 
-        let window = self.window.clone();
+        let window = match self.window.clone() {
+            Some(window) => window,
+            None => return,
+        };
         let pane_id = self.delegate.pane_id();
 
         promise::spawn::spawn(async move {
@@ -316,9 +384,8 @@ impl CopyRenderable {
         self.last_result_seqno = self.delegate.get_current_seqno();
 
         let pattern = self.get_pattern();
-        if !pattern.is_empty() {
+        if let (false, Some(window)) = (pattern.is_empty(), self.window.clone()) {
             let pane: Arc<dyn Pane> = self.delegate.clone();
-            let window = self.window.clone();
             let dims = pane.get_dimensions();
 
             let end = dims.scrollback_top + dims.scrollback_rows as StableRowIndex;
@@ -354,7 +421,7 @@ impl CopyRenderable {
             self.searching.take();
             self.clear_selection();
         }
-        self.window.invalidate();
+        self.invalidate();
     }
 
     fn processed_search_chunk(
@@ -363,7 +430,7 @@ impl CopyRenderable {
         results: Vec<SearchResult>,
         range: Range<StableRowIndex>,
     ) {
-        self.window.invalidate();
+        self.invalidate();
         if pattern != self.get_pattern() {
             return;
         }
@@ -387,7 +454,10 @@ impl CopyRenderable {
 
         // Search next chunk
         let pane: Arc<dyn Pane> = self.delegate.clone();
-        let window = self.window.clone();
+        let window = match self.window.clone() {
+            Some(window) => window,
+            None => return,
+        };
         let end = range.start;
         let range = end
             .saturating_sub(SEARCH_CHUNK_SIZE)
@@ -421,12 +491,11 @@ impl CopyRenderable {
 
     fn clear_selection(&mut self) {
         let pane_id = self.delegate.pane_id();
-        self.window
-            .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                let mut selection = term_window.selection(pane_id);
-                selection.origin.take();
-                selection.range.take();
-            })));
+        self.notify(move |term_window| {
+            let mut selection = term_window.selection(pane_id);
+            selection.origin.take();
+            selection.range.take();
+        });
     }
 
     fn activate_match_number(&mut self, n: usize) {
@@ -504,22 +573,22 @@ impl CopyRenderable {
             self.adjust_selection(start, SelectionRange { start, end });
         } else {
             self.adjust_viewport_for_cursor_position();
-            self.window.invalidate();
+            self.invalidate();
         }
     }
 
     fn adjust_selection(&self, start: SelectionCoordinate, range: SelectionRange) {
         let pane_id = self.delegate.pane_id();
-        let window = self.window.clone();
         let mode = self.selection_mode;
-        self.window
-            .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                let mut selection = term_window.selection(pane_id);
-                selection.origin = Some(start);
-                selection.range = Some(range);
-                selection.rectangular = mode == SelectionMode::Block;
+        self.notify(move |term_window| {
+            let mut selection = term_window.selection(pane_id);
+            selection.origin = Some(start);
+            selection.range = Some(range);
+            selection.rectangular = mode == SelectionMode::Block;
+            if let Some(window) = term_window.window.as_ref() {
                 window.invalidate();
-            })));
+            }
+        });
         self.adjust_viewport_for_cursor_position();
     }
 
@@ -564,14 +633,15 @@ impl CopyRenderable {
     fn set_viewport(&self, row: Option<StableRowIndex>) {
         let dims = self.delegate.get_dimensions();
         let pane_id = self.delegate.pane_id();
-        self.window
-            .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
-                term_window.set_viewport(pane_id, row, dims);
-            })));
+        self.notify(move |term_window| {
+            term_window.set_viewport(pane_id, row, dims);
+        });
     }
 
     fn close(&self) {
-        TermWindow::schedule_cancel_overlay_for_pane(self.window.clone(), self.delegate.pane_id());
+        if let Some(window) = self.window.clone() {
+            TermWindow::schedule_cancel_overlay_for_pane(window, self.delegate.pane_id());
+        }
     }
 
     fn move_by_page(&mut self, amount: f64) {
@@ -646,6 +716,7 @@ impl CopyRenderable {
             PatternType::CaseSensitiveString => Pattern::CaseSensitiveString(pattern),
             PatternType::CaseInSensitiveString => Pattern::CaseInSensitiveString(pattern),
             PatternType::Regex => Pattern::Regex(pattern),
+            PatternType::Fuzzy => Pattern::Fuzzy(pattern),
         }
     }
 
@@ -665,10 +736,9 @@ impl CopyRenderable {
     }
 
     fn update_key_table(&mut self) {
-        let window = self.window.clone();
         let pane_id = self.delegate.pane_id();
 
-        window.notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+        self.notify(move |term_window| {
             let mut state = term_window.pane_state(pane_id);
             if let Some(overlay) = state.overlay.as_mut() {
                 if let Some(copy_overlay) = overlay.pane.downcast_ref::<CopyOverlay>() {
@@ -688,14 +758,15 @@ impl CopyRenderable {
                     });
                 }
             }
-        })));
+        });
     }
 
     fn cycle_match_type(&mut self) {
         let pattern_type = match &self.pattern_type {
             PatternType::CaseSensitiveString => PatternType::CaseInSensitiveString,
             PatternType::CaseInSensitiveString => PatternType::Regex,
-            PatternType::Regex => PatternType::CaseSensitiveString,
+            PatternType::Regex => PatternType::Fuzzy,
+            PatternType::Fuzzy => PatternType::CaseSensitiveString,
         };
         self.pattern_type = pattern_type;
         self.schedule_update_search();
@@ -719,23 +790,23 @@ impl CopyRenderable {
         self.select_to_cursor_pos();
     }
 
-    fn move_left_single_cell(&mut self) {
-        self.cursor.x = self.cursor.x.saturating_sub(1);
+    fn move_left(&mut self, cells: usize) {
+        self.cursor.x = self.cursor.x.saturating_sub(cells);
         self.select_to_cursor_pos();
     }
 
-    fn move_right_single_cell(&mut self) {
-        self.cursor.x += 1;
+    fn move_right(&mut self, cells: usize) {
+        self.cursor.x = self.cursor.x.saturating_add(cells);
         self.select_to_cursor_pos();
     }
 
-    fn move_up_single_row(&mut self) {
-        self.cursor.y = self.cursor.y.saturating_sub(1);
+    fn move_up(&mut self, rows: usize) {
+        self.cursor.y = self.cursor.y.saturating_sub(rows as StableRowIndex);
         self.select_to_cursor_pos();
     }
 
-    fn move_down_single_row(&mut self) {
-        self.cursor.y += 1;
+    fn move_down(&mut self, rows: usize) {
+        self.cursor.y = self.cursor.y.saturating_add(rows as StableRowIndex);
         self.select_to_cursor_pos();
     }
     fn move_to_start_of_line(&mut self) {
@@ -1061,9 +1132,12 @@ impl CopyRenderable {
         }
     }
 
-    fn jump(&mut self, forward: bool, prev_char: bool) {
-        self.pending_jump
-            .replace(PendingJump { forward, prev_char });
+    fn jump(&mut self, forward: bool, prev_char: bool, count: usize) {
+        self.pending_jump.replace(PendingJump {
+            forward,
+            prev_char,
+            count,
+        });
     }
 
     fn jump_again(&mut self, reverse: bool) {
@@ -1075,6 +1149,210 @@ impl CopyRenderable {
         }
     }
 
+    fn bell(&self) {
+        self.delegate
+            .perform_actions(vec![termwiz::escape::Action::Control(
+                termwiz::escape::ControlCode::Bell,
+            )]);
+    }
+
+    fn cursor_position(&self) -> Position {
+        Position::new(self.cursor.x, self.cursor.y)
+    }
+
+    /// Returns the lines around the cursor
+    fn line_buffer(&self) -> LineBuffer {
+        let dims = self.delegate.get_dimensions();
+        let y = self.cursor.y;
+        let start = y
+            .saturating_sub(TEXT_OBJECT_CONTEXT)
+            .max(dims.scrollback_top);
+        let end = (y + TEXT_OBJECT_CONTEXT + 1)
+            .min(dims.scrollback_top + dims.scrollback_rows as StableRowIndex);
+        let (top, lines) = self.delegate.get_lines(start..end);
+        LineBuffer::new(top, &lines)
+    }
+
+    fn set_mark(&mut self, name: char) {
+        let position = self.cursor_position();
+        SAVED_MARKS
+            .lock()
+            .entry(self.delegate.pane_id())
+            .or_default()
+            .insert(name, position);
+    }
+
+    /// Remember the current position so that we can jump back to it
+    fn remember_position(&mut self) {
+        self.set_mark(PRIOR_POSITION_MARK);
+    }
+
+    fn jump_to_mark(&mut self, name: char, exact: bool) {
+        // Both `''` and ``` `` ``` refer to the prior position
+        let name = if name == '`' {
+            PRIOR_POSITION_MARK
+        } else {
+            name
+        };
+        let position = SAVED_MARKS
+            .lock()
+            .get(&self.delegate.pane_id())
+            .and_then(|marks| marks.get(&name))
+            .copied();
+        match position {
+            Some(position) => {
+                self.remember_position();
+                self.cursor.y = position.y;
+                if exact {
+                    self.cursor.x = position.x;
+                    self.select_to_cursor_pos();
+                } else {
+                    self.move_to_start_of_line_content();
+                }
+            }
+            None => self.bell(),
+        }
+    }
+
+    fn move_to_matching_bracket(&mut self) {
+        match self.line_buffer().matching_bracket(self.cursor_position()) {
+            Some(position) => {
+                self.remember_position();
+                self.cursor.x = position.x;
+                self.cursor.y = position.y;
+                self.select_to_cursor_pos();
+            }
+            None => self.bell(),
+        }
+    }
+
+    fn select_text_object(&mut self, object: TextObject, around: bool) {
+        match self
+            .line_buffer()
+            .text_object(self.cursor_position(), object, around)
+        {
+            Some(range) => {
+                self.selection_mode = if range.linewise {
+                    SelectionMode::Line
+                } else {
+                    SelectionMode::Cell
+                };
+                self.start
+                    .replace(SelectionCoordinate::x_y(range.start.x, range.start.y));
+                self.cursor.x = range.end.x;
+                self.cursor.y = range.end.y;
+                self.select_to_cursor_pos();
+            }
+            None => self.bell(),
+        }
+    }
+
+    /// Move to the specified 1-based line of the scrollback, as
+    /// vim does for `G` with a count
+    fn move_to_line(&mut self, line: usize) {
+        let dims = self.delegate.get_dimensions();
+        self.remember_position();
+        self.cursor.y = dims.scrollback_top + line.saturating_sub(1) as StableRowIndex;
+        self.select_to_cursor_pos();
+    }
+
+    /// Copy the selection into the selected register rather than
+    /// to the requested destination
+    fn copy_to_register(&mut self, name: char) {
+        let pane = Arc::clone(&self.delegate);
+        self.notify(move |term_window| {
+            let text = term_window.selection_text(&pane);
+            term_window.copy_to_clipboard(ClipboardCopyDestination::Register(name), text);
+        });
+    }
+
+    /// Performs a copy mode assignment, repeating it `count` times
+    /// where that is meaningful
+    fn perform(&mut self, assignment: &CopyModeAssignment, explicit_count: Option<usize>) {
+        use CopyModeAssignment::*;
+        let count = explicit_count.unwrap_or(1);
+        let repeat = |render: &mut Self, func: fn(&mut Self)| {
+            for _ in 0..count {
+                func(render);
+            }
+        };
+        match assignment {
+            MoveToViewportBottom => self.move_to_viewport_bottom(),
+            MoveToViewportTop => self.move_to_viewport_top(),
+            MoveToViewportMiddle => self.move_to_viewport_middle(),
+            MoveToScrollbackTop | MoveToScrollbackBottom if explicit_count.is_some() => {
+                self.move_to_line(count)
+            }
+            MoveToScrollbackTop => {
+                self.remember_position();
+                self.move_to_top()
+            }
+            MoveToScrollbackBottom => {
+                self.remember_position();
+                self.move_to_bottom()
+            }
+            MoveToStartOfLineContent => self.move_to_start_of_line_content(),
+            MoveToEndOfLineContent => {
+                // As in vim, a count moves to the end of a later line
+                self.cursor.y += count as StableRowIndex - 1;
+                self.move_to_end_of_line_content()
+            }
+            MoveToStartOfLine => self.move_to_start_of_line(),
+            MoveToStartOfNextLine => {
+                self.cursor.y += count as StableRowIndex - 1;
+                self.move_to_start_of_next_line()
+            }
+            MoveToSelectionOtherEnd => self.move_to_selection_other_end(),
+            MoveToSelectionOtherEndHoriz => self.move_to_selection_other_end_horiz(),
+            MoveBackwardWord => repeat(self, Self::move_backward_one_word),
+            MoveForwardWord => repeat(self, Self::move_forward_one_word),
+            MoveForwardWordEnd => repeat(self, Self::move_to_end_of_word),
+            MoveRight => self.move_right(count),
+            MoveLeft => self.move_left(count),
+            MoveUp => self.move_up(count),
+            MoveDown => self.move_down(count),
+            MoveByPage(n) => self.move_by_page(**n * count as f64),
+            PageUp => self.move_by_page(-(count as f64)),
+            PageDown => self.move_by_page(count as f64),
+            Close => self.close(),
+            PriorMatch => repeat(self, Self::prior_match),
+            NextMatch => repeat(self, Self::next_match),
+            PriorMatchPage => repeat(self, Self::prior_match_page),
+            NextMatchPage => repeat(self, Self::next_match_page),
+            CycleMatchType => self.cycle_match_type(),
+            ClearPattern => self.clear_pattern(),
+            EditPattern => self.edit_pattern(),
+            AcceptPattern => self.accept_pattern(),
+            SetSelectionMode(mode) => self.set_selection_mode(mode),
+            ClearSelectionMode => self.clear_selection_mode(),
+            MoveBackwardSemanticZone => self.move_by_zone(-(count as isize), None),
+            MoveForwardSemanticZone => self.move_by_zone(count as isize, None),
+            MoveBackwardZoneOfType(zone_type) => {
+                self.move_by_zone(-(count as isize), Some(*zone_type))
+            }
+            MoveForwardZoneOfType(zone_type) => self.move_by_zone(count as isize, Some(*zone_type)),
+            JumpForward { prev_char } => self.jump(true, *prev_char, count),
+            JumpBackward { prev_char } => self.jump(false, *prev_char, count),
+            JumpAgain => {
+                for _ in 0..count {
+                    self.jump_again(false);
+                }
+            }
+            JumpReverse => {
+                for _ in 0..count {
+                    self.jump_again(true);
+                }
+            }
+            MoveToMatchingBracket => self.move_to_matching_bracket(),
+            SetMark(name) => self.set_mark(*name),
+            JumpToMark { mark, exact } => self.jump_to_mark(*mark, *exact),
+            SelectTextObject { object, around } => self.select_text_object(*object, *around),
+            SelectRegister(name) => {
+                self.register.replace(*name);
+            }
+        }
+    }
+
     fn set_selection_mode(&mut self, mode: &Option<SelectionMode>) {
         match mode {
             None => self.clear_selection_mode(),
@@ -1140,6 +1418,7 @@ impl Pane for CopyOverlay {
         let mut render = self.render.lock();
         let mods = mods.remove_positional_mods();
         if let Some(jump) = render.pending_jump.take() {
+            let jump_count = jump.count;
             match (key, mods) {
                 (KeyCode::Char(c), KeyModifiers::NONE)
                 | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
@@ -1150,12 +1429,39 @@ impl Pane for CopyOverlay {
                     };
                     render.last_jump.replace(jump);
                     render.perform_jump(jump, false);
+                    for _ in 1..jump_count {
+                        render.perform_jump(jump, true);
+                    }
+                }
+                _ => render.bell(),
+            }
+            return Ok(());
+        }
+
+        if !render.editing_search {
+            let routed_start_of_line = std::mem::take(&mut render.routed_start_of_line);
+            match (key, mods) {
+                (KeyCode::Char(c), KeyModifiers::NONE)
+                | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
+                    let was_pending = render.grammar.has_prefix() || render.grammar.has_count();
+                    match render.grammar.feed(c) {
+                        GrammarResult::Pending => {}
+                        GrammarResult::Resolved(assignment) => {
+                            let count = render.grammar.take_count();
+                            render.perform(&assignment, count);
+                        }
+                        GrammarResult::Invalid if routed_start_of_line => {
+                            render.move_to_start_of_line();
+                        }
+                        GrammarResult::Invalid if was_pending => render.bell(),
+                        GrammarResult::Invalid => {}
+                    }
                 }
                 _ => {
-                    self.delegate
-                        .perform_actions(vec![termwiz::escape::Action::Control(
-                            termwiz::escape::ControlCode::Bell,
-                        )]);
+                    render.grammar.reset();
+                    if routed_start_of_line {
+                        render.move_to_start_of_line();
+                    }
                 }
             }
             return Ok(());
@@ -1239,56 +1545,30 @@ impl Pane for CopyOverlay {
     }
 
     fn perform_assignment(&self, assignment: &KeyAssignment) -> PerformAssignmentResult {
-        use CopyModeAssignment::*;
         let mut render = self.render.lock();
-        if render.pending_jump.is_some() {
+        if render.pending_jump.is_some() || render.grammar.has_prefix() {
             // Block key assignments until key_down is called
             // and resolves the next state
             return PerformAssignmentResult::BlockAssignmentAndRouteToKeyDown;
         }
+        if render.grammar.has_count()
+            && *assignment == KeyAssignment::CopyMode(CopyModeAssignment::MoveToStartOfLine)
+        {
+            // This is usually the `0` key continuing a count; let
+            // key_down decide
+            render.routed_start_of_line = true;
+            return PerformAssignmentResult::BlockAssignmentAndRouteToKeyDown;
+        }
         match assignment {
             KeyAssignment::CopyMode(assignment) => {
-                match assignment {
-                    MoveToViewportBottom => render.move_to_viewport_bottom(),
-                    MoveToViewportTop => render.move_to_viewport_top(),
-                    MoveToViewportMiddle => render.move_to_viewport_middle(),
-                    MoveToScrollbackTop => render.move_to_top(),
-                    MoveToScrollbackBottom => render.move_to_bottom(),
-                    MoveToStartOfLineContent => render.move_to_start_of_line_content(),
-                    MoveToEndOfLineContent => render.move_to_end_of_line_content(),
-                    MoveToStartOfLine => render.move_to_start_of_line(),
-                    MoveToStartOfNextLine => render.move_to_start_of_next_line(),
-                    MoveToSelectionOtherEnd => render.move_to_selection_other_end(),
-                    MoveToSelectionOtherEndHoriz => render.move_to_selection_other_end_horiz(),
-                    MoveBackwardWord => render.move_backward_one_word(),
-                    MoveForwardWord => render.move_forward_one_word(),
-                    MoveForwardWordEnd => render.move_to_end_of_word(),
-                    MoveRight => render.move_right_single_cell(),
-                    MoveLeft => render.move_left_single_cell(),
-                    MoveUp => render.move_up_single_row(),
-                    MoveDown => render.move_down_single_row(),
-                    MoveByPage(n) => render.move_by_page(**n),
-                    PageUp => render.move_by_page(-1.0),
-                    PageDown => render.move_by_page(1.0),
-                    Close => render.close(),
-                    PriorMatch => render.prior_match(),
-                    NextMatch => render.next_match(),
-                    PriorMatchPage => render.prior_match_page(),
-                    NextMatchPage => render.next_match_page(),
-                    CycleMatchType => render.cycle_match_type(),
-                    ClearPattern => render.clear_pattern(),
-                    EditPattern => render.edit_pattern(),
-                    AcceptPattern => render.accept_pattern(),
-                    SetSelectionMode(mode) => render.set_selection_mode(mode),
-                    ClearSelectionMode => render.clear_selection_mode(),
-                    MoveBackwardSemanticZone => render.move_by_zone(-1, None),
-                    MoveForwardSemanticZone => render.move_by_zone(1, None),
-                    MoveBackwardZoneOfType(zone_type) => render.move_by_zone(-1, Some(*zone_type)),
-                    MoveForwardZoneOfType(zone_type) => render.move_by_zone(1, Some(*zone_type)),
-                    JumpForward { prev_char } => render.jump(true, *prev_char),
-                    JumpBackward { prev_char } => render.jump(false, *prev_char),
-                    JumpAgain => render.jump_again(false),
-                    JumpReverse => render.jump_again(true),
+                let count = render.grammar.take_count();
+                render.perform(assignment, count);
+                PerformAssignmentResult::Handled
+            }
+            KeyAssignment::CopyTo(_) if render.register.is_some() => {
+                render.grammar.reset();
+                if let Some(name) = render.register.take() {
+                    render.copy_to_register(name);
                 }
                 PerformAssignmentResult::Handled
             }
@@ -1434,6 +1714,7 @@ impl Pane for CopyOverlay {
                             Pattern::CaseSensitiveString(_) => "case-sensitive",
                             Pattern::CaseInSensitiveString(_) => "ignore-case",
                             Pattern::Regex(_) => "regex",
+                            Pattern::Fuzzy(_) => "fuzzy",
                         };
 
                         let remain = match &self.renderer.searching {
@@ -1535,6 +1816,7 @@ impl Pane for CopyOverlay {
                     Pattern::CaseSensitiveString(_) => "case-sensitive",
                     Pattern::CaseInSensitiveString(_) => "ignore-case",
                     Pattern::Regex(_) => "regex",
+                    Pattern::Fuzzy(_) => "fuzzy",
                 };
                 line.overlay_text_with_attribute(
                     0,
@@ -2021,3 +2303,330 @@ pub fn copy_key_table() -> KeyTable {
     }
     table
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::surface::CursorShape;
+
+    /// A pane that holds a fixed set of lines
+    struct LinesPane {
+        id: PaneId,
+        lines: Vec<Line>,
+        writer: Mutex<Vec<u8>>,
+    }
+
+    impl Pane for LinesPane {
+        fn pane_id(&self) -> PaneId {
+            self.id
+        }
+
+        fn get_cursor_position(&self) -> StableCursorPosition {
+            StableCursorPosition {
+                x: 0,
+                y: 0,
+                shape: CursorShape::Default,
+                visibility: CursorVisibility::Visible,
+            }
+        }
+
+        fn get_current_seqno(&self) -> SequenceNo {
+            SEQ_ZERO
+        }
+
+        fn get_changed_since(
+            &self,
+            _lines: Range<StableRowIndex>,
+            _: SequenceNo,
+        ) -> RangeSet<StableRowIndex> {
+            RangeSet::default()
+        }
+
+        fn with_lines_mut(
+            &self,
+            _stable_range: Range<StableRowIndex>,
+            _with_lines: &mut dyn WithPaneLines,
+        ) {
+            unimplemented!();
+        }
+
+        fn for_each_logical_line_in_stable_range_mut(
+            &self,
+            _lines: Range<StableRowIndex>,
+            _for_line: &mut dyn ForEachPaneLogicalLine,
+        ) {
+            unimplemented!();
+        }
+
+        fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+            let start = (lines.start.max(0) as usize).min(self.lines.len());
+            let end = (lines.end.max(0) as usize).clamp(start, self.lines.len());
+            (start as StableRowIndex, self.lines[start..end].to_vec())
+        }
+
+        fn get_logical_lines(&self, _lines: Range<StableRowIndex>) -> Vec<LogicalLine> {
+            unimplemented!();
+        }
+
+        fn get_dimensions(&self) -> RenderableDimensions {
+            RenderableDimensions {
+                cols: 40,
+                viewport_rows: self.lines.len(),
+                scrollback_rows: self.lines.len(),
+                physical_top: 0,
+                scrollback_top: 0,
+                dpi: 96,
+                pixel_width: 400,
+                pixel_height: 20 * self.lines.len(),
+                reverse_video: false,
+            }
+        }
+
+        fn get_title(&self) -> String {
+            "lines".to_string()
+        }
+        fn send_paste(&self, _text: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn reader(&self) -> anyhow::Result<Option<Box<dyn std::io::Read + Send>>> {
+            Ok(None)
+        }
+        fn writer(&self) -> MappedMutexGuard<dyn std::io::Write> {
+            MutexGuard::map(self.writer.lock(), |writer| {
+                let w: &mut dyn std::io::Write = writer;
+                w
+            })
+        }
+        fn resize(&self, _size: TerminalSize) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn key_down(&self, _key: KeyCode, _mods: KeyModifiers) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn key_up(&self, _: KeyCode, _: KeyModifiers) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn mouse_event(&self, _event: MouseEvent) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn is_dead(&self) -> bool {
+            false
+        }
+        fn palette(&self) -> ColorPalette {
+            ColorPalette::default()
+        }
+        fn domain_id(&self) -> DomainId {
+            0
+        }
+        fn is_mouse_grabbed(&self) -> bool {
+            false
+        }
+        fn is_alt_screen_active(&self) -> bool {
+            false
+        }
+        fn get_current_working_dir(&self, _policy: CachePolicy) -> Option<Url> {
+            None
+        }
+    }
+
+    const TEXT: &[&str] = &[
+        "alpha beta gamma delta",
+        "    indented (one two)",
+        "third line",
+        "",
+        "last",
+    ];
+
+    /// Returns a copy overlay for a pane that holds TEXT.
+    /// Marks are shared between overlays of the same pane, so each
+    /// test uses its own `pane_id`.
+    fn overlay(pane_id: PaneId) -> CopyOverlay {
+        let pane: Arc<dyn Pane> = Arc::new(LinesPane {
+            id: pane_id,
+            lines: TEXT
+                .iter()
+                .map(|s| Line::from_text(s, &CellAttributes::default(), SEQ_ZERO, None))
+                .collect(),
+            writer: Mutex::new(vec![]),
+        });
+        CopyOverlay::new(
+            &pane,
+            None,
+            None,
+            0,
+            CopyModeParams {
+                pattern: Pattern::default(),
+                editing_search: false,
+            },
+        )
+    }
+
+    fn keys(overlay: &CopyOverlay, keys: &str) {
+        for c in keys.chars() {
+            overlay
+                .key_down(KeyCode::Char(c), KeyModifiers::NONE)
+                .unwrap();
+        }
+    }
+
+    fn cursor(overlay: &CopyOverlay) -> (usize, StableRowIndex) {
+        let render = overlay.render.lock();
+        (render.cursor.x, render.cursor.y)
+    }
+
+    fn set_cursor(overlay: &CopyOverlay, x: usize, y: StableRowIndex) {
+        let mut render = overlay.render.lock();
+        render.cursor.x = x;
+        render.cursor.y = y;
+    }
+
+    fn assignment(overlay: &CopyOverlay, assignment: CopyModeAssignment) {
+        assert_eq!(
+            overlay.perform_assignment(&KeyAssignment::CopyMode(assignment)),
+            PerformAssignmentResult::Handled
+        );
+    }
+
+    #[test]
+    fn counts() {
+        let overlay = overlay(1);
+
+        keys(&overlay, "2");
+        assignment(&overlay, CopyModeAssignment::MoveDown);
+        assert_eq!(cursor(&overlay), (0, 2));
+
+        // The count is consumed by the command that it precedes
+        assignment(&overlay, CopyModeAssignment::MoveUp);
+        assert_eq!(cursor(&overlay), (0, 1));
+
+        set_cursor(&overlay, 0, 0);
+        keys(&overlay, "2");
+        assignment(&overlay, CopyModeAssignment::MoveForwardWord);
+        assert_eq!(cursor(&overlay), (11, 0));
+
+        // A multi-digit count, which is clamped to the scrollback
+        keys(&overlay, "12");
+        assignment(&overlay, CopyModeAssignment::MoveDown);
+        assert_eq!(cursor(&overlay), (11, 4));
+
+        // A count with `G` moves to that line
+        keys(&overlay, "3");
+        assignment(&overlay, CopyModeAssignment::MoveToScrollbackBottom);
+        assert_eq!(cursor(&overlay).1, 2);
+
+        // `0` continues a count rather than moving to the start of
+        // the line
+        set_cursor(&overlay, 0, 0);
+        keys(&overlay, "1");
+        assert_eq!(
+            overlay.perform_assignment(&KeyAssignment::CopyMode(
+                CopyModeAssignment::MoveToStartOfLine
+            )),
+            PerformAssignmentResult::BlockAssignmentAndRouteToKeyDown
+        );
+        keys(&overlay, "0");
+        assignment(&overlay, CopyModeAssignment::MoveRight);
+        assert_eq!(cursor(&overlay), (10, 0));
+    }
+
+    #[test]
+    fn text_objects_and_brackets() {
+        let overlay = overlay(2);
+
+        set_cursor(&overlay, 15, 1);
+        keys(&overlay, "ib");
+        {
+            let render = overlay.render.lock();
+            assert_eq!(render.start, Some(SelectionCoordinate::x_y(14, 1)));
+            assert_eq!((render.cursor.x, render.cursor.y), (20, 1));
+            assert_eq!(render.selection_mode, SelectionMode::Cell);
+        }
+
+        overlay.render.lock().start.take();
+        set_cursor(&overlay, 7, 0);
+        keys(&overlay, "aw");
+        {
+            let render = overlay.render.lock();
+            assert_eq!(render.start, Some(SelectionCoordinate::x_y(6, 0)));
+            assert_eq!((render.cursor.x, render.cursor.y), (10, 0));
+        }
+
+        overlay.render.lock().start.take();
+        set_cursor(&overlay, 0, 2);
+        keys(&overlay, "ip");
+        {
+            let render = overlay.render.lock();
+            assert_eq!(render.selection_mode, SelectionMode::Line);
+            assert_eq!(render.start.map(|start| start.y), Some(0));
+            assert_eq!(render.cursor.y, 2);
+        }
+
+        overlay.render.lock().start.take();
+        set_cursor(&overlay, 13, 1);
+        keys(&overlay, "%");
+        assert_eq!(cursor(&overlay), (21, 1));
+        keys(&overlay, "%");
+        assert_eq!(cursor(&overlay), (13, 1));
+    }
+
+    #[test]
+    fn marks() {
+        let overlay = overlay(3);
+
+        set_cursor(&overlay, 8, 1);
+        keys(&overlay, "ma");
+        set_cursor(&overlay, 2, 4);
+
+        // Jumping to the line of a mark goes to its first non-blank
+        keys(&overlay, "'a");
+        assert_eq!(cursor(&overlay), (4, 1));
+
+        // `` ` `` jumps to the exact position
+        keys(&overlay, "`a");
+        assert_eq!(cursor(&overlay), (8, 1));
+
+        // `''` returns to the position prior to the last jump
+        keys(&overlay, "''");
+        assert_eq!(cursor(&overlay), (4, 1));
+
+        // An unknown mark leaves the cursor where it is
+        keys(&overlay, "`z");
+        assert_eq!(cursor(&overlay), (4, 1));
+
+        // Marks outlive the overlay, but not the pane
+        drop(overlay);
+        let overlay = self::overlay(3);
+        keys(&overlay, "`a");
+        assert_eq!(cursor(&overlay), (8, 1));
+
+        forget_marks(3);
+        let overlay = self::overlay(3);
+        keys(&overlay, "`a");
+        assert_eq!(cursor(&overlay), (0, 0));
+    }
+
+    #[test]
+    fn registers() {
+        let overlay = overlay(4);
+
+        keys(&overlay, "\"a");
+        assert_eq!(overlay.render.lock().register, Some('a'));
+
+        // Copying goes to the register, which is then forgotten
+        assert_eq!(
+            overlay.perform_assignment(&KeyAssignment::CopyTo(ClipboardCopyDestination::Clipboard)),
+            PerformAssignmentResult::Handled
+        );
+        assert_eq!(overlay.render.lock().register, None);
+
+        // Without a register, copying is left to the key assignment
+        assert_eq!(
+            overlay.perform_assignment(&KeyAssignment::CopyTo(ClipboardCopyDestination::Clipboard)),
+            PerformAssignmentResult::Unhandled
+        );
+
+        // An invalid register name selects nothing
+        keys(&overlay, "\"!");
+        assert_eq!(overlay.render.lock().register, None);
+    }
+}
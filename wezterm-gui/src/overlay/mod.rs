@@ -6,15 +6,21 @@ use std::pin::Pin;
 use std::sync::Arc;
 use wezterm_term::{TerminalConfiguration, TerminalSize};
 
+pub mod command_history;
 pub mod confirm;
+pub mod confirm_clipboard_read;
 pub mod confirm_close_pane;
 pub mod copy;
 pub mod debug;
 pub mod launcher;
 pub mod prompt;
 pub mod quickselect;
+pub mod scrollback_search;
 pub mod selector;
+pub mod textobject;
+pub mod vimgrammar;
 
+pub use confirm_clipboard_read::confirm_clipboard_read;
 pub use confirm_close_pane::{
     confirm_close_pane, confirm_close_tab, confirm_close_window, confirm_quit_program,
 };
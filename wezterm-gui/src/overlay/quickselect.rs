@@ -1,17 +1,23 @@
+use crate::scripting::guiwin::GuiWin;
 use crate::selection::{SelectionCoordinate, SelectionRange};
 use crate::termwindow::{TermWindow, TermWindowNotif};
-use config::keyassignment::{ClipboardCopyDestination, QuickSelectArguments, ScrollbackEraseMode};
-use config::ConfigHandle;
+use config::keyassignment::{
+    ClipboardCopyDestination, KeyAssignment, QuickSelectArguments, ScrollbackEraseMode,
+};
+use config::{ColorSpec, ConfigHandle};
+use fancy_regex::Regex;
 use mux::domain::DomainId;
 use mux::pane::{
     CachePolicy, ForEachPaneLogicalLine, LogicalLine, Pane, PaneId, Pattern, SearchResult,
     WithPaneLines,
 };
 use mux::renderable::*;
+use mux_lua::MuxPane;
 use parking_lot::{MappedMutexGuard, Mutex};
 use rangeset::RangeSet;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Range;
+use std::rc::Rc;
 use std::sync::Arc;
 use termwiz::cell::{Cell, CellAttributes};
 use termwiz::color::AnsiColor;
@@ -202,14 +208,61 @@ pub struct QuickSelectOverlay {
 struct MatchResult {
     range: Range<usize>,
     label: String,
+    label_color: Option<ColorSpec>,
+}
+
+/// A pattern along with what to do with the things that it matches
+#[derive(Debug)]
+struct MatchRule {
+    /// The pattern that is searched for.  Its named capture groups
+    /// are made non-capturing, so that they don't narrow the match
+    /// in the way that the other capture groups do.
+    pattern: Pattern,
+    /// The regex as it was written; used to recover the named captures
+    /// of the match that the user selects
+    regex: Option<Regex>,
+    action: Option<KeyAssignment>,
+    label_color: Option<ColorSpec>,
+    name: String,
+}
+
+impl MatchRule {
+    fn new(
+        regex: &str,
+        action: Option<KeyAssignment>,
+        label_color: Option<ColorSpec>,
+        name: String,
+    ) -> Self {
+        let regex = format!("(?m)({})", regex);
+        Self {
+            regex: match Regex::new(&regex) {
+                Ok(re) => Some(re),
+                Err(err) => {
+                    log::error!("quick select pattern {}: {:#}", regex, err);
+                    None
+                }
+            },
+            pattern: Pattern::Regex(without_named_groups(&regex)),
+            action,
+            label_color,
+            name,
+        }
+    }
+}
+
+/// A search result along with the index of the rule that produced it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct RuleMatch {
+    result: SearchResult,
+    rule: usize,
 }
 
 struct QuickSelectRenderable {
     delegate: Arc<dyn Pane>,
-    /// The text that the user entered
-    pattern: Pattern,
+    /// The patterns to search for, in order of precedence
+    rules: Arc<Vec<MatchRule>>,
     /// The most recently queried set of matches
-    results: Vec<SearchResult>,
+    results: Vec<RuleMatch>,
     by_line: HashMap<StableRowIndex, Vec<MatchResult>>,
     by_label: HashMap<String, usize>,
     selection: String,
@@ -240,7 +293,20 @@ impl QuickSelectOverlay {
 
         let config = term_window.config.clone();
 
-        let mut pattern = "(?m)(".to_string();
+        let mut rules: Vec<MatchRule> = args
+            .rules
+            .iter()
+            .map(|rule| {
+                MatchRule::new(
+                    &rule.regex,
+                    rule.action.as_deref().or(args.action.as_deref()).cloned(),
+                    rule.label_color,
+                    rule.name.clone(),
+                )
+            })
+            .collect();
+
+        let mut pattern = String::new();
         let mut have_patterns = false;
         if !args.patterns.is_empty() {
             for p in &args.patterns {
@@ -250,7 +316,7 @@ impl QuickSelectOverlay {
                 pattern.push_str(p);
                 have_patterns = true;
             }
-        } else {
+        } else if rules.is_empty() {
             // User-provided patterns take precedence over built-ins
             for p in &config.quick_select_patterns {
                 if have_patterns {
@@ -269,14 +335,19 @@ impl QuickSelectOverlay {
                 }
             }
         }
-        pattern.push(')');
-
-        let pattern = Pattern::Regex(pattern);
+        if rules.is_empty() || have_patterns {
+            rules.push(MatchRule::new(
+                &pattern,
+                args.action.as_deref().cloned(),
+                None,
+                String::new(),
+            ));
+        }
 
         let window = term_window.window.clone().unwrap();
         let mut renderer = QuickSelectRenderable {
             delegate: Arc::clone(pane),
-            pattern,
+            rules: Arc::new(rules),
             selection: "".to_string(),
             results: vec![],
             by_line: HashMap::new(),
@@ -375,7 +446,7 @@ impl Pane for QuickSelectOverlay {
                     if let Some(pos) = r
                         .results
                         .iter()
-                        .position(|res| res.start_y > prior && res.start_y < top)
+                        .position(|res| res.result.start_y > prior && res.result.start_y < top)
                     {
                         r.activate_match_number(pos);
                     } else {
@@ -391,7 +462,11 @@ impl Pane for QuickSelectOverlay {
                 if let Some(cur) = r.result_pos {
                     let top = r.viewport.unwrap_or(dims.physical_top);
                     let bottom = top + dims.viewport_rows as isize;
-                    if let Some(pos) = r.results.iter().position(|res| res.start_y >= bottom) {
+                    if let Some(pos) = r
+                        .results
+                        .iter()
+                        .position(|res| res.result.start_y >= bottom)
+                    {
                         r.activate_match_number(pos);
                     } else {
                         let len = r.results.len().saturating_sub(1);
@@ -616,8 +691,8 @@ impl Pane for QuickSelectOverlay {
                                         .unwrap_or(AnsiColor::Black.into()),
                                 )
                                 .set_foreground(
-                                    colors
-                                        .quick_select_label_fg
+                                    m.label_color
+                                        .or(colors.quick_select_label_fg)
                                         .unwrap_or(AnsiColor::Olive.into()),
                                 )
                                 .set_reverse(false)
@@ -708,8 +783,8 @@ impl Pane for QuickSelectOverlay {
                                 .unwrap_or(AnsiColor::Black.into()),
                         )
                         .set_foreground(
-                            colors
-                                .quick_select_label_fg
+                            m.label_color
+                                .or(colors.quick_select_label_fg)
                                 .unwrap_or(AnsiColor::Olive.into()),
                         )
                         .set_reverse(false)
@@ -765,8 +840,8 @@ impl QuickSelectRenderable {
 
     fn recompute_results(&mut self) {
         /// Produce the sorted seq of unique match_ids from the results
-        fn compute_uniq_results(results: &[SearchResult]) -> Vec<usize> {
-            let mut ids: Vec<usize> = results.iter().map(|sr| sr.match_id).collect();
+        fn compute_uniq_results(results: &[RuleMatch]) -> Vec<usize> {
+            let mut ids: Vec<usize> = results.iter().map(|rm| rm.result.match_id).collect();
             ids.sort();
             ids.dedup();
             ids
@@ -790,7 +865,8 @@ impl QuickSelectRenderable {
 
         // Work through the results in reverse order, so that we assign eg: `a` to the
         // bottom-right-most result first and so on
-        for (result_index, res) in self.results.iter().enumerate().rev() {
+        for (result_index, rule_match) in self.results.iter().enumerate().rev() {
+            let res = &rule_match.result;
             // Figure out which label to use based on the match_id
             let label_index = match assigned_labels.get(&res.match_id).copied() {
                 Some(idx) => idx,
@@ -831,6 +907,7 @@ impl QuickSelectRenderable {
                 let result = MatchResult {
                     range,
                     label: label.clone(),
+                    label_color: self.rules[rule_match.rule].label_color,
                 };
 
                 let matches = self.by_line.entry(idx).or_insert_with(|| vec![]);
@@ -856,10 +933,10 @@ impl QuickSelectRenderable {
         let bar_pos = self.compute_search_row();
         self.dirty_results.add(bar_pos);
 
-        if !self.pattern.is_empty() {
+        if !self.rules.is_empty() {
             let pane: Arc<dyn Pane> = self.delegate.clone();
             let window = self.window.clone();
-            let pattern = self.pattern.clone();
+            let rules = Arc::clone(&self.rules);
             let scope = self.args.scope_lines;
             let viewport = self.viewport;
             promise::spawn::spawn(async move {
@@ -868,9 +945,15 @@ impl QuickSelectRenderable {
                 let top = viewport.unwrap_or(dims.physical_top);
                 let range = top.saturating_sub(scope as StableRowIndex)
                     ..top + (dims.viewport_rows + scope) as StableRowIndex;
-                let limit = None;
-                let mut results = pane.search(pattern, range, limit).await?;
-                results.sort();
+                let mut rule_results = vec![];
+                for rule in rules.iter() {
+                    let limit = None;
+                    rule_results.push(
+                        pane.search(rule.pattern.clone(), range.clone(), limit)
+                            .await?,
+                    );
+                }
+                let results = merge_rule_results(rule_results);
 
                 let pane_id = pane.pane_id();
                 let mut results = Some(results);
@@ -888,10 +971,8 @@ impl QuickSelectRenderable {
                             if !r.results.is_empty() {
                                 match &r.viewport {
                                     Some(y) if is_initial_run => {
-                                        r.result_pos = r
-                                            .results
-                                            .iter()
-                                            .position(|result| result.start_y >= *y);
+                                        r.result_pos =
+                                            r.results.iter().position(|rm| rm.result.start_y >= *y);
                                     }
                                     _ => {
                                         r.activate_match_number(num_results - 1);
@@ -928,10 +1009,13 @@ impl QuickSelectRenderable {
     }
 
     fn select_and_copy_match_number(&mut self, n: usize, paste: bool) {
-        let result = self.results[n].clone();
+        let RuleMatch { result, rule } = self.results[n];
+        let rule = &self.rules[rule];
 
         let pane_id = self.delegate.pane_id();
-        let action = self.args.action.clone();
+        let action = rule.action.clone();
+        let regex = rule.regex.clone();
+        let rule_name = rule.name.clone();
         let skip_action_on_paste = self.args.skip_action_on_paste;
         self.window
             .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
@@ -962,7 +1046,27 @@ impl QuickSelectRenderable {
                         }
                         if let Some(action) = action {
                             if !paste || !skip_action_on_paste {
-                                let _ = term_window.perform_key_assignment(&pane, &action);
+                                match action {
+                                    KeyAssignment::EmitEvent(event) => {
+                                        // Callbacks also receive the text and captures
+                                        let captures = regex
+                                            .and_then(|re| match_captures(&re, &*pane, &result))
+                                            .unwrap_or_default();
+                                        trampoline(
+                                            event,
+                                            GuiWin::new(term_window),
+                                            MuxPane(pane_id),
+                                            QuickSelectMatch {
+                                                text,
+                                                name: rule_name,
+                                                captures,
+                                            },
+                                        );
+                                    }
+                                    action => {
+                                        let _ = term_window.perform_key_assignment(&pane, &action);
+                                    }
+                                }
                             }
                         } else {
                             term_window.copy_to_clipboard(
@@ -977,7 +1081,275 @@ impl QuickSelectRenderable {
 
     fn activate_match_number(&mut self, n: usize) {
         self.result_pos.replace(n);
-        let result = self.results[n].clone();
+        let result = self.results[n].result;
         self.set_viewport(Some(result.start_y));
     }
 }
+
+/// Combines the results of searching for each of the rules into a single
+/// sorted list.  Where the matches of different rules overlap, the match
+/// from the earlier rule wins.  Each search numbers its match_ids from
+/// zero, so they are re-assigned to remain unique across the rules.
+fn merge_rule_results(rule_results: Vec<Vec<SearchResult>>) -> Vec<RuleMatch> {
+    // Start coordinate -> (end coordinate, result)
+    let mut accepted: BTreeMap<(StableRowIndex, usize), ((StableRowIndex, usize), RuleMatch)> =
+        BTreeMap::new();
+    let mut match_ids: HashMap<(usize, usize), usize> = HashMap::new();
+
+    for (rule, results) in rule_results.into_iter().enumerate() {
+        for mut result in results {
+            let start = (result.start_y, result.start_x);
+            let end = (result.end_y, result.end_x);
+
+            // The accepted results don't overlap each other, so the only
+            // one that can overlap this result is the last one that starts
+            // before this one ends
+            if let Some((_, (prior_end, _))) = accepted.range(..end).next_back() {
+                if *prior_end > start {
+                    continue;
+                }
+            }
+
+            let next_id = match_ids.len();
+            result.match_id = *match_ids.entry((rule, result.match_id)).or_insert(next_id);
+            accepted.insert(start, (end, RuleMatch { result, rule }));
+        }
+    }
+
+    accepted
+        .into_iter()
+        .map(|(_, (_, rule_match))| rule_match)
+        .collect()
+}
+
+/// Returns `regex` with its named capture groups replaced by
+/// non-capturing groups
+fn without_named_groups(regex: &str) -> String {
+    let mut result = String::with_capacity(regex.len());
+    let mut chars = regex.chars().peekable();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                result.push(c);
+                if let Some(escaped) = chars.next() {
+                    result.push(escaped);
+                }
+                continue;
+            }
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '(' if !in_class => {
+                let rest: String = chars.clone().take(3).collect();
+                // `(?<=` and `(?<!` are lookbehinds rather than names
+                let name_start = if rest.starts_with("?P<") {
+                    Some(3)
+                } else if rest.starts_with("?<") && !rest.ends_with('=') && !rest.ends_with('!') {
+                    Some(2)
+                } else {
+                    None
+                };
+                if let Some(name_start) = name_start {
+                    chars.nth(name_start - 1);
+                    for c in chars.by_ref() {
+                        if c == '>' {
+                            break;
+                        }
+                    }
+                    result.push_str("(?:");
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Returns the byte offset of the cell at `x` in the text of `line`
+fn byte_offset_of_cell(line: &Line, x: usize) -> usize {
+    line.visible_cells()
+        .take_while(|cell| cell.cell_index() < x)
+        .map(|cell| cell.str().len())
+        .sum()
+}
+
+/// Returns the named capture groups of the match for `regex` in `pane`
+/// that produced `result`
+fn match_captures(
+    regex: &Regex,
+    pane: &dyn Pane,
+    result: &SearchResult,
+) -> Option<HashMap<String, String>> {
+    let lines = pane.get_logical_lines(result.start_y..result.start_y + 1);
+    let line = lines.iter().find(|line| line.contains_y(result.start_y))?;
+    let start = line.xy_to_logical_x(result.start_x, result.start_y);
+    let end = line.xy_to_logical_x(result.end_x, result.end_y);
+    named_captures(
+        regex,
+        &line.logical.as_str(),
+        byte_offset_of_cell(&line.logical, start)..byte_offset_of_cell(&line.logical, end),
+    )
+}
+
+/// Finds the match for `regex` in `haystack` that produced the quick
+/// select result at the byte offsets `range`, and returns its named
+/// capture groups.
+/// The search reports the last unnamed capture group that participated
+/// in a match, so that is what we compare against `range`.
+fn named_captures(
+    regex: &Regex,
+    haystack: &str,
+    range: Range<usize>,
+) -> Option<HashMap<String, String>> {
+    let named: Vec<bool> = regex.capture_names().map(|n| n.is_some()).collect();
+    for captures in regex.captures_iter(haystack) {
+        let captures = match captures {
+            Ok(c) => c,
+            Err(_) => break,
+        };
+        let reported = (0..captures.len())
+            .rev()
+            .filter(|idx| !named.get(*idx).copied().unwrap_or(false))
+            .find_map(|idx| captures.get(idx))
+            .map(|m| m.range());
+        if reported != Some(range.clone()) {
+            continue;
+        }
+        return Some(
+            regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| {
+                    captures
+                        .name(name)
+                        .map(|m| (name.to_string(), m.as_str().to_string()))
+                })
+                .collect(),
+        );
+    }
+    None
+}
+
+#[cfg(test)]
+mod rule_test {
+    use super::*;
+
+    fn result(
+        start_y: StableRowIndex,
+        start_x: usize,
+        end_x: usize,
+        match_id: usize,
+    ) -> SearchResult {
+        SearchResult {
+            start_y,
+            start_x,
+            end_y: start_y,
+            end_x,
+            match_id,
+        }
+    }
+
+    #[test]
+    fn earlier_rules_win_overlaps() {
+        let merged = merge_rule_results(vec![
+            vec![result(1, 0, 10, 0), result(3, 5, 9, 0)],
+            vec![result(1, 4, 12, 0), result(2, 0, 4, 1), result(3, 9, 12, 0)],
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                RuleMatch {
+                    result: result(1, 0, 10, 0),
+                    rule: 0
+                },
+                RuleMatch {
+                    result: result(2, 0, 4, 1),
+                    rule: 1
+                },
+                RuleMatch {
+                    result: result(3, 5, 9, 0),
+                    rule: 0
+                },
+                RuleMatch {
+                    result: result(3, 9, 12, 2),
+                    rule: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn captures() {
+        let re = Regex::new(r"(?m)((?P<file>[\w./]+):(?P<line>\d+))").unwrap();
+        let haystack = "error at src/main.rs:10 and lib.rs:42";
+        let caps = named_captures(&re, haystack, 28..37).unwrap();
+        assert_eq!(caps.get("file").map(String::as_str), Some("lib.rs"));
+        assert_eq!(caps.get("line").map(String::as_str), Some("42"));
+        assert!(named_captures(&re, haystack, 27..37).is_none());
+
+        // The same text matched twice is told apart by its position
+        let haystack = "a.rs:1 b/a.rs:1";
+        let caps = named_captures(&re, haystack, 7..15).unwrap();
+        assert_eq!(caps.get("file").map(String::as_str), Some("b/a.rs"));
+    }
+
+    #[test]
+    fn named_groups_are_not_captured() {
+        assert_eq!(
+            without_named_groups(r"(?m)((?P<file>\w+):(?<line>\d+))"),
+            r"(?m)((?:\w+):(?:\d+))"
+        );
+        // Lookbehinds, escaped parens and classes are left alone
+        assert_eq!(
+            without_named_groups(r"(?<=x)(?<!y)\((?<a>[(?<b>])"),
+            r"(?<=x)(?<!y)\((?:[(?<b>])"
+        );
+
+        let re = Regex::new(r"(?m)(\w+=(?P<value>\w+))").unwrap();
+        let stripped = Regex::new(&without_named_groups(r"(?m)(\w+=(?P<value>\w+))")).unwrap();
+        assert_eq!(re.captures_len(), 3);
+        assert_eq!(stripped.captures_len(), 2);
+    }
+}
+
+/// Passed as the third parameter to an `action_callback` that is
+/// triggered by selecting a match
+struct QuickSelectMatch {
+    text: String,
+    name: String,
+    captures: HashMap<String, String>,
+}
+
+fn trampoline(name: String, window: GuiWin, pane: MuxPane, selected: QuickSelectMatch) {
+    promise::spawn::spawn(async move {
+        config::with_lua_config_on_main_thread(move |lua| {
+            do_event(lua, name, window, pane, selected)
+        })
+        .await
+    })
+    .detach();
+}
+
+async fn do_event(
+    lua: Option<Rc<mlua::Lua>>,
+    name: String,
+    window: GuiWin,
+    pane: MuxPane,
+    selected: QuickSelectMatch,
+) -> anyhow::Result<()> {
+    if let Some(lua) = lua {
+        let info = lua.create_table()?;
+        info.set("text", selected.text)?;
+        info.set("name", selected.name)?;
+        info.set("captures", lua.create_table_from(selected.captures)?)?;
+        let args = lua.pack_multi((window, pane, info))?;
+
+        if let Err(err) = config::lua::emit_event(&lua, (name.clone(), args)).await {
+            log::error!("while processing {} event: {:#}", name, err);
+        }
+    }
+
+    Ok(())
+}
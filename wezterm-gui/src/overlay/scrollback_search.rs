@@ -0,0 +1,560 @@
+//! The scrollback search overlay searches the scrollback of every pane
+//! in the active workspace and lists the matches, ranked by how well
+//! they match and how recently they were output, allowing one of them
+//! to be activated.
+use crate::overlay::selector::{matcher_pattern, matcher_score};
+use crate::selection::{SelectionCoordinate, SelectionRange};
+use crate::termwindow::TermWindowNotif;
+use mux::pane::{search_stream, Pane, PaneId, Pattern, PatternType, SearchResult};
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use smol::channel::{Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity};
+use termwiz::color::{AnsiColor, ColorAttribute};
+use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+use termwiz_funcs::{truncate_left, truncate_right};
+use wezterm_term::{unicode_column_width, StableRowIndex};
+use window::WindowOps;
+
+const ROW_OVERHEAD: usize = 3;
+
+/// How many rows of scrollback are searched at a time
+const SEARCH_CHUNK_SIZE: StableRowIndex = 1000;
+
+/// How long to wait after the last keypress before searching
+const TYPING_DELAY: Duration = Duration::from_millis(200);
+
+/// How often to check for results while waiting for input
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The most hits that we keep; the lowest ranked are discarded
+const MAX_HITS: usize = 2000;
+
+/// The match quality of a hit is halved for each this many rows
+/// that it is away from the bottom of its scrollback
+const RECENCY_HALF_LIFE_ROWS: f64 = 2000.;
+
+const TITLE_WIDTH: usize = 20;
+
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    pub pane_id: PaneId,
+    pub pane_title: String,
+    pub result: SearchResult,
+    /// How many rows above the bottom of the scrollback the match is
+    pub age: StableRowIndex,
+    /// The text of the line that precedes the match
+    pub before: String,
+    /// The matching text; only the portion on the first line of
+    /// a multi-line match
+    pub matched: String,
+    /// The text of the line that follows the match
+    pub after: String,
+}
+
+impl SearchHit {
+    /// Build hits for a chunk of results from `pane`.
+    /// Must be called on the Mux thread!
+    fn from_results(pane: &Arc<dyn Pane>, results: &[SearchResult]) -> Vec<Self> {
+        let dims = pane.get_dimensions();
+        let bottom = dims.scrollback_top + dims.scrollback_rows as StableRowIndex;
+        let pane_title = pane.get_title();
+
+        let mut hits = vec![];
+        for result in results {
+            let (_top, lines) = pane.get_lines(result.start_y..result.start_y + 1);
+            let line = match lines.get(0) {
+                Some(line) => line,
+                None => continue,
+            };
+
+            let end_x = if result.end_y == result.start_y {
+                result.end_x
+            } else {
+                usize::MAX
+            };
+            let mut before = String::new();
+            let mut matched = String::new();
+            let mut after = String::new();
+            for cell in line.visible_cells() {
+                let idx = cell.cell_index();
+                let text = if idx < result.start_x {
+                    &mut before
+                } else if idx < end_x {
+                    &mut matched
+                } else {
+                    &mut after
+                };
+                text.push_str(cell.str());
+            }
+
+            hits.push(Self {
+                pane_id: pane.pane_id(),
+                pane_title: pane_title.clone(),
+                result: *result,
+                age: bottom - result.start_y,
+                before: before.trim_start().to_string(),
+                matched,
+                after: after.trim_end().to_string(),
+            });
+        }
+        hits
+    }
+}
+
+/// Combine the quality of a match with how recent it is.
+/// `score` is the fuzzy match score, which is 0 for the other
+/// types of pattern, so that those are ranked by recency alone.
+fn rank(score: u32, age: StableRowIndex) -> f64 {
+    (score as f64 + 1.) * 0.5f64.powf(age.max(0) as f64 / RECENCY_HALF_LIFE_ROWS)
+}
+
+/// Returns the panes in the active workspace.
+/// Must be called on the Mux thread!
+fn workspace_panes() -> Vec<Arc<dyn Pane>> {
+    let mux = Mux::get();
+    let workspace = mux.active_workspace();
+    let mut panes = vec![];
+    for window_id in mux.iter_windows_in_workspace(&workspace) {
+        if let Some(window) = mux.get_window(window_id) {
+            for tab in window.iter() {
+                for pos in tab.iter_panes_ignoring_zoom() {
+                    panes.push(pos.pane);
+                }
+            }
+        }
+    }
+    panes
+}
+
+/// Search all panes in the workspace for `pattern`, sending the hits
+/// to `tx` as they are found.  Each pane is searched concurrently from
+/// the bottom up, so the most recent hits arrive first.  The channel
+/// closes when all of the panes have been searched.
+fn start_search(pattern: Pattern, tx: Sender<Vec<SearchHit>>) {
+    promise::spawn::spawn_into_main_thread(async move {
+        for pane in workspace_panes() {
+            let pattern = pattern.clone();
+            let tx = tx.clone();
+            promise::spawn::spawn(async move {
+                let chunks = search_stream(Arc::clone(&pane), pattern, SEARCH_CHUNK_SIZE);
+                while let Ok(chunk) = chunks.recv().await {
+                    match chunk {
+                        Ok(chunk) => {
+                            let hits = SearchHit::from_results(&pane, &chunk.results);
+                            if tx.send(hits).await.is_err() {
+                                // The search was superseded or the
+                                // overlay was closed
+                                break;
+                            }
+                        }
+                        Err(err) => {
+                            log::error!("searching pane {}: {:#}", pane.pane_id(), err);
+                            break;
+                        }
+                    }
+                }
+            })
+            .detach();
+        }
+    })
+    .detach();
+}
+
+/// Activate the pane that contains `hit`, along with its tab and
+/// window, and scroll it to show the match selected.
+fn activate(hit: SearchHit) {
+    promise::spawn::spawn_into_main_thread(async move {
+        let mux = Mux::get();
+        let pane_id = hit.pane_id;
+        if let Err(err) = mux.focus_pane_and_containing_tab(pane_id) {
+            log::error!("Unable to activate pane {}: {:#}", pane_id, err);
+            return;
+        }
+        let (pane, window_id) = match (mux.get_pane(pane_id), mux.resolve_pane_id(pane_id)) {
+            (Some(pane), Some((_domain, window_id, _tab_id))) => (pane, window_id),
+            _ => return,
+        };
+        let gui_win = match crate::frontend::front_end().gui_window_for_mux_window(window_id) {
+            Some(gui_win) => gui_win,
+            None => return,
+        };
+        gui_win.window.focus();
+
+        let result = hit.result;
+        let window = gui_win.window.clone();
+        gui_win
+            .window
+            .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                let dims = pane.get_dimensions();
+                // Position the match in the middle of the viewport
+                let top = result.start_y - dims.viewport_rows as StableRowIndex / 2;
+                term_window.set_viewport(pane_id, Some(top.max(dims.scrollback_top)), dims);
+
+                let start = SelectionCoordinate::x_y(result.start_x, result.start_y);
+                let end = SelectionCoordinate::x_y(result.end_x.saturating_sub(1), result.end_y);
+                let mut selection = term_window.selection(pane_id);
+                selection.origin = Some(start);
+                selection.range = Some(SelectionRange { start, end });
+                selection.rectangular = false;
+                window.invalidate();
+            })));
+    })
+    .detach();
+}
+
+struct ScrollbackSearchState {
+    active_idx: usize,
+    max_items: usize,
+    top_row: usize,
+    query: String,
+    pattern_type: PatternType,
+    hits: Vec<(f64, SearchHit)>,
+    /// Set when the query has changed but hasn't been searched yet
+    typed_at: Option<Instant>,
+    /// Receives hits for the current query while it is being searched
+    results: Option<Receiver<Vec<SearchHit>>>,
+}
+
+impl ScrollbackSearchState {
+    fn pattern(&self) -> Pattern {
+        let query = self.query.clone();
+        match self.pattern_type {
+            PatternType::CaseSensitiveString => Pattern::CaseSensitiveString(query),
+            PatternType::CaseInSensitiveString => Pattern::CaseInSensitiveString(query),
+            PatternType::Regex => Pattern::Regex(query),
+            PatternType::Fuzzy => Pattern::Fuzzy(query),
+        }
+    }
+
+    fn query_changed(&mut self) {
+        self.typed_at.replace(Instant::now());
+    }
+
+    fn cycle_match_type(&mut self) {
+        self.pattern_type = match self.pattern_type {
+            PatternType::CaseSensitiveString => PatternType::CaseInSensitiveString,
+            PatternType::CaseInSensitiveString => PatternType::Regex,
+            PatternType::Regex => PatternType::Fuzzy,
+            PatternType::Fuzzy => PatternType::CaseSensitiveString,
+        };
+        self.query_changed();
+    }
+
+    /// Start searching if the user has stopped typing.
+    /// Returns true if the display needs to be updated.
+    fn maybe_search(&mut self) -> bool {
+        match self.typed_at {
+            Some(typed_at) if typed_at.elapsed() >= TYPING_DELAY => {}
+            _ => return false,
+        }
+        self.typed_at.take();
+        self.hits.clear();
+        self.active_idx = 0;
+        self.top_row = 0;
+        // Dropping the prior receiver cancels the prior search
+        self.results.take();
+        if !self.query.is_empty() {
+            let (tx, rx) = smol::channel::unbounded();
+            start_search(self.pattern(), tx);
+            self.results.replace(rx);
+        }
+        true
+    }
+
+    /// Collect any hits that have arrived since the last call.
+    /// Returns true if the display needs to be updated.
+    fn receive_hits(&mut self) -> bool {
+        let mut new_hits = vec![];
+        let mut finished = false;
+        if let Some(rx) = &self.results {
+            loop {
+                match rx.try_recv() {
+                    Ok(hits) => new_hits.extend(hits),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Closed) => {
+                        finished = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if finished {
+            self.results.take();
+        }
+        if new_hits.is_empty() {
+            return finished;
+        }
+
+        let pattern = match self.pattern_type {
+            PatternType::Fuzzy => Some(matcher_pattern(&self.query)),
+            _ => None,
+        };
+        for hit in new_hits {
+            let score = pattern
+                .as_ref()
+                .and_then(|pattern| matcher_score(pattern, &hit.matched))
+                .unwrap_or(0);
+            self.hits.push((rank(score, hit.age), hit));
+        }
+        self.hits
+            .sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        self.hits.truncate(MAX_HITS);
+        true
+    }
+
+    fn render(&mut self, term: &mut TermWizTerminal) -> termwiz::Result<()> {
+        let size = term.get_screen_size()?;
+        let max_width = size.cols.saturating_sub(2);
+        self.max_items = size.rows.saturating_sub(ROW_OVERHEAD);
+
+        let mode = match self.pattern_type {
+            PatternType::CaseSensitiveString => "case-sensitive",
+            PatternType::CaseInSensitiveString => "ignore-case",
+            PatternType::Regex => "regex",
+            PatternType::Fuzzy => "fuzzy",
+        };
+        let status = if self.results.is_some() || self.typed_at.is_some() {
+            "searching...".to_string()
+        } else if self.query.is_empty() {
+            "Type to search  Enter=activate  CTRL-R=mode  Esc=cancel".to_string()
+        } else {
+            format!("{} matches", self.hits.len())
+        };
+        let header = format!("Search all panes ({mode}): {}  {status}", self.query);
+
+        let mut changes = vec![
+            Change::ClearScreen(ColorAttribute::Default),
+            Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(0),
+            },
+            Change::Text(format!("{}\r\n", truncate_right(&header, max_width))),
+            Change::AllAttributes(CellAttributes::default()),
+        ];
+
+        if self.hits.is_empty() && !self.query.is_empty() && self.results.is_none() {
+            changes.push(Change::Text(" No matches".to_string()));
+        }
+
+        for (row_num, (entry_idx, (_rank, hit))) in
+            self.hits.iter().enumerate().skip(self.top_row).enumerate()
+        {
+            if row_num > self.max_items {
+                break;
+            }
+
+            if entry_idx == self.active_idx {
+                changes.push(AttributeChange::Reverse(true).into());
+            }
+
+            let title = truncate_right(&hit.pane_title, TITLE_WIDTH);
+            let padding = TITLE_WIDTH.saturating_sub(unicode_column_width(&title, None));
+            changes.push(AttributeChange::Intensity(Intensity::Half).into());
+            changes.push(Change::Text(format!(" {title}{} ", " ".repeat(padding))));
+            changes.push(AttributeChange::Intensity(Intensity::Normal).into());
+
+            // Show as much context around the match as will fit
+            let width = max_width.saturating_sub(TITLE_WIDTH + 2);
+            let matched = truncate_right(&hit.matched, width);
+            let remain = width.saturating_sub(unicode_column_width(&matched, None));
+            let before = truncate_left(&hit.before, remain / 2);
+            let remain = remain.saturating_sub(unicode_column_width(&before, None));
+            let after = truncate_right(&hit.after, remain);
+            let remain = remain.saturating_sub(unicode_column_width(&after, None));
+
+            changes.push(Change::Text(before));
+            changes.push(AttributeChange::Foreground(AnsiColor::Yellow.into()).into());
+            changes.push(AttributeChange::Intensity(Intensity::Bold).into());
+            changes.push(Change::Text(matched));
+            changes.push(AttributeChange::Foreground(ColorAttribute::Default).into());
+            changes.push(AttributeChange::Intensity(Intensity::Normal).into());
+            changes.push(Change::Text(format!("{after}{}", " ".repeat(remain))));
+            changes.push(Change::AllAttributes(CellAttributes::default()));
+            changes.push(Change::Text("\r\n".to_string()));
+        }
+
+        term.render(&changes)
+    }
+
+    fn move_up(&mut self) {
+        self.active_idx = self.active_idx.saturating_sub(1);
+        if self.active_idx < self.top_row {
+            self.top_row = self.active_idx;
+        }
+    }
+
+    fn move_down(&mut self) {
+        self.active_idx = (self.active_idx + 1).min(self.hits.len().saturating_sub(1));
+        if self.active_idx > self.top_row + self.max_items {
+            self.top_row = self.active_idx.saturating_sub(self.max_items);
+        }
+    }
+
+    fn activate(&self, active_idx: usize) -> bool {
+        if let Some((_rank, hit)) = self.hits.get(active_idx) {
+            activate(hit.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn run_loop(&mut self, term: &mut TermWizTerminal) -> anyhow::Result<()> {
+        loop {
+            let event = match term.poll_input(Some(POLL_INTERVAL)) {
+                Ok(Some(event)) => event,
+                Ok(None) => {
+                    let searched = self.maybe_search();
+                    if self.receive_hits() || searched {
+                        self.render(term)?;
+                    }
+                    continue;
+                }
+                Err(_) => break,
+            };
+            match event {
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('G') | KeyCode::Char('['),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::Escape,
+                    ..
+                }) => {
+                    break;
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('P' | 'K'),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::UpArrow,
+                    ..
+                }) => {
+                    self.move_up();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('N' | 'J'),
+                    modifiers: Modifiers::CTRL,
+                })
+                | InputEvent::Key(KeyEvent {
+                    key: KeyCode::DownArrow,
+                    ..
+                }) => {
+                    self.move_down();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('R'),
+                    modifiers: Modifiers::CTRL,
+                }) => {
+                    self.cycle_match_type();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char('U'),
+                    modifiers: Modifiers::CTRL,
+                }) => {
+                    self.query.clear();
+                    self.query_changed();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Backspace,
+                    ..
+                }) => {
+                    self.query.pop();
+                    self.query_changed();
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Enter,
+                    ..
+                }) => {
+                    if self.activate(self.active_idx) {
+                        break;
+                    }
+                }
+                InputEvent::Key(KeyEvent {
+                    key: KeyCode::Char(c),
+                    modifiers: Modifiers::NONE | Modifiers::SHIFT,
+                }) => {
+                    self.query.push(c);
+                    self.query_changed();
+                }
+                InputEvent::Mouse(MouseEvent { mouse_buttons, .. })
+                    if mouse_buttons.contains(MouseButtons::VERT_WHEEL) =>
+                {
+                    if mouse_buttons.contains(MouseButtons::WHEEL_POSITIVE) {
+                        self.move_up();
+                    } else {
+                        self.move_down();
+                    }
+                }
+                InputEvent::Mouse(MouseEvent {
+                    y, mouse_buttons, ..
+                }) => {
+                    if y > 0 && y as usize <= self.hits.len() {
+                        self.active_idx = self.top_row + y as usize - 1;
+
+                        if mouse_buttons == MouseButtons::LEFT {
+                            if self.activate(self.active_idx) {
+                                break;
+                            }
+                        }
+                    }
+                    if mouse_buttons != MouseButtons::NONE {
+                        // Treat any other mouse button as cancel
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            self.render(term)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn scrollback_search(pattern: Pattern, mut term: TermWizTerminal) -> anyhow::Result<()> {
+    let mut state = ScrollbackSearchState {
+        active_idx: 0,
+        max_items: 0,
+        top_row: 0,
+        query: pattern.to_string(),
+        pattern_type: PatternType::from(&pattern),
+        hits: vec![],
+        typed_at: None,
+        results: None,
+    };
+
+    term.set_raw_mode()?;
+    term.render(&[Change::Title("Search All Panes".to_string())])?;
+    if !state.query.is_empty() {
+        // Search immediately for the initial query
+        state.typed_at = Some(Instant::now() - TYPING_DELAY);
+        state.maybe_search();
+    }
+    state.render(&mut term)?;
+    state.run_loop(&mut term)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ranking() {
+        // Recency breaks ties between equally good matches
+        assert!(rank(0, 10) > rank(0, 500));
+        assert!(rank(100, 0) > rank(100, 1));
+        // A much better match outranks a slightly more recent one
+        assert!(rank(200, 100) > rank(100, 0));
+        // but an old match decays away
+        assert!(rank(200, 10_000) < rank(100, 0));
+        assert_eq!(rank(0, 0), 1.);
+        assert_eq!(rank(9, RECENCY_HALF_LIFE_ROWS as StableRowIndex), 5.);
+    }
+}
@@ -9,6 +9,7 @@ use nucleo_matcher::{Matcher, Utf32Str};
 use rayon::prelude::*;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Arc;
 use termwiz::cell::{AttributeChange, CellAttributes};
 use termwiz::color::ColorAttribute;
 use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
@@ -37,6 +38,15 @@ pub fn matcher_pattern(s: &str) -> Pattern {
     )
 }
 
+/// What to do with the chosen entry, which is None if the
+/// selector was cancelled
+enum OnSelect {
+    /// Emit the event that was registered by wezterm.action_callback
+    Event(String),
+    /// Call a function on the main thread
+    Callback(Arc<dyn Fn(Option<InputSelectorEntry>) + Send + Sync>),
+}
+
 struct SelectorState {
     active_idx: usize,
     max_items: usize,
@@ -48,7 +58,7 @@ struct SelectorState {
     filtering: bool,
     always_fuzzy: bool,
     args: InputSelector,
-    event_name: String,
+    on_select: OnSelect,
     selection: String,
     labels: Vec<String>,
 }
@@ -205,15 +215,27 @@ impl SelectorState {
     }
 
     fn trigger_event(&self, entry: Option<InputSelectorEntry>) {
-        let name = self.event_name.clone();
-        let window = self.window.clone();
-        let pane = self.pane.clone();
-
-        promise::spawn::spawn_into_main_thread(async move {
-            trampoline(name, window, pane, entry);
-            anyhow::Result::<()>::Ok(())
-        })
-        .detach();
+        match &self.on_select {
+            OnSelect::Event(name) => {
+                let name = name.clone();
+                let window = self.window.clone();
+                let pane = self.pane.clone();
+
+                promise::spawn::spawn_into_main_thread(async move {
+                    trampoline(name, window, pane, entry);
+                    anyhow::Result::<()>::Ok(())
+                })
+                .detach();
+            }
+            OnSelect::Callback(callback) => {
+                let callback = Arc::clone(callback);
+                promise::spawn::spawn_into_main_thread(async move {
+                    callback(entry);
+                    anyhow::Result::<()>::Ok(())
+                })
+                .detach();
+            }
+        }
     }
 
     fn launch(&self, active_idx: usize) -> bool {
@@ -413,7 +435,7 @@ async fn do_event(
 }
 
 pub fn selector(
-    mut term: TermWizTerminal,
+    term: TermWizTerminal,
     args: InputSelector,
     window: GuiWin,
     pane: MuxPane,
@@ -424,6 +446,37 @@ pub fn selector(
             anyhow::bail!("InputSelector requires action to be defined by wezterm.action_callback")
         }
     };
+    run_selector(term, args, window, pane, OnSelect::Event(event_name))
+}
+
+/// Like `selector`, but calls `on_select` on the main thread rather than
+/// emitting the event for `args.action`, which is ignored
+pub fn selector_with_callback<F>(
+    term: TermWizTerminal,
+    args: InputSelector,
+    window: GuiWin,
+    pane: MuxPane,
+    on_select: F,
+) -> anyhow::Result<()>
+where
+    F: Fn(Option<InputSelectorEntry>) + Send + Sync + 'static,
+{
+    run_selector(
+        term,
+        args,
+        window,
+        pane,
+        OnSelect::Callback(Arc::new(on_select)),
+    )
+}
+
+fn run_selector(
+    mut term: TermWizTerminal,
+    args: InputSelector,
+    window: GuiWin,
+    pane: MuxPane,
+    on_select: OnSelect,
+) -> anyhow::Result<()> {
     let mut state = SelectorState {
         active_idx: 0,
         max_items: 0,
@@ -435,7 +488,7 @@ pub fn selector(
         filtering: args.fuzzy,
         always_fuzzy: args.fuzzy,
         args,
-        event_name,
+        on_select,
         selection: String::new(),
         labels: vec![],
     };
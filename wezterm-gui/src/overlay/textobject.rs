@@ -0,0 +1,524 @@
+//! Resolves vim-style text objects and bracket matching against a
+//! snapshot of the lines around the copy mode cursor
+use config::keyassignment::TextObject;
+use wezterm_term::{Line, StableRowIndex};
+
+/// A position in the scrollback, ordered by row and then by column
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub y: StableRowIndex,
+    pub x: usize,
+}
+
+impl Position {
+    pub fn new(x: usize, y: StableRowIndex) -> Self {
+        Self { x, y }
+    }
+}
+
+/// The extent of a text object.  Both ends are inclusive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextRange {
+    pub start: Position,
+    pub end: Position,
+    /// The object is made up of whole lines, and should be
+    /// selected a line at a time
+    pub linewise: bool,
+}
+
+impl TextRange {
+    fn new(start: Position, end: Position) -> Self {
+        Self {
+            start,
+            end,
+            linewise: false,
+        }
+    }
+}
+
+/// A cell and the first character of its text
+#[derive(Copy, Clone, Debug)]
+struct CellChar {
+    pos: Position,
+    c: char,
+}
+
+/// The text of a range of lines, indexed by cell
+pub struct LineBuffer {
+    first_row: StableRowIndex,
+    rows: Vec<Vec<CellChar>>,
+}
+
+impl LineBuffer {
+    pub fn new(first_row: StableRowIndex, lines: &[Line]) -> Self {
+        let rows = lines
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| {
+                let y = first_row + idx as StableRowIndex;
+                line.visible_cells()
+                    .map(|cell| CellChar {
+                        pos: Position::new(cell.cell_index(), y),
+                        c: cell.str().chars().next().unwrap_or(' '),
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { first_row, rows }
+    }
+
+    fn row(&self, y: StableRowIndex) -> Option<&[CellChar]> {
+        let idx = y.checked_sub(self.first_row)?;
+        if idx < 0 {
+            return None;
+        }
+        self.rows.get(idx as usize).map(|row| row.as_slice())
+    }
+
+    /// Returns all of the cells, in order
+    fn flatten(&self) -> Vec<CellChar> {
+        self.rows.iter().flatten().copied().collect()
+    }
+
+    /// Returns the text object around the cursor
+    pub fn text_object(
+        &self,
+        cursor: Position,
+        object: TextObject,
+        around: bool,
+    ) -> Option<TextRange> {
+        match object {
+            TextObject::Word => self.word(cursor, false, around),
+            TextObject::BigWord => self.word(cursor, true, around),
+            TextObject::Quoted(quote) => self.quoted(cursor, quote, around),
+            TextObject::Bracketed(open) => self.bracketed(cursor, open, around),
+            TextObject::Paragraph => self.paragraph(cursor, around),
+        }
+    }
+
+    fn word(&self, cursor: Position, big: bool, around: bool) -> Option<TextRange> {
+        let row = self.row(cursor.y)?;
+        let idx = index_of(row, cursor)?;
+        let class = |cell: &CellChar| char_class(cell.c, big);
+
+        let extend = |start: usize, end: usize| {
+            let target = class(&row[start]);
+            let mut start = start;
+            let mut end = end;
+            while start > 0 && class(&row[start - 1]) == target {
+                start -= 1;
+            }
+            while end + 1 < row.len() && class(&row[end + 1]) == target {
+                end += 1;
+            }
+            (start, end)
+        };
+
+        let (mut start, mut end) = extend(idx, idx);
+        if around {
+            if class(&row[idx]) == CharClass::Whitespace {
+                // The whitespace and the word that follows it
+                if end + 1 < row.len() {
+                    end = extend(end + 1, end + 1).1;
+                }
+            } else if end + 1 < row.len() && class(&row[end + 1]) == CharClass::Whitespace {
+                // The word and the whitespace that follows it
+                end = extend(end + 1, end + 1).1;
+            } else if start > 0 && class(&row[start - 1]) == CharClass::Whitespace {
+                // Nothing follows, so take the whitespace before it
+                start = extend(start - 1, start - 1).0;
+            }
+        }
+
+        Some(TextRange::new(row[start].pos, row[end].pos))
+    }
+
+    fn quoted(&self, cursor: Position, quote: char, around: bool) -> Option<TextRange> {
+        let row = self.row(cursor.y)?;
+        let idx = index_of(row, cursor)?;
+
+        let quotes: Vec<usize> = row
+            .iter()
+            .enumerate()
+            .filter(|(i, cell)| cell.c == quote && (*i == 0 || row[i - 1].c != '\\'))
+            .map(|(i, _)| i)
+            .collect();
+        let pairs: Vec<(usize, usize)> = quotes
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+
+        // Prefer the pair that contains the cursor, otherwise the
+        // first pair that follows it
+        let (open, close) = pairs
+            .iter()
+            .find(|(open, close)| *open <= idx && idx <= *close)
+            .or_else(|| pairs.iter().find(|(open, _)| *open > idx))
+            .copied()?;
+
+        if around {
+            let mut end = close;
+            while end + 1 < row.len() && row[end + 1].c.is_whitespace() {
+                end += 1;
+            }
+            let mut start = open;
+            if end == close {
+                while start > 0 && row[start - 1].c.is_whitespace() {
+                    start -= 1;
+                }
+            }
+            Some(TextRange::new(row[start].pos, row[end].pos))
+        } else if close > open + 1 {
+            Some(TextRange::new(row[open + 1].pos, row[close - 1].pos))
+        } else {
+            None
+        }
+    }
+
+    fn bracketed(&self, cursor: Position, open: char, around: bool) -> Option<TextRange> {
+        let close = closing_bracket(open)?;
+        let cells = self.flatten();
+        let idx = cells.iter().rposition(|cell| cell.pos <= cursor)?;
+
+        // On a closing bracket, this finds the bracket that it closes
+        let open_idx = if cells[idx].c == open {
+            idx
+        } else {
+            find_match(&cells, idx, close, open, false)?
+        };
+        let close_idx = find_match(&cells, open_idx, open, close, true)?;
+
+        if around {
+            return Some(TextRange::new(cells[open_idx].pos, cells[close_idx].pos));
+        }
+
+        // If the brackets are on lines of their own, then the object
+        // starts on the line after the opening bracket and ends with
+        // the last non-blank cell before the line of the closing one
+        let open_row = cells[open_idx].pos.y;
+        let close_row = cells[close_idx].pos.y;
+        let mut start = open_idx + 1;
+        while start < close_idx && cells[start].pos.y == open_row && cells[start].c.is_whitespace()
+        {
+            start += 1;
+        }
+        if start < close_idx && cells[start].pos.y == open_row {
+            start = open_idx + 1;
+        }
+
+        let mut end = close_idx.checked_sub(1)?;
+        while end > open_idx && cells[end].pos.y == close_row && cells[end].c.is_whitespace() {
+            end -= 1;
+        }
+        if end > open_idx && cells[end].pos.y == close_row {
+            end = close_idx - 1;
+        } else {
+            while end > open_idx && cells[end].c.is_whitespace() {
+                end -= 1;
+            }
+        }
+
+        if start > end || end <= open_idx {
+            return None;
+        }
+        Some(TextRange::new(cells[start].pos, cells[end].pos))
+    }
+
+    fn paragraph(&self, cursor: Position, around: bool) -> Option<TextRange> {
+        let is_blank = |y: StableRowIndex| {
+            self.row(y)
+                .map(|row| row.iter().all(|cell| cell.c.is_whitespace()))
+        };
+        let extend = |y: StableRowIndex| {
+            let blank = is_blank(y);
+            let mut start = y;
+            let mut end = y;
+            while is_blank(start - 1).is_some() && is_blank(start - 1) == blank {
+                start -= 1;
+            }
+            while is_blank(end + 1).is_some() && is_blank(end + 1) == blank {
+                end += 1;
+            }
+            (start, end)
+        };
+
+        is_blank(cursor.y)?;
+        let (mut start, mut end) = extend(cursor.y);
+        if around {
+            if is_blank(end + 1).is_some() {
+                end = extend(end + 1).1;
+            } else if is_blank(start - 1).is_some() {
+                start = extend(start - 1).0;
+            }
+        }
+
+        let last_x = self
+            .row(end)
+            .and_then(|row| row.last())
+            .map(|cell| cell.pos.x)
+            .unwrap_or(0);
+        Some(TextRange {
+            start: Position::new(0, start),
+            end: Position::new(last_x, end),
+            linewise: true,
+        })
+    }
+
+    /// Implements vim's `%`: finds the first bracket at or after the
+    /// cursor on its line, and returns the position of its match
+    pub fn matching_bracket(&self, cursor: Position) -> Option<Position> {
+        let cells = self.flatten();
+        let idx = cells.iter().rposition(|cell| cell.pos <= cursor)?;
+        let (bracket_idx, bracket) = cells[idx..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, cell)| cell.pos.y == cursor.y)
+            .find(|(_, cell)| matches!(cell.c, '(' | ')' | '[' | ']' | '{' | '}'))
+            .map(|(i, cell)| (idx + i, cell.c))?;
+
+        let matched = match closing_bracket(bracket) {
+            Some(close) => find_match(&cells, bracket_idx, bracket, close, true)?,
+            None => {
+                let open = opening_bracket(bracket)?;
+                find_match(&cells, bracket_idx, bracket, open, false)?
+            }
+        };
+        Some(cells[matched].pos)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+/// Classifies characters in the same way as vim: a word is made of
+/// alphanumerics and underscores, or of other non-blank characters,
+/// while a WORD is made of any non-blank characters
+fn char_class(c: char, big: bool) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Whitespace
+    } else if big || c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// Returns the index of the cell that contains the cursor
+fn index_of(row: &[CellChar], cursor: Position) -> Option<usize> {
+    row.iter().rposition(|cell| cell.pos.x <= cursor.x)
+}
+
+fn closing_bracket(open: char) -> Option<char> {
+    match open {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        '<' => Some('>'),
+        _ => None,
+    }
+}
+
+fn opening_bracket(close: char) -> Option<char> {
+    match close {
+        ')' => Some('('),
+        ']' => Some('['),
+        '}' => Some('{'),
+        '>' => Some('<'),
+        _ => None,
+    }
+}
+
+/// Starting after (or before, when searching backwards) the cell at
+/// `idx`, finds the `target` bracket that balances the nesting of
+/// `nested` brackets
+fn find_match(
+    cells: &[CellChar],
+    idx: usize,
+    nested: char,
+    target: char,
+    forward: bool,
+) -> Option<usize> {
+    let mut depth = 0;
+    let candidates: Box<dyn Iterator<Item = usize>> = if forward {
+        Box::new(idx + 1..cells.len())
+    } else {
+        Box::new((0..idx).rev())
+    };
+    for i in candidates {
+        let c = cells[i].c;
+        if c == nested {
+            depth += 1;
+        } else if c == target {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::cell::CellAttributes;
+    use termwiz::surface::SEQ_ZERO;
+
+    const FIRST_ROW: StableRowIndex = 100;
+
+    fn buffer(text: &[&str]) -> LineBuffer {
+        let lines: Vec<Line> = text
+            .iter()
+            .map(|s| Line::from_text(s, &CellAttributes::default(), SEQ_ZERO, None))
+            .collect();
+        LineBuffer::new(FIRST_ROW, &lines)
+    }
+
+    fn pos(x: usize, row: StableRowIndex) -> Position {
+        Position::new(x, FIRST_ROW + row)
+    }
+
+    /// Returns the text covered by a range on a single line
+    fn text_of(text: &[&str], range: TextRange) -> String {
+        assert_eq!(range.start.y, range.end.y);
+        let line = text[(range.start.y - FIRST_ROW) as usize];
+        line[range.start.x..=range.end.x].to_string()
+    }
+
+    #[test]
+    fn words() {
+        let text = ["  let foo_bar=baz.qux()  "];
+        let buf = buffer(&text);
+        let word = |x, object, around| {
+            buf.text_object(pos(x, 0), object, around)
+                .map(|range| text_of(&text, range))
+        };
+
+        assert_eq!(word(7, TextObject::Word, false).unwrap(), "foo_bar");
+        // No whitespace follows, so the whitespace before it is taken
+        assert_eq!(word(7, TextObject::Word, true).unwrap(), " foo_bar");
+        assert_eq!(word(2, TextObject::Word, true).unwrap(), "let ");
+        assert_eq!(word(13, TextObject::Word, false).unwrap(), "=");
+        assert_eq!(
+            word(7, TextObject::BigWord, false).unwrap(),
+            "foo_bar=baz.qux()"
+        );
+        assert_eq!(
+            word(7, TextObject::BigWord, true).unwrap(),
+            "foo_bar=baz.qux()  "
+        );
+        // On whitespace, the inner object is the whitespace
+        assert_eq!(word(0, TextObject::Word, false).unwrap(), "  ");
+        assert_eq!(word(0, TextObject::Word, true).unwrap(), "  let");
+
+        let text = ["one two"];
+        let buf = buffer(&text);
+        let range = buf.text_object(pos(5, 0), TextObject::Word, true).unwrap();
+        assert_eq!(text_of(&text, range), " two");
+    }
+
+    #[test]
+    fn quotes() {
+        let text = [r#"echo "hello \"world\"" 'x' done"#];
+        let buf = buffer(&text);
+        let quoted = |x, quote, around| {
+            buf.text_object(pos(x, 0), TextObject::Quoted(quote), around)
+                .map(|range| text_of(&text, range))
+        };
+
+        assert_eq!(quoted(8, '"', false).unwrap(), r#"hello \"world\""#);
+        assert_eq!(quoted(8, '"', true).unwrap(), r#""hello \"world\"" "#);
+        // Before any quotes, we find the next pair
+        assert_eq!(quoted(0, '\'', false).unwrap(), "x");
+        assert_eq!(quoted(0, '\'', true).unwrap(), "'x' ");
+        // After all of the quotes, there's nothing to find
+        assert_eq!(quoted(29, '\'', false), None);
+
+        let text = [r#"a "" b"#];
+        let buf = buffer(&text);
+        assert_eq!(
+            buf.text_object(pos(2, 0), TextObject::Quoted('"'), false),
+            None
+        );
+    }
+
+    #[test]
+    fn brackets() {
+        let text = ["call(a, (b + c), d)"];
+        let buf = buffer(&text);
+        let bracketed = |x, around| {
+            buf.text_object(pos(x, 0), TextObject::Bracketed('('), around)
+                .map(|range| text_of(&text, range))
+        };
+        assert_eq!(bracketed(5, false).unwrap(), "a, (b + c), d");
+        assert_eq!(bracketed(10, false).unwrap(), "b + c");
+        assert_eq!(bracketed(10, true).unwrap(), "(b + c)");
+        // On a bracket, the object is the one that it delimits
+        assert_eq!(bracketed(8, false).unwrap(), "b + c");
+        assert_eq!(bracketed(14, false).unwrap(), "b + c");
+        assert_eq!(bracketed(18, true).unwrap(), "(a, (b + c), d)");
+        assert_eq!(bracketed(0, false), None);
+    }
+
+    #[test]
+    fn multi_line_brackets() {
+        let buf = buffer(&["fn main() {", "    body();", "    more();", "}"]);
+        let range = buf
+            .text_object(pos(6, 1), TextObject::Bracketed('{'), false)
+            .unwrap();
+        assert_eq!(range.start, pos(0, 1));
+        assert_eq!(range.end, pos(10, 2));
+
+        let range = buf
+            .text_object(pos(6, 1), TextObject::Bracketed('{'), true)
+            .unwrap();
+        assert_eq!(range.start, pos(10, 0));
+        assert_eq!(range.end, pos(0, 3));
+    }
+
+    #[test]
+    fn paragraphs() {
+        let buf = buffer(&["one", "two", "", "  ", "three", "four", "five"]);
+
+        let range = buf
+            .text_object(pos(1, 1), TextObject::Paragraph, false)
+            .unwrap();
+        assert!(range.linewise);
+        assert_eq!((range.start.y, range.end.y), (FIRST_ROW, FIRST_ROW + 1));
+
+        let range = buf
+            .text_object(pos(1, 1), TextObject::Paragraph, true)
+            .unwrap();
+        assert_eq!((range.start.y, range.end.y), (FIRST_ROW, FIRST_ROW + 3));
+
+        // Nothing follows the last paragraph, so the blank lines that
+        // precede it are included
+        let range = buf
+            .text_object(pos(0, 5), TextObject::Paragraph, true)
+            .unwrap();
+        assert_eq!((range.start.y, range.end.y), (FIRST_ROW + 2, FIRST_ROW + 6));
+
+        let range = buf
+            .text_object(pos(0, 2), TextObject::Paragraph, false)
+            .unwrap();
+        assert_eq!((range.start.y, range.end.y), (FIRST_ROW + 2, FIRST_ROW + 3));
+    }
+
+    #[test]
+    fn matching_bracket() {
+        let buf = buffer(&["if (a[1]) {", "  x = {};", "}"]);
+        // From before the first bracket on the line
+        assert_eq!(buf.matching_bracket(pos(0, 0)), Some(pos(8, 0)));
+        assert_eq!(buf.matching_bracket(pos(8, 0)), Some(pos(3, 0)));
+        assert_eq!(buf.matching_bracket(pos(5, 0)), Some(pos(7, 0)));
+        assert_eq!(buf.matching_bracket(pos(10, 0)), Some(pos(0, 2)));
+        assert_eq!(buf.matching_bracket(pos(0, 2)), Some(pos(10, 0)));
+        assert_eq!(buf.matching_bracket(pos(6, 1)), Some(pos(7, 1)));
+        // No bracket after the cursor on this line
+        assert_eq!(buf.matching_bracket(pos(9, 1)), None);
+    }
+}
@@ -0,0 +1,253 @@
+//! A small parser for the vim-style key sequences that copy mode
+//! accepts in addition to its key table: numeric counts, and the
+//! multi-key commands for marks, registers and text objects.
+//! Keys that are bound in the `copy_mode` key table are resolved
+//! by the key table; this parser sees the keys that are not.
+use config::keyassignment::{CopyModeAssignment, TextObject};
+
+/// The first key of a command that needs a second key to name
+/// its argument
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Prefix {
+    /// `m`
+    SetMark,
+    /// `'` or `` ` ``
+    JumpToMark { exact: bool },
+    /// `"`
+    Register,
+    /// `i` or `a`
+    TextObject { around: bool },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GrammarResult {
+    /// The key was consumed, and more keys are needed
+    Pending,
+    /// The key completed a command
+    Resolved(CopyModeAssignment),
+    /// The key isn't valid in the current state.  Any pending
+    /// state has been discarded.
+    Invalid,
+}
+
+#[derive(Default, Debug)]
+pub struct KeyGrammar {
+    count: Option<usize>,
+    prefix: Option<Prefix>,
+}
+
+/// The largest count that we'll accept; vim has a similar limit,
+/// and it prevents an accidental "999999999j" from taking forever
+const MAX_COUNT: usize = 99_999;
+
+impl KeyGrammar {
+    /// Returns true if the grammar is part way through a multi-key
+    /// command, in which case the next key belongs to it, even if
+    /// it is bound in the key table
+    pub fn has_prefix(&self) -> bool {
+        self.prefix.is_some()
+    }
+
+    /// Returns true if a count has been entered and not yet consumed
+    pub fn has_count(&self) -> bool {
+        self.count.is_some()
+    }
+
+    /// Returns the count for the next command, if one was entered,
+    /// and resets it
+    pub fn take_count(&mut self) -> Option<usize> {
+        self.count.take()
+    }
+
+    /// Discard any partially entered command
+    pub fn reset(&mut self) {
+        self.count.take();
+        self.prefix.take();
+    }
+
+    /// Process the next key
+    pub fn feed(&mut self, c: char) -> GrammarResult {
+        if let Some(prefix) = self.prefix.take() {
+            return match Self::complete(prefix, c) {
+                Some(assignment) => GrammarResult::Resolved(assignment),
+                None => {
+                    self.count.take();
+                    GrammarResult::Invalid
+                }
+            };
+        }
+
+        match c {
+            // A leading zero is a motion rather than a count, but that
+            // motion is bound in the key table, so we only see zero
+            // here when it continues a count
+            '0'..='9' if c != '0' || self.count.is_some() => {
+                let digit = c as usize - '0' as usize;
+                let count = self.count.unwrap_or(0);
+                self.count.replace((count * 10 + digit).min(MAX_COUNT));
+                GrammarResult::Pending
+            }
+            '%' => GrammarResult::Resolved(CopyModeAssignment::MoveToMatchingBracket),
+            'm' => self.begin(Prefix::SetMark),
+            '\'' => self.begin(Prefix::JumpToMark { exact: false }),
+            '`' => self.begin(Prefix::JumpToMark { exact: true }),
+            '"' => self.begin(Prefix::Register),
+            'i' => self.begin(Prefix::TextObject { around: false }),
+            'a' => self.begin(Prefix::TextObject { around: true }),
+            _ => {
+                self.reset();
+                GrammarResult::Invalid
+            }
+        }
+    }
+
+    fn begin(&mut self, prefix: Prefix) -> GrammarResult {
+        self.prefix.replace(prefix);
+        GrammarResult::Pending
+    }
+
+    fn complete(prefix: Prefix, c: char) -> Option<CopyModeAssignment> {
+        match prefix {
+            Prefix::SetMark if is_mark_name(c) => Some(CopyModeAssignment::SetMark(c)),
+            Prefix::JumpToMark { exact } if is_mark_name(c) || c == '\'' || c == '`' => {
+                Some(CopyModeAssignment::JumpToMark { mark: c, exact })
+            }
+            Prefix::Register if is_register_name(c) => Some(CopyModeAssignment::SelectRegister(c)),
+            Prefix::TextObject { around } => {
+                let object = match c {
+                    'w' => TextObject::Word,
+                    'W' => TextObject::BigWord,
+                    'p' => TextObject::Paragraph,
+                    '"' | '\'' | '`' => TextObject::Quoted(c),
+                    '(' | ')' | 'b' => TextObject::Bracketed('('),
+                    '[' | ']' => TextObject::Bracketed('['),
+                    '{' | '}' | 'B' => TextObject::Bracketed('{'),
+                    '<' | '>' => TextObject::Bracketed('<'),
+                    _ => return None,
+                };
+                Some(CopyModeAssignment::SelectTextObject { object, around })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_mark_name(c: char) -> bool {
+    c.is_ascii_alphabetic()
+}
+
+/// Named registers are letters (uppercase appends to the lowercase
+/// register) and digits, along with `"` for the unnamed register and
+/// `+` and `*` for the clipboard and primary selection
+pub fn is_register_name(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '"' | '+' | '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn feed_all(grammar: &mut KeyGrammar, keys: &str) -> Vec<GrammarResult> {
+        keys.chars().map(|c| grammar.feed(c)).collect()
+    }
+
+    #[test]
+    fn counts() {
+        let mut grammar = KeyGrammar::default();
+        assert_eq!(grammar.take_count(), None);
+
+        feed_all(&mut grammar, "5");
+        assert!(grammar.has_count());
+        assert_eq!(grammar.take_count(), Some(5));
+        assert!(!grammar.has_count());
+
+        feed_all(&mut grammar, "120");
+        assert_eq!(grammar.take_count(), Some(120));
+
+        // A leading zero isn't a count
+        assert_eq!(grammar.feed('0'), GrammarResult::Invalid);
+        assert!(!grammar.has_count());
+
+        feed_all(&mut grammar, "99999999999");
+        assert_eq!(grammar.take_count(), Some(MAX_COUNT));
+    }
+
+    #[test]
+    fn marks_and_registers() {
+        let mut grammar = KeyGrammar::default();
+        assert_eq!(grammar.feed('m'), GrammarResult::Pending);
+        assert!(grammar.has_prefix());
+        assert_eq!(
+            grammar.feed('a'),
+            GrammarResult::Resolved(CopyModeAssignment::SetMark('a'))
+        );
+        assert!(!grammar.has_prefix());
+
+        assert_eq!(
+            feed_all(&mut grammar, "'a").pop().unwrap(),
+            GrammarResult::Resolved(CopyModeAssignment::JumpToMark {
+                mark: 'a',
+                exact: false
+            })
+        );
+        assert_eq!(
+            feed_all(&mut grammar, "``").pop().unwrap(),
+            GrammarResult::Resolved(CopyModeAssignment::JumpToMark {
+                mark: '`',
+                exact: true
+            })
+        );
+        assert_eq!(
+            feed_all(&mut grammar, "\"A").pop().unwrap(),
+            GrammarResult::Resolved(CopyModeAssignment::SelectRegister('A'))
+        );
+
+        // Not a valid mark name; the count is discarded too
+        feed_all(&mut grammar, "3m");
+        assert_eq!(grammar.feed('!'), GrammarResult::Invalid);
+        assert!(!grammar.has_prefix());
+        assert!(!grammar.has_count());
+    }
+
+    #[test]
+    fn text_objects() {
+        let cases = [
+            ("iw", TextObject::Word, false),
+            ("aW", TextObject::BigWord, true),
+            ("i\"", TextObject::Quoted('"'), false),
+            ("a'", TextObject::Quoted('\''), true),
+            ("i(", TextObject::Bracketed('('), false),
+            ("ib", TextObject::Bracketed('('), false),
+            ("a)", TextObject::Bracketed('('), true),
+            ("i}", TextObject::Bracketed('{'), false),
+            ("a]", TextObject::Bracketed('['), true),
+            ("i>", TextObject::Bracketed('<'), false),
+            ("ip", TextObject::Paragraph, false),
+        ];
+        for (keys, object, around) in cases {
+            let mut grammar = KeyGrammar::default();
+            assert_eq!(
+                feed_all(&mut grammar, keys),
+                vec![
+                    GrammarResult::Pending,
+                    GrammarResult::Resolved(CopyModeAssignment::SelectTextObject {
+                        object,
+                        around
+                    })
+                ],
+                "{}",
+                keys
+            );
+        }
+
+        let mut grammar = KeyGrammar::default();
+        assert_eq!(
+            feed_all(&mut grammar, "iq"),
+            vec![GrammarResult::Pending, GrammarResult::Invalid]
+        );
+        assert_eq!(
+            grammar.feed('%'),
+            GrammarResult::Resolved(CopyModeAssignment::MoveToMatchingBracket)
+        );
+    }
+}
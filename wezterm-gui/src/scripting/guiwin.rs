@@ -88,7 +88,8 @@ impl UserData for GuiWin {
                     title,
                     message,
                     url,
-                    timeout: timeout.map(std::time::Duration::from_millis)
+                    timeout: timeout.map(std::time::Duration::from_millis),
+                    ..Default::default()
                 });
                 Ok(())
             },
@@ -170,6 +171,22 @@ impl UserData for GuiWin {
                 result.map_err(mlua::Error::external)
             },
         );
+        methods.add_async_method(
+            "screenshot",
+            |_, this, (path, pane): (String, Option<UserDataRef<MuxPane>>)| async move {
+                let pane_id = pane.map(|pane| pane.0);
+                let (tx, rx) = smol::channel::bounded(1);
+                this.window
+                    .notify(TermWindowNotif::Apply(Box::new(move |term_window| {
+                        let result = term_window
+                            .screenshot(pane_id)
+                            .and_then(|image| Ok(image.save(&path)?));
+                        tx.try_send(result).ok();
+                    })));
+                let result = rx.recv().await.map_err(mlua::Error::external)?;
+                result.map_err(luaerr)
+            },
+        );
         methods.add_async_method("effective_config", |_, this, _: ()| async move {
             let (tx, rx) = smol::channel::bounded(1);
             this.window.notify(TermWindowNotif::GetEffectiveConfig(tx));
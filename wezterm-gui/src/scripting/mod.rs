@@ -78,6 +78,11 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    window_mod.set(
+        "add_command_palette_provider",
+        lua.create_function(crate::termwindow::palette::add_command_palette_provider)?,
+    )?;
+
     window_mod.set(
         "enumerate_gpus",
         lua.create_function(|_, _: ()| {
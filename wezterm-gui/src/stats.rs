@@ -5,7 +5,7 @@ use hdrhistogram::Histogram;
 use metrics::{Counter, Gauge, Key, KeyName, Metadata, Recorder, SharedString, Unit};
 use parking_lot::Mutex;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tabout::{tabulate_output, Alignment, Column};
@@ -136,10 +136,44 @@ impl metrics::CounterFn for MyCounter {
     }
 }
 
+/// Holds the bits of an f64 value
+struct MyGauge {
+    value: AtomicU64,
+}
+
+impl MyGauge {
+    fn get(&self) -> f64 {
+        f64::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    fn update(&self, f: impl Fn(f64) -> f64) {
+        self.value
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some(f(f64::from_bits(bits)).to_bits())
+            })
+            .ok();
+    }
+}
+
+impl metrics::GaugeFn for MyGauge {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.value.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
 struct Inner {
     histograms: HashMap<Key, Arc<ScaledHistogram>>,
     throughput: HashMap<Key, Arc<Throughput>>,
     counters: HashMap<Key, Arc<MyCounter>>,
+    gauges: HashMap<Key, Arc<MyGauge>>,
 }
 
 impl Inner {
@@ -258,6 +292,9 @@ impl Inner {
                         count.value.load(Ordering::Relaxed).to_string(),
                     ]);
                 }
+                for (key, gauge) in &inner.gauges {
+                    data.push(vec![key.to_string(), gauge.get().to_string()]);
+                }
                 data.sort_by(|a, b| a[0].cmp(&b[0]));
                 eprintln!();
                 tabulate_output(&count_cols, &data, &mut std::io::stderr().lock()).ok();
@@ -273,6 +310,7 @@ fn make_inner() -> Arc<Mutex<Inner>> {
         histograms: HashMap::new(),
         throughput: HashMap::new(),
         counters: HashMap::new(),
+        gauges: HashMap::new(),
     }))
 }
 
@@ -317,8 +355,18 @@ impl Recorder for Stats {
         }
     }
 
-    fn register_gauge(&self, _key: &Key, _metadata: &Metadata) -> Gauge {
-        Gauge::noop()
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata) -> Gauge {
+        let mut inner = self.inner.lock();
+        match inner.gauges.get(key) {
+            Some(existing) => Gauge::from_arc(existing.clone()),
+            None => {
+                let gauge = Arc::new(MyGauge {
+                    value: AtomicU64::new(0f64.to_bits()),
+                });
+                inner.gauges.insert(key.clone(), gauge.clone());
+                Gauge::from_arc(gauge)
+            }
+        }
     }
 
     fn register_histogram(&self, key: &Key, _metadata: &Metadata) -> metrics::Histogram {
@@ -368,6 +416,18 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
             Ok(counters)
         })?,
     )?;
+    metrics_mod.set(
+        "get_gauges",
+        lua.create_function(|_, _: ()| {
+            let inner = INNER.lock();
+            let gauges: HashMap<String, f64> = inner
+                .gauges
+                .iter()
+                .map(|(k, v)| (k.name().to_string(), v.get()))
+                .collect();
+            Ok(gauges)
+        })?,
+    )?;
     metrics_mod.set(
         "get_throughput",
         lua.create_function(|_, _: ()| {
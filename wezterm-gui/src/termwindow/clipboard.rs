@@ -1,20 +1,59 @@
+use crate::overlay::{confirm_clipboard_read, start_overlay_pane};
 use crate::termwindow::TermWindowNotif;
 use crate::TermWindow;
 use config::keyassignment::{ClipboardCopyDestination, ClipboardPasteSource};
-use mux::pane::Pane;
+use config::ClipboardReadPolicy;
+use mux::pane::{Pane, PaneId};
 use mux::Mux;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use wezterm_term::ClipboardSelection;
 use window::{Clipboard, WindowOps};
 
+lazy_static::lazy_static! {
+    static ref REGISTERS: Mutex<HashMap<char, String>> = Mutex::new(HashMap::new());
+}
+
+/// Stores text in a named register.  As in vim, an uppercase name
+/// appends to the register of the corresponding lowercase name, and
+/// the unnamed register `"` holds the most recently stored text.
+fn store_register(name: char, text: String) {
+    let mut registers = REGISTERS.lock();
+    let text = if name.is_ascii_uppercase() {
+        let register = registers.entry(name.to_ascii_lowercase()).or_default();
+        register.push_str(&text);
+        register.clone()
+    } else {
+        registers.insert(name, text.clone());
+        text
+    };
+    registers.insert('"', text);
+}
+
+fn register_text(name: char) -> Option<String> {
+    REGISTERS.lock().get(&name.to_ascii_lowercase()).cloned()
+}
+
 impl TermWindow {
     pub fn copy_to_clipboard(&self, clipboard: ClipboardCopyDestination, text: String) {
         let clipboard = match clipboard {
-            ClipboardCopyDestination::Clipboard => [Some(Clipboard::Clipboard), None],
-            ClipboardCopyDestination::PrimarySelection => [Some(Clipboard::PrimarySelection), None],
+            // The `+` and `*` registers are the clipboard and primary
+            // selection, as they are in vim
+            ClipboardCopyDestination::Clipboard | ClipboardCopyDestination::Register('+') => {
+                [Some(Clipboard::Clipboard), None]
+            }
+            ClipboardCopyDestination::PrimarySelection
+            | ClipboardCopyDestination::Register('*') => [Some(Clipboard::PrimarySelection), None],
             ClipboardCopyDestination::ClipboardAndPrimarySelection => [
                 Some(Clipboard::Clipboard),
                 Some(Clipboard::PrimarySelection),
             ],
+            ClipboardCopyDestination::Register(name) => {
+                store_register(name, text);
+                return;
+            }
         };
         for &c in &clipboard {
             if let Some(c) = c {
@@ -32,8 +71,25 @@ impl TermWindow {
         );
         let window = self.window.as_ref().unwrap().clone();
         let clipboard = match clipboard {
-            ClipboardPasteSource::Clipboard => Clipboard::Clipboard,
-            ClipboardPasteSource::PrimarySelection => Clipboard::PrimarySelection,
+            ClipboardPasteSource::Clipboard | ClipboardPasteSource::Register('+') => {
+                Clipboard::Clipboard
+            }
+            ClipboardPasteSource::PrimarySelection | ClipboardPasteSource::Register('*') => {
+                Clipboard::PrimarySelection
+            }
+            ClipboardPasteSource::Register(name) => {
+                if let Some(text) = register_text(name) {
+                    let target = self
+                        .pane_state(pane_id)
+                        .overlay
+                        .as_ref()
+                        .map(|overlay| overlay.pane.clone())
+                        .unwrap_or_else(|| Arc::clone(pane));
+                    target.send_paste(&text).ok();
+                }
+                self.maybe_scroll_to_bottom_for_input(&pane);
+                return;
+            }
         };
         let future = window.get_clipboard(clipboard);
         promise::spawn::spawn(async move {
@@ -57,4 +113,100 @@ impl TermWindow {
         .detach();
         self.maybe_scroll_to_bottom_for_input(&pane);
     }
+
+    /// Handle an OSC 52 clipboard query from the application running
+    /// in the specified pane, according to the clipboard_read_policy
+    /// that applies to its domain.
+    pub fn query_clipboard_for_pane(&mut self, pane_id: PaneId, selection: ClipboardSelection) {
+        let mux = Mux::get();
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+        let domain_name = mux
+            .get_domain(pane.domain_id())
+            .map(|domain| domain.domain_name().to_string())
+            .unwrap_or_default();
+
+        let window = self.window.as_ref().unwrap().clone();
+        match self.config.clipboard_read_policy_for_domain(&domain_name) {
+            ClipboardReadPolicy::Never => {
+                log::debug!(
+                    "declining clipboard query from pane {} in domain {}",
+                    pane_id,
+                    domain_name
+                );
+                pane.answer_clipboard_query(selection, None);
+            }
+            ClipboardReadPolicy::Always => {
+                Self::answer_clipboard_query(window, pane_id, selection);
+            }
+            ClipboardReadPolicy::Ask => {
+                let message = format!(
+                    "🔒 Pane {} \"{}\" in domain \"{}\" wants to read the {}. Allow it?",
+                    pane_id,
+                    pane.get_title(),
+                    domain_name,
+                    match selection {
+                        ClipboardSelection::Clipboard => "clipboard",
+                        ClipboardSelection::PrimarySelection => "primary selection",
+                    }
+                );
+
+                // The new prompt replaces any earlier one for this pane,
+                // so the earlier query must be denied now, as it will
+                // never be answered by its prompt
+                let earlier = self.pane_state(pane_id).clipboard_query.take();
+                if let Some((earlier, answered)) = earlier {
+                    if !answered.swap(true, Ordering::SeqCst) {
+                        pane.answer_clipboard_query(earlier, None);
+                    }
+                }
+
+                let answered = Arc::new(AtomicBool::new(false));
+                let overlay_answered = Arc::clone(&answered);
+                let (overlay, future) = start_overlay_pane(self, &pane, move |pane_id, term| {
+                    confirm_clipboard_read(
+                        pane_id,
+                        selection,
+                        message,
+                        overlay_answered,
+                        term,
+                        window,
+                    )
+                });
+                self.assign_overlay_for_pane(pane_id, overlay);
+                self.pane_state(pane_id)
+                    .clipboard_query
+                    .replace((selection, answered));
+                promise::spawn::spawn(future).detach();
+            }
+        }
+    }
+
+    /// Read the clipboard and deliver its contents to the pane
+    /// in answer to its OSC 52 query
+    pub fn answer_clipboard_query(
+        window: ::window::Window,
+        pane_id: PaneId,
+        selection: ClipboardSelection,
+    ) {
+        let future = window.get_clipboard(match selection {
+            ClipboardSelection::Clipboard => Clipboard::Clipboard,
+            ClipboardSelection::PrimarySelection => Clipboard::PrimarySelection,
+        });
+        promise::spawn::spawn(async move {
+            let text = match future.await {
+                Ok(text) => Some(text),
+                Err(err) => {
+                    log::error!("failed to read clipboard for pane {}: {:#}", pane_id, err);
+                    None
+                }
+            };
+            if let Some(pane) = Mux::get().get_pane(pane_id) {
+                pane.answer_clipboard_query(selection, text);
+            }
+        })
+        .detach();
+    }
 }
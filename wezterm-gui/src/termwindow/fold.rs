@@ -0,0 +1,206 @@
+//! Collapsed command output is folded into a single placeholder row
+//! when a pane is rendered.  The folded rows remain in the pane, so
+//! this only changes which rows are displayed in the viewport, and where.
+use mux::pane::Pane;
+use std::ops::Range;
+use std::sync::Arc;
+use termwiz::surface::SEQ_ZERO;
+use wezterm_term::{
+    CellAttributes, Intensity, Line, SemanticType, StableRowIndex, VisibleRowIndex,
+};
+
+/// Describes what is displayed in a row of the viewport
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViewportRow {
+    /// The line with this index is displayed as-is
+    Line(StableRowIndex),
+    /// These rows are folded into a single placeholder row
+    Fold(Range<StableRowIndex>),
+}
+
+impl ViewportRow {
+    /// Returns the first row represented by this viewport row
+    pub fn stable_row(&self) -> StableRowIndex {
+        match self {
+            Self::Line(row) => *row,
+            Self::Fold(rows) => rows.start,
+        }
+    }
+
+    /// Returns the row immediately after the rows represented
+    /// by this viewport row
+    pub fn end_row(&self) -> StableRowIndex {
+        match self {
+            Self::Line(row) => *row + 1,
+            Self::Fold(rows) => rows.end,
+        }
+    }
+
+    /// Returns the placeholder line that is displayed for a fold
+    pub fn placeholder(&self) -> Option<Line> {
+        let rows = match self {
+            Self::Line(_) => return None,
+            Self::Fold(rows) => rows,
+        };
+        let mut attrs = CellAttributes::default();
+        attrs
+            .set_intensity(Intensity::Half)
+            .set_semantic_type(SemanticType::Output);
+        Some(Line::from_text(
+            &format!("\u{22ef} {} lines collapsed", rows.end - rows.start),
+            &attrs,
+            SEQ_ZERO,
+            None,
+        ))
+    }
+}
+
+/// Computes the rows that are displayed in a viewport of `num_rows`
+/// rows whose first row is `top`, given that the pane holds the rows
+/// in `available`.  Each of the `folds`, which must be ordered, is
+/// displayed as a single row.
+/// If the end of the pane is reached before the viewport has been
+/// filled, rows from above `top` are displayed too, so that the
+/// bottom of the pane remains at the bottom of the viewport.
+pub fn fold_viewport(
+    top: StableRowIndex,
+    num_rows: usize,
+    available: Range<StableRowIndex>,
+    folds: &[Range<StableRowIndex>],
+) -> Vec<ViewportRow> {
+    let fold_at = |row: StableRowIndex| {
+        folds
+            .iter()
+            .find(|fold| fold.contains(&row))
+            .map(|fold| fold.start.max(available.start)..fold.end.min(available.end))
+    };
+
+    let mut rows = vec![];
+    let mut row = top.max(available.start);
+    while rows.len() < num_rows && row < available.end {
+        match fold_at(row) {
+            Some(fold) => {
+                row = fold.end;
+                rows.push(ViewportRow::Fold(fold));
+            }
+            None => {
+                rows.push(ViewportRow::Line(row));
+                row += 1;
+            }
+        }
+    }
+
+    let mut above = vec![];
+    let mut row = rows.first().map(ViewportRow::stable_row).unwrap_or(row);
+    while rows.len() + above.len() < num_rows && row > available.start {
+        row -= 1;
+        match fold_at(row) {
+            Some(fold) => {
+                row = fold.start;
+                above.push(ViewportRow::Fold(fold));
+            }
+            None => above.push(ViewportRow::Line(row)),
+        }
+    }
+
+    above.reverse();
+    above.append(&mut rows);
+    above
+}
+
+impl crate::TermWindow {
+    /// Returns the rows that are displayed in the viewport of `pane`
+    pub fn viewport_rows(&self, pane: &Arc<dyn Pane>) -> Vec<ViewportRow> {
+        let dims = pane.get_dimensions();
+        let top = self
+            .get_viewport(pane.pane_id())
+            .unwrap_or(dims.physical_top);
+        let folds = pane.get_collapsed_output_ranges().unwrap_or_default();
+        fold_viewport(
+            top,
+            dims.viewport_rows,
+            dims.scrollback_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
+            &folds,
+        )
+    }
+
+    /// Returns the StableRowIndex of the line displayed at `row`
+    /// of the viewport of `pane`.  A row that holds a fold placeholder
+    /// maps to the first of the folded rows.
+    pub fn viewport_row_to_stable_row(
+        &self,
+        pane: &Arc<dyn Pane>,
+        row: VisibleRowIndex,
+    ) -> StableRowIndex {
+        let rows = self.viewport_rows(pane);
+        match (rows.first(), rows.last()) {
+            (Some(first), _) if row < 0 => first.stable_row() + row as StableRowIndex,
+            (_, Some(last)) if row as usize >= rows.len() => {
+                last.end_row() + (row as usize - rows.len()) as StableRowIndex
+            }
+            (Some(_), Some(_)) => rows[row as usize].stable_row(),
+            _ => {
+                let dims = pane.get_dimensions();
+                self.get_viewport(pane.pane_id())
+                    .unwrap_or(dims.physical_top)
+                    + row as StableRowIndex
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ViewportRow::{Fold, Line};
+
+    #[test]
+    fn no_folds() {
+        assert_eq!(
+            fold_viewport(2, 3, 0..10, &[]),
+            vec![Line(2), Line(3), Line(4)]
+        );
+    }
+
+    #[test]
+    fn fold_below_top() {
+        assert_eq!(
+            fold_viewport(0, 4, 0..20, &[2..8]),
+            vec![Line(0), Line(1), Fold(2..8), Line(8)]
+        );
+    }
+
+    #[test]
+    fn top_within_fold() {
+        assert_eq!(
+            fold_viewport(4, 3, 0..20, &[2..8]),
+            vec![Fold(2..8), Line(8), Line(9)]
+        );
+    }
+
+    #[test]
+    fn bottom_stays_anchored() {
+        // The viewport is at the bottom of the pane; the fold frees
+        // up rows that are filled from the scrollback
+        assert_eq!(
+            fold_viewport(10, 4, 0..14, &[11..13]),
+            vec![Line(9), Line(10), Fold(11..13), Line(13)]
+        );
+        assert_eq!(
+            fold_viewport(10, 4, 0..14, &[2..4, 11..13]),
+            vec![Line(9), Line(10), Fold(11..13), Line(13)]
+        );
+        assert_eq!(
+            fold_viewport(10, 5, 0..14, &[5..10, 11..13]),
+            vec![Line(4), Fold(5..10), Line(10), Fold(11..13), Line(13)]
+        );
+    }
+
+    #[test]
+    fn fold_clipped_by_scrollback() {
+        assert_eq!(
+            fold_viewport(3, 3, 3..10, &[0..5]),
+            vec![Fold(3..5), Line(5), Line(6)]
+        );
+    }
+}
@@ -30,8 +30,9 @@ use ::wezterm_term::input::{ClickPosition, MouseButton as TMB};
 use ::window::*;
 use anyhow::{anyhow, ensure, Context};
 use config::keyassignment::{
-    Confirmation, KeyAssignment, LauncherActionArgs, PaneDirection, Pattern, PromptInputLine,
-    QuickSelectArguments, RotationDirection, SpawnCommand, SplitSize,
+    Confirmation, DirectoryJumpTarget, InputSelectorEntry, KeyAssignment, LauncherActionArgs,
+    PaneDirection, Pattern, PromptInputLine, QuickSelectArguments, RotationDirection, SpawnCommand,
+    SpawnTabDomain, SplitSize,
 };
 use config::window::WindowLevel;
 use config::{
@@ -55,7 +56,7 @@ use smol::channel::Sender;
 use smol::Timer;
 use std::cell::{RefCell, RefMut};
 use std::collections::{HashMap, LinkedList};
-use std::ops::Add;
+use std::ops::{Add, Range};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
@@ -66,12 +67,16 @@ use wezterm_dynamic::Value;
 use wezterm_font::FontConfiguration;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::input::LastMouseClick;
-use wezterm_term::{Alert, Progress, StableRowIndex, TerminalConfiguration, TerminalSize};
+use wezterm_term::{
+    Alert, ClipboardSelection, CommandBlock, Progress, StableRowIndex, TerminalConfiguration,
+    TerminalSize,
+};
 
 pub mod background;
 pub mod box_model;
 pub mod charselect;
 pub mod clipboard;
+mod fold;
 pub mod keyevent;
 pub mod modal;
 mod mouseevent;
@@ -107,6 +112,59 @@ pub fn get_window_class() -> String {
     WINDOW_CLASS.lock().unwrap().clone()
 }
 
+/// Acts on the directory chosen by the `JumpToDirectory` selector
+fn jump_to_directory(
+    window: &::window::Window,
+    pane_id: PaneId,
+    target: DirectoryJumpTarget,
+    dir: String,
+) {
+    use std::io::Write;
+    let mux = Mux::get();
+    let pane = match mux.get_pane(pane_id) {
+        Some(pane) => pane,
+        None => return,
+    };
+    // Typing `cd` is only safe when the shell is waiting for input;
+    // otherwise it would be sent to whatever is running in the pane
+    let at_prompt = pane
+        .get_command_blocks()
+        .ok()
+        .and_then(|blocks| blocks.last().cloned())
+        .map(|block| block.input_start.is_some() && !block.has_run())
+        .unwrap_or(false);
+    let target = match target {
+        DirectoryJumpTarget::ChangeDirectory if !at_prompt => DirectoryJumpTarget::SpawnTab,
+        target => target,
+    };
+
+    match target {
+        DirectoryJumpTarget::ChangeDirectory => {
+            let quoted = match shlex::try_quote(&dir) {
+                Ok(quoted) => quoted.to_string(),
+                Err(_) => {
+                    log::error!("JumpToDirectory: {dir} has embedded NUL bytes");
+                    return;
+                }
+            };
+            if let Err(err) = pane.writer().write_all(format!("cd {quoted}\r").as_bytes()) {
+                log::error!("JumpToDirectory: {err:#}");
+            }
+        }
+        DirectoryJumpTarget::SpawnTab => {
+            window.notify(TermWindowNotif::PerformAssignment {
+                pane_id,
+                assignment: KeyAssignment::SpawnCommandInNewTab(SpawnCommand {
+                    cwd: Some(dir.into()),
+                    domain: SpawnTabDomain::CurrentPaneDomain,
+                    ..Default::default()
+                }),
+                tx: None,
+            });
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum MouseCapture {
     UI,
@@ -159,6 +217,10 @@ pub enum UIItemType {
     ScrollThumb,
     BelowScrollThumb,
     Split(PositionedSplit),
+    StickyPrompt {
+        pane_id: PaneId,
+        row: StableRowIndex,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -183,6 +245,9 @@ impl UIItem {
 pub struct SemanticZoneCache {
     seqno: SequenceNo,
     zones: Vec<StableRowIndex>,
+    /// Maps the rows of command output to the row holding the
+    /// prompt and input of the command that produced it
+    headers: Vec<(Range<StableRowIndex>, StableRowIndex)>,
 }
 
 pub struct OverlayState {
@@ -201,6 +266,9 @@ pub struct PaneState {
     /// contents, we're overlaying a little internal application
     /// tab.  We'll also route input to it.
     pub overlay: Option<OverlayState>,
+    /// The OSC 52 query whose confirmation prompt is displayed
+    /// in the overlay, and whether it has been answered
+    pub clipboard_query: Option<(ClipboardSelection, Arc<AtomicBool>)>,
 
     bell_start: Option<Instant>,
     pub mouse_terminal_coords: Option<(ClickPosition, StableRowIndex)>,
@@ -1201,6 +1269,24 @@ impl TermWindow {
                 } => {
                     self.emit_user_var_event(pane_id, name, value);
                 }
+                MuxNotification::Alert {
+                    alert:
+                        Alert::CommandFinished {
+                            command,
+                            exit_status,
+                            duration,
+                        },
+                    pane_id,
+                } => {
+                    let threshold =
+                        Duration::from_millis(self.config.command_finished_event_threshold_ms);
+                    if duration >= threshold {
+                        self.emit_command_finished_event(pane_id, command, exit_status, duration);
+                    }
+                    if self.config.show_command_status {
+                        self.mux_pane_output_event(pane_id);
+                    }
+                }
                 MuxNotification::WindowTitleChanged { .. }
                 | MuxNotification::Alert {
                     alert:
@@ -1247,7 +1333,10 @@ impl TermWindow {
                     window.invalidate();
                 }
                 MuxNotification::Alert {
-                    alert: Alert::ToastNotification { .. },
+                    alert:
+                        Alert::ToastNotification { .. }
+                        | Alert::DesktopNotification(_)
+                        | Alert::CloseDesktopNotification { .. },
                     ..
                 } => {}
                 MuxNotification::TabAddedToWindow {
@@ -1294,6 +1383,9 @@ impl TermWindow {
                 MuxNotification::AssignClipboard { .. } => {
                     // Handled by frontend
                 }
+                MuxNotification::QueryClipboard { pane_id, selection } => {
+                    self.query_clipboard_for_pane(pane_id, selection);
+                }
                 MuxNotification::SaveToDownloads { .. } => {
                     // Handled by frontend
                 }
@@ -1462,6 +1554,7 @@ impl TermWindow {
                     | Alert::IconTitleChanged(_)
                     | Alert::Progress(_)
                     | Alert::SetUserVar { .. }
+                    | Alert::CommandFinished { .. }
                     | Alert::Bell,
             }
             | MuxNotification::PaneFocused(pane_id)
@@ -1499,6 +1592,15 @@ impl TermWindow {
                     return true;
                 }
             }
+            MuxNotification::QueryClipboard { pane_id, .. } => {
+                let mux = Mux::get();
+                match mux.resolve_pane_id(pane_id) {
+                    Some((_domain, window_id, _tab_id)) if window_id == mux_window_id => {
+                        // fall through
+                    }
+                    _ => return true,
+                }
+            }
             MuxNotification::TabResized(tab_id)
             | MuxNotification::TabTitleChanged { tab_id, .. } => {
                 let mux = Mux::get();
@@ -1509,7 +1611,10 @@ impl TermWindow {
                 }
             }
             MuxNotification::Alert {
-                alert: Alert::ToastNotification { .. },
+                alert:
+                    Alert::ToastNotification { .. }
+                    | Alert::DesktopNotification(_)
+                    | Alert::CloseDesktopNotification { .. },
                 ..
             }
             | MuxNotification::AssignClipboard { .. }
@@ -1942,6 +2047,53 @@ impl TermWindow {
         .detach();
     }
 
+    fn emit_command_finished_event(
+        &mut self,
+        pane_id: PaneId,
+        command: String,
+        exit_status: Option<i32>,
+        duration: Duration,
+    ) {
+        if !self.window_contains_pane(pane_id) {
+            return;
+        }
+
+        let mux = Mux::get();
+        let window = GuiWin::new(self);
+        let pane = match mux.get_pane(pane_id) {
+            Some(pane) => mux_lua::MuxPane(pane.pane_id()),
+            None => return,
+        };
+
+        async fn do_event(
+            lua: Option<Rc<mlua::Lua>>,
+            command: String,
+            exit_status: Option<i32>,
+            duration: Duration,
+            window: GuiWin,
+            pane: MuxPane,
+        ) -> anyhow::Result<()> {
+            if let Some(lua) = lua {
+                let info = lua.create_table()?;
+                info.set("command", command)?;
+                info.set("exit_status", exit_status)?;
+                info.set("duration", duration.as_secs_f64())?;
+                let args = lua.pack_multi((window, pane, info))?;
+                if let Err(err) =
+                    config::lua::emit_event(&lua, ("command-finished".to_string(), args)).await
+                {
+                    log::error!("while processing command-finished event: {:#}", err);
+                }
+            }
+            Ok(())
+        }
+
+        promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
+            do_event(lua, command, exit_status, duration, window, pane)
+        }))
+        .detach();
+    }
+
     /// Called by window:set_right_status after the status has
     /// been updated; let's update the bar
     pub fn update_title_post_status(&mut self) {
@@ -2290,6 +2442,59 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    fn show_directory_jumper(&mut self, target: DirectoryJumpTarget) {
+        let mux = Mux::get();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        let pane = match self.get_active_pane_no_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let pane_id = pane.pane_id();
+
+        let choices = mux::frecent_dirs::frecent_dirs()
+            .into_iter()
+            .map(|dir| InputSelectorEntry {
+                label: match &dir.host {
+                    Some(host) => format!("{} ({host})", dir.path),
+                    None => dir.path.clone(),
+                },
+                id: Some(dir.path),
+            })
+            .collect();
+        let args = config::keyassignment::InputSelector {
+            action: Box::new(KeyAssignment::Nop),
+            title: "Jump to directory".to_string(),
+            choices,
+            fuzzy: true,
+            alphabet: config::keyassignment::default_num_alphabet(),
+            description: config::keyassignment::default_description(),
+            fuzzy_description: config::keyassignment::default_fuzzy_description(),
+        };
+
+        let gui_win = GuiWin::new(self);
+        let window = self.window.clone().unwrap();
+
+        let (overlay, future) = start_overlay(self, &tab, move |_tab_id, term| {
+            crate::overlay::selector::selector_with_callback(
+                term,
+                args,
+                gui_win,
+                MuxPane(pane_id),
+                move |entry| {
+                    if let Some(dir) = entry.and_then(|entry| entry.id) {
+                        jump_to_directory(&window, pane_id, target, dir);
+                    }
+                },
+            )
+        });
+        self.assign_overlay(tab.tab_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
     fn show_prompt_input_line(&mut self, args: &PromptInputLine) {
         let mux = Mux::get();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -2338,6 +2543,55 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    fn show_command_history(&mut self) {
+        let mux = Mux::get();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        let pane = match self.get_active_pane_or_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let pane_id = pane.pane_id();
+        let window = self.window.as_ref().unwrap().clone();
+        let entries = crate::overlay::command_history::CommandHistoryEntry::collect();
+
+        let (overlay, future) = start_overlay(self, &tab, move |_tab_id, term| {
+            crate::overlay::command_history::command_history(entries, term, window, pane_id)
+        });
+        self.assign_overlay(tab.tab_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
+    fn show_scrollback_search(&mut self, pattern: Pattern) {
+        let mux = Mux::get();
+        let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+
+        let pane = match self.get_active_pane_or_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let pattern = match pattern {
+            // Fuzzy matching is the most useful default when
+            // searching everything
+            Pattern::CurrentSelectionOrEmptyString => {
+                MuxPattern::Fuzzy(self.resolve_search_pattern(pattern, &pane).to_string())
+            }
+            pattern => self.resolve_search_pattern(pattern, &pane),
+        };
+
+        let (overlay, future) = start_overlay(self, &tab, move |_tab_id, term| {
+            crate::overlay::scrollback_search::scrollback_search(pattern, term)
+        });
+        self.assign_overlay(tab.tab_id(), overlay);
+        promise::spawn::spawn(future).detach();
+    }
+
     fn show_debug_overlay(&mut self) {
         let mux = Mux::get();
         let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -2456,8 +2710,7 @@ impl TermWindow {
         .detach();
     }
 
-    /// Returns the Prompt semantic zones
-    fn get_semantic_prompt_zones(&mut self, pane: &Arc<dyn Pane>) -> &[StableRowIndex] {
+    fn semantic_zone_cache(&mut self, pane: &Arc<dyn Pane>) -> &SemanticZoneCache {
         let cache = self
             .semantic_zones
             .entry(pane.pane_id())
@@ -2466,25 +2719,65 @@ impl TermWindow {
         let seqno = pane.get_current_seqno();
         if cache.seqno != seqno {
             let zones = pane.get_semantic_zones().unwrap_or_else(|_| vec![]);
-            let mut zones: Vec<StableRowIndex> = zones
-                .into_iter()
-                .filter_map(|zone| {
-                    if zone.semantic_type == wezterm_term::SemanticType::Prompt {
-                        Some(zone.start_y)
-                    } else {
-                        None
+            let mut prompts = vec![];
+            let mut headers = vec![];
+            let mut header = None;
+            for zone in zones {
+                match zone.semantic_type {
+                    wezterm_term::SemanticType::Prompt => {
+                        prompts.push(zone.start_y);
+                        header.replace(zone.start_y);
                     }
-                })
-                .collect();
+                    wezterm_term::SemanticType::Input => {
+                        // The input usually shares its line with the end
+                        // of the prompt, which is the most useful line to
+                        // show for multi-line prompts
+                        header.replace(zone.start_y);
+                    }
+                    wezterm_term::SemanticType::Output => {
+                        if let Some(header) = header {
+                            headers.push((zone.start_y..zone.end_y + 1, header));
+                        }
+                    }
+                }
+            }
             // dedup to avoid issues where both left and right prompts are
             // defined: we only care if there were 1+ prompts on a line,
             // not about how many prompts are on a line.
             // <https://github.com/wezterm/wezterm/issues/1121>
-            zones.dedup();
-            cache.zones = zones;
+            prompts.dedup();
+            cache.zones = prompts;
+            cache.headers = headers;
             cache.seqno = seqno;
         }
-        &cache.zones
+        cache
+    }
+
+    /// Returns the Prompt semantic zones
+    fn get_semantic_prompt_zones(&mut self, pane: &Arc<dyn Pane>) -> &[StableRowIndex] {
+        &self.semantic_zone_cache(pane).zones
+    }
+
+    /// Returns the row holding the prompt of the command whose output
+    /// is shown on the `top` row of the viewport, provided that the
+    /// prompt itself has been scrolled out of view
+    pub fn sticky_prompt_row(
+        &mut self,
+        pane: &Arc<dyn Pane>,
+        top: StableRowIndex,
+    ) -> Option<StableRowIndex> {
+        let headers = &self.semantic_zone_cache(pane).headers;
+        let idx = match headers.binary_search_by(|(output, _)| output.start.cmp(&top)) {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        let (output, header) = &headers[idx];
+        if output.contains(&top) && *header < top {
+            Some(*header)
+        } else {
+            None
+        }
     }
 
     fn scroll_to_prompt(&mut self, amount: isize, pane: &Arc<dyn Pane>) -> anyhow::Result<()> {
@@ -2510,6 +2803,17 @@ impl TermWindow {
         Ok(())
     }
 
+    /// Returns the most recent command whose prompt is at or above
+    /// the bottom of the viewport
+    fn command_block_for_viewport(&self, pane: &Arc<dyn Pane>) -> Option<CommandBlock> {
+        let bottom = self.viewport_rows(pane).last()?.end_row();
+        pane.get_command_blocks()
+            .ok()?
+            .into_iter()
+            .rev()
+            .find(|block| block.has_run() && block.prompt_start < bottom)
+    }
+
     fn scroll_by_page(&mut self, amount: f64, pane: &Arc<dyn Pane>) -> anyhow::Result<()> {
         let dims = pane.get_dimensions();
         let position = self
@@ -2763,6 +3067,24 @@ impl TermWindow {
             ScrollByLine(n) => self.scroll_by_line(*n, pane)?,
             ScrollByCurrentEventWheelDelta => self.scroll_by_current_event_wheel_delta(pane)?,
             ScrollToPrompt(n) => self.scroll_to_prompt(*n, pane)?,
+            CopyCommandOutput(dest) => {
+                if let Some(block) = self.command_block_for_viewport(pane) {
+                    if let Some(text) = pane.get_command_output(block.id)? {
+                        self.copy_to_clipboard(*dest, text);
+                    }
+                }
+            }
+            ToggleCommandOutputCollapse => {
+                if let Some(block) = self.command_block_for_viewport(pane) {
+                    pane.set_command_block_collapsed(block.id, !block.collapsed)?;
+                    if let Some(window) = self.window.as_ref() {
+                        window.invalidate();
+                    }
+                }
+            }
+            ShowCommandHistory => self.show_command_history(),
+            SearchAllPanes(pattern) => self.show_scrollback_search(pattern.clone()),
+            JumpToDirectory(target) => self.show_directory_jumper(*target),
             ScrollToTop => self.scroll_to_top(pane),
             ScrollToBottom => self.scroll_to_bottom(pane),
             ShowTabNavigator => self.show_tab_navigator(),
@@ -2847,6 +3169,18 @@ impl TermWindow {
                 let window = self.window.as_ref().unwrap();
                 window.invalidate();
             }
+            ExportScrollback(args) => {
+                let name = args.file_name.clone().unwrap_or_else(|| {
+                    format!(
+                        "wezterm-pane-{}.{}",
+                        pane.pane_id(),
+                        args.format.extension()
+                    )
+                });
+                crate::download::save_to_downloads_with(Some(name), |out| {
+                    mux::export::export_pane(&*pane, args.format, out)
+                })?;
+            }
             Search(pattern) => {
                 if let Some(pane) = self.get_active_pane_or_overlay() {
                     let mut replace_current = false;
@@ -3376,6 +3710,28 @@ impl TermWindow {
             .and_then(|tab| tab.get_active_pane())
     }
 
+    /// Render `pane_id`, or all of the panes in the active tab when
+    /// it is None, into an image, without involving the GPU
+    pub fn screenshot(&self, pane_id: Option<PaneId>) -> anyhow::Result<image::RgbaImage> {
+        let mux = Mux::get();
+        let snapshot = match pane_id {
+            Some(pane_id) => {
+                let pane = mux
+                    .get_pane(pane_id)
+                    .ok_or_else(|| anyhow!("pane {pane_id} not found"))?;
+                mux::export::TabSnapshot::single(mux::export::PaneSnapshot::from_pane(&*pane))
+            }
+            None => {
+                let tab = mux
+                    .get_active_tab_for_window(self.mux_window_id)
+                    .ok_or_else(|| anyhow!("window has no active tab"))?;
+                mux::export::TabSnapshot::from_tab(&tab)
+            }
+        };
+        let mut renderer = crate::offscreen::OffscreenRenderer::new(&self.fonts)?;
+        renderer.render_tab(&snapshot)
+    }
+
     /// Returns a Pane that we can interact with; this will typically be
     /// the active tab for the window, but if the window has a tab-wide
     /// overlay (such as the launcher / tab navigator),
@@ -3594,6 +3950,7 @@ impl TermWindow {
             Pattern::CaseSensitiveString(s) => MuxPattern::CaseSensitiveString(s),
             Pattern::CaseInSensitiveString(s) => MuxPattern::CaseInSensitiveString(s),
             Pattern::Regex(s) => MuxPattern::Regex(s),
+            Pattern::Fuzzy(s) => MuxPattern::Fuzzy(s),
             Pattern::CurrentSelectionOrEmptyString => {
                 let text = self.selection_text(pane);
                 let first_line = text
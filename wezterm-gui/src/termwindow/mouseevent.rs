@@ -8,7 +8,7 @@ use ::window::{
 };
 use config::keyassignment::{KeyAssignment, MouseEventTrigger, SpawnTabDomain};
 use config::MouseEventAltScreen;
-use mux::pane::{Pane, WithPaneLines};
+use mux::pane::{Pane, PaneId, WithPaneLines};
 use mux::tab::SplitDirection;
 use mux::Mux;
 use mux_lua::MuxPane;
@@ -43,7 +43,8 @@ impl super::TermWindow {
             | UIItemType::AboveScrollThumb
             | UIItemType::BelowScrollThumb
             | UIItemType::ScrollThumb
-            | UIItemType::Split(_) => {}
+            | UIItemType::Split(_)
+            | UIItemType::StickyPrompt { .. } => {}
         }
     }
 
@@ -54,7 +55,8 @@ impl super::TermWindow {
             | UIItemType::AboveScrollThumb
             | UIItemType::BelowScrollThumb
             | UIItemType::ScrollThumb
-            | UIItemType::Split(_) => {}
+            | UIItemType::Split(_)
+            | UIItemType::StickyPrompt { .. } => {}
         }
     }
 
@@ -382,6 +384,37 @@ impl super::TermWindow {
             UIItemType::CloseTab(idx) => {
                 self.mouse_event_close_tab(idx, event, context);
             }
+            UIItemType::StickyPrompt { pane_id, row } => {
+                self.mouse_event_sticky_prompt(pane_id, row, event, context);
+            }
+        }
+    }
+
+    pub fn mouse_event_sticky_prompt(
+        &mut self,
+        pane_id: PaneId,
+        row: StableRowIndex,
+        event: MouseEvent,
+        context: &dyn WindowOps,
+    ) {
+        context.set_cursor(Some(MouseCursor::Hand));
+        let pane = match Mux::get().get_pane(pane_id) {
+            Some(pane) => pane,
+            None => return,
+        };
+        match event.kind {
+            WMEK::Press(MousePress::Left) => {
+                // Jump to the prompt, placing it at the top of the viewport
+                let dims = pane.get_dimensions();
+                self.set_viewport(pane_id, Some(row), dims);
+                context.invalidate();
+            }
+            WMEK::VertWheel(_) => {
+                // Don't let the pinned prompt get in the way of scrolling
+                self.scroll_by_current_event_wheel_delta(&pane).ok();
+                context.invalidate();
+            }
+            _ => {}
         }
     }
 
@@ -767,11 +800,7 @@ impl super::TermWindow {
             event
         );
 
-        let dims = pane.get_dimensions();
-        let stable_row = self
-            .get_viewport(pane.pane_id())
-            .unwrap_or(dims.physical_top)
-            + row as StableRowIndex;
+        let stable_row = self.viewport_row_to_stable_row(&pane, row);
 
         self.pane_state(pane.pane_id())
             .mouse_terminal_coords
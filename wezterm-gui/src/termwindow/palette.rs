@@ -6,31 +6,88 @@ use crate::termwindow::render::corners::{
     BOTTOM_LEFT_ROUNDED_CORNER, BOTTOM_RIGHT_ROUNDED_CORNER, TOP_LEFT_ROUNDED_CORNER,
     TOP_RIGHT_ROUNDED_CORNER,
 };
-use crate::termwindow::{DimensionContext, GuiWin, TermWindow};
+use crate::termwindow::{DimensionContext, GuiWin, TermWindow, TermWindowNotif};
 use crate::utilsprites::RenderMetrics;
 use config::keyassignment::KeyAssignment;
 use config::Dimension;
 use frecency::Frecency;
 use luahelper::{from_lua_value_dynamic, impl_lua_conversion_dynamic};
+use mlua::Lua;
 use mux_lua::MuxPane;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::cell::{Ref, RefCell};
+use std::cell::{Cell, Ref, RefCell};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
 use termwiz::nerdfonts::NERD_FONTS;
 use wezterm_dynamic::{FromDynamic, ToDynamic};
 use wezterm_term::{KeyCode, KeyModifiers, MouseEvent};
 use window::color::LinearRgba;
-use window::Modifiers;
+use window::{Modifiers, WindowOps};
 
 struct MatchResults {
     selection: String,
     matches: Vec<usize>,
 }
 
+/// An entry whose parameters are being prompted for
+struct PendingCommand {
+    command: ExpandedCommand,
+    params: Vec<PaletteParameter>,
+    /// The values entered for the leading parameters
+    values: Vec<String>,
+    /// The choices for the current parameter
+    choices: Vec<ExpandedCommand>,
+}
+
+impl PendingCommand {
+    fn new(command: ExpandedCommand, params: Vec<PaletteParameter>) -> Self {
+        let mut pending = Self {
+            command,
+            params,
+            values: vec![],
+            choices: vec![],
+        };
+        pending.update_choices();
+        pending
+    }
+
+    fn current_param(&self) -> &PaletteParameter {
+        &self.params[self.values.len()]
+    }
+
+    fn update_choices(&mut self) {
+        self.choices = match self.params.get(self.values.len()) {
+            Some(param) => param
+                .choices
+                .iter()
+                .map(|choice| ExpandedCommand {
+                    brief: choice.clone().into(),
+                    doc: "".into(),
+                    action: KeyAssignment::Nop,
+                    keys: vec![],
+                    menubar: &[],
+                    icon: None,
+                })
+                .collect(),
+            None => vec![],
+        };
+    }
+
+    fn prompt(&self) -> String {
+        let param = self.current_param();
+        format!(
+            "{} › {}: ",
+            self.command.brief,
+            param.prompt.as_deref().unwrap_or(&param.name)
+        )
+    }
+}
+
 pub struct CommandPalette {
     element: RefCell<Option<Vec<ComputedElement>>>,
     selection: RefCell<String>,
@@ -38,7 +95,26 @@ pub struct CommandPalette {
     selected_row: RefCell<usize>,
     top_row: RefCell<usize>,
     max_rows_on_screen: RefCell<usize>,
-    commands: Vec<ExpandedCommand>,
+    /// The built-in and augment-command-palette entries, followed by
+    /// the entries that the providers returned for the current selection
+    commands: RefCell<Vec<ExpandedCommand>>,
+    /// The number of entries in `commands` that didn't come from providers
+    num_static_commands: usize,
+    /// The parameters declared by entries in `commands`, by index
+    params: RefCell<HashMap<usize, Vec<PaletteParameter>>>,
+    pending: RefCell<Option<PendingCommand>>,
+    gui_window: GuiWin,
+    pane: Option<MuxPane>,
+    /// Incremented when the providers are queried; results that
+    /// arrive for an earlier query are discarded
+    typing_cookie: Rc<Cell<usize>>,
+    /// The selection that the providers were last queried for
+    provided_selection: RefCell<Option<String>>,
+    /// The entries that the providers returned for the current query,
+    /// waiting to be merged into `commands`.  The query task holds its
+    /// own reference, so its results can only reach this palette, even
+    /// if another palette has been opened by the time they arrive.
+    provided: Rc<RefCell<Vec<UserPaletteEntry>>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -85,15 +161,132 @@ pub struct UserPaletteEntry {
     pub doc: Option<String>,
     pub action: KeyAssignment,
     pub icon: Option<String>,
+    /// Values to prompt for before performing the action
+    #[dynamic(default)]
+    pub params: Vec<PaletteParameter>,
 }
 impl_lua_conversion_dynamic!(UserPaletteEntry);
 
+impl UserPaletteEntry {
+    fn into_command(self) -> anyhow::Result<(ExpandedCommand, Vec<PaletteParameter>)> {
+        // The values are passed to the callback as an extra argument,
+        // which only makes sense for an action_callback
+        anyhow::ensure!(
+            self.params.is_empty() || matches!(self.action, KeyAssignment::EmitEvent(_)),
+            "{}: palette entries with params require the action \
+             to be defined by wezterm.action_callback",
+            self.brief
+        );
+        Ok((
+            ExpandedCommand {
+                brief: self.brief.into(),
+                doc: match self.doc {
+                    Some(doc) => doc.into(),
+                    None => "".into(),
+                },
+                action: self.action,
+                keys: vec![],
+                menubar: &[],
+                icon: self.icon.map(Cow::Owned),
+            },
+            self.params,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, FromDynamic, ToDynamic)]
+pub struct PaletteParameter {
+    /// The key under which the value is passed to the action
+    pub name: String,
+    /// Shown ahead of the input; defaults to the name
+    #[dynamic(default)]
+    pub prompt: Option<String>,
+    /// If not empty, the values to choose from.  The text that was
+    /// typed is used if it doesn't match any of them
+    #[dynamic(default)]
+    pub choices: Vec<String>,
+    /// Used when nothing was typed
+    #[dynamic(default)]
+    pub default: Option<String>,
+}
+
+/// The lua registry key under which the providers registered by
+/// `wezterm.gui.add_command_palette_provider` are kept
+const PROVIDERS: &str = "wezterm-command-palette-providers";
+
+/// This implements `wezterm.gui.add_command_palette_provider`.
+/// Registering a provider with the same name as an existing one replaces it.
+pub fn add_command_palette_provider<'lua>(
+    lua: &'lua Lua,
+    (name, provider): (String, mlua::Function<'lua>),
+) -> mlua::Result<()> {
+    let providers = match lua.named_registry_value(PROVIDERS)? {
+        mlua::Value::Table(tbl) => tbl,
+        _ => {
+            let tbl = lua.create_table()?;
+            lua.set_named_registry_value(PROVIDERS, tbl.clone())?;
+            tbl
+        }
+    };
+    providers.set(name, provider)
+}
+
+/// Returns the names of the registered providers, in a stable order
+fn provider_names(lua: &Lua) -> mlua::Result<Vec<String>> {
+    let providers = match lua.named_registry_value(PROVIDERS)? {
+        mlua::Value::Table(tbl) => tbl,
+        _ => return Ok(vec![]),
+    };
+    let mut names = providers
+        .pairs::<String, mlua::Value>()
+        .map(|pair| pair.map(|(name, _)| name))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    // Lua doesn't preserve the order of the table
+    names.sort();
+    Ok(names)
+}
+
+/// Asks the provider `name` for the entries that match `query`
+async fn query_provider(
+    lua: Option<Rc<Lua>>,
+    name: String,
+    window: GuiWin,
+    pane: Option<MuxPane>,
+    query: String,
+) -> anyhow::Result<Vec<UserPaletteEntry>> {
+    let lua = match lua {
+        Some(lua) => lua,
+        None => return Ok(vec![]),
+    };
+    let provider = match lua.named_registry_value(PROVIDERS)? {
+        mlua::Value::Table(tbl) => tbl.get::<_, Option<mlua::Function>>(name)?,
+        _ => None,
+    };
+    let provider = match provider {
+        Some(provider) => provider,
+        // It was replaced or removed by a config reload
+        None => return Ok(vec![]),
+    };
+
+    match provider
+        .call_async::<_, mlua::Value>((window, pane, query))
+        .await?
+    {
+        mlua::Value::Nil => Ok(vec![]),
+        result => Ok(from_lua_value_dynamic(result)?),
+    }
+}
+
 fn build_commands(
     gui_window: GuiWin,
     pane: Option<MuxPane>,
     filter_copy_mode: bool,
-) -> Vec<ExpandedCommand> {
-    let mut commands = CommandDef::actions_for_palette_and_menubar(&config::configuration());
+) -> (Vec<ExpandedCommand>, HashMap<usize, Vec<PaletteParameter>>) {
+    let mut commands: Vec<(ExpandedCommand, Vec<PaletteParameter>)> =
+        CommandDef::actions_for_palette_and_menubar(&config::configuration())
+            .into_iter()
+            .map(|cmd| (cmd, vec![]))
+            .collect();
 
     match config::run_immediate_with_lua_config(|lua| {
         let mut entries: Vec<UserPaletteEntry> = vec![];
@@ -113,17 +306,10 @@ fn build_commands(
     }) {
         Ok(entries) => {
             for entry in entries {
-                commands.push(ExpandedCommand {
-                    brief: entry.brief.into(),
-                    doc: match entry.doc {
-                        Some(doc) => doc.into(),
-                        None => "".into(),
-                    },
-                    action: entry.action,
-                    keys: vec![],
-                    menubar: &[],
-                    icon: entry.icon.map(Cow::Owned),
-                });
+                match entry.into_command() {
+                    Ok(command) => commands.push(command),
+                    Err(err) => log::warn!("augment-command-palette: {err:#}"),
+                }
             }
         }
         Err(err) => {
@@ -131,7 +317,7 @@ fn build_commands(
         }
     }
 
-    commands.retain(|cmd| {
+    commands.retain(|(cmd, _)| {
         if filter_copy_mode {
             !matches!(cmd.action, KeyAssignment::CopyMode(_))
         } else {
@@ -147,7 +333,7 @@ fn build_commands(
         }
     }
 
-    commands.sort_by(|a, b| {
+    commands.sort_by(|(a, _), (b, _)| {
         match (scores.get(&*a.brief), scores.get(&*b.brief)) {
             // Want descending frecency score, so swap a<->b
             // for the compare here
@@ -166,7 +352,37 @@ fn build_commands(
         }
     });
 
-    commands
+    let mut params = HashMap::new();
+    let commands = commands
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (cmd, cmd_params))| {
+            if !cmd_params.is_empty() {
+                params.insert(idx, cmd_params);
+            }
+            cmd
+        })
+        .collect();
+
+    (commands, params)
+}
+
+async fn emit_with_params(
+    lua: Option<Rc<Lua>>,
+    name: String,
+    window: GuiWin,
+    pane: Option<MuxPane>,
+    values: HashMap<String, String>,
+) -> anyhow::Result<()> {
+    if let Some(lua) = lua {
+        let args = lua.pack_multi((window, pane, values))?;
+
+        if let Err(err) = config::lua::emit_event(&lua, (name.clone(), args)).await {
+            log::error!("while processing {} event: {:#}", name, err);
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -202,8 +418,13 @@ fn compute_matches(selection: &str, commands: &[ExpandedCommand]) -> Vec<usize>
             .par_iter()
             .enumerate()
             .filter_map(|(row_idx, entry)| {
-                let group = entry.menubar.join(" ");
-                let text = format!("{group}: {}. {} {:?}", entry.brief, entry.doc, entry.action);
+                let text = if matches!(entry.action, KeyAssignment::Nop) {
+                    // The choices for a parameter have no action of their own
+                    entry.brief.to_string()
+                } else {
+                    let group = entry.menubar.join(" ");
+                    format!("{group}: {}. {} {:?}", entry.brief, entry.doc, entry.action)
+                };
                 matcher_score(&pattern, &text)
                     .map(|score| MatchResult::new(row_idx, score, selection, commands))
             })
@@ -232,21 +453,138 @@ impl CommandPalette {
             .get_active_pane_or_overlay()
             .map(|pane| MuxPane(pane.pane_id()));
 
-        let commands = build_commands(GuiWin::new(term_window), mux_pane, filter_copy_mode);
+        let gui_window = GuiWin::new(term_window);
+        let (commands, params) = build_commands(gui_window.clone(), mux_pane, filter_copy_mode);
 
         Self {
             element: RefCell::new(None),
             selection: RefCell::new(String::new()),
-            commands,
+            num_static_commands: commands.len(),
+            commands: RefCell::new(commands),
+            params: RefCell::new(params),
+            pending: RefCell::new(None),
             matches: RefCell::new(None),
             selected_row: RefCell::new(0),
             top_row: RefCell::new(0),
             max_rows_on_screen: RefCell::new(0),
+            gui_window,
+            pane: mux_pane,
+            typing_cookie: Rc::new(Cell::new(0)),
+            provided_selection: RefCell::new(None),
+            provided: Rc::new(RefCell::new(vec![])),
         }
     }
 
+    /// Drops the entries from the providers and, once typing has paused,
+    /// asks the providers for the entries that match `selection`.
+    /// The entries are collected in `provided` and merged in by
+    /// `merge_provided_commands` as each provider returns them.
+    fn schedule_provider_query(&self, selection: &str) {
+        let cookie = self.typing_cookie.get() + 1;
+        self.typing_cookie.set(cookie);
+        self.provided_selection
+            .borrow_mut()
+            .replace(selection.to_string());
+
+        self.commands
+            .borrow_mut()
+            .truncate(self.num_static_commands);
+        self.params
+            .borrow_mut()
+            .retain(|idx, _| *idx < self.num_static_commands);
+        self.provided.borrow_mut().clear();
+
+        let typing_cookie = Rc::clone(&self.typing_cookie);
+        let provided = Rc::clone(&self.provided);
+        let gui_window = self.gui_window.clone();
+        let pane = self.pane;
+        let query = selection.to_string();
+
+        promise::spawn::spawn(async move {
+            smol::Timer::after(Duration::from_millis(250)).await;
+            if typing_cookie.get() != cookie {
+                return Ok(());
+            }
+
+            let names = config::with_lua_config_on_main_thread(|lua| async move {
+                match lua {
+                    Some(lua) => Ok(provider_names(&lua)?),
+                    None => Ok(vec![]),
+                }
+            })
+            .await?;
+
+            for name in names {
+                if typing_cookie.get() != cookie {
+                    break;
+                }
+
+                let window = gui_window.clone();
+                let query = query.clone();
+                let provider_name = name.clone();
+                let entries = match config::with_lua_config_on_main_thread(move |lua| {
+                    query_provider(lua, provider_name, window, pane, query)
+                })
+                .await
+                {
+                    Ok(entries) => entries,
+                    Err(err) => {
+                        log::warn!("command palette provider {name}: {err:#}");
+                        continue;
+                    }
+                };
+                if entries.is_empty() || typing_cookie.get() != cookie {
+                    continue;
+                }
+                provided.borrow_mut().extend(entries);
+
+                gui_window
+                    .window
+                    .notify(TermWindowNotif::Apply(Box::new(|term_window| {
+                        if let Some(modal) = term_window.get_modal() {
+                            if let Some(palette) = modal.downcast_ref::<CommandPalette>() {
+                                palette.merge_provided_commands();
+                            }
+                        }
+                        term_window.invalidate_modal();
+                    })));
+            }
+            anyhow::Result::<()>::Ok(())
+        })
+        .detach();
+    }
+
+    /// Adds the entries that the providers have returned for the
+    /// current selection since this was last called
+    fn merge_provided_commands(&self) {
+        let entries = std::mem::take(&mut *self.provided.borrow_mut());
+        if entries.is_empty() {
+            return;
+        }
+
+        {
+            let mut commands = self.commands.borrow_mut();
+            let mut params = self.params.borrow_mut();
+            for entry in entries {
+                match entry.into_command() {
+                    Ok((command, entry_params)) => {
+                        if !entry_params.is_empty() {
+                            params.insert(commands.len(), entry_params);
+                        }
+                        commands.push(command);
+                    }
+                    Err(err) => log::warn!("command palette provider: {err:#}"),
+                }
+            }
+        }
+
+        // Recompute the matches to include the new entries
+        self.matches.borrow_mut().take();
+    }
+
     fn compute(
         term_window: &mut TermWindow,
+        prompt: &str,
         selection: &str,
         commands: &[ExpandedCommand],
         matches: &MatchResults,
@@ -271,7 +609,7 @@ impl CommandPalette {
 
         let mut elements =
             vec![
-                Element::new(&font, ElementContent::Text(format!("> {selection}_")))
+                Element::new(&font, ElementContent::Text(format!("{prompt}{selection}_")))
                     .colors(ElementColors {
                         border: BorderColor::default(),
                         bg: LinearRgba::TRANSPARENT.into(),
@@ -539,6 +877,73 @@ impl CommandPalette {
         Ok(vec![computed])
     }
 
+    /// Records the value for the parameter that is being prompted for,
+    /// and performs the action once all of the values have been entered
+    fn accept_param_value(
+        &self,
+        choice_idx: Option<usize>,
+        term_window: &mut TermWindow,
+    ) -> anyhow::Result<bool> {
+        let mut pending = self.pending.borrow_mut();
+        let pending_command = match pending.as_mut() {
+            Some(pending) => pending,
+            None => return Ok(true),
+        };
+
+        let selection = std::mem::take(&mut *self.selection.borrow_mut());
+        let value = match choice_idx.and_then(|idx| pending_command.choices.get(idx)) {
+            Some(choice) => choice.brief.to_string(),
+            None if selection.is_empty() => pending_command
+                .current_param()
+                .default
+                .clone()
+                .unwrap_or_default(),
+            None => selection,
+        };
+        pending_command.values.push(value);
+
+        if pending_command.values.len() < pending_command.params.len() {
+            pending_command.update_choices();
+            self.matches.borrow_mut().take();
+            self.updated_input();
+            term_window.invalidate_modal();
+            return Ok(true);
+        }
+
+        let PendingCommand {
+            command,
+            params,
+            values,
+            ..
+        } = pending.take().unwrap();
+        term_window.cancel_modal();
+
+        let name = match command.action {
+            KeyAssignment::EmitEvent(name) => name,
+            // into_command only allows params for an action_callback
+            _ => return Ok(true),
+        };
+
+        let values: HashMap<String, String> = params
+            .into_iter()
+            .map(|param| param.name)
+            .zip(values)
+            .collect();
+        let window = self.gui_window.clone();
+        let pane = term_window
+            .get_active_pane_or_overlay()
+            .map(|pane| MuxPane(pane.pane_id()));
+        promise::spawn::spawn(async move {
+            config::with_lua_config_on_main_thread(move |lua| {
+                emit_with_params(lua, name, window, pane, values)
+            })
+            .await
+        })
+        .detach();
+
+        Ok(true)
+    }
+
     fn updated_input(&self) {
         *self.selected_row.borrow_mut() = 0;
         *self.top_row.borrow_mut() = 0;
@@ -561,7 +966,10 @@ impl CommandPalette {
             .borrow()
             .as_ref()
             .map(|m| m.matches.len())
-            .unwrap_or_else(|| self.commands.len())
+            .unwrap_or_else(|| match self.pending.borrow().as_ref() {
+                Some(pending) => pending.choices.len(),
+                None => self.commands.borrow().len(),
+            })
             .saturating_sub(1);
         let mut row = self.selected_row.borrow_mut();
         *row = row.saturating_add(1).min(limit);
@@ -610,7 +1018,18 @@ impl Modal for CommandPalette {
             (KeyCode::Backspace, KeyModifiers::NONE) => {
                 // Backspace to edit the selection
                 let mut selection = self.selection.borrow_mut();
-                selection.pop();
+                if selection.is_empty() {
+                    // Return to the prior parameter
+                    if let Some(pending) = self.pending.borrow_mut().as_mut() {
+                        if let Some(value) = pending.values.pop() {
+                            pending.update_choices();
+                            *selection = value;
+                            self.matches.borrow_mut().take();
+                        }
+                    }
+                } else {
+                    selection.pop();
+                }
                 self.updated_input();
             }
             (KeyCode::Char('u'), KeyModifiers::CTRL) => {
@@ -620,19 +1039,37 @@ impl Modal for CommandPalette {
                 self.updated_input();
             }
             (KeyCode::Enter, KeyModifiers::NONE) => {
-                // Enter the selected character to the current pane
                 let selected_idx = *self.selected_row.borrow();
-                let alias_idx = match self.matches.borrow().as_ref() {
+                let alias_idx = self
+                    .matches
+                    .borrow()
+                    .as_ref()
+                    .and_then(|results| results.matches.get(selected_idx).copied());
+
+                if self.pending.borrow().is_some() {
+                    return self.accept_param_value(alias_idx, term_window);
+                }
+
+                let alias_idx = match alias_idx {
+                    Some(i) => i,
                     None => return Ok(true),
-                    Some(results) => match results.matches.get(selected_idx) {
-                        Some(i) => *i,
-                        None => return Ok(true),
-                    },
                 };
-                let item = &self.commands[alias_idx];
-                if let Err(err) = save_recent(item) {
+                let item = self.commands.borrow()[alias_idx].clone();
+                if let Err(err) = save_recent(&item) {
                     log::error!("Error while saving recents: {err:#}");
                 }
+
+                if let Some(params) = self.params.borrow_mut().remove(&alias_idx) {
+                    self.pending
+                        .borrow_mut()
+                        .replace(PendingCommand::new(item, params));
+                    self.selection.borrow_mut().clear();
+                    self.matches.borrow_mut().take();
+                    self.updated_input();
+                    term_window.invalidate_modal();
+                    return Ok(true);
+                }
+
                 term_window.cancel_modal();
 
                 if let Some(pane) = term_window.get_active_pane_or_overlay() {
@@ -671,23 +1108,39 @@ impl Modal for CommandPalette {
         }
         *self.max_rows_on_screen.borrow_mut() = max_rows_on_screen;
 
+        let pending = self.pending.borrow();
         let rebuild_matches = results
             .as_ref()
             .map(|m| m.selection != selection)
             .unwrap_or(true);
         if rebuild_matches {
+            let matches = match pending.as_ref() {
+                Some(pending) => compute_matches(selection, &pending.choices),
+                None => {
+                    if self.provided_selection.borrow().as_deref() != Some(selection) {
+                        self.schedule_provider_query(selection);
+                    }
+                    compute_matches(selection, &self.commands.borrow())
+                }
+            };
             results.replace(MatchResults {
                 selection: selection.to_string(),
-                matches: compute_matches(selection, &self.commands),
+                matches,
             });
         };
         let matches = results.as_ref().unwrap();
 
         if self.element.borrow().is_none() {
+            let commands = self.commands.borrow();
+            let (prompt, commands) = match pending.as_ref() {
+                Some(pending) => (pending.prompt(), &pending.choices),
+                None => ("> ".to_string(), &*commands),
+            };
             let element = Self::compute(
                 term_window,
+                &prompt,
                 selection,
-                &self.commands,
+                commands,
                 matches,
                 max_rows_on_screen,
                 *self.selected_row.borrow(),
@@ -848,6 +848,39 @@ impl crate::TermWindow {
         Ok(glyph_info)
     }
 
+    /// Shapes the text of a block that was sized using the text sizing
+    /// protocol with `font`, which was loaded at the size of the block,
+    /// so that its glyphs are rasterized at that size.
+    /// The shape cache is keyed by style rather than by font, so the
+    /// result isn't cached here; the shaped line is cached instead.
+    fn shape_text_size_block(
+        &self,
+        style: &TextStyle,
+        cluster: &CellCluster,
+        gl_state: &RenderState,
+        font: &Rc<LoadedFont>,
+    ) -> anyhow::Result<Rc<Vec<ShapedInfo>>> {
+        let window = self.window.as_ref().unwrap().clone();
+        let presentation_width = PresentationWidth::with_cluster(&cluster);
+        let info = font.shape(
+            &cluster.text,
+            move || window.notify(TermWindowNotif::InvalidateShapeCache),
+            BlockKey::filter_out_synthetic,
+            Some(cluster.presentation),
+            cluster.direction,
+            None,
+            Some(&presentation_width),
+        )?;
+        let glyphs = self.glyph_infos_to_glyphs(
+            style,
+            &mut gl_state.glyph_cache.borrow_mut(),
+            &info,
+            font,
+            &self.render_metrics,
+        )?;
+        Ok(Rc::new(ShapedInfo::process(&info, &glyphs)))
+    }
+
     fn lookup_cached_shape(
         &self,
         key: &dyn ShapeCacheKeyTrait,
@@ -904,7 +937,7 @@ impl crate::TermWindow {
     }
 }
 
-fn resolve_fg_color_attr(
+pub fn resolve_fg_color_attr(
     attrs: &CellAttributes,
     fg: ColorAttribute,
     palette: &ColorPalette,
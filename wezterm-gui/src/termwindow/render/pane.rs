@@ -1,6 +1,7 @@
 use crate::quad::{HeapQuadAllocator, QuadTrait, TripleLayerQuadAllocator};
 use crate::selection::SelectionRange;
 use crate::termwindow::box_model::*;
+use crate::termwindow::fold::ViewportRow;
 use crate::termwindow::render::{
     same_hyperlink, CursorProperties, LineQuadCacheKey, LineQuadCacheValue, LineToEleShapeCacheKey,
     RenderScreenLineParams,
@@ -14,12 +15,19 @@ use mux::pane::{PaneId, WithPaneLines};
 use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::PositionedPane;
 use ordered_float::NotNan;
+use std::ops::Range;
 use std::time::Instant;
 use wezterm_dynamic::Value;
 use wezterm_term::color::{ColorAttribute, ColorPalette};
-use wezterm_term::{Line, StableRowIndex};
+use wezterm_term::{
+    unicode_column_width, CellAttributes, CommandBlock, Intensity, Line, StableRowIndex,
+};
 use window::color::LinearRgba;
 
+/// The number of rows above its first row that a text sized block
+/// can cover, given that the text can be scaled by at most 7
+const MAX_TEXT_SIZE_ROWS: usize = 6;
+
 impl crate::TermWindow {
     fn paint_pane_box_model(&mut self, pos: &PositionedPane) -> anyhow::Result<()> {
         let computed = self.build_pane(pos)?;
@@ -303,13 +311,52 @@ impl crate::TermWindow {
             palette.cursor_fg == global_cursor_fg && palette.cursor_bg == global_cursor_bg;
 
         {
-            let stable_range = match current_viewport {
-                Some(top) => top..top + dims.viewport_rows as StableRowIndex,
-                None => dims.physical_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
+            // Collapsed command output is folded into a single row, so
+            // the rows in the viewport are not necessarily contiguous
+            let viewport_rows = self.viewport_rows(&pos.pane);
+            let stable_range = match (viewport_rows.first(), viewport_rows.last()) {
+                (Some(first), Some(last)) => first.stable_row()..last.end_row(),
+                _ => {
+                    let top = current_viewport.unwrap_or(dims.physical_top);
+                    top..top + dims.viewport_rows as StableRowIndex
+                }
+            };
+
+            // The runs of rows that are displayed as-is, along with the
+            // viewport row at which each run starts
+            let mut runs: Vec<(usize, Range<StableRowIndex>)> = vec![];
+            for (line_idx, row) in viewport_rows.iter().enumerate() {
+                match (row, runs.last_mut()) {
+                    (ViewportRow::Line(row), Some((_, run))) if run.end == *row => {
+                        run.end += 1;
+                    }
+                    (ViewportRow::Line(row), _) => runs.push((line_idx, *row..*row + 1)),
+                    (ViewportRow::Fold(_), _) => {}
+                }
+            }
+
+            for (_, run) in &runs {
+                pos.pane
+                    .apply_hyperlinks(run.clone(), &self.config.hyperlink_rules);
+            }
+
+            let command_status = if self.config.show_command_status {
+                pos.pane
+                    .get_command_blocks_in_range(stable_range.clone())
+                    .unwrap_or_default()
+            } else {
+                vec![]
             };
 
-            pos.pane
-                .apply_hyperlinks(stable_range.clone(), &self.config.hyperlink_rules);
+            let sticky_prompt = if self.config.sticky_prompt && dims.viewport_rows > 1 {
+                self.sticky_prompt_row(&pos.pane, stable_range.start)
+                    .and_then(|row| {
+                        let (_, mut lines) = pos.pane.get_lines(row..row + 1);
+                        lines.pop().map(|line| (row, line))
+                    })
+            } else {
+                None
+            };
 
             struct LineRender<'a, 'b> {
                 term_window: &'a mut crate::TermWindow,
@@ -334,6 +381,11 @@ impl crate::TermWindow {
                 filled_box: TextureRect,
                 window_is_transparent: bool,
                 layers: &'a mut TripleLayerQuadAllocator<'b>,
+                command_status: Vec<CommandBlock>,
+                sticky_prompt: Option<(StableRowIndex, Line)>,
+                /// The viewport row at which the lines passed to
+                /// with_lines_mut are to be rendered
+                run_start: (usize, StableRowIndex),
                 error: Option<anyhow::Error>,
             }
 
@@ -364,17 +416,168 @@ impl crate::TermWindow {
                 filled_box,
                 window_is_transparent,
                 layers,
+                command_status,
+                sticky_prompt: sticky_prompt.clone(),
+                run_start: (0, stable_range.start),
                 error: None,
             };
 
             impl<'a, 'b> LineRender<'a, 'b> {
+                /// If `stable_row` holds the prompt of a command that has
+                /// finished, returns a copy of `line` with the status and
+                /// elapsed time of that command right-aligned in its
+                /// trailing blank cells.
+                fn annotate_command_status(
+                    &self,
+                    stable_row: StableRowIndex,
+                    line: &Line,
+                ) -> Option<Line> {
+                    let block = self.command_status.iter().find(|block| {
+                        block.prompt_start == stable_row && block.finished.is_some()
+                    })?;
+                    let duration = block
+                        .duration()
+                        .map(crate::overlay::command_history::format_duration)
+                        .unwrap_or_default();
+
+                    let mut attr = CellAttributes::default();
+                    let text = match block.exit_status {
+                        Some(0) => {
+                            attr.set_foreground(ColorAttribute::PaletteIndex(2));
+                            format!("\u{2714} {duration}")
+                        }
+                        Some(status) => {
+                            attr.set_foreground(ColorAttribute::PaletteIndex(1));
+                            format!("\u{2718} {status} {duration}")
+                        }
+                        None => {
+                            attr.set_intensity(Intensity::Half);
+                            duration
+                        }
+                    };
+                    let width = unicode_column_width(&text, None);
+
+                    let used = line
+                        .visible_cells()
+                        .filter(|cell| cell.str() != " ")
+                        .map(|cell| cell.cell_index() + cell.width())
+                        .max()
+                        .unwrap_or(0);
+                    // Leave a gap between the prompt and the annotation,
+                    // and don't butt up against the right edge
+                    if used + width + 2 > self.dims.cols {
+                        return None;
+                    }
+
+                    let mut annotated = line.clone();
+                    // Ensure that the shape cache doesn't confuse this
+                    // with the original line
+                    annotated.clear_appdata();
+                    let seqno = annotated.current_seqno();
+                    annotated.overlay_text_with_attribute(
+                        self.dims.cols - width - 1,
+                        &text,
+                        attr,
+                        seqno,
+                    );
+                    Some(annotated)
+                }
+
+                /// If `line` holds rows of text sized blocks other than
+                /// their first row, returns a copy of `line` in which those
+                /// rows hold the text of their block, taken from the origin
+                /// cell in `lines_above`, so that the portion of the block
+                /// that covers this row can be drawn.
+                fn resolve_text_size_blocks(
+                    line: &Line,
+                    lines_above: &[&mut Line],
+                ) -> Option<Line> {
+                    let mut resolved: Option<Line> = None;
+                    for cell in line.visible_cells() {
+                        let size = match cell.attrs().text_size() {
+                            Some(size) if size.x == 0 && size.y > 0 => size,
+                            _ => continue,
+                        };
+                        let above = match lines_above
+                            .len()
+                            .checked_sub(size.y as usize)
+                            .and_then(|idx| lines_above.get(idx))
+                        {
+                            Some(above) => above,
+                            None => continue,
+                        };
+                        let origin = match above
+                            .visible_cells()
+                            .find(|origin| origin.cell_index() == cell.cell_index())
+                        {
+                            Some(origin) => origin,
+                            None => continue,
+                        };
+                        match origin.attrs().text_size() {
+                            Some(origin_size)
+                                if origin_size.is_origin()
+                                    && origin_size.scale == size.scale
+                                    && origin_size.width == size.width => {}
+                            _ => continue,
+                        }
+
+                        let resolved = resolved.get_or_insert_with(|| {
+                            let resolved = line.clone();
+                            // Ensure that the shape cache doesn't confuse
+                            // this with the original line
+                            resolved.clear_appdata();
+                            resolved
+                        });
+                        let mut attrs = origin.attrs().clone();
+                        attrs.set_text_size(Some(size));
+                        let seqno = resolved.current_seqno();
+                        resolved.set_cell_grapheme(
+                            cell.cell_index(),
+                            origin.str(),
+                            size.width as usize,
+                            attrs,
+                            seqno,
+                        );
+                    }
+                    resolved
+                }
+
+                /// Renders `line`, which holds `stable_row`, at row
+                /// `line_idx` of the viewport.  `lines_above` holds the
+                /// lines that precede it in the pane, if any.
                 fn render_line(
                     &mut self,
-                    stable_top: StableRowIndex,
+                    stable_row: StableRowIndex,
                     line_idx: usize,
-                    line: &&mut Line,
+                    line: &Line,
+                    lines_above: &[&mut Line],
                 ) -> anyhow::Result<()> {
-                    let stable_row = stable_top + line_idx as StableRowIndex;
+                    // The pinned prompt, if any, replaces the top row
+                    let sticky_line;
+                    let (stable_row, line, lines_above): (StableRowIndex, &Line, &[&mut Line]) =
+                        match &self.sticky_prompt {
+                            Some((row, sticky)) if line_idx == 0 => {
+                                sticky_line = sticky.clone();
+                                (*row, &sticky_line, &[])
+                            }
+                            _ => (stable_row, line, lines_above),
+                        };
+                    let resolved;
+                    let line: &Line = match Self::resolve_text_size_blocks(line, lines_above) {
+                        Some(line) => {
+                            resolved = line;
+                            &resolved
+                        }
+                        None => line,
+                    };
+                    let annotated;
+                    let line: &Line = match self.annotate_command_status(stable_row, line) {
+                        Some(line) => {
+                            annotated = line;
+                            &annotated
+                        }
+                        None => line,
+                    };
                     let selrange = self
                         .selrange
                         .map_or(0..0, |sel| sel.cols_for_row(stable_row, self.rectangular));
@@ -556,8 +759,19 @@ impl crate::TermWindow {
 
             impl<'a, 'b> WithPaneLines for LineRender<'a, 'b> {
                 fn with_lines_mut(&mut self, stable_top: StableRowIndex, lines: &mut [&mut Line]) {
-                    for (line_idx, line) in lines.iter().enumerate() {
-                        if let Err(err) = self.render_line(stable_top, line_idx, line) {
+                    let (run_idx, run_top) = self.run_start;
+                    for (idx, line) in lines.iter().enumerate() {
+                        let stable_row = stable_top + idx as StableRowIndex;
+                        // The lines above the run are only fetched so
+                        // that text sized blocks that begin above it
+                        // can be drawn
+                        if stable_row < run_top {
+                            continue;
+                        }
+                        let line_idx = run_idx + (stable_row - run_top) as usize;
+                        let lines_above = &lines[idx.saturating_sub(MAX_TEXT_SIZE_ROWS)..idx];
+                        if let Err(err) = self.render_line(stable_row, line_idx, line, lines_above)
+                        {
                             self.error.replace(err);
                             return;
                         }
@@ -565,9 +779,100 @@ impl crate::TermWindow {
                 }
             }
 
-            pos.pane.with_lines_mut(stable_range.clone(), &mut render);
-            if let Some(error) = render.error.take() {
-                return Err(error).context("error while calling with_lines_mut");
+            for (run_idx, run) in runs {
+                render.run_start = (run_idx, run.start);
+                let fetch_top =
+                    (run.start - MAX_TEXT_SIZE_ROWS as StableRowIndex).max(dims.scrollback_top);
+                pos.pane.with_lines_mut(fetch_top..run.end, &mut render);
+                if let Some(error) = render.error.take() {
+                    return Err(error).context("error while calling with_lines_mut");
+                }
+            }
+            for (line_idx, row) in viewport_rows.iter().enumerate() {
+                if let Some(placeholder) = row.placeholder() {
+                    render
+                        .render_line(row.stable_row(), line_idx, &placeholder, &[])
+                        .context("render fold placeholder")?;
+                }
+            }
+            let command_status = std::mem::take(&mut render.command_status);
+
+            let pane_top_pixel_y =
+                top_pixel_y + pos.top as f32 * self.render_metrics.cell_size.height as f32;
+            if let Some((row, _)) = sticky_prompt {
+                // Separate the pinned prompt from the output beneath it,
+                // and allow clicking on it to jump to the prompt
+                let cell_height = self.render_metrics.cell_size.height as f32;
+                let pane_width = dims.cols as f32 * self.render_metrics.cell_size.width as f32;
+                let thickness = (cell_height / 16.).max(1.).floor();
+                self.filled_rectangle(
+                    layers,
+                    2,
+                    euclid::rect(
+                        left_pixel_x,
+                        pane_top_pixel_y + cell_height - thickness,
+                        pane_width,
+                        thickness,
+                    ),
+                    palette.foreground.to_linear().mul_alpha(0.5),
+                )
+                .context("filled_rectangle")?;
+                self.ui_items.push(UIItem {
+                    x: left_pixel_x as usize,
+                    y: pane_top_pixel_y as usize,
+                    width: pane_width as usize,
+                    height: cell_height as usize,
+                    item_type: UIItemType::StickyPrompt { pane_id, row },
+                });
+            }
+
+            // Draw a gutter alongside each finished command, colored
+            // according to its exit status
+            let cell_width = self.render_metrics.cell_size.width as f32;
+            let cell_height = self.render_metrics.cell_size.height as f32;
+            // The gutter sits in the padding for the left-most pane,
+            // or in the split separator otherwise
+            let available = if pos.left == 0 {
+                padding_left
+            } else {
+                cell_width
+            };
+            let gutter_width = (cell_width / 4.).min(available).floor();
+            if gutter_width >= 1. {
+                for block in &command_status {
+                    let color = match block.exit_status {
+                        Some(0) => palette.colors.0[2],
+                        Some(_) => palette.colors.0[1],
+                        None => continue,
+                    };
+                    let end = match block.output_end {
+                        Some(end) => end.max(block.prompt_start + 1),
+                        None => continue,
+                    };
+                    let first = match viewport_rows.iter().position(|row| {
+                        row.end_row() > block.prompt_start && row.stable_row() < end
+                    }) {
+                        Some(first) => first,
+                        None => continue,
+                    };
+                    let num_rows = viewport_rows[first..]
+                        .iter()
+                        .take_while(|row| row.stable_row() < end)
+                        .count();
+                    let y = top_pixel_y + (first as f32 + pos.top as f32) * cell_height;
+                    self.filled_rectangle(
+                        layers,
+                        2,
+                        euclid::rect(
+                            left_pixel_x - gutter_width,
+                            y,
+                            gutter_width,
+                            num_rows as f32 * cell_height,
+                        ),
+                        color.to_linear(),
+                    )
+                    .context("filled_rectangle")?;
+                }
             }
         }
 
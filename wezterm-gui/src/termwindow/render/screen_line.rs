@@ -5,13 +5,14 @@ use crate::termwindow::render::{
     RenderScreenLineParams, RenderScreenLineResult,
 };
 use crate::termwindow::LineToElementShapeItem;
+use crate::utilsprites::RenderMetrics;
 use ::window::DeadKeyStatus;
 use anyhow::Context;
 use config::{HsbTransform, TextStyle};
 use std::ops::Range;
 use std::rc::Rc;
 use std::time::Instant;
-use termwiz::cell::{unicode_column_width, Blink};
+use termwiz::cell::{unicode_column_width, Blink, TextSize, TextSizingAlign};
 use termwiz::color::LinearRgba;
 use termwiz::surface::CursorShape;
 use wezterm_bidi::Direction;
@@ -433,6 +434,33 @@ impl crate::TermWindow {
         for item in shaped.iter() {
             let cluster = &item.cluster;
             let glyph_info = &item.glyph_info;
+
+            // Text sized by the text sizing protocol is drawn separately,
+            // and always occupies exactly the cells of its block.
+            // Each line of the block draws its own slice of the text.
+            if let Some(text_size) = cluster.attrs.text_size() {
+                if text_size.x == 0 {
+                    self.render_text_size_block(
+                        item,
+                        text_size,
+                        params.left_pixel_x
+                            + phys(cluster.first_cell_idx, num_cols, direction) as f32 * cell_width,
+                        params.top_pixel_y,
+                        cell_width,
+                        cell_height,
+                        &params.render_metrics,
+                        layers,
+                        hsv,
+                    )?;
+                }
+                let width = cluster.width as f32 * cell_width;
+                match direction {
+                    Direction::LeftToRight => cluster_x_pos += width,
+                    Direction::RightToLeft => cluster_x_pos -= width,
+                }
+                visual_cell_idx += cluster.width;
+                continue;
+            }
             let images = cluster.attrs.images().unwrap_or_else(|| vec![]);
             let valign_adjust = match cluster.attrs.vertical_align() {
                 termwiz::cell::VerticalAlign::BaseLine => 0.,
@@ -717,6 +745,105 @@ impl crate::TermWindow {
         })
     }
 
+    /// Renders the slice of the text of a block that was sized using
+    /// the text sizing protocol that falls within the line at
+    /// `top_pixel_y`, so that the block is displayed even when some of
+    /// its lines are not.
+    /// The glyphs were rasterized at the scaled size of the block.
+    fn render_text_size_block(
+        &self,
+        item: &LineToElementShape,
+        text_size: TextSize,
+        left_pixel_x: f32,
+        top_pixel_y: f32,
+        cell_width: f32,
+        cell_height: f32,
+        render_metrics: &RenderMetrics,
+        layers: &mut TripleLayerQuadAllocator,
+        hsv: Option<HsbTransform>,
+    ) -> anyhow::Result<()> {
+        if item.cluster.attrs.invisible() || item.fg_color == item.bg_color {
+            return Ok(());
+        }
+
+        let scale = text_size.font_scale();
+        let block_width = text_size.width as f32 * cell_width;
+        let block_height = text_size.scale as f32 * cell_height;
+        let text_width: f32 = item
+            .glyph_info
+            .iter()
+            .map(|info| info.glyph.x_advance.get() as f32)
+            .sum();
+        let text_height = cell_height * scale;
+
+        fn align_offset(align: TextSizingAlign, available: f32, used: f32) -> f32 {
+            match align {
+                TextSizingAlign::Start => 0.,
+                TextSizingAlign::End => (available - used).max(0.),
+                TextSizingAlign::Center => ((available - used) / 2.).max(0.),
+            }
+        }
+
+        let gl_x = self.dimensions.pixel_width as f32 / -2.;
+        let gl_y = self.dimensions.pixel_height as f32 / -2.;
+        // The glyphs are clipped to this line
+        let clip_top = gl_y + top_pixel_y;
+        let clip_bottom = clip_top + cell_height;
+        let block_top = clip_top - text_size.y as f32 * cell_height;
+
+        let mut pen_x =
+            gl_x + left_pixel_x + align_offset(text_size.horizontal_align, block_width, text_width);
+        let pos_y = block_top + align_offset(text_size.vertical_align, block_height, text_height);
+        let baseline = pos_y + (cell_height + render_metrics.descender.get() as f32) * scale;
+
+        for info in item.glyph_info.iter() {
+            let glyph = &info.glyph;
+            if let Some(texture) = &glyph.texture {
+                let x = pen_x + (glyph.x_offset + glyph.bearing_x).get() as f32;
+                let top = baseline - (glyph.y_offset + glyph.bearing_y).get() as f32;
+                let height = texture.coords.size.height as f32;
+                let bottom = top + height;
+
+                let visible_top = top.max(clip_top);
+                let visible_bottom = bottom.min(clip_bottom);
+                if visible_top < visible_bottom {
+                    let coords = texture.texture_coords();
+                    let tex_y = |y: f32| {
+                        coords.min_y() + (y - top) / height * (coords.max_y() - coords.min_y())
+                    };
+
+                    let mut quad = layers.allocate(1).context("layers.allocate(1)")?;
+                    quad.set_position(
+                        x,
+                        visible_top,
+                        x + texture.coords.size.width as f32,
+                        visible_bottom,
+                    );
+                    quad.set_fg_color(item.fg_color);
+                    quad.set_texture_discrete(
+                        coords.min_x(),
+                        coords.max_x(),
+                        tex_y(visible_top),
+                        tex_y(visible_bottom),
+                    );
+                    quad.set_hsv(if glyph.brightness_adjust != 1.0 {
+                        let hsv = hsv.unwrap_or_else(|| HsbTransform::default());
+                        Some(HsbTransform {
+                            brightness: hsv.brightness * glyph.brightness_adjust,
+                            ..hsv
+                        })
+                    } else {
+                        hsv
+                    });
+                    quad.set_has_color(glyph.has_color);
+                }
+            }
+            pen_x += glyph.x_advance.get() as f32;
+        }
+
+        Ok(())
+    }
+
     fn build_line_element_shape(
         &self,
         params: LineToElementParams,
@@ -855,13 +982,21 @@ impl crate::TermWindow {
 
             let style_params = last_style.as_ref().expect("we just set it up").clone();
 
-            let glyph_info = self.cached_cluster_shape(
-                style_params.style,
-                &cluster,
-                &gl_state,
-                None,
-                &self.render_metrics,
-            )?;
+            let glyph_info = match cluster.attrs.text_size() {
+                Some(text_size) if text_size.x == 0 => {
+                    let font = self
+                        .fonts
+                        .resolve_font_scaled(style_params.style, text_size.font_scale() as f64)?;
+                    self.shape_text_size_block(style_params.style, &cluster, &gl_state, &font)?
+                }
+                _ => self.cached_cluster_shape(
+                    style_params.style,
+                    &cluster,
+                    &gl_state,
+                    None,
+                    &self.render_metrics,
+                )?,
+            };
             let pixel_width = glyph_info
                 .iter()
                 .map(|info| info.glyph.x_advance.get() as f32)
@@ -141,6 +141,13 @@ where
                 .await?;
                 stream.flush().await.context("flushing PDU to client")?;
             }
+            Ok(Item::Notif(MuxNotification::QueryClipboard { pane_id, selection })) => {
+                // There is no way for the client to consent to sharing
+                // its clipboard, so remote panes cannot read it
+                if let Some(pane) = Mux::get().get_pane(pane_id) {
+                    pane.answer_clipboard_query(selection, None);
+                }
+            }
             Ok(Item::Notif(MuxNotification::TabAddedToWindow { tab_id, window_id })) => {
                 Pdu::TabAddedToWindow(codec::TabAddedToWindow { tab_id, window_id })
                     .encode_async(&mut stream, 0)
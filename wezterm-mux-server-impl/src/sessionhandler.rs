@@ -82,6 +82,7 @@ impl PerPane {
 
         let old_seqno = self.seqno;
         self.seqno = pane.get_current_seqno();
+        let synchronized = pane.get_synchronized_update_seqno() > old_seqno;
         let mut all_dirty_lines = pane.get_changed_since(
             0..dims.physical_top + dims.viewport_rows as StableRowIndex,
             old_seqno,
@@ -139,6 +140,7 @@ impl PerPane {
             working_dir: working_dir.map(Into::into),
             input_serial: force_with_input_serial,
             seqno: self.seqno,
+            synchronized,
         })
     }
 }
@@ -757,6 +759,25 @@ impl SessionHandler {
                 .detach();
             }
 
+            Pdu::GetPanePalette(GetPanePalette { pane_id }) => {
+                spawn_into_main_thread(async move {
+                    catch(
+                        move || {
+                            let mux = Mux::get();
+                            let pane = mux
+                                .get_pane(pane_id)
+                                .ok_or_else(|| anyhow!("no such pane {}", pane_id))?;
+                            Ok(Pdu::GetPanePaletteResponse(GetPanePaletteResponse {
+                                pane_id,
+                                palette: pane.palette(),
+                            }))
+                        },
+                        send_response,
+                    )
+                })
+                .detach();
+            }
+
             Pdu::GetPaneRenderChanges(GetPaneRenderChanges { pane_id, .. }) => {
                 let sender = self.to_write_tx.clone();
                 let per_pane = self.per_pane(pane_id);
@@ -1010,6 +1031,7 @@ impl SessionHandler {
             | Pdu::MovePaneToNewTabResponse { .. }
             | Pdu::TabAddedToWindow { .. }
             | Pdu::GetPaneRenderableDimensionsResponse { .. }
+            | Pdu::GetPanePaletteResponse { .. }
             | Pdu::ErrorResponse { .. } => {
                 send_response(Err(anyhow!("expected a request, got {:?}", decoded.pdu)))
             }
@@ -84,7 +84,12 @@ impl CellCluster {
                     ))
                 }
                 Some(mut last) => {
-                    if last.attrs != *normalized_attr || last.presentation != presentation {
+                    // Each block of text sized by the text sizing protocol
+                    // is rendered separately, so it gets a cluster of its own
+                    if last.attrs != *normalized_attr
+                        || last.presentation != presentation
+                        || c.attrs().text_size().is_some()
+                    {
                         // Flush pending cluster and start a new one
                         clusters.push(last);
 
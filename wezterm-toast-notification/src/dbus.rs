@@ -1,7 +1,7 @@
 #![cfg(all(not(target_os = "macos"), not(windows)))]
 //! See <https://developer.gnome.org/notification-spec/>
 
-use crate::ToastNotification;
+use crate::{ToastHandle, ToastNotification, ToastUrgency};
 use futures_util::stream::{abortable, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -86,7 +86,10 @@ impl Reason {
     }
 }
 
-async fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::error::Error>> {
+async fn show_notif_impl(
+    notif: ToastNotification,
+    handle: ToastHandle,
+) -> Result<(), Box<dyn std::error::Error>> {
     let connection = zbus::ConnectionBuilder::session()?.build().await?;
 
     let proxy = NotificationsProxy::new(&connection).await?;
@@ -99,8 +102,25 @@ async fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::er
         return Ok(());
     }
 
+    let mut actions = vec![];
+    if notif.url.is_some() {
+        actions.extend_from_slice(&["show", "Show"]);
+    }
+    if notif.on_activate.is_some() {
+        // The default action is invoked when the notification
+        // itself is clicked
+        actions.extend_from_slice(&["default", "Activate"]);
+    }
+
     let mut hints = HashMap::new();
-    hints.insert("urgency", Value::U8(2 /* Critical */));
+    hints.insert(
+        "urgency",
+        Value::U8(match notif.urgency {
+            Some(ToastUrgency::Low) => 0,
+            Some(ToastUrgency::Normal) => 1,
+            Some(ToastUrgency::Critical) | None => 2,
+        }),
+    );
     let notification = proxy
         .notify(
             "wezterm",
@@ -108,16 +128,25 @@ async fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::er
             "org.wezfurlong.wezterm",
             &notif.title,
             &notif.message,
-            if notif.url.is_some() {
-                &["show", "Show"]
-            } else {
-                &[]
-            },
+            &actions,
             &hints,
             notif.timeout.map(|d| d.as_millis() as _).unwrap_or(0),
         )
         .await?;
 
+    handle.set_closer(move || {
+        std::thread::spawn(move || {
+            let res = async_io::block_on(async move {
+                let connection = zbus::ConnectionBuilder::session()?.build().await?;
+                let proxy = NotificationsProxy::new(&connection).await?;
+                proxy.close_notification(notification).await
+            });
+            if let Err(err) = res {
+                log::error!("while closing notification: {:#}", err);
+            }
+        });
+    });
+
     let (mut invoked_stream, abort_invoked) = abortable(proxy.receive_action_invoked().await?);
     let (mut closed_stream, abort_closed) = abortable(proxy.receive_notification_closed().await?);
 
@@ -125,13 +154,26 @@ async fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::er
         async {
             while let Some(signal) = invoked_stream.next().await {
                 let args = signal.args()?;
-                if args.nid == notification {
-                    if let Some(url) = notif.url.as_ref() {
-                        wezterm_open_url::open_url(url);
-                        abort_closed.abort();
-                        break;
+                if args.nid != notification {
+                    continue;
+                }
+                match args.action_key.as_str() {
+                    "show" => {
+                        if let Some(url) = notif.url.as_ref() {
+                            wezterm_open_url::open_url(url);
+                        }
+                    }
+                    "default" => {
+                        if let Some(on_activate) = notif.on_activate.as_ref() {
+                            on_activate.call();
+                        }
                     }
+                    _ => continue,
                 }
+                if notif.on_close.is_none() {
+                    abort_closed.abort();
+                }
+                break;
             }
             Ok::<(), zbus::Error>(())
         },
@@ -140,6 +182,9 @@ async fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::er
                 let args = signal.args()?;
                 let _reason = Reason::new(args.reason);
                 if args.nid == notification {
+                    if let Some(on_close) = notif.on_close.as_ref() {
+                        on_close.call();
+                    }
                     abort_invoked.abort();
                     break;
                 }
@@ -151,11 +196,14 @@ async fn show_notif_impl(notif: ToastNotification) -> Result<(), Box<dyn std::er
     Ok(())
 }
 
-pub fn show_notif(notif: ToastNotification) -> Result<(), Box<dyn std::error::Error>> {
+pub fn show_notif(
+    notif: ToastNotification,
+    handle: ToastHandle,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Run this in a separate thread as we don't know if dbus or the notification
     // service on the other end are up, and we'd otherwise block for some time.
     std::thread::spawn(move || {
-        let res = async_io::block_on(async move { show_notif_impl(notif).await });
+        let res = async_io::block_on(async move { show_notif_impl(notif, handle).await });
         if let Err(err) = res {
             log::error!("while showing notification: {:#}", err);
         }
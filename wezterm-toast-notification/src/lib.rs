@@ -1,21 +1,106 @@
+use std::sync::{Arc, Mutex};
+
 mod dbus;
 mod macos;
 mod windows;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ToastNotification {
     pub title: String,
     pub message: String,
     pub url: Option<String>,
     pub timeout: Option<std::time::Duration>,
+    /// None uses the default, which is critical on systems that
+    /// support specifying the urgency
+    pub urgency: Option<ToastUrgency>,
+    /// Called when the user clicks on the notification
+    pub on_activate: Option<ToastCallback>,
+    /// Called when the notification is dismissed by the user,
+    /// or is closed via its ToastHandle
+    pub on_close: Option<ToastCallback>,
 }
 
 impl ToastNotification {
-    pub fn show(self) {
+    pub fn show(self) -> ToastHandle {
         show(self)
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastUrgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+/// A callback that is invoked in response to the user interacting
+/// with a notification.  It may be called from any thread.
+#[derive(Clone)]
+pub struct ToastCallback(Arc<dyn Fn() + Send + Sync>);
+
+impl ToastCallback {
+    pub fn new<F: Fn() + Send + Sync + 'static>(func: F) -> Self {
+        Self(Arc::new(func))
+    }
+
+    pub(crate) fn call(&self) {
+        (self.0)()
+    }
+}
+
+impl std::fmt::Debug for ToastCallback {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("ToastCallback").finish()
+    }
+}
+
+type Closer = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct HandleState {
+    closer: Option<Closer>,
+    closed: bool,
+}
+
+/// Allows closing a notification after it has been shown.
+/// Notifications are shown asynchronously, so the backend registers
+/// the means to close it once it is known; closing it before then
+/// causes it to be closed as soon as that happens.
+#[derive(Clone, Default)]
+pub struct ToastHandle {
+    state: Arc<Mutex<HandleState>>,
+}
+
+impl std::fmt::Debug for ToastHandle {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("ToastHandle").finish()
+    }
+}
+
+impl ToastHandle {
+    pub fn close(&self) {
+        let closer = {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            state.closer.take()
+        };
+        if let Some(closer) = closer {
+            closer();
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn set_closer<F: FnOnce() + Send + 'static>(&self, closer: F) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            drop(state);
+            closer();
+        } else {
+            state.closer.replace(Box::new(closer));
+        }
+    }
+}
+
 #[cfg(windows)]
 use crate::windows as backend;
 #[cfg(all(not(target_os = "macos"), not(windows)))]
@@ -27,15 +112,20 @@ mod nop {
     use super::*;
 
     #[allow(dead_code)]
-    pub fn show_notif(_: ToastNotification) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn show_notif(
+        _: ToastNotification,
+        _: ToastHandle,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         Ok(())
     }
 }
 
-pub fn show(notif: ToastNotification) {
-    if let Err(err) = backend::show_notif(notif) {
+pub fn show(notif: ToastNotification) -> ToastHandle {
+    let handle = ToastHandle::default();
+    if let Err(err) = backend::show_notif(notif, handle.clone()) {
         log::error!("Failed to show notification: {}", err);
     }
+    handle
 }
 
 pub fn persistent_toast_notification_with_click_to_open_url(title: &str, message: &str, url: &str) {
@@ -43,7 +133,7 @@ pub fn persistent_toast_notification_with_click_to_open_url(title: &str, message
         title: title.to_string(),
         message: message.to_string(),
         url: Some(url.to_string()),
-        timeout: None,
+        ..Default::default()
     });
 }
 
@@ -51,8 +141,7 @@ pub fn persistent_toast_notification(title: &str, message: &str) {
     show(ToastNotification {
         title: title.to_string(),
         message: message.to_string(),
-        url: None,
-        timeout: None,
+        ..Default::default()
     });
 }
 
@@ -1,5 +1,5 @@
 #![cfg(target_os = "macos")]
-use crate::ToastNotification;
+use crate::{ToastCallback, ToastHandle, ToastNotification};
 use block2::{Block, RcBlock};
 use objc2::rc::Retained;
 use objc2::runtime::{Bool, NSObject, NSObjectProtocol, ProtocolObject};
@@ -8,14 +8,26 @@ use objc2_foundation::{ns_string, NSArray, NSDictionary, NSError, NSSet, NSStrin
 use objc2_user_notifications::{
     UNAuthorizationOptions, UNMutableNotificationContent, UNNotification, UNNotificationAction,
     UNNotificationActionOptions, UNNotificationCategory, UNNotificationCategoryOptions,
+    UNNotificationDefaultActionIdentifier, UNNotificationDismissActionIdentifier,
     UNNotificationPresentationOptions, UNNotificationRequest, UNNotificationResponse,
     UNUserNotificationCenter, UNUserNotificationCenterDelegate,
 };
-use std::sync::{LazyLock, Once};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, Once};
 
 const NEEDS_SIGN: &str = "Note that the application must be code-signed \
                           for UNUserNotificationCenter to work";
 
+/// The activation and close callbacks for the notifications that
+/// are currently displayed, keyed by their request identifier
+static CALLBACKS: LazyLock<Mutex<HashMap<String, Callbacks>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+struct Callbacks {
+    on_activate: Option<ToastCallback>,
+    on_close: Option<ToastCallback>,
+}
+
 fn ns_error_to_string(err: *mut NSError) -> String {
     if err.is_null() {
         "null error".to_string()
@@ -60,12 +72,26 @@ define_class!(
             completion_handler: &Block<dyn Fn()>,
         ) {
             let action = response.actionIdentifier();
-            let user_info = response.notification().request().content().userInfo();
+            let request = response.notification().request();
+            let identifier = request.identifier().to_string();
+            let user_info = request.content().userInfo();
             let url = user_info.valueForKey(ns_string!("url"));
 
             log::debug!("did_receive_notification -> action={action:?} url={url:?}");
 
-            if let Some(url) = url {
+            if action.isEqualToString(UNNotificationDismissActionIdentifier) {
+                let callbacks = CALLBACKS.lock().unwrap().remove(&identifier);
+                if let Some(on_close) = callbacks.and_then(|cb| cb.on_close) {
+                    on_close.call();
+                }
+            } else if action.isEqualToString(UNNotificationDefaultActionIdentifier)
+                && user_info.valueForKey(ns_string!("activate")).is_some()
+            {
+                let callbacks = CALLBACKS.lock().unwrap().remove(&identifier);
+                if let Some(on_activate) = callbacks.and_then(|cb| cb.on_activate) {
+                    on_activate.call();
+                }
+            } else if let Some(url) = url {
                 if let Ok(url_str) = url.downcast::<NSString>() {
                     wezterm_open_url::open_url(&url_str.to_string());
                 }
@@ -123,7 +149,17 @@ pub fn initialize() {
                 &NSArray::from_slice(&[]),
                 UNNotificationCategoryOptions::CustomDismissAction,
             );
-        CENTER.setNotificationCategories(&NSSet::from_retained_slice(&[show_url_cat]));
+        // Notifications with callbacks use this category so that we
+        // are told when they are dismissed
+        let callback_cat =
+            UNNotificationCategory::categoryWithIdentifier_actions_intentIdentifiers_options(
+                ns_string!("CALLBACK_ACTION"),
+                &NSArray::from_slice(&[]),
+                &NSArray::from_slice(&[]),
+                UNNotificationCategoryOptions::CustomDismissAction,
+            );
+        CENTER
+            .setNotificationCategories(&NSSet::from_retained_slice(&[show_url_cat, callback_cat]));
 
         let delegate = NotifDelegate::new();
         let delegate_proto = ProtocolObject::from_retained(delegate.clone());
@@ -145,7 +181,10 @@ pub fn initialize() {
     });
 }
 
-pub fn show_notif(toast: ToastNotification) -> Result<(), Box<dyn std::error::Error>> {
+pub fn show_notif(
+    toast: ToastNotification,
+    handle: ToastHandle,
+) -> Result<(), Box<dyn std::error::Error>> {
     initialize();
     unsafe {
         log::debug!("show_notif center.delegate is {:?}", CENTER.delegate());
@@ -154,6 +193,8 @@ pub fn show_notif(toast: ToastNotification) -> Result<(), Box<dyn std::error::Er
         notif.setTitle(&NSString::from_str(&toast.title));
         notif.setBody(&NSString::from_str(&toast.message));
 
+        let identifier = uuid::Uuid::new_v4().to_string();
+
         if let Some(url) = &toast.url {
             let info =
                 NSDictionary::from_slices(&[ns_string!("url")], &[&*NSString::from_str(&url)]);
@@ -162,9 +203,35 @@ pub fn show_notif(toast: ToastNotification) -> Result<(), Box<dyn std::error::Er
                     .expect("is NSDictionary"),
             );
             notif.setCategoryIdentifier(ns_string!("SHOW_URL_ACTION"));
+        } else if toast.on_activate.is_some() || toast.on_close.is_some() {
+            let info = NSDictionary::from_slices(
+                &[ns_string!("activate")],
+                &[&*NSString::from_str(&identifier)],
+            );
+            notif.setUserInfo(
+                info.downcast_ref::<NSDictionary>()
+                    .expect("is NSDictionary"),
+            );
+            notif.setCategoryIdentifier(ns_string!("CALLBACK_ACTION"));
+            CALLBACKS.lock().unwrap().insert(
+                identifier.clone(),
+                Callbacks {
+                    on_activate: toast.on_activate.clone(),
+                    on_close: toast.on_close.clone(),
+                },
+            );
         }
 
-        let identifier = uuid::Uuid::new_v4().to_string();
+        let close_identifier = identifier.clone();
+        handle.set_closer(move || {
+            let ident_array =
+                NSArray::from_retained_slice(&[NSString::from_str(&close_identifier)]);
+            CENTER.removeDeliveredNotificationsWithIdentifiers(&ident_array);
+            let callbacks = CALLBACKS.lock().unwrap().remove(&close_identifier);
+            if let Some(on_close) = callbacks.and_then(|cb| cb.on_close) {
+                on_close.call();
+            }
+        });
         let request = UNNotificationRequest::requestWithIdentifier_content_trigger(
             &NSString::from_str(&identifier),
             &*notif,
@@ -181,13 +248,11 @@ pub fn show_notif(toast: ToastNotification) -> Result<(), Box<dyn std::error::Er
                         // because we're not guaranteed to be called from the main
                         // thread.  We also don't have access to the executor machinery
                         // from the window crate here, so we just do this basic take.
-                        let identifier = identifier.clone();
+                        let handle = handle.clone();
                         std::thread::spawn(move || {
                             std::thread::sleep(timeout);
                             // Remove this notification
-                            let ident_array =
-                                NSArray::from_retained_slice(&[NSString::from_str(&identifier)]);
-                            CENTER.removeDeliveredNotificationsWithIdentifiers(&ident_array);
+                            handle.close();
                         });
                     }
                 } else {
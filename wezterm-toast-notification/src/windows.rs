@@ -1,6 +1,6 @@
 #![cfg(windows)]
 
-use crate::ToastNotification as TN;
+use crate::{ToastHandle, ToastNotification as TN};
 use xml::escape::escape_str_pcdata;
 
 use windows::core::{Error as WinError, IInspectable, Interface, HSTRING};
@@ -8,7 +8,7 @@ use windows::Data::Xml::Dom::XmlDocument;
 use windows::Foundation::TypedEventHandler;
 use windows::Win32::Foundation::E_POINTER;
 use windows::UI::Notifications::{
-    ToastActivatedEventArgs, ToastNotification, ToastNotificationManager,
+    ToastActivatedEventArgs, ToastDismissedEventArgs, ToastNotification, ToastNotificationManager,
 };
 
 fn unwrap_arg<T>(a: &Option<T>) -> Result<&T, WinError> {
@@ -18,7 +18,7 @@ fn unwrap_arg<T>(a: &Option<T>) -> Result<&T, WinError> {
     }
 }
 
-fn show_notif_impl(toast: TN) -> Result<(), Box<dyn std::error::Error>> {
+fn show_notif_impl(toast: TN, handle: ToastHandle) -> Result<(), Box<dyn std::error::Error>> {
     let xml = XmlDocument::new()?;
 
     let url_actions = if toast.url.is_some() {
@@ -31,8 +31,16 @@ fn show_notif_impl(toast: TN) -> Result<(), Box<dyn std::error::Error>> {
         ""
     };
 
+    // Clicking on the body of the toast activates it with the
+    // launch arguments
+    let launch = if toast.on_activate.is_some() {
+        r#" launch="activate""#
+    } else {
+        ""
+    };
+
     xml.LoadXml(HSTRING::from(format!(
-        r#"<toast duration="long">
+        r#"<toast duration="long"{}>
         <visual>
             <binding template="ToastGeneric">
                 <text>{}</text>
@@ -41,6 +49,7 @@ fn show_notif_impl(toast: TN) -> Result<(), Box<dyn std::error::Error>> {
         </visual>
         {}
     </toast>"#,
+        launch,
         escape_str_pcdata(&toast.title),
         escape_str_pcdata(&toast.message),
         url_actions
@@ -48,6 +57,7 @@ fn show_notif_impl(toast: TN) -> Result<(), Box<dyn std::error::Error>> {
 
     let notif = ToastNotification::CreateToastNotification(xml)?;
 
+    let on_close = toast.on_close.clone();
     notif.Activated(TypedEventHandler::new(
         move |_: &Option<ToastNotification>, result: &Option<IInspectable>| {
             // let myself = unwrap_arg(myself)?;
@@ -59,17 +69,26 @@ fn show_notif_impl(toast: TN) -> Result<(), Box<dyn std::error::Error>> {
                 if let Some(url) = toast.url.as_ref() {
                     wezterm_open_url::open_url(url);
                 }
+            } else if args == "activate" {
+                if let Some(on_activate) = toast.on_activate.as_ref() {
+                    on_activate.call();
+                }
             }
 
             Ok(())
         },
     ))?;
 
+    if let Some(on_close) = on_close {
+        notif.Dismissed(TypedEventHandler::new(
+            move |_: &Option<ToastNotification>, _: &Option<ToastDismissedEventArgs>| {
+                on_close.call();
+                Ok(())
+            },
+        ))?;
+    }
+
     /*
-    notif.dismissed(TypedEventHandler::new(|sender, result| {
-        log::info!("dismissed {:?}", result);
-        Ok(())
-    }))?;
 
     notif.failed(TypedEventHandler::new(|sender, result| {
         log::warn!("toasts are disabled {:?}", result);
@@ -83,15 +102,21 @@ fn show_notif_impl(toast: TN) -> Result<(), Box<dyn std::error::Error>> {
 
     notifier.Show(&notif)?;
 
+    handle.set_closer(move || {
+        if let Err(err) = notifier.Hide(&notif) {
+            log::error!("Failed to hide toast notification: {:#}", err);
+        }
+    });
+
     Ok(())
 }
 
-pub fn show_notif(notif: TN) -> Result<(), Box<dyn std::error::Error>> {
+pub fn show_notif(notif: TN, handle: ToastHandle) -> Result<(), Box<dyn std::error::Error>> {
     // We need to be in a different thread from the caller
     // in case we get called in the guts of a windows message
     // loop dispatch and are unable to pump messages
     std::thread::spawn(move || {
-        if let Err(err) = show_notif_impl(notif) {
+        if let Err(err) = show_notif_impl(notif, handle) {
             log::error!("Failed to show toast notification: {:#}", err);
         }
     });
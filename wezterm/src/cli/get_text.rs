@@ -1,4 +1,5 @@
 use clap::Parser;
+use mux::export::lines_to_html;
 use mux::pane::PaneId;
 use termwiz_funcs::lines_to_escapes;
 use wezterm_client::client::Client;
@@ -32,6 +33,12 @@ pub struct GetText {
     /// If omitted, unattributed text will be returned.
     #[arg(long)]
     escapes: bool,
+
+    /// Produce a self-contained HTML document that preserves colors
+    /// (resolved through the color palette of the pane), styling,
+    /// hyperlinks and semantic zones.
+    #[arg(long, conflicts_with = "escapes")]
+    html: bool,
 }
 
 impl GetText {
@@ -75,7 +82,7 @@ impl GetText {
             })
             .await?;
 
-        let lines = lines
+        let lines: Vec<_> = lines
             .lines
             .extract_data()
             .0
@@ -83,7 +90,16 @@ impl GetText {
             .map(|(_idx, line)| line)
             .collect();
 
-        if self.escapes {
+        if self.html {
+            let palette = client
+                .get_pane_palette(codec::GetPanePalette { pane_id })
+                .await?
+                .palette;
+            print!(
+                "{}",
+                lines_to_html(&lines, &palette, &format!("wezterm pane {pane_id}"))
+            );
+        } else if self.escapes {
             println!("{}", lines_to_escapes(lines)?);
         } else {
             lines.iter().for_each(|line| println!("{}", line.as_str()));
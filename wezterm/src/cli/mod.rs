@@ -15,6 +15,7 @@ mod list_clients;
 mod move_pane_to_new_tab;
 mod proxy;
 mod rename_workspace;
+mod screenshot;
 mod send_text;
 mod set_tab_title;
 mod set_window_title;
@@ -121,6 +122,11 @@ Outputs the pane-id for the newly created pane on success"
     #[command(name = "get-text", rename_all = "kebab")]
     GetText(get_text::GetText),
 
+    /// Render the content of a pane, or of its tab, to a png image.
+    /// This doesn't require a window or a GPU.
+    #[command(name = "screenshot", rename_all = "kebab")]
+    Screenshot(screenshot::Screenshot),
+
     /// Activate an adjacent pane in the specified direction.
     #[command(name = "activate-pane-direction", rename_all = "kebab")]
     ActivatePaneDirection(activate_pane_direction::ActivatePaneDirection),
@@ -186,6 +192,7 @@ async fn run_cli_async(opts: &crate::Opt, cli: CliCommand) -> anyhow::Result<()>
         CliSubCommand::SplitPane(cmd) => cmd.run(client).await,
         CliSubCommand::SendText(cmd) => cmd.run(client).await,
         CliSubCommand::GetText(cmd) => cmd.run(client).await,
+        CliSubCommand::Screenshot(cmd) => cmd.run(client, opts).await,
         CliSubCommand::SpawnCommand(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
         CliSubCommand::Proxy(cmd) => cmd.run(client, &crate::init_config(opts)?).await,
         CliSubCommand::TlsCreds(cmd) => cmd.run(client).await,
@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Context};
+use clap::builder::ValueHint;
+use clap::Parser;
+use mux::export::{PaneSnapshot, PositionedSnapshot, TabSnapshot};
+use mux::pane::PaneId;
+use mux::tab::PaneEntry;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use wezterm_client::client::Client;
+use wezterm_term::StableRowIndex;
+
+#[derive(Debug, Parser, Clone)]
+pub struct Screenshot {
+    /// Specify the target pane.
+    /// The default is to use the current pane based on the
+    /// environment variable WEZTERM_PANE.
+    #[arg(long)]
+    pane_id: Option<PaneId>,
+
+    /// Render all of the panes in the tab that contains the
+    /// target pane, rather than just the target pane
+    #[arg(long)]
+    tab: bool,
+
+    /// The png file to write
+    #[arg(value_hint = ValueHint::FilePath)]
+    output: PathBuf,
+}
+
+impl Screenshot {
+    pub async fn run(self, client: Client, opts: &crate::Opt) -> anyhow::Result<()> {
+        let pane_id = client.resolve_pane_id(self.pane_id).await?;
+
+        let snapshot = if self.tab {
+            snapshot_tab(&client, pane_id).await?
+        } else {
+            TabSnapshot::single(snapshot_pane(&client, pane_id).await?)
+        };
+
+        // The rendering requires the fonts and the glyph rasterization
+        // from the gui, so hand the snapshot over to it
+        let mut cmd = Command::new(crate::gui_exe_path()?);
+        if opts.skip_config {
+            cmd.arg("--skip-config");
+        }
+        if let Some(config_file) = &opts.config_file {
+            cmd.arg("--config-file");
+            cmd.arg(config_file);
+        }
+        for (name, value) in &opts.config_override {
+            cmd.arg("--config");
+            cmd.arg(format!("{name}={value}"));
+        }
+        cmd.arg("render-snapshot");
+        cmd.arg(&self.output);
+        cmd.stdin(Stdio::piped());
+
+        let mut child = cmd.spawn().with_context(|| format!("spawning {cmd:?}"))?;
+        {
+            let stdin = child
+                .stdin
+                .as_mut()
+                .ok_or_else(|| anyhow!("no stdin for {cmd:?}"))?;
+            serde_json::to_writer(&mut *stdin, &snapshot)?;
+            stdin.flush()?;
+        }
+        // Close stdin so that the child sees EOF
+        drop(child.stdin.take());
+
+        let status = child.wait()?;
+        if !status.success() {
+            anyhow::bail!("rendering the screenshot failed: {status}");
+        }
+        Ok(())
+    }
+}
+
+async fn snapshot_pane(client: &Client, pane_id: PaneId) -> anyhow::Result<PaneSnapshot> {
+    let info = client
+        .get_dimensions(codec::GetPaneRenderableDimensions { pane_id })
+        .await?;
+    let dims = info.dimensions;
+
+    let lines = client
+        .get_lines(codec::GetLines {
+            pane_id,
+            lines: vec![
+                dims.physical_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
+            ],
+        })
+        .await?;
+    let lines = lines
+        .lines
+        .extract_data()
+        .0
+        .into_iter()
+        .map(|(_idx, line)| line)
+        .collect();
+
+    let palette = client
+        .get_pane_palette(codec::GetPanePalette { pane_id })
+        .await?
+        .palette;
+
+    Ok(PaneSnapshot::from_parts(
+        &dims,
+        &info.cursor_position,
+        lines,
+        palette,
+    ))
+}
+
+async fn snapshot_tab(client: &Client, pane_id: PaneId) -> anyhow::Result<TabSnapshot> {
+    let panes = client.list_panes().await?;
+
+    for tabroot in panes.tabs {
+        let mut entries: Vec<PaneEntry> = vec![];
+        let mut cursor = tabroot.into_tree().cursor();
+        loop {
+            if let Some(entry) = cursor.leaf_mut() {
+                entries.push(entry.clone());
+            }
+            match cursor.preorder_next() {
+                Ok(c) => cursor = c,
+                Err(_) => break,
+            }
+        }
+
+        if !entries.iter().any(|entry| entry.pane_id == pane_id) {
+            continue;
+        }
+
+        // A zoomed pane occupies the whole tab
+        if let Some(zoomed) = entries.iter().find(|entry| entry.is_zoomed_pane) {
+            return Ok(TabSnapshot::single(
+                snapshot_pane(client, zoomed.pane_id).await?,
+            ));
+        }
+
+        let mut tab = TabSnapshot {
+            cols: 0,
+            rows: 0,
+            panes: vec![],
+        };
+        for entry in entries {
+            tab.cols = tab.cols.max(entry.left_col + entry.size.cols);
+            tab.rows = tab.rows.max(entry.top_row + entry.size.rows);
+            tab.panes.push(PositionedSnapshot {
+                left: entry.left_col,
+                top: entry.top_row,
+                snapshot: snapshot_pane(client, entry.pane_id).await?,
+            });
+        }
+        return Ok(tab);
+    }
+
+    anyhow::bail!("unable to resolve the tab for pane {pane_id}");
+}
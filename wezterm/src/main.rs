@@ -6,10 +6,12 @@ use config::{wezterm_version, ConfigHandle};
 use mux::Mux;
 use std::ffi::OsString;
 use std::io::Read;
+use std::path::PathBuf;
 use termwiz::caps::Capabilities;
 use termwiz::escape::esc::{Esc, EscCode};
 use termwiz::escape::OneBased;
 use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers};
+use termwiz::sixel::{SixelEncoder, DEFAULT_COLOR_REGISTERS};
 use termwiz::surface::change::Change;
 use termwiz::surface::Position;
 use termwiz::terminal::{ScreenSize, Terminal};
@@ -239,6 +241,26 @@ struct ImgCatCommand {
     #[arg(long)]
     show_resample_timing: bool,
 
+    /// Encode the image as sixel graphics rather than using the
+    /// iTerm2 image protocol.  Use this to display images in
+    /// terminals that only support sixel, such as foot, mlterm
+    /// and xterm.
+    /// Only the first frame of animated images is shown.
+    #[arg(long)]
+    sixel: bool,
+
+    /// The number of colors to use when encoding sixel graphics.
+    /// The default is to use the number of color registers that
+    /// the terminal reports via XTSMGRAPHICS, or 256 if it doesn't
+    /// report it.
+    #[arg(long, requires = "sixel")]
+    sixel_colors: Option<usize>,
+
+    /// Do not dither the image when reducing it to the colors
+    /// available for sixel graphics.
+    #[arg(long, requires = "sixel")]
+    no_dither: bool,
+
     /// The name of the image file to be displayed.
     /// If omitted, will attempt to read it from stdin.
     #[arg(value_parser, value_hint=ValueHint::FilePath)]
@@ -317,6 +339,18 @@ impl ImgCatCommand {
         &self,
         info: ImageInfo,
         term_size: ScreenSize,
+    ) -> (usize, usize) {
+        if term_size.xpixel == 0 || term_size.ypixel == 0 {
+            return (0, 0);
+        }
+        let (width, height) = self.compute_image_pixel_dimensions(info, term_size);
+        (width / term_size.xpixel, height / term_size.ypixel)
+    }
+
+    fn compute_image_pixel_dimensions(
+        &self,
+        info: ImageInfo,
+        term_size: ScreenSize,
     ) -> (usize, usize) {
         let physical_cols = term_size.cols;
         let physical_rows = term_size.rows;
@@ -379,8 +413,7 @@ impl ImgCatCommand {
             (Some(w), Some(h)) => (w, h),
         };
 
-        // And convert to cells
-        (width / cell_pixel_width, height / cell_pixel_height)
+        (width, height)
     }
 
     fn image_dimensions(data: &[u8]) -> anyhow::Result<ImageInfo> {
@@ -417,15 +450,7 @@ impl ImgCatCommand {
         }
 
         let start = std::time::Instant::now();
-        use image::imageops::FilterType;
-        let filter = match self.resample_filter {
-            ResampleFilter::Nearest => FilterType::Nearest,
-            ResampleFilter::Triangle => FilterType::Triangle,
-            ResampleFilter::CatmullRom => FilterType::CatmullRom,
-            ResampleFilter::Gaussian => FilterType::Gaussian,
-            ResampleFilter::Lanczos3 => FilterType::Lanczos3,
-        };
-        let im = im.resize_to_fill(target_width, target_height, filter);
+        let im = im.resize_to_fill(target_width, target_height, self.filter_type());
         if self.show_resample_timing {
             eprintln!("resizing took {:?}", start.elapsed());
         }
@@ -458,6 +483,47 @@ impl ImgCatCommand {
         Ok((data, new_info))
     }
 
+    fn filter_type(&self) -> image::imageops::FilterType {
+        use image::imageops::FilterType;
+        match self.resample_filter {
+            ResampleFilter::Nearest => FilterType::Nearest,
+            ResampleFilter::Triangle => FilterType::Triangle,
+            ResampleFilter::CatmullRom => FilterType::CatmullRom,
+            ResampleFilter::Gaussian => FilterType::Gaussian,
+            ResampleFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+
+    /// Sixel graphics are displayed at their native size, so the image
+    /// is scaled to the size that it would otherwise have been displayed
+    /// at before it is encoded.
+    fn encode_sixel(
+        &self,
+        data: &[u8],
+        image_info: ImageInfo,
+        term_size: ScreenSize,
+        color_registers: usize,
+    ) -> anyhow::Result<String> {
+        let im = image::load_from_memory(data).context("decoding image for sixel")?;
+
+        let (width, height) = self.compute_image_pixel_dimensions(image_info, term_size);
+        let (width, height) = (width as u32, height as u32);
+        let im = if width > 0 && height > 0 && (width, height) != (im.width(), im.height()) {
+            im.resize_exact(width, height, self.filter_type())
+        } else {
+            im
+        };
+        let im = im.into_rgba8();
+
+        let encoder = SixelEncoder {
+            max_colors: color_registers,
+            dither: !self.no_dither,
+            ..SixelEncoder::default()
+        };
+        let sixel = encoder.encode(im.width(), im.height(), im.as_raw())?;
+        Ok(format!("{sixel}{}", Esc::Code(EscCode::StringTerminator)))
+    }
+
     fn get_image_data(&self) -> anyhow::Result<(Vec<u8>, ImageInfo)> {
         let mut data = Vec::new();
         if let Some(file_name) = self.file_name.as_ref() {
@@ -509,6 +575,14 @@ impl ImgCatCommand {
 
         let term_size = probe.screen_size()?;
 
+        let color_registers = match self.sixel_colors {
+            Some(n) => n,
+            None if self.sixel => probe
+                .sixel_color_registers()?
+                .unwrap_or(DEFAULT_COLOR_REGISTERS),
+            None => DEFAULT_COLOR_REGISTERS,
+        };
+
         let is_tmux = xt_version.is_tmux();
 
         // TODO: ideally we'd do some kind of probing to see if conpty
@@ -557,25 +631,36 @@ impl ImgCatCommand {
             }])?;
         }
 
-        let osc = OperatingSystemCommand::ITermProprietary(ITermProprietary::File(Box::new(
-            ITermFileData {
-                name: None,
-                size: Some(data.len()),
-                width: self.width.unwrap_or_default(),
-                height: self.height.unwrap_or_default(),
-                preserve_aspect_ratio: !self.no_preserve_aspect_ratio,
-                inline: true,
-                do_not_move_cursor: self.no_move_cursor,
-                data,
-            },
-        )));
-        let encoded = self
-            .tmux_passthru
-            .unwrap_or_default()
-            .encode(osc.to_string());
+        // The sixel protocol has no way to ask the terminal to leave
+        // the cursor where it is
+        let sixel_hold_cursor = self.sixel && self.no_move_cursor && self.position.is_none();
+
+        let encoded = if self.sixel {
+            if sixel_hold_cursor {
+                print!("{save_cursor}");
+            }
+            self.encode_sixel(&data, image_info, term_size, color_registers)?
+        } else {
+            OperatingSystemCommand::ITermProprietary(ITermProprietary::File(Box::new(
+                ITermFileData {
+                    name: None,
+                    size: Some(data.len()),
+                    width: self.width.unwrap_or_default(),
+                    height: self.height.unwrap_or_default(),
+                    preserve_aspect_ratio: !self.no_preserve_aspect_ratio,
+                    inline: true,
+                    do_not_move_cursor: self.no_move_cursor,
+                    data,
+                },
+            )))
+            .to_string()
+        };
+        let encoded = self.tmux_passthru.unwrap_or_default().encode(encoded);
         println!("{encoded}");
 
-        if let ((_cursor_x, cursor_y), true) = (image_dims, needs_force_cursor_move) {
+        if sixel_hold_cursor {
+            print!("{restore_cursor}");
+        } else if let ((_cursor_x, cursor_y), true) = (image_dims, needs_force_cursor_move) {
             // tell the terminal that doesn't fully understand the image sequence
             // to move the cursor to where it should end up
             term.render(&[Change::CursorPosition {
@@ -765,24 +850,28 @@ fn run() -> anyhow::Result<()> {
     }
 }
 
-fn delegate_to_gui(saver: UmaskSaver) -> anyhow::Result<()> {
-    use std::process::Command;
-
-    // Restore the original umask
-    drop(saver);
-
+/// Returns the path to the wezterm-gui executable that lives
+/// alongside this one
+fn gui_exe_path() -> anyhow::Result<PathBuf> {
     let exe_name = if cfg!(windows) {
         "wezterm-gui.exe"
     } else {
         "wezterm-gui"
     };
 
-    let exe = std::env::current_exe()?
+    Ok(std::env::current_exe()?
         .parent()
         .ok_or_else(|| anyhow!("exe has no parent dir!?"))?
-        .join(exe_name);
+        .join(exe_name))
+}
+
+fn delegate_to_gui(saver: UmaskSaver) -> anyhow::Result<()> {
+    use std::process::Command;
+
+    // Restore the original umask
+    drop(saver);
 
-    let mut cmd = Command::new(exe);
+    let mut cmd = Command::new(gui_exe_path()?);
     if cfg!(windows) {
         cmd.arg("--attach-parent-console");
     }